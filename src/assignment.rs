@@ -0,0 +1,119 @@
+use std::collections::BTreeSet;
+
+use crate::{
+    ast::Statement,
+    cfg::{BlockId, Cfg, Node, VariableId, ENTRY},
+};
+
+
+// the variables that hold a value on every path to the start of each block
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Assigned {
+    pub assigned_in: Vec<BTreeSet<VariableId>>,
+}
+
+impl Assigned {
+    fn transfer(node: &Node, assigned: &mut BTreeSet<VariableId>) {
+        match node.statement {
+            // a `let` without a value only zeroes its slot, which doesn't count as assigning it, and
+            // in a loop it forgets the value of the previous iteration
+            Statement::Let { value: None, .. } => {
+                let declared = node.defs.last().expect("a declared variable");
+                assigned.remove(declared);
+            },
+            _ => assigned.extend(node.defs.iter().copied()),
+        }
+    }
+
+    fn assigned_out(cfg: &Cfg, block: BlockId, assigned_in: &BTreeSet<VariableId>) -> BTreeSet<VariableId> {
+        let mut assigned = assigned_in.clone();
+        cfg.blocks[block].nodes.iter().for_each(|node| Self::transfer(node, &mut assigned));
+        assigned
+    }
+
+    // the first variable that is read on some path before it has been assigned
+    pub fn first_unassigned_read(&self, cfg: &Cfg) -> Option<VariableId> {
+        cfg.blocks.iter().enumerate().find_map(|(block, block_data)| {
+            let mut assigned = self.assigned_in[block].clone();
+            for node in &block_data.nodes {
+                if let Some(variable) = node.uses.iter().find(|variable| !assigned.contains(variable)) {
+                    return Some(*variable);
+                }
+                Self::transfer(node, &mut assigned);
+            }
+            block_data.terminator.uses().iter().find(|variable| !assigned.contains(variable)).copied()
+        })
+    }
+}
+
+impl From<&Cfg> for Assigned {
+    fn from(cfg: &Cfg) -> Self {
+        let count = cfg.blocks.len();
+        let mut predecessors = vec![Vec::new(); count];
+        for (block, block_data) in cfg.blocks.iter().enumerate() {
+            block_data.terminator.successors().into_iter().for_each(|successor| predecessors[successor].push(block));
+        }
+
+        // iterate forwards to a fixed point, starting from everything assigned so the intersection
+        // over predecessors only ever shrinks
+        let params: BTreeSet<VariableId> = (0..cfg.variables.len()).filter(|id| cfg.variables[*id].is_param).collect();
+        let everything: BTreeSet<VariableId> = (0..cfg.variables.len()).collect();
+        let mut assigned = Self { assigned_in: vec![everything; count] };
+        assigned.assigned_in[ENTRY] = params.clone();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (block, predecessors) in predecessors.iter().enumerate() {
+                let mut assigned_in = predecessors
+                    .iter()
+                    .map(|predecessor| Self::assigned_out(cfg, *predecessor, &assigned.assigned_in[*predecessor]))
+                    .reduce(|a, b| a.intersection(&b).copied().collect())
+                    .unwrap_or_default();
+                if block == ENTRY {
+                    assigned_in = params.clone();
+                }
+                if assigned_in != assigned.assigned_in[block] {
+                    assigned.assigned_in[block] = assigned_in;
+                    changed = true;
+                }
+            }
+        }
+        assigned
+    }
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[cfg(test)]
+fn unassigned_read(source: &str) -> Option<String> {
+    use crate::{parser::Parser, tokenize::Tokenize};
+    let program = Parser::new(source.tokenize()).parse().expect("source parses");
+    let cfg = Cfg::from(program.0.first().expect("an item"));
+    Assigned::from(&cfg).first_unassigned_read(&cfg).map(|variable| cfg.variables[variable].name.clone())
+}
+
+#[test]
+fn variables_are_assigned_on_every_branch() {
+    assert_eq!(unassigned_read("fn f(a) { let mut x; if (a) x = 1; else x = 2; return x; }"), None);
+    assert_eq!(unassigned_read("fn f(a) { let mut x; if (a) x = 1; return x; }"), Some(String::from("x")));
+    assert_eq!(unassigned_read("fn f(a) { let mut x; if (a) return 0; else x = 2; return x; }"), None);
+}
+
+#[test]
+fn reads_before_assignment_are_found() {
+    assert_eq!(unassigned_read("fn f() { let mut x; x += 1; return x; }"), Some(String::from("x")));
+    assert_eq!(unassigned_read("fn f() { let mut x; x = x + 1; return x; }"), Some(String::from("x")));
+    assert_eq!(unassigned_read("fn f() { let mut x; x = 1; x += 1; return x; }"), None);
+}
+
+#[test]
+fn declarations_in_loops_forget_earlier_iterations() {
+    assert_eq!(unassigned_read("fn f(a) { loop { let mut x; if (a) x = 1; exit(x); } }"), Some(String::from("x")));
+    assert_eq!(unassigned_read("fn f(a) { let mut x; while (a) x = 1; return x; }"), Some(String::from("x")));
+    assert_eq!(unassigned_read("fn f(a) { let mut x; loop { x = 1; break; } return x; }"), None);
+}
@@ -1,32 +1,285 @@
 
-#[derive(Clone, Debug)]
+use crate::{
+    codegen::x86::intrinsics::{definition, Safety, INTRINSICS},
+    tokenize::{Location, quoted},
+};
+
+
+/// the items of a source file in the order they are written
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Program(pub Vec<Item>);
 
 
-#[derive(Clone, Debug)]
+/// a function or constant at the top level of a file
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Item {
-    Function { name: String, params: Vec<Param>, body: Statement },
+    Function {
+        name: String,
+        params: Vec<Param>,
+        // `None` where the function has no `-> type`
+        return_type: Option<Type>,
+        body: Statement,
+        attributes: Vec<Attribute>,
+        is_const: bool,
+    },
+    Const { identifier: String, value: Expr, location: Location },
 }
 
 
-#[derive(Clone, Debug)]
+/// values are integers, `i64` unless a type says otherwise, and `()` is the type of a function
+/// that gives none. every integer is held in 64 bits, those that are narrower sign or zero extended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Type {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    Unit,
+}
+
+impl Type {
+    const INTEGERS: [Self; 8] = [Self::I8, Self::I16, Self::I32, Self::I64, Self::U8, Self::U16, Self::U32, Self::U64];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::INTEGERS.into_iter().find(|ty| ty.to_string() == name)
+    }
+
+    // the width in bits, `None` for `()`
+    pub fn bits(self) -> Option<u32> {
+        match self {
+            Self::I8 | Self::U8 => Some(8),
+            Self::I16 | Self::U16 => Some(16),
+            Self::I32 | Self::U32 => Some(32),
+            Self::I64 | Self::U64 => Some(64),
+            Self::Unit => None,
+        }
+    }
+
+    pub fn is_signed(self) -> bool {
+        matches!(self, Self::I8 | Self::I16 | Self::I32 | Self::I64)
+    }
+
+    // the value cut down to the width of the type and extended back to 64 bits, as it is held
+    pub fn wrap(self, value: i64) -> i64 {
+        match self {
+            Self::I8 => value as i8 as i64,
+            Self::I16 => value as i16 as i64,
+            Self::I32 => value as i32 as i64,
+            Self::U8 => value as u8 as i64,
+            Self::U16 => value as u16 as i64,
+            Self::U32 => value as u32 as i64,
+            Self::I64 | Self::U64 | Self::Unit => value,
+        }
+    }
+
+    // whether a literal stands for a value of the type as it is written. negative `u64` literals
+    // are the only ones a 64-bit value can't tell apart
+    pub fn fits(self, value: i64) -> bool {
+        match self {
+            Self::U64 => value >= 0,
+            ty => ty.wrap(value) == value,
+        }
+    }
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::I8 => write!(f, "i8"),
+            Self::I16 => write!(f, "i16"),
+            Self::I32 => write!(f, "i32"),
+            Self::I64 => write!(f, "i64"),
+            Self::U8 => write!(f, "u8"),
+            Self::U16 => write!(f, "u16"),
+            Self::U32 => write!(f, "u32"),
+            Self::U64 => write!(f, "u64"),
+            Self::Unit => write!(f, "()"),
+        }
+    }
+}
+
+
+/// an attribute written as `#[name]` or `#[name(args)]` before an item
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attribute {
+    pub name: String,
+    pub args: Vec<AttributeArg>,
+    pub location: Location,
+}
+
+
+/// an argument of an attribute
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AttributeArg {
+    Identifier(String),
+    IntegerLiteral(String),
+    StringLiteral(String),
+}
+
+
+/// the arguments an attribute is written with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AttributeArgs {
+    None,
+    // none, or one of the words
+    OptionalWord(&'static [&'static str]),
+    Integer,
+}
+
+/// the attributes the compiler knows, any other is an error so a misspelled one isn't ignored
+pub const ATTRIBUTES: [(&str, AttributeArgs); 5] = [
+    ("bits", AttributeArgs::Integer),
+    ("export", AttributeArgs::None),
+    ("inline", AttributeArgs::OptionalWord(&["always", "never"])),
+    ("noinline", AttributeArgs::None),
+    ("test", AttributeArgs::None),
+];
+
+impl Attribute {
+    // the ways the attribute can be written, `None` if it isn't a known one
+    pub fn usage(&self) -> Option<String> {
+        let (name, args) = ATTRIBUTES.iter().find(|(name, _)| *name == self.name)?;
+        Some(match args {
+            AttributeArgs::None => format!("`#[{name}]`"),
+            AttributeArgs::OptionalWord(words) => std::iter::once(format!("`#[{name}]`"))
+                .chain(words.iter().map(|word| format!("`#[{name}({word})]`")))
+                .collect::<Vec<_>>()
+                .join(", "),
+            AttributeArgs::Integer => format!("`#[{name}(n)]`"),
+        })
+    }
+
+    pub fn is_valid(&self) -> bool {
+        let Some((_, args)) = ATTRIBUTES.iter().find(|(name, _)| *name == self.name) else {
+            return false;
+        };
+        match (args, self.args.as_slice()) {
+            (AttributeArgs::None | AttributeArgs::OptionalWord(_), []) => true,
+            (AttributeArgs::OptionalWord(words), [AttributeArg::Identifier(word)]) => words.contains(&word.as_str()),
+            (AttributeArgs::Integer, [AttributeArg::IntegerLiteral(literal)]) => literal.parse::<u64>().is_ok(),
+            _ => false,
+        }
+    }
+}
+
+
+/// whether `#[inline]` asks for a function to always or never be inlined
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InlineHint {
+    #[default]
+    Default,
+    Always,
+    Never,
+}
+
+impl From<&[Attribute]> for InlineHint {
+    fn from(attributes: &[Attribute]) -> Self {
+        attributes.iter().fold(Self::Default, |hint, attribute| match (attribute.name.as_str(), attribute.args.as_slice()) {
+            ("inline", []) => Self::Always,
+            ("inline", [AttributeArg::Identifier(arg)]) if arg == "always" => Self::Always,
+            ("inline", [AttributeArg::Identifier(arg)]) if arg == "never" => Self::Never,
+            ("noinline", []) => Self::Never,
+            _ => hint,
+        })
+    }
+}
+
+
+/// builtins written like function calls, which codegen expands in place and the optimizer leaves
+/// where they are. their names cannot be used for functions, these and their other properties are
+/// in the table of `codegen::x86::intrinsics`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Intrinsic {
+    VolatileLoad,
+    VolatileStore,
+    Inb,
+    Inw,
+    Outb,
+    Outw,
+    AtomicLoad,
+    AtomicStore,
+    AtomicAdd,
+    AtomicCas,
+    // takes the name of a function without parameters rather than a value
+    Spawn,
+    Join,
+    // exchanges the values of two mutable variables and gives no value, like an assignment
+    Swap,
+    Min,
+    Max,
+    Abs,
+    // the number of bits that are set
+    Popcount,
+    // prints the source file and the line given to stderr. inserted by `--instrument=trace`, it
+    // can't be called by name
+    Trace,
+}
+
+impl Intrinsic {
+    pub fn from_name(name: &str) -> Option<Self> {
+        INTRINSICS.iter().find(|def| def.callable() && def.name == name).map(|def| def.intrinsic)
+    }
+
+    pub fn name(&self) -> &'static str {
+        definition(*self).name
+    }
+
+    pub fn is_unsafe(&self) -> bool {
+        definition(*self).safety == Safety::Unsafe
+    }
+
+    pub fn is_pure(&self) -> bool {
+        definition(*self).safety == Safety::Pure
+    }
+
+    pub fn arity(&self) -> usize {
+        definition(*self).arity
+    }
+}
+
+
+/// a parameter of a function, with its type if one is written
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Param {
     pub name: String,
 }
 
 
-#[derive(Clone, Debug)]
+/// a statement in the body of a function
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Statement {
     Exit { value: Expr },
+    Panic { message: String, location: Location },
+    Unreachable { location: Location },
+    // checked before codegen, which emits nothing for it
+    StaticAssert { condition: Expr, message: String, location: Location },
     Expr(Expr),
-    Let { identifier: String, value: Expr, is_mutable: bool },
+    // only mutable variables may be declared without a value, which has to be assigned before it is read
+    Let { identifier: String, ty: Option<Type>, value: Option<Expr>, is_mutable: bool },
+    // evaluated at compile time and substituted at its uses before codegen
+    Const { identifier: String, value: Expr, location: Location },
 
-    Break, Continue,
-    Return(Expr),
+    // the label of the loop they leave or go on with, the innermost one if there is none
+    Break(Option<String>), Continue(Option<String>),
+    // a bare `return;` gives no value, only functions that never return one may use it
+    Return(Option<Expr>),
 }
 
 
-#[derive(Clone, Debug)]
+/// an expression, which gives a value
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Expr {
     Add(Box<Expr>, Box<Expr>),
     Sub(Box<Expr>, Box<Expr>),
@@ -34,11 +287,14 @@ pub enum Expr {
     Div(Box<Expr>, Box<Expr>),
     Mod(Box<Expr>, Box<Expr>),
 
+    Assign { identifier: String, value: Box<Expr> },
     AddAssign { identifier: String, value: Box<Expr> },
     SubAssign { identifier: String, value: Box<Expr> },
     MulAssign { identifier: String, value: Box<Expr> },
     DivAssign { identifier: String, value: Box<Expr> },
     ModAssign { identifier: String, value: Box<Expr> },
+    // indexing is checked against the length of the array, a failed check panics at the location
+    IndexAssign { identifier: String, index: Box<Expr>, value: Box<Expr>, location: Location },
 
     Equality(Box<Expr>, Box<Expr>),
     NonEquality(Box<Expr>, Box<Expr>),
@@ -48,14 +304,26 @@ pub enum Expr {
     GreaterEq(Box<Expr>, Box<Expr>),
 
     FunctionCall { name: String, args: Vec<Expr> },
+    Intrinsic { intrinsic: Intrinsic, args: Vec<Expr> },
+    Try(Box<Expr>),
+    Neg(Box<Expr>),
+    // cuts the value down to the width of the type, then sign or zero extends it back
+    Cast { value: Box<Expr>, ty: Type },
 
     IntegerLiteral(String),
+    // evaluates to the address of its bytes
+    StringLiteral(String),
     Identifier(String),
+    // only ever the value of a `let`, which it gives one slot for each element
+    Array(Vec<Expr>),
+    Index { identifier: String, index: Box<Expr>, location: Location },
 
     Block(Vec<Statement>),
     If { check: Box<Expr>, body: Box<Statement>, els: Option<Box<Statement>> },
-    Loop { body: Box<Statement> },
-    While { check: Box<Expr>, body: Box<Statement> },
+    Loop { label: Option<String>, body: Box<Statement> },
+    While { label: Option<String>, check: Box<Expr>, body: Box<Statement> },
+    // always holds a `Block`
+    Unsafe(Box<Expr>),
 }
 
 impl Expr {
@@ -72,3 +340,160 @@ impl Expr {
     }
 }
 
+
+
+impl std::fmt::Display for Item {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Function { name, params, return_type, body, attributes, is_const } => {
+                for attribute in attributes {
+                    write!(f, "{attribute} ")?;
+                }
+                if *is_const {
+                    write!(f, "const ")?;
+                }
+                let params: Vec<&str> = params.iter().map(|param| param.name.as_str()).collect();
+                write!(f, "fn {name}({})", params.join(", "))?;
+                if let Some(return_type) = return_type {
+                    write!(f, " -> {return_type}")?;
+                }
+                write!(f, " {body}")
+            },
+            Self::Const { identifier, value, location: _ } => write!(f, "const {identifier} = {value};"),
+        }
+    }
+}
+
+impl std::fmt::Display for Attribute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let args: Vec<String> = self.args.iter().map(|arg| match arg {
+            AttributeArg::Identifier(text) | AttributeArg::IntegerLiteral(text) => text.clone(),
+            AttributeArg::StringLiteral(value) => quoted(value),
+        }).collect();
+        match args.is_empty() {
+            true => write!(f, "#[{}]", self.name),
+            false => write!(f, "#[{}({})]", self.name, args.join(", ")),
+        }
+    }
+}
+
+impl std::fmt::Display for Statement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exit { value } => write!(f, "exit({value});"),
+            Self::Panic { message, location: _ } => write!(f, "panic({});", quoted(message)),
+            Self::Unreachable { location: _ } => write!(f, "unreachable();"),
+            Self::StaticAssert { condition, message, location: _ } => write!(f, "static_assert({condition}, {});", quoted(message)),
+            Self::Expr(value @ (Expr::Block(_) | Expr::If { .. } | Expr::Loop { .. } | Expr::While { .. } | Expr::Unsafe(_)))
+                => write!(f, "{value}"),
+            Self::Expr(value) => write!(f, "{value};"),
+            Self::Const { identifier, value, location: _ } => write!(f, "const {identifier} = {value};"),
+            Self::Let { identifier, ty, value, is_mutable } => {
+                write!(f, "let {}{identifier}", if *is_mutable { "mut " } else { "" })?;
+                if let Some(ty) = ty {
+                    write!(f, ": {ty}")?;
+                }
+                match value {
+                    Some(value) => write!(f, " = {value};"),
+                    None => write!(f, ";"),
+                }
+            },
+            Self::Break(None) => write!(f, "break;"),
+            Self::Break(Some(label)) => write!(f, "break '{label};"),
+            Self::Continue(None) => write!(f, "continue;"),
+            Self::Continue(Some(label)) => write!(f, "continue '{label};"),
+            Self::Return(Some(value)) => write!(f, "return {value};"),
+            Self::Return(None) => write!(f, "return;"),
+        }
+    }
+}
+
+impl Expr {
+    fn binary_operands(&self) -> Option<(&'static str, &Expr, &Expr)> {
+        match self {
+            Self::Add(a, b) => Some(("+", a, b)),
+            Self::Sub(a, b) => Some(("-", a, b)),
+            Self::Mul(a, b) => Some(("*", a, b)),
+            Self::Div(a, b) => Some(("/", a, b)),
+            Self::Mod(a, b) => Some(("%", a, b)),
+            Self::Equality(a, b) => Some(("==", a, b)),
+            Self::NonEquality(a, b) => Some(("!=", a, b)),
+            Self::Less(a, b) => Some(("<", a, b)),
+            Self::LessEq(a, b) => Some(("<=", a, b)),
+            Self::Greater(a, b) => Some((">", a, b)),
+            Self::GreaterEq(a, b) => Some((">=", a, b)),
+            _ => None,
+        }
+    }
+
+    // a cast binds looser than `-` and `?`, so it needs parentheses as their operand
+    fn fmt_operand(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.binary_operands(), self) {
+            (Some(_), _) | (None, Self::Cast { .. }) => write!(f, "({self})"),
+            (None, _) => write!(f, "{self}"),
+        }
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some((operator, a, b)) = self.binary_operands() {
+            a.fmt_operand(f)?;
+            write!(f, " {operator} ")?;
+            return b.fmt_operand(f);
+        }
+        match self {
+            Self::Assign { identifier, value } => write!(f, "{identifier} = {value}"),
+            Self::AddAssign { identifier, value } => write!(f, "{identifier} += {value}"),
+            Self::SubAssign { identifier, value } => write!(f, "{identifier} -= {value}"),
+            Self::MulAssign { identifier, value } => write!(f, "{identifier} *= {value}"),
+            Self::DivAssign { identifier, value } => write!(f, "{identifier} /= {value}"),
+            Self::ModAssign { identifier, value } => write!(f, "{identifier} %= {value}"),
+            Self::IndexAssign { identifier, index, value, location: _ } => write!(f, "{identifier}[{index}] = {value}"),
+
+            Self::FunctionCall { name, args } => {
+                let args: Vec<String> = args.iter().map(Expr::to_string).collect();
+                write!(f, "{name}({})", args.join(", "))
+            },
+            Self::Intrinsic { intrinsic, args } => {
+                let args: Vec<String> = args.iter().map(Expr::to_string).collect();
+                write!(f, "{}({})", intrinsic.name(), args.join(", "))
+            },
+            Self::Try(value) => {
+                value.fmt_operand(f)?;
+                write!(f, "?")
+            },
+            Self::Neg(value) => {
+                write!(f, "-")?;
+                value.fmt_operand(f)
+            },
+            Self::Cast { value, ty } => {
+                value.fmt_operand(f)?;
+                write!(f, " as {ty}")
+            },
+
+            Self::IntegerLiteral(value) | Self::Identifier(value) => write!(f, "{value}"),
+            Self::StringLiteral(value) => write!(f, "{}", quoted(value)),
+            Self::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(Expr::to_string).collect();
+                write!(f, "[{}]", elements.join(", "))
+            },
+            Self::Index { identifier, index, location: _ } => write!(f, "{identifier}[{index}]"),
+
+            Self::Block(stmts) => {
+                write!(f, "{{")?;
+                stmts.iter().try_for_each(|stmt| write!(f, " {stmt}"))?;
+                write!(f, " }}")
+            },
+            Self::If { check, body, els: None } => write!(f, "if ({check}) {body}"),
+            Self::If { check, body, els: Some(els) } => write!(f, "if ({check}) {body} else {els}"),
+            Self::Loop { label: None, body } => write!(f, "loop {body}"),
+            Self::Loop { label: Some(label), body } => write!(f, "'{label}: loop {body}"),
+            Self::While { label: None, check, body } => write!(f, "while ({check}) {body}"),
+            Self::While { label: Some(label), check, body } => write!(f, "'{label}: while ({check}) {body}"),
+            Self::Unsafe(body) => write!(f, "unsafe {body}"),
+
+            _ => unreachable!("binary operators are formatted above"),
+        }
+    }
+}
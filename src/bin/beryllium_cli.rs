@@ -1,41 +1,572 @@
 
-use std::path::PathBuf;
+use std::{io::IsTerminal, path::PathBuf};
 
-use clap::{Args, Parser as ArgParser, Subcommand};
+use beryllium::Diagnostic;
+
+use clap::{Args, Parser as ArgParser, Subcommand, ValueEnum};
 
 
 #[derive(ArgParser)]
 pub struct Cli {
     #[command(subcommand)]
     command: Command,
+    /// Print the full details of any error
+    #[arg(long, global = true)]
+    verbose: bool,
+    #[arg(long, value_enum, global = true, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// Language for error messages
+    #[arg(long, value_enum, global = true, default_value_t = Lang::En)]
+    lang: Lang,
 }
 
 #[derive(Subcommand)]
 pub enum Command {
     Compile(CompileArgs),
+    Test(CompileArgs),
+    /// Print the source file with syntax highlighting
+    Highlight(HighlightArgs),
+    /// Print the compiler version and options a binary was built with
+    Inspect {
+        binary: PathBuf,
+        /// Print the disassembly and check its symbols against the source
+        #[arg(long)]
+        disasm: bool,
+        /// Source file the binary was built from, defaults to the binary with a `.be` extension
+        #[arg(long, requires = "disasm")]
+        source: Option<PathBuf>,
+    },
+    /// Describe an error code, such as BE0003, with an example and a fix
+    Explain { code: String },
+    /// Print function counts, nesting depth, cyclomatic complexity and token counts
+    Stats { source_file: PathBuf },
+    /// Rewrite the source file
+    #[command(subcommand)]
+    Refactor(RefactorCommand),
+    /// Apply the fixes suggested for the errors in the source file
+    Fix {
+        source_file: PathBuf,
+        /// Print the fixed source instead of saving it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Read the profiles written by binaries built with `--instrument=profile`
+    #[command(subcommand)]
+    Prof(ProfCommand),
+}
+
+impl Command {
+    // the source file of a build, whose errors can come with fixes
+    fn source_file(&self) -> Option<PathBuf> {
+        match self {
+            Self::Compile(args) | Self::Test(args) => Some(args.source_file.clone()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum ProfCommand {
+    /// Print the calls and cycles of each function, the slowest first
+    Report {
+        #[arg(default_value = "beryllium.prof")]
+        profile: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RefactorCommand {
+    /// Rename every function, parameter and variable called OLD
+    Rename {
+        old: String,
+        new: String,
+        source_file: PathBuf,
+        /// Print the rewritten source instead of saving it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Move the statements on lines START to END into a new function
+    Extract {
+        name: String,
+        source_file: PathBuf,
+        #[arg(long)]
+        start: u64,
+        #[arg(long)]
+        end: u64,
+        /// Print the rewritten source instead of saving it
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
 #[derive(Args)]
 pub struct CompileArgs {
     source_file: PathBuf,
     target_file: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t = Emit::Binary)]
+    emit: Emit,
+    /// Emit every function, even those unreachable from the entry point
+    #[arg(long)]
+    keep_all: bool,
+    /// Number of spaces to indent instructions by in the generated assembly
+    #[arg(long, default_value_t = 4)]
+    asm_indent: usize,
+    #[arg(long, value_enum, default_value_t = LabelStyle::Flush)]
+    asm_label_style: LabelStyle,
+    /// Separate the assembly for each statement with a blank line
+    #[arg(long)]
+    asm_group_statements: bool,
+    /// Precede the assembly for each statement with the statement as a comment
+    #[arg(long)]
+    asm_source_comments: bool,
+    /// Write a summary of the build next to the output
+    #[arg(long, value_enum)]
+    report: Option<ReportFormat>,
+    /// Optimization level, 0 disables the optimizer
+    #[arg(short = 'O', long, default_value_t = 1)]
+    opt_level: u8,
+    /// Include debug information in the object file
+    #[arg(short = 'g', long)]
+    debug_info: bool,
+    /// Compile runtime checks such as `unreachable()` out of the program
+    #[arg(long)]
+    unchecked: bool,
+    #[arg(long, value_enum, default_value_t = Target::X86_64Linux)]
+    target: Target,
+    /// Build twice and fail unless the outputs are byte-for-byte identical
+    #[arg(long)]
+    reproducible: bool,
+    /// Linker script passed to `ld`
+    #[arg(long)]
+    linker_script: Option<PathBuf>,
+    /// Place a section at an address, e.g. `.text=0x100000`
+    #[arg(long, value_parser = parse_section_start)]
+    section_start: Vec<(String, u64)>,
+    /// Function to start the program at instead of `_start`
+    #[arg(long)]
+    entry: Option<String>,
+    /// Operand size for functions without a `#[bits(n)]` attribute
+    #[arg(long, value_enum, default_value_t = Bits::Bits64)]
+    bits: Bits,
+    /// What to do with integer literals that do not fit in 64 bits
+    #[arg(long, value_enum, default_value_t = LiteralOverflow::Deny)]
+    literal_overflow: LiteralOverflow,
+    /// Version of the grammar for files without a `#version` line
+    #[arg(long, value_enum, default_value_t = Edition::V2)]
+    edition: Edition,
+    /// Stop after this many errors, 0 for no limit
+    #[arg(long, value_name = "N")]
+    max_errors: Option<usize>,
+    /// Treat warnings as errors
+    #[arg(long)]
+    fatal_warnings: bool,
+    /// Processor to generate code for, which decides the instruction-set extensions that may be used
+    #[arg(long, value_enum, default_value_t = Cpu::X86_64)]
+    cpu: Cpu,
+    /// Enable or disable a single extension on top of those of the cpu, e.g. `+popcnt` or `-cmov`
+    #[arg(long, value_parser = parse_target_feature)]
+    target_feature: Vec<(beryllium::Feature, bool)>,
+    /// Check the processor at startup and use the extensions it has beyond those of the cpu
+    #[arg(long)]
+    detect_features: bool,
+    /// Fail if the generated code refers to any address absolutely, as position-independent executables and shared libraries cannot
+    #[arg(long)]
+    verify_pic: bool,
+    /// Disassemble the binary and check it holds the labels and instructions that were emitted
+    #[arg(long)]
+    verify: bool,
+    /// Guard each function's return address with a canary, panicking if it has been overwritten
+    #[arg(long)]
+    stack_protector: bool,
+    /// Add instrumentation to the binary, `profile` counts the calls and cycles of each function and writes them to beryllium.prof on exit, `trace` prints the file and line of each statement as it runs
+    #[arg(long, value_enum)]
+    instrument: Vec<Instrument>,
+    /// Write the interface of the exported functions to a .bemeta file next to the binary
+    #[arg(long)]
+    emit_meta: bool,
+    /// Call the exported functions of a separately compiled module through its .bemeta file, linking the object file next to it
+    #[arg(long = "extern", value_name = "BEMETA")]
+    externs: Vec<PathBuf>,
+}
+
+fn parse_section_start(value: &str) -> Result<(String, u64), String> {
+    let (section, address) = value.split_once('=').ok_or("expected SECTION=ADDRESS")?;
+    let address = match address.strip_prefix("0x").or_else(|| address.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => address.parse(),
+    };
+    Ok((section.to_string(), address.map_err(|err| format!("invalid address: {err}"))?))
+}
+
+fn parse_target_feature(value: &str) -> Result<(beryllium::Feature, bool), String> {
+    let (name, enabled) = match (value.strip_prefix('+'), value.strip_prefix('-')) {
+        (Some(name), _) => (name, true),
+        (_, Some(name)) => (name, false),
+        _ => return Err(String::from("expected +FEATURE or -FEATURE")),
+    };
+    let feature = beryllium::Feature::from_name(name).ok_or(format!("unknown feature `{name}`, expected cmov or popcnt"))?;
+    Ok((feature, enabled))
 }
 
 impl From<CompileArgs> for beryllium::CompileArgs {
     fn from(value: CompileArgs) -> Self {
-        Self {
-            source_file: value.source_file,
-            target_file: value.target_file,
+        let mut args = Self::new(value.source_file)
+            .with_emit(value.emit.into())
+            .with_keep_all(value.keep_all)
+            .with_asm_format(beryllium::AsmFormat {
+                indent: value.asm_indent,
+                label_style: value.asm_label_style.into(),
+                group_statements: value.asm_group_statements,
+                source_comments: value.asm_source_comments,
+            })
+            .with_opt_level(value.opt_level)
+            .with_debug_info(value.debug_info)
+            .with_checked(!value.unchecked)
+            .with_target(value.target.into())
+            .with_reproducible(value.reproducible)
+            .with_bits(value.bits.into())
+            .with_literal_overflow(value.literal_overflow.into())
+            .with_edition(value.edition.into())
+            .with_fatal_warnings(value.fatal_warnings)
+            .with_cpu(value.cpu.into())
+            .with_detect_features(value.detect_features)
+            .with_verify_pic(value.verify_pic)
+            .with_verify(value.verify)
+            .with_stack_protector(value.stack_protector)
+            .with_emit_meta(value.emit_meta);
+        if let Some(target_file) = value.target_file {
+            args = args.with_target_file(target_file);
+        }
+        if let Some(report) = value.report {
+            args = args.with_report(report.into());
+        }
+        if let Some(linker_script) = value.linker_script {
+            args = args.with_linker_script(linker_script);
+        }
+        for (section, address) in value.section_start {
+            args = args.with_section_start(section, address);
         }
+        if let Some(entry) = value.entry {
+            args = args.with_entry(entry);
+        }
+        if let Some(max_errors) = value.max_errors {
+            args = args.with_max_errors(max_errors);
+        }
+        for (feature, enabled) in value.target_feature {
+            args = args.with_target_feature(feature, enabled);
+        }
+        for instrument in value.instrument {
+            args = args.with_instrument(instrument.into());
+        }
+        for meta in value.externs {
+            args = args.with_extern(meta);
+        }
+        args
     }
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Bits {
+    #[value(name = "16")]
+    Bits16,
+    #[value(name = "32")]
+    Bits32,
+    #[value(name = "64")]
+    Bits64,
+}
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let command = Cli::parse();
-    match command.command {
-        Command::Compile(args) => beryllium::compile(&args.into())?,
-    };
-    Ok(())
+impl From<Bits> for beryllium::Bits {
+    fn from(value: Bits) -> Self {
+        match value {
+            Bits::Bits16 => Self::Bits16,
+            Bits::Bits32 => Self::Bits32,
+            Bits::Bits64 => Self::Bits64,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Cpu {
+    I386,
+    I686,
+    #[value(name = "x86-64")]
+    X86_64,
+    #[value(name = "x86-64-v2")]
+    X86_64V2,
+}
+
+impl From<Cpu> for beryllium::Cpu {
+    fn from(value: Cpu) -> Self {
+        match value {
+            Cpu::I386 => Self::I386,
+            Cpu::I686 => Self::I686,
+            Cpu::X86_64 => Self::X86_64,
+            Cpu::X86_64V2 => Self::X86_64V2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LiteralOverflow {
+    /// Reject them
+    Deny,
+    /// Read literals up to 2^64 - 1 as the signed number with the same bits
+    Wrap,
+}
+
+impl From<LiteralOverflow> for beryllium::parser::LiteralOverflow {
+    fn from(value: LiteralOverflow) -> Self {
+        match value {
+            LiteralOverflow::Deny => Self::Deny,
+            LiteralOverflow::Wrap => Self::Wrap,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Edition {
+    /// Conditions of `if` and `while` need parentheses
+    #[value(name = "1")]
+    V1,
+    /// Conditions may leave out their parentheses when the body is braced
+    #[value(name = "2")]
+    V2,
+}
+
+impl From<Edition> for beryllium::parser::Edition {
+    fn from(value: Edition) -> Self {
+        match value {
+            Edition::V1 => Self::V1,
+            Edition::V2 => Self::V2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Instrument {
+    Profile,
+    Trace,
+}
+
+impl From<Instrument> for beryllium::Instrument {
+    fn from(value: Instrument) -> Self {
+        match value {
+            Instrument::Profile => Self::Profile,
+            Instrument::Trace => Self::Trace,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Target {
+    #[value(name = "x86_64-linux")]
+    X86_64Linux,
 }
 
+impl From<Target> for beryllium::Target {
+    fn from(value: Target) -> Self {
+        match value {
+            Target::X86_64Linux => Self::X86_64Linux,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct HighlightArgs {
+    source_file: PathBuf,
+    #[arg(long, value_enum, default_value_t = HighlightFormat::Html)]
+    format: HighlightFormat,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum HighlightFormat {
+    Html,
+    Json,
+}
+
+impl From<HighlightFormat> for beryllium::HighlightFormat {
+    fn from(value: HighlightFormat) -> Self {
+        match value {
+            HighlightFormat::Html => Self::Html,
+            HighlightFormat::Json => Self::Json,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Emit {
+    Binary,
+    Callgraph,
+    Cfg,
+}
+
+impl From<Emit> for beryllium::Emit {
+    fn from(value: Emit) -> Self {
+        match value {
+            Emit::Binary => Self::Binary,
+            Emit::Callgraph => Self::CallGraph,
+            Emit::Cfg => Self::Cfg,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LabelStyle {
+    Flush,
+    Inline,
+}
+
+impl From<LabelStyle> for beryllium::LabelStyle {
+    fn from(value: LabelStyle) -> Self {
+        match value {
+            LabelStyle::Flush => Self::Flush,
+            LabelStyle::Inline => Self::Inline,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ReportFormat {
+    Json,
+}
+
+impl From<ReportFormat> for beryllium::ReportFormat {
+    fn from(value: ReportFormat) -> Self {
+        match value {
+            ReportFormat::Json => Self::Json,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Lang {
+    En,
+    De,
+}
+
+impl From<Lang> for beryllium::Lang {
+    fn from(value: Lang) -> Self {
+        match value {
+            Lang::En => Self::En,
+            Lang::De => Self::De,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn use_color(&self) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+        }
+    }
+}
+
+
+fn run(command: Command, color: bool, lang: beryllium::Lang) -> Result<i32, beryllium::CompileError> {
+    match command {
+        Command::Compile(args) => {
+            let compiled = beryllium::compile(&beryllium::CompileArgs::from(args).with_lang(lang))?;
+            show(&compiled.warnings, color);
+            Ok(0)
+        },
+        Command::Test(args) => {
+            let harness = beryllium::compile_tests(&beryllium::CompileArgs::from(args).with_lang(lang))?;
+            show(&harness.warnings, color);
+            let status = std::process::Command::new(&harness.target_file).status()?;
+            Ok(status.code().unwrap_or(1))
+        },
+        Command::Highlight(args) => {
+            print!("{}", beryllium::highlight(&args.source_file, args.format.into())?);
+            Ok(0)
+        },
+        Command::Inspect { binary, disasm: true, source } => {
+            let source = source.unwrap_or_else(|| binary.with_extension("be"));
+            print!("{}", beryllium::disassemble(&binary, &source)?);
+            Ok(0)
+        },
+        Command::Inspect { binary, .. } => {
+            let info = beryllium::inspect(&binary)?;
+            println!("version: {}", info.version);
+            info.options.iter().for_each(|(name, value)| println!("{name}: {value}"));
+            Ok(0)
+        },
+        Command::Explain { code } => match beryllium::explain(&code) {
+            Some(explanation) => {
+                print!("{explanation}");
+                Ok(0)
+            },
+            None => {
+                eprint!("{}", Diagnostic::error(format!("no error has the code `{code}`")).render(color));
+                Ok(1)
+            },
+        },
+        Command::Stats { source_file } => {
+            print!("{}", beryllium::stats(&source_file)?);
+            Ok(0)
+        },
+        Command::Prof(ProfCommand::Report { profile }) => {
+            print!("{}", beryllium::profile_report(&profile)?);
+            Ok(0)
+        },
+        Command::Refactor(RefactorCommand::Rename { old, new, source_file, dry_run }) => {
+            let source = beryllium::rename(&source_file, &old, &new)?;
+            save(&source_file, source, dry_run)
+        },
+        Command::Refactor(RefactorCommand::Extract { name, source_file, start, end, dry_run }) => {
+            let source = beryllium::extract_function(&source_file, &name, start, end)?;
+            save(&source_file, source, dry_run)
+        },
+        // what could be fixed is kept even if an error is left
+        Command::Fix { source_file, dry_run } => {
+            let fixes = beryllium::fix(&source_file)?;
+            if fixes.applied > 0 || dry_run {
+                save(&source_file, fixes.source, dry_run)?;
+            }
+            eprintln!("applied {} fix(es)", fixes.applied);
+            fixes.remaining.map_or(Ok(0), Err)
+        },
+    }
+}
+
+fn show(diagnostics: &[Diagnostic], color: bool) {
+    diagnostics.iter().for_each(|diagnostic| eprint!("{}", diagnostic.render(color)));
+}
+
+fn save(source_file: &PathBuf, source: String, dry_run: bool) -> Result<i32, beryllium::CompileError> {
+    match dry_run {
+        true => print!("{source}"),
+        false => std::fs::write(source_file, source)?,
+    }
+    Ok(0)
+}
+
+
+fn main() {
+    let cli = Cli::parse();
+    let color = cli.color.use_color();
+    let source_file = cli.command.source_file();
+    let code = match run(cli.command, color, cli.lang.into()) {
+        Ok(code) => code,
+        Err(err) => {
+            show(err.diagnostics(), color);
+            let suggestions = source_file.map(|source_file| beryllium::suggestions(&source_file, &err, cli.lang.into())).unwrap_or_default();
+            let diagnostic = suggestions.into_iter().fold(Diagnostic::localized(&err, cli.lang.into()), Diagnostic::with_suggestion);
+            let diagnostic = match cli.verbose {
+                true => diagnostic.with_note(format!("{err:#?}")),
+                false => diagnostic,
+            };
+            eprint!("{}", diagnostic.render(color));
+            1
+        },
+    };
+    std::process::exit(code);
+}
@@ -0,0 +1,119 @@
+use crate::{CompileArgs, Feature, Instrument};
+
+
+const PREFIX: &str = "beryllium ";
+
+
+// the compiler version and options a binary was built with, stored as a nul-terminated string in
+// the `.comment` section, e.g. `beryllium 0.2.0 target=x86_64-linux opt-level=1`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BuildInfo {
+    pub version: String,
+    pub options: Vec<(String, String)>,
+}
+
+impl BuildInfo {
+    pub fn new(args: &CompileArgs, test_harness: bool) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            options: vec![
+                (String::from("target"), args.target.name().to_string()),
+                (String::from("bits"), args.bits.number().to_string()),
+                (String::from("cpu"), args.cpu.name().to_string()),
+                (String::from("features"), args.features().iter().map(Feature::name).collect::<Vec<_>>().join(",")),
+                (String::from("detect-features"), args.detect_features.to_string()),
+                (String::from("opt-level"), args.opt_level.to_string()),
+                (String::from("debug-info"), args.debug_info.to_string()),
+                (String::from("checked"), args.checked.to_string()),
+                (String::from("stack-protector"), args.stack_protector.to_string()),
+                (String::from("instrument"), args.instrument.iter().map(Instrument::name).collect::<Vec<_>>().join(",")),
+                (String::from("test-harness"), test_harness.to_string()),
+            ],
+        }
+    }
+
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options.iter().find(|(option, _)| option == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn to_asm(&self) -> String {
+        let bytes: Vec<String> = self.to_string().bytes().chain([0]).map(|byte| byte.to_string()).collect();
+        format!("section .comment\n    db {}\n", bytes.join(", "))
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut words = text.strip_prefix(PREFIX)?.split(' ');
+        let version = words.next()?.to_string();
+        let options = words
+            .map(|word| word.split_once('=').map(|(name, value)| (name.to_string(), value.to_string())))
+            .collect::<Option<_>>()?;
+        Some(Self { version, options })
+    }
+
+    // looks through the `.comment` section of an ELF64 file, which also collects the comments of
+    // other tools, for the string we wrote
+    pub fn read(elf: &[u8]) -> Option<Self> {
+        if elf.get(..5)? != b"\x7fELF\x02" {
+            return None;
+        }
+        let section_offset = read_u64(elf, 0x28)? as usize;
+        let header_size = read_u16(elf, 0x3a)? as usize;
+        let section_count = read_u16(elf, 0x3c)? as usize;
+        let names_index = read_u16(elf, 0x3e)? as usize;
+
+        let section = |index: usize| -> Option<(u32, &[u8])> {
+            let header = section_offset + index * header_size;
+            let name = read_u32(elf, header)?;
+            let offset = read_u64(elf, header + 0x18)? as usize;
+            let size = read_u64(elf, header + 0x20)? as usize;
+            Some((name, elf.get(offset..offset.checked_add(size)?)?))
+        };
+        let (_, names) = section(names_index)?;
+        (0..section_count)
+            .filter_map(section)
+            .filter(|(name, _)| names.get(*name as usize..).is_some_and(|name| name.starts_with(b".comment\0")))
+            .flat_map(|(_, contents)| contents.split(|byte| *byte == 0))
+            .find_map(|text| Self::parse(std::str::from_utf8(text).ok()?))
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(bytes.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+impl std::fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{PREFIX}{}", self.version)?;
+        self.options.iter().try_for_each(|(name, value)| write!(f, " {name}={value}"))
+    }
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn build_info_round_trips() {
+    let info = BuildInfo::new(&CompileArgs::default().with_opt_level(0), false);
+    assert_eq!(BuildInfo::parse(&info.to_string()), Some(info.clone()));
+    assert_eq!(info.option("opt-level"), Some("0"));
+    assert!(info.to_asm().ends_with(", 0\n"));
+    assert_eq!(BuildInfo::parse("GCC: (Debian) 12.2.0"), None);
+}
+
+#[test]
+fn non_elf_files_have_no_build_info() {
+    assert_eq!(BuildInfo::read(b"#!/bin/sh\n"), None);
+    assert_eq!(BuildInfo::read(b"\x7fELF\x02"), None);
+}
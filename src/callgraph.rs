@@ -0,0 +1,119 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    ast::{Expr, Intrinsic, Item, Program},
+    visit::{self, Visitor},
+};
+
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CallGraph {
+    calls: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl CallGraph {
+    pub fn callees(&self, name: &str) -> impl Iterator<Item = &String> {
+        self.calls.get(name).into_iter().flatten()
+    }
+
+    pub fn reachable_from<'a>(&self, roots: impl IntoIterator<Item = &'a str>) -> BTreeSet<String> {
+        let mut reached = BTreeSet::new();
+        let mut pending: Vec<String> = roots.into_iter().map(String::from).collect();
+        while let Some(name) = pending.pop() {
+            if reached.insert(name.clone()) {
+                pending.extend(self.callees(&name).cloned());
+            }
+        }
+        reached
+    }
+
+    pub fn is_recursive(&self, name: &str) -> bool {
+        self.reachable_from(self.callees(name).map(String::as_str)).contains(name)
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph callgraph {\n");
+        for (caller, callees) in &self.calls {
+            dot += &format!("    \"{caller}\";\n");
+            for callee in callees {
+                dot += &format!("    \"{caller}\" -> \"{callee}\";\n");
+            }
+        }
+        dot += "}\n";
+        dot
+    }
+}
+
+impl From<&Program> for CallGraph {
+    fn from(program: &Program) -> Self {
+        let mut graph = Self::default();
+        for item in &program.0 {
+            match item {
+                Item::Function { name, .. } => {
+                    let mut collector = CallCollector::default();
+                    collector.visit_item(item);
+                    graph.calls.entry(name.clone()).or_default().extend(collector.0);
+                },
+                Item::Const { .. } => (),
+            }
+        }
+        graph
+    }
+}
+
+
+#[derive(Default)]
+struct CallCollector(BTreeSet<String>);
+
+impl Visitor for CallCollector {
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::FunctionCall { name, args: _ } => { self.0.insert(name.clone()); },
+            Expr::Intrinsic { intrinsic: Intrinsic::Spawn, args } => if let [Expr::Identifier(name)] = args.as_slice() {
+                self.0.insert(name.clone());
+            },
+            _ => (),
+        }
+        visit::walk_expr(self, expr)
+    }
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[cfg(test)]
+fn graph_of(source: &str) -> CallGraph {
+    use crate::{parser::Parser, tokenize::Tokenize};
+    let program = Parser::new(source.tokenize()).parse().expect("source parses");
+    CallGraph::from(&program)
+}
+
+#[test]
+fn calls_are_collected() {
+    let graph = graph_of("fn _start() { exit(f(1) + g()); } fn f(x) return g(); fn g() return 1;");
+    assert_eq!(graph.callees("_start").collect::<Vec<_>>(), vec!["f", "g"]);
+    assert_eq!(graph.callees("f").collect::<Vec<_>>(), vec!["g"]);
+    assert_eq!(graph.callees("g").count(), 0);
+}
+
+#[test]
+fn recursion_is_detected() {
+    let graph = graph_of("fn a() return b(); fn b() return a(); fn c() return c(); fn d() return a();");
+    assert!(graph.is_recursive("a"));
+    assert!(graph.is_recursive("b"));
+    assert!(graph.is_recursive("c"));
+    assert!(!graph.is_recursive("d"));
+}
+
+#[test]
+fn dot_output_lists_edges() {
+    let graph = graph_of("fn _start() { f(); } fn f() { exit(0); }");
+    assert_eq!(
+        graph.to_dot(),
+        "digraph callgraph {\n    \"_start\";\n    \"_start\" -> \"f\";\n    \"f\";\n}\n",
+    );
+}
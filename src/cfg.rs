@@ -0,0 +1,498 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    ast::{Expr, Intrinsic, Item, Program, Statement},
+    liveness::Liveness,
+    visit::{self, Visitor},
+};
+
+
+pub type BlockId = usize;
+pub type VariableId = usize;
+
+pub const ENTRY: BlockId = 0;
+pub const EXIT: BlockId = 1;
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    pub is_param: bool,
+}
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Node {
+    pub statement: Statement,
+    pub uses: Vec<VariableId>,
+    pub defs: Vec<VariableId>,
+}
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Terminator {
+    Goto(BlockId),
+    Branch { check: Expr, uses: Vec<VariableId>, then: BlockId, els: BlockId },
+    // falls through to `next` unless a `?` in the block's last node returns early
+    Try { next: BlockId },
+    Return,
+    Halt,
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Self::Goto(target) => vec![*target],
+            Self::Branch { check: _, uses: _, then, els } => vec![*then, *els],
+            Self::Try { next } => vec![*next, EXIT],
+            Self::Return => vec![EXIT],
+            Self::Halt => vec![],
+        }
+    }
+
+    pub fn uses(&self) -> &[VariableId] {
+        match self {
+            Self::Branch { check: _, uses, then: _, els: _ } => uses,
+            _ => &[],
+        }
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub nodes: Vec<Node>,
+    pub terminator: Terminator,
+}
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cfg {
+    pub name: String,
+    pub blocks: Vec<BasicBlock>,
+    pub variables: Vec<Variable>,
+}
+
+impl Cfg {
+    fn variable_names(&self, ids: &BTreeSet<VariableId>) -> String {
+        let names: Vec<&str> = ids.iter().map(|id| self.variables[*id].name.as_str()).collect();
+        names.join(", ")
+    }
+
+    pub fn to_dot(&self) -> String {
+        let liveness = Liveness::from(self);
+        let name = &self.name;
+        let mut dot = format!("    subgraph \"cluster_{name}\" {{\n");
+        dot += &format!("        label = \"{name}\";\n");
+        for (id, block) in self.blocks.iter().enumerate() {
+            let mut label = match id {
+                ENTRY => format!("bb{id} (entry)\\l"),
+                EXIT => format!("bb{id} (exit)\\l"),
+                _ => format!("bb{id}\\l"),
+            };
+            label += &format!("live in: {}\\l", self.variable_names(&liveness.live_in[id]));
+            for node in &block.nodes {
+                label += &escape(&node.statement.to_string());
+                label += "\\l";
+            }
+            match &block.terminator {
+                Terminator::Branch { check, .. } => label += &format!("branch {}\\l", escape(&check.to_string())),
+                Terminator::Return => label += "return\\l",
+                Terminator::Halt if id != EXIT => label += "halt\\l",
+                _ => (),
+            }
+            label += &format!("live out: {}\\l", self.variable_names(&liveness.live_out[id]));
+            dot += &format!("        \"{name}.{id}\" [shape=box, label=\"{label}\"];\n");
+        }
+        for (id, block) in self.blocks.iter().enumerate() {
+            match &block.terminator {
+                Terminator::Branch { check: _, uses: _, then, els } => {
+                    dot += &format!("        \"{name}.{id}\" -> \"{name}.{then}\" [label=\"true\"];\n");
+                    dot += &format!("        \"{name}.{id}\" -> \"{name}.{els}\" [label=\"false\"];\n");
+                },
+                Terminator::Try { next } => {
+                    dot += &format!("        \"{name}.{id}\" -> \"{name}.{next}\";\n");
+                    dot += &format!("        \"{name}.{id}\" -> \"{name}.{EXIT}\" [label=\"error\"];\n");
+                },
+                terminator => for successor in terminator.successors() {
+                    dot += &format!("        \"{name}.{id}\" -> \"{name}.{successor}\";\n");
+                },
+            }
+        }
+        dot += "    }\n";
+        dot
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl From<&Item> for Cfg {
+    fn from(item: &Item) -> Self {
+        match item {
+            Item::Function { name, params, return_type: _, body, attributes: _, is_const: _ } => {
+                let mut builder = Builder::new(name.clone());
+                for param in params {
+                    builder.declare(&param.name, true);
+                }
+                builder.lower_statement(body);
+                builder.finish()
+            },
+            Item::Const { identifier, .. } => Builder::new(identifier.clone()).finish(),
+        }
+    }
+}
+
+
+pub fn program_to_dot(program: &Program) -> String {
+    let mut dot = String::from("digraph cfg {\n");
+    for item in &program.0 {
+        dot += &Cfg::from(item).to_dot();
+    }
+    dot += "}\n";
+    dot
+}
+
+
+struct Builder {
+    name: String,
+    blocks: Vec<(Vec<Node>, Option<Terminator>)>,
+    variables: Vec<Variable>,
+    scopes: Vec<HashMap<String, VariableId>>,
+    // the label, head and block after each loop we are in, the innermost last
+    loops: Vec<(Option<String>, BlockId, BlockId)>,
+    current: BlockId,
+}
+
+impl Builder {
+    fn new(name: String) -> Self {
+        let mut builder = Self {
+            name,
+            blocks: Vec::new(),
+            variables: Vec::new(),
+            scopes: vec![HashMap::new()],
+            loops: Vec::new(),
+            current: ENTRY,
+        };
+        builder.new_block();
+        builder.new_block();
+        builder.blocks[EXIT].1 = Some(Terminator::Halt);
+        builder
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push((Vec::new(), None));
+        self.blocks.len() - 1
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        let block = &mut self.blocks[self.current];
+        if block.1.is_none() {
+            block.1 = Some(terminator);
+        }
+    }
+
+    // ends the current block and continues in a fresh one with no predecessors
+    fn terminate_and_detach(&mut self, terminator: Terminator) {
+        self.terminate(terminator);
+        self.current = self.new_block();
+    }
+
+    fn declare(&mut self, name: &str, is_param: bool) -> VariableId {
+        self.variables.push(Variable { name: name.to_string(), is_param });
+        let id = self.variables.len() - 1;
+        self.scopes.last_mut().expect("a scope").insert(name.to_string(), id);
+        id
+    }
+
+    fn resolve(&self, name: &str) -> Option<VariableId> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name).copied())
+    }
+
+    fn usage(&self, expr: &Expr) -> Usage {
+        let mut usage = Usage::default();
+        usage.visit_expr(expr);
+        usage
+    }
+
+    fn push(&mut self, statement: &Statement, uses: Vec<VariableId>, defs: Vec<VariableId>) {
+        self.blocks[self.current].0.push(Node { statement: statement.clone(), uses, defs });
+    }
+
+    fn push_expr_statement(&mut self, statement: &Statement, value: &Expr) -> Usage {
+        let usage = self.usage(value);
+        let uses = usage.reads.iter().filter_map(|name| self.resolve(name)).collect();
+        let defs = usage.writes.iter().filter_map(|name| self.resolve(name)).collect();
+        self.push(statement, uses, defs);
+        usage
+    }
+
+    // the head and the block after the loop a `break` or `continue` with this label leaves
+    fn target(&self, label: &Option<String>) -> Option<(BlockId, BlockId)> {
+        self.loops
+            .iter()
+            .rev()
+            .find(|(name, _, _)| label.is_none() || name == label)
+            .map(|&(_, head, after)| (head, after))
+    }
+
+    fn lower_scoped(&mut self, statement: &Statement) {
+        self.scopes.push(HashMap::new());
+        self.lower_statement(statement);
+        self.scopes.pop();
+    }
+
+    fn lower_block(&mut self, stmts: &[Statement]) {
+        self.scopes.push(HashMap::new());
+        stmts.iter().for_each(|stmt| self.lower_statement(stmt));
+        self.scopes.pop();
+    }
+
+    fn lower_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let { identifier, ty: _, value, is_mutable: _ } => {
+                let usage = value.as_ref().map(|value| self.usage(value)).unwrap_or_default();
+                let uses = usage.reads.iter().filter_map(|name| self.resolve(name)).collect();
+                let mut defs: Vec<_> = usage.writes.iter().filter_map(|name| self.resolve(name)).collect();
+                // the declared variable comes last, and is written even without a value as its slot
+                // is zeroed
+                defs.push(self.declare(identifier, false));
+                self.push(statement, uses, defs);
+                if usage.tries {
+                    let next = self.new_block();
+                    self.terminate(Terminator::Try { next });
+                    self.current = next;
+                }
+            },
+            Statement::Expr(Expr::Block(stmts)) => self.lower_block(stmts),
+            Statement::Expr(Expr::Unsafe(body)) => {
+                let Expr::Block(stmts) = body.as_ref() else { unreachable!("`unsafe` always holds a block") };
+                self.lower_block(stmts);
+            },
+            Statement::Expr(Expr::If { check, body, els }) => {
+                let uses = self.usage(check).reads.iter().filter_map(|name| self.resolve(name)).collect();
+                let then = self.new_block();
+                let otherwise = self.new_block();
+                let join = self.new_block();
+                self.terminate(Terminator::Branch { check: *check.clone(), uses, then, els: otherwise });
+
+                self.current = then;
+                self.lower_scoped(body);
+                self.terminate(Terminator::Goto(join));
+
+                self.current = otherwise;
+                if let Some(els) = els {
+                    self.lower_scoped(els);
+                }
+                self.terminate(Terminator::Goto(join));
+
+                self.current = join;
+            },
+            Statement::Expr(Expr::Loop { label, body }) => {
+                let head = self.new_block();
+                let after = self.new_block();
+                self.terminate(Terminator::Goto(head));
+
+                self.current = head;
+                self.loops.push((label.clone(), head, after));
+                self.lower_statement(body);
+                self.loops.pop();
+                self.terminate(Terminator::Goto(head));
+
+                self.current = after;
+            },
+            Statement::Expr(Expr::While { label, check, body }) => {
+                let head = self.new_block();
+                let inner = self.new_block();
+                let after = self.new_block();
+                self.terminate(Terminator::Goto(head));
+
+                self.current = head;
+                let uses = self.usage(check).reads.iter().filter_map(|name| self.resolve(name)).collect();
+                self.terminate(Terminator::Branch { check: *check.clone(), uses, then: inner, els: after });
+
+                self.current = inner;
+                self.loops.push((label.clone(), head, after));
+                self.lower_statement(body);
+                self.loops.pop();
+                self.terminate(Terminator::Goto(head));
+
+                self.current = after;
+            },
+            Statement::Expr(value) => {
+                if self.push_expr_statement(statement, value).tries {
+                    let next = self.new_block();
+                    self.terminate(Terminator::Try { next });
+                    self.current = next;
+                }
+            },
+            Statement::Exit { value } => {
+                self.push_expr_statement(statement, value);
+                self.terminate_and_detach(Terminator::Halt);
+            },
+            Statement::Return(value) => {
+                match value {
+                    Some(value) => { self.push_expr_statement(statement, value); },
+                    None => self.push(statement, Vec::new(), Vec::new()),
+                }
+                self.terminate_and_detach(Terminator::Return);
+            },
+            Statement::StaticAssert { .. } | Statement::Const { .. } => (),
+            Statement::Panic { .. } | Statement::Unreachable { .. } => {
+                self.push(statement, Vec::new(), Vec::new());
+                self.terminate_and_detach(Terminator::Halt);
+            },
+            Statement::Break(label) => {
+                let (_, after) = self.target(label).expect("can't break from current context");
+                self.terminate_and_detach(Terminator::Goto(after));
+            },
+            Statement::Continue(label) => {
+                let (head, _) = self.target(label).expect("can't continue from current context");
+                self.terminate_and_detach(Terminator::Goto(head));
+            },
+        }
+    }
+
+    fn finish(mut self) -> Cfg {
+        // falling off the end of the body returns from the function
+        for (id, block) in self.blocks.iter_mut().enumerate() {
+            if block.1.is_none() {
+                block.1 = Some(if id == EXIT { Terminator::Halt } else { Terminator::Return });
+            }
+        }
+        let blocks: Vec<BasicBlock> = self.blocks
+            .into_iter()
+            .map(|(nodes, terminator)| BasicBlock { nodes, terminator: terminator.expect("a terminator") })
+            .collect();
+        prune(Cfg { name: self.name, blocks, variables: self.variables })
+    }
+}
+
+// removes blocks that cannot be reached from the entry, keeping the exit block in place
+fn prune(cfg: Cfg) -> Cfg {
+    let mut reached = vec![false; cfg.blocks.len()];
+    let mut pending = vec![ENTRY];
+    while let Some(id) = pending.pop() {
+        if !reached[id] {
+            reached[id] = true;
+            pending.extend(cfg.blocks[id].terminator.successors());
+        }
+    }
+    reached[EXIT] = true;
+
+    let mut renumbered = HashMap::new();
+    for id in (0..cfg.blocks.len()).filter(|id| reached[*id]) {
+        renumbered.insert(id, renumbered.len());
+    }
+    let renumber = |id: &BlockId| renumbered[id];
+    let blocks = cfg.blocks
+        .into_iter()
+        .enumerate()
+        .filter(|(id, _)| reached[*id])
+        .map(|(_, block)| BasicBlock {
+            nodes: block.nodes,
+            terminator: match block.terminator {
+                Terminator::Goto(target) => Terminator::Goto(renumber(&target)),
+                Terminator::Branch { check, uses, then, els } => Terminator::Branch {
+                    check, uses, then: renumber(&then), els: renumber(&els),
+                },
+                Terminator::Try { next } => Terminator::Try { next: renumber(&next) },
+                terminator => terminator,
+            },
+        })
+        .collect();
+    Cfg { blocks, ..cfg }
+}
+
+
+#[derive(Default)]
+struct Usage {
+    reads: Vec<String>,
+    writes: Vec<String>,
+    tries: bool,
+}
+
+impl Visitor for Usage {
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            // storing to one element keeps the others, so it is a read of the array rather than a write
+            Expr::Identifier(name) | Expr::Index { identifier: name, .. } | Expr::IndexAssign { identifier: name, .. } =>
+                self.reads.push(name.clone()),
+            Expr::Assign { identifier, value: _ } => self.writes.push(identifier.clone()),
+            Expr::Intrinsic { intrinsic: Intrinsic::Swap, args } => for arg in args {
+                if let Expr::Identifier(name) = arg {
+                    self.writes.push(name.clone());
+                }
+            },
+            Expr::AddAssign { identifier, value: _ } | Expr::SubAssign { identifier, value: _ }
+                | Expr::MulAssign { identifier, value: _ } | Expr::DivAssign { identifier, value: _ }
+                | Expr::ModAssign { identifier, value: _ } => {
+                self.reads.push(identifier.clone());
+                self.writes.push(identifier.clone());
+            },
+            Expr::Try(_) => self.tries = true,
+            _ => (),
+        }
+        visit::walk_expr(self, expr)
+    }
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[cfg(test)]
+fn cfg_of(source: &str) -> Cfg {
+    use crate::{parser::Parser, tokenize::Tokenize};
+    let program = Parser::new(source.tokenize()).parse().expect("source parses");
+    Cfg::from(program.0.first().expect("an item"))
+}
+
+#[test]
+fn straight_line_code_is_one_block() {
+    let cfg = cfg_of("fn f(x) { let y = x + 1; return y; }");
+    assert_eq!(cfg.blocks.len(), 2);
+    assert_eq!(cfg.blocks[ENTRY].nodes.len(), 2);
+    assert_eq!(cfg.blocks[ENTRY].terminator, Terminator::Return);
+}
+
+#[test]
+fn if_else_branches_and_joins() {
+    let cfg = cfg_of("fn f(x) { if (x) x += 1; else x -= 1; return x; }");
+    let (then, els) = match &cfg.blocks[ENTRY].terminator {
+        Terminator::Branch { check: _, uses, then, els } => {
+            assert_eq!(uses, &vec![0]);
+            (*then, *els)
+        },
+        terminator => panic!("expected a branch, got {terminator:?}"),
+    };
+    assert_eq!(cfg.blocks[then].terminator.successors(), cfg.blocks[els].terminator.successors());
+}
+
+#[test]
+fn while_loops_back_to_its_check() {
+    let cfg = cfg_of("fn f() { let mut x = 0; while (x < 10) x += 1; return x; }");
+    let head = cfg.blocks[ENTRY].terminator.successors()[0];
+    let inner = match &cfg.blocks[head].terminator {
+        Terminator::Branch { then, .. } => *then,
+        terminator => panic!("expected a branch, got {terminator:?}"),
+    };
+    assert_eq!(cfg.blocks[inner].terminator, Terminator::Goto(head));
+}
+
+#[test]
+fn shadowed_variables_are_distinct() {
+    let cfg = cfg_of("fn f() { let x = 1; { let x = 2; exit(x); } }");
+    assert_eq!(cfg.variables.len(), 2);
+    assert_eq!(cfg.blocks[ENTRY].nodes[2].uses, vec![1]);
+}
+
+#[test]
+fn code_after_return_is_pruned() {
+    let cfg = cfg_of("fn f() { return 1; exit(2); }");
+    assert_eq!(cfg.blocks.len(), 2);
+}
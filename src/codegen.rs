@@ -6,6 +6,18 @@ pub enum CodegenError {
     IdentifierNotDeclared(String),
     FunctionNotDeclared(String),
     ChangedImmutableVariable(String),
+    InvalidTestFunction(String),
+    InvalidThreadFunction(String),
+    RequiresUnsafe(String),
+    InvalidConstFunction { function: String, reason: String },
+    UnassignedVariable(String),
+    NotAVariable(String),
+    UnsupportedBits { function: String, bits: u64, reason: String },
+    AbsoluteReference { function: String, instruction: String },
+    MissingValue(String),
+    MixedReturns(String),
+    NotAnArray(String),
+    ArrayAsValue(String),
 }
 
 impl std::fmt::Display for CodegenError {
@@ -1,12 +1,33 @@
+use std::collections::BTreeSet;
+
 use crate::{
+    assignment::Assigned,
     ast::*,
+    callgraph::CallGraph,
+    cfg::Cfg,
+    const_eval,
     context::{Context, LabelFrame},
+    liveness::Interference,
+    profile::Profile,
+    tokenize::Location,
+    type_registry::ReturnKind,
 };
 use super::{
     CodegenError,
     Result,
 };
 
+pub mod bits;
+pub mod cpu;
+pub mod format;
+pub mod intrinsics;
+pub mod pic;
+pub mod runtime;
+pub mod width;
+
+use bits::Bits;
+use runtime::Routine;
+
 
 pub trait Codegen {
     fn codegen_x86(self, context: &mut Context) -> Result;
@@ -15,30 +36,219 @@ pub trait Codegen {
 
 impl Codegen for Program {
     fn codegen_x86(self, context: &mut Context) -> Result {
+        if let Some(entry) = context.entry() {
+            if context.type_registry().get_function(entry).is_none() {
+                return Err(CodegenError::FunctionNotDeclared(entry.to_string()));
+            }
+        }
+        let roots = self.roots(context);
+        let live = match context.keep_all() {
+            true => None,
+            false => Some(CallGraph::from(&self).reachable_from(roots.iter().map(String::as_str))),
+        };
+
+        for item in &self.0 {
+            match item {
+                Item::Function { name, .. } => if let Some(interference) = Interference::of(item) {
+                    context.add_interference(name.clone(), interference);
+                },
+                Item::Const { .. } => (),
+            }
+        }
+
         let mut code = String::from("global _start\n");
+        for name in self.exported(context) {
+            code += &format!("global {name}\n");
+        }
+        for (name, _) in context.type_registry().functions().filter(|(_, function)| function.is_extern) {
+            code += &format!("extern {name}\n");
+        }
+        if context.test_harness() {
+            code += &Self::test_harness(context)?;
+        }
         for item in self.0 {
-            code.push_str(item.codegen_x86(context)?.as_str());
+            match &item {
+                // the harness replaces the program's own entry point
+                Item::Function { name, .. } if context.test_harness() && name == "_start" => continue,
+                Item::Function { name, .. } if live.as_ref().is_some_and(|live| !live.contains(name)) => continue,
+                _ => code.push_str(item.codegen_x86(context)?.as_str()),
+            }
         }
+        code += &context.runtime_code();
+        code += &context.data_section();
+        if context.profile() {
+            code += &Profile::table_asm(context.profiled());
+        }
+        if context.verify_pic() {
+            let functions = context.type_registry().functions().map(|(name, _)| name.clone()).collect();
+            if let Some((function, instruction)) = pic::absolute_reference(&code, &functions) {
+                return Err(CodegenError::AbsoluteReference { function, instruction });
+            }
+        }
+        Ok(context.asm_format().apply(&code))
+    }
+}
+
+
+impl Program {
+    fn functions_with_attribute<'a>(&'a self, context: &'a Context, attribute: &'a str) -> impl Iterator<Item = String> + 'a {
+        self.0.iter()
+            .filter_map(|item| match item {
+                Item::Function { name, .. } => Some(name),
+                Item::Const { .. } => None,
+            })
+            .filter(move |name| context.type_registry()
+                .get_function(*name)
+                .is_some_and(|function| function.has_attribute(attribute))
+            )
+            .cloned()
+    }
+
+    // an entry point passed to the linker has to be global as well
+    fn exported(&self, context: &Context) -> BTreeSet<String> {
+        self.functions_with_attribute(context, "export")
+            .chain(context.entry().filter(|entry| *entry != "_start").map(String::from))
+            .collect()
+    }
+
+    fn roots(&self, context: &Context) -> BTreeSet<String> {
+        let mut roots = self.exported(context);
+        match context.test_harness() {
+            true => roots.extend(self.functions_with_attribute(context, "test")),
+            false => roots.extend(["_start".to_string(), "main".to_string()]),
+        };
+        roots
+    }
+
+    fn test_harness(context: &mut Context) -> Result {
+        let mut tests: Vec<_> = context.type_registry()
+            .functions()
+            .filter(|(_, function)| function.has_attribute("test"))
+            .map(|(name, function)| (name.clone(), function.params.len()))
+            .collect();
+        tests.sort();
+
+        let mut code = String::from("_start:\n");
+        if context.stack_protector() {
+            code += &format!("    call {}\n", context.require_runtime(Routine::SeedCanary));
+        }
+        if context.detect_features() {
+            code += &format!("    call {}\n", context.require_runtime(Routine::DetectFeatures));
+        }
+        // r12 counts passed tests, r13 counts failed tests
+        code += "    xor r12, r12\n";
+        code += "    xor r13, r13\n";
+        for (name, param_count) in tests {
+            if param_count != 0 {
+                return Err(CodegenError::InvalidTestFunction(name));
+            }
+            let parent_label = context.create_label("testparent");
+            let failed_label = context.create_label("testfailed");
+            let end_label = context.create_label("testend");
+
+            code += &Self::print(context, format!("test {name} ... "));
+            // run each test in a child process so a panic only fails that test
+            code += "    mov rax, 57\n";
+            code += "    syscall\n";
+            code += "    or rax, rax\n";
+            code += &format!("    jnz {parent_label}\n");
+            // any value but zero fails, even one whose lowest byte is zero
+            code += &format!("    call {name}\n");
+            code += "    xor rdi, rdi\n";
+            code += "    test rax, rax\n";
+            code += "    setnz dil\n";
+            code += "    mov rax, 231\n";
+            code += "    syscall\n";
+            code += &format!("{parent_label}:\n");
+            code += "    mov rdi, rax\n";
+            code += "    push 0\n";
+            code += "    mov rsi, rsp\n";
+            code += "    xor rdx, rdx\n";
+            code += "    xor r10, r10\n";
+            code += "    mov rax, 61\n";
+            code += "    syscall\n";
+            code += "    pop rax\n";
+            code += "    or rax, rax\n";
+            code += &format!("    jnz {failed_label}\n");
+            code += "    inc r12\n";
+            code += &Self::print(context, "ok\n");
+            code += &format!("    jmp {end_label}\n");
+            code += &format!("{failed_label}:\n");
+            code += "    inc r13\n";
+            code += &Self::print(context, "FAILED\n");
+            code += &format!("{end_label}:\n");
+        }
+        code += &Self::print(context, "\ntest result: ");
+        code += "    mov rax, r12\n";
+        code += &format!("    call {}\n", context.require_runtime(Routine::PrintDecimal));
+        code += &Self::print(context, " passed; ");
+        code += "    mov rax, r13\n";
+        code += &format!("    call {}\n", context.require_runtime(Routine::PrintDecimal));
+        code += &Self::print(context, " failed\n");
+        code += "    xor rdi, rdi\n";
+        code += "    or r13, r13\n";
+        code += "    setnz dil\n";
+        code += "    mov rax, 231\n";
+        code += "    syscall\n";
         Ok(code)
     }
+
+    fn print(context: &mut Context, text: impl Into<String>) -> String {
+        let text: String = text.into();
+        let length = text.len();
+        let label = context.add_data("text", text.into_bytes());
+        let mut code = format!("    lea rsi, [rel {label}]\n");
+        code += &format!("    mov rdx, {length}\n");
+        code += &format!("    call {}\n", context.require_runtime(Routine::Print));
+        code
+    }
 }
 
 
 impl Codegen for Item {
     fn codegen_x86(self, context: &mut Context) -> Result {
+        let cfg = Cfg::from(&self);
+        if let Some(variable) = Assigned::from(&cfg).first_unassigned_read(&cfg) {
+            return Err(CodegenError::UnassignedVariable(cfg.variables[variable].name.clone()));
+        }
         match self {
-            Self::Function { name, params: _, body } => {
+            Self::Function { name, params: _, return_type: _, body, attributes: _, is_const } => {
+                if is_const {
+                    const_eval::check(&body, context.type_registry())
+                        .map_err(|reason| CodegenError::InvalidConstFunction { function: name.clone(), reason })?;
+                }
+                let bits = context.function_bits(&name)?;
                 let end_label = context.create_label(format!("end{name}"));
 
-                context.enter_labelled_region((name.clone(), end_label.clone()));
-
                 let mut code = format!("{name}:\n");
-                code += &context.enter_function(name)?;
+                let is_entry = name == context.entry().unwrap_or("_start");
+                // only 64-bit functions check a canary, and a narrower entry point may only call
+                // functions of its own width, so there is nothing for it to seed
+                if context.stack_protector() && is_entry && bits == Bits::Bits64 {
+                    code += &format!("    call {}\n", context.require_runtime(Routine::SeedCanary));
+                }
+                if context.detect_features() && is_entry {
+                    code += &format!("    call {}\n", context.require_runtime(Routine::DetectFeatures));
+                }
+                code += &context.enter_function(name.clone(), end_label)?;
                 code += &body.codegen_x86(context)?;
-                code += &format!("{end_label}:\n");
-                code += &context.exit_function()?;
-                Ok(code)
+                // rather than running into whatever follows, an entry point that reaches its end
+                // exits with 0. 16 and 32-bit entry points have no system calls to exit with
+                if is_entry && bits == Bits::Bits64 {
+                    code += "    xor rax, rax\n";
+                    code += &context.exit_entry_function()?;
+                } else {
+                    // one that gives no value gives zero, as a bare `return;` does, rather than
+                    // whatever was left in rax
+                    if context.type_registry().get_function(&name).is_some_and(|function| function.returns == ReturnKind::Void) {
+                        code += "    xor rax, rax\n";
+                    }
+                    code += &context.exit_function()?;
+                }
+                bits.lower(&code).map_err(|reason| CodegenError::UnsupportedBits { function: name, bits: bits.number(), reason })
             },
+            // constants have been inlined where they are used by now
+            Self::Const { .. } => Ok(String::new()),
         }
     }
 }
@@ -46,43 +256,161 @@ impl Codegen for Item {
 
 impl Codegen for Statement {
     fn codegen_x86(self, context: &mut Context) -> Result {
-        match self {
+        let annotation = self.annotation(context);
+        let code = match self {
             Self::Exit { value } => {
+                // exit_group rather than exit, so threads started with `spawn` end as well
                 let mut code = value.codegen_x86(context)?;
-                code.push_str("    mov rax, 60\n");
+                // the functions still running aren't timed, their calls are counted already
+                if context.profile() {
+                    code += &format!("    call {}\n", context.require_runtime(Routine::ProfileDump));
+                }
+                code.push_str("    mov rax, 231\n");
                 code.push_str(context.pop("rdi").as_str());
                 code.push_str("    syscall\n");
                 Ok(code)
             },
-            Self::Expr(value) => value.codegen_x86(context),
-            Self::Let { identifier, value, is_mutable } => {
-                let code = value.codegen_x86(context);
-                context.declare_variable(identifier, is_mutable);
+            Self::Panic { message, location } => Ok(Self::panic(context, message, location)),
+            // unchecked builds take the programmer's word for it
+            Self::Unreachable { location: _ } if !context.checked() => Ok(String::new()),
+            Self::Unreachable { location } => Ok(Self::panic(context, "entered unreachable code".into(), location)),
+            Self::StaticAssert { .. } | Self::Const { .. } => Ok(String::new()),
+            // an `if` or block standing on its own gives no value, so it need not end in one
+            Self::Expr(Expr::If { check, body, els }) => Expr::if_statement(context, *check, *body, els.map(|els| *els)),
+            Self::Expr(Expr::Block(stmts)) => Expr::block_statement(context, stmts),
+            Self::Expr(Expr::Unsafe(body)) => {
+                context.enter_unsafe();
+                let code = Self::Expr(*body).codegen_x86(context);
+                context.exit_unsafe();
                 code
             },
+            Self::Expr(value) => value.codegen_x86(context),
+            // each element is computed on top of the slots made for them, then moved down into its own
+            Self::Let { identifier, ty: _, value: Some(Expr::Array(elements)), is_mutable } => {
+                let length = elements.len() as u64;
+                let mut code = context.reserve(length);
+                for (i, element) in elements.into_iter().enumerate() {
+                    code += &element.codegen_x86(context)?;
+                    code += &context.pop("rax");
+                    code += &format!("    mov qword [rsp + {}], rax\n", i * 8);
+                }
+                context.declare_array(identifier, is_mutable, length);
+                Ok(code)
+            },
+            Self::Let { identifier, ty, value, is_mutable } => {
+                let mut code = match value {
+                    Some(value) => value.codegen_x86(context)?,
+                    None => context.push("0"),
+                };
+                code += &context.declare_variable(identifier, is_mutable, ty);
+                Ok(code)
+            },
 
-            Self::Break => {
-                let LabelFrame { start: _, end } = context.get_labelled_region().expect("can't break from current context");
-                Ok(format!("    jmp {end}\n"))
+            Self::Break(label) => {
+                let LabelFrame { start: _, end, name: _ } = context.get_labelled_region(label.as_deref()).expect("can't break from current context");
+                Ok(context.unwind_labelled_region(label.as_deref()) + &format!("    jmp {end}\n"))
             },
-            Self::Continue => {
-                let LabelFrame { start, end: _ } = context.get_labelled_region().expect("can't continue from current context");
-                Ok(format!("    jmp {start}\n"))
+            Self::Continue(label) => {
+                let LabelFrame { start, end: _, name: _ } = context.get_labelled_region(label.as_deref()).expect("can't continue from current context");
+                Ok(context.unwind_labelled_region(label.as_deref()) + &format!("    jmp {start}\n"))
             },
 
-            Self::Return(value) => {
+            // a function of a narrower type gives the value as a variable of it would hold it
+            Self::Return(Some(value)) => {
+                let function = context.innermost_function().expect("can't return from current context").to_string();
+                let return_type = context.type_registry().get_function(&function).and_then(|function| function.return_type);
                 let mut code = value.codegen_x86(context)?;
                 code += &context.pop("rax");
-                let LabelFrame { start: _, end } = context.get_labelled_region().expect("can't return from current context");
-                code += &format!("    jmp {end}\n");
+                code += &return_type.map(width::truncate).unwrap_or_default();
+                code += &context.return_from_function();
                 Ok(code)
             },
-        }
+            // gives zero, which is the exit code when leaving the entry point this way
+            Self::Return(None) => {
+                let function = context.innermost_function().expect("can't return from current context").to_string();
+                if context.type_registry().get_function(&function).is_some_and(|function| function.returns == ReturnKind::Value) {
+                    return Err(CodegenError::MixedReturns(function));
+                }
+                Ok(String::from("    mov rax, 0\n") + &context.return_from_function())
+            },
+        }?;
+        Ok(annotation + &code)
     }
 }
 
 
+impl Statement {
+    // control flow statements are left bare, the statements inside them are annotated instead
+    fn annotation(&self, context: &Context) -> String {
+        if let Self::Expr(Expr::Block(_) | Expr::If { .. } | Expr::Loop { .. } | Expr::While { .. } | Expr::Unsafe(_)) = self {
+            return String::new();
+        }
+        let format = context.asm_format();
+        let mut annotation = String::new();
+        if format.group_statements {
+            annotation += "\n";
+        }
+        if format.source_comments {
+            annotation += &format!("    ; {self}\n");
+        }
+        annotation
+    }
+
+    fn panic(context: &mut Context, message: String, location: Location) -> String {
+        let message = format!(
+            "panicked at {}:{}:{}: {message}\n",
+            context.source_name(), location.line, location.column,
+        );
+        let length = message.len();
+        let label = context.add_data("panicmsg", message.into_bytes());
+        let mut code = format!("    lea rsi, [rel {label}]\n");
+        code += &format!("    mov rdx, {length}\n");
+        code += &format!("    call {}\n", context.require_runtime(Routine::Panic));
+        code
+    }
+}
+
 impl Expr {
+    // the type of an integer value, `None` for a literal, which takes the type of what it is used
+    // with, or where it isn't known
+    fn int_type(&self, context: &Context) -> Option<Type> {
+        match self {
+            Self::Identifier(name) => context.variable_type(name),
+            Self::FunctionCall { name, args: _ } => context.type_registry().get_function(name).and_then(|function| function.return_type),
+            Self::Add(a, b) | Self::Sub(a, b) | Self::Mul(a, b) | Self::Div(a, b) | Self::Mod(a, b)
+                => a.int_type(context).or_else(|| b.int_type(context)),
+            Self::Neg(value) | Self::Try(value) => value.int_type(context),
+            Self::Cast { value: _, ty } => Some(*ty),
+            _ => None,
+        }
+    }
+
+    // comparisons, divisions, `min` and `max` are unsigned if either side is of an unsigned type
+    fn is_unsigned(context: &Context, a: &Expr, b: &Expr) -> bool {
+        [a, b].into_iter().any(|expr| expr.int_type(context).is_some_and(|ty| ty != Type::Unit && !ty.is_signed()))
+    }
+
+    // divides rax by rbx, leaving the quotient in rax and the remainder in rdx. rdx holds the upper
+    // half of the dividend, so it is sign extended from rax or cleared first
+    fn divide(unsigned: bool) -> &'static str {
+        match unsigned {
+            true => "    xor rdx, rdx\n    div rbx\n",
+            false => "    cqo\n    idiv rbx\n",
+        }
+    }
+
+    // the index in rbx is compared unsigned, so a negative one is out of bounds as well
+    fn bounds_check(context: &mut Context, length: u64, location: Location) -> String {
+        if !context.checked() {
+            return String::new();
+        }
+        let ok_label = context.create_label("inbounds");
+        let mut code = format!("    cmp rbx, {length}\n");
+        code += &format!("    jb {ok_label}\n");
+        code += &Statement::panic(context, format!("index out of bounds, the length is {length}"), location);
+        code + &format!("{ok_label}:\n")
+    }
+
     fn prepare_binop_registers(context: &mut Context, a: Expr, b: Expr) -> Result {
         let mut code = String::new();
         code.push_str(a.codegen_x86(context)?.as_str());
@@ -91,6 +419,70 @@ impl Expr {
         code.push_str(context.pop("rax").as_str());
         Ok(code)
     }
+
+    fn if_statement(context: &mut Context, check: Expr, body: Statement, els: Option<Statement>) -> Result {
+        let if_label = context.create_label("if");
+        let else_label = context.create_label("else");
+        let endif_label = context.create_label("endif");
+
+        let mut code = format!("{if_label}:\n");
+        code += check.codegen_x86(context)?.as_str();
+        code += context.pop("rax").as_str();
+        code += "    or rax, rax\n";
+        code += format!("    jz {else_label}\n").as_str();
+        code += context.enter().as_str();
+        code += body.codegen_x86(context)?.as_str();
+        code += context.exit().as_str();
+        code += format!("    jmp {endif_label}\n").as_str();
+        code += format!("{else_label}:\n").as_str();
+        if let Some(els) = els {
+            code += context.enter().as_str();
+            code += els.codegen_x86(context)?.as_str();
+            code += context.exit().as_str();
+        }
+        code += format!("{endif_label}:\n").as_str();
+        Ok(code)
+    }
+
+    fn block_statement(context: &mut Context, stmts: Vec<Statement>) -> Result {
+        let mut code = context.enter();
+        code += stmts
+            .into_iter()
+            .map(|stmt| stmt.codegen_x86(context))
+            .reduce(|a, b| Ok(a? + &b?))
+            .unwrap_or(Ok(String::new()))?
+            .as_str();
+        code += context.exit().as_str();
+        Ok(code)
+    }
+
+    fn branch_value(context: &mut Context, branch: Statement, name: &str) -> Result {
+        match branch {
+            Statement::Expr(Expr::Block(stmts)) => Self::block_value(context, stmts, name),
+            branch => Self::block_value(context, vec![branch], name),
+        }
+    }
+
+    // the value of a block is that of the expression it ends in, left in rax once the block's own
+    // variables are dropped. a block that leaves the function or loop has no value to give
+    fn block_value(context: &mut Context, mut stmts: Vec<Statement>, name: &str) -> Result {
+        let last = stmts.pop();
+        let mut code = context.enter();
+        for stmt in stmts {
+            code += &stmt.codegen_x86(context)?;
+        }
+        match last {
+            Some(Statement::Expr(value)) => {
+                code += &value.codegen_x86(context)?;
+                code += &context.pop("rax");
+            },
+            Some(last @ (Statement::Exit { .. } | Statement::Return(_) | Statement::Panic { .. } | Statement::Unreachable { .. }
+                | Statement::Break(_) | Statement::Continue(_))) => code += &last.codegen_x86(context)?,
+            Some(_) | None => return Err(CodegenError::MissingValue(name.to_string())),
+        }
+        code += &context.exit();
+        Ok(code)
+    }
 }
 
 impl Codegen for Expr {
@@ -115,18 +507,36 @@ impl Codegen for Expr {
                 Ok(code)
             },
             Self::Div(a, b) => {
+                let divide = Self::divide(Self::is_unsigned(context, &a, &b));
                 let mut code = Self::prepare_binop_registers(context, *a, *b)?;
-                code.push_str("    div rbx\n");
+                code.push_str(divide);
                 code.push_str(context.push("rax").as_str());
                 Ok(code)
             },
             Self::Mod(a, b) => {
+                let divide = Self::divide(Self::is_unsigned(context, &a, &b));
                 let mut code = Self::prepare_binop_registers(context, *a, *b)?;
-                code.push_str("    div rbx\n");
+                code.push_str(divide);
                 code.push_str(context.push("rdx").as_str());
                 Ok(code)
             },
 
+            Self::IndexAssign { identifier, index, value, location } => {
+                let mut code = index.codegen_x86(context)?;
+                code += &value.codegen_x86(context)?;
+                code += &context.pop("rax");
+                code += &context.pop("rbx");
+                let (element, length) = context.element(&identifier, "rbx", true)?;
+                code += &Self::bounds_check(context, length, location);
+                code += &format!("    mov {element}, rax\n");
+                Ok(code)
+            },
+            Self::Assign { identifier, value } => {
+                let mut code = value.codegen_x86(context)?;
+                code += context.pop("rax").as_str();
+                code += context.set_variable(&identifier, "rax")?.as_str();
+                Ok(code)
+            },
             Self::AddAssign { identifier, value } => {
                 let mut code = String::new();
                 code += value.codegen_x86(context)?.as_str();
@@ -167,6 +577,7 @@ impl Codegen for Expr {
                 Ok(code)
             },
             Self::DivAssign { identifier, value } => {
+                let divide = Self::divide(Self::is_unsigned(context, &Self::Identifier(identifier.clone()), &value));
                 let mut code = String::new();
                 code += value.codegen_x86(context)?.as_str();
                 code += context.get_variable(&identifier)
@@ -174,12 +585,13 @@ impl Codegen for Expr {
                     .as_str();
                 code += context.pop("rax").as_str();
                 code += context.pop("rbx").as_str();
-                code += "    div rbx\n";
+                code += divide;
                 code += context.set_variable(&identifier, "rax")?
                     .as_str();
                 Ok(code)
             },
             Self::ModAssign { identifier, value } => {
+                let divide = Self::divide(Self::is_unsigned(context, &Self::Identifier(identifier.clone()), &value));
                 let mut code = String::new();
                 code += value.codegen_x86(context)?.as_str();
                 code += context.get_variable(&identifier)
@@ -187,7 +599,7 @@ impl Codegen for Expr {
                     .as_str();
                 code += context.pop("rax").as_str();
                 code += context.pop("rbx").as_str();
-                code += "    div rbx\n";
+                code += divide;
                 code += context.set_variable(&identifier, "rdx")?
                     .as_str();
                 Ok(code)
@@ -210,45 +622,70 @@ impl Codegen for Expr {
                 Ok(code)
             },
             Self::Less(a, b) => {
+                let set = if Self::is_unsigned(context, &a, &b) { "setb" } else { "setl" };
                 let mut code = Self::prepare_binop_registers(context, *a, *b)?;
                 code += "    mov rcx, 0\n";
                 code += "    cmp rax, rbx\n";
-                code += "    setl cl\n";
+                code += &format!("    {set} cl\n");
                 code += context.push("rcx").as_str();
                 Ok(code)
             },
             Self::LessEq(a, b) => {
+                let set = if Self::is_unsigned(context, &a, &b) { "setbe" } else { "setle" };
                 let mut code = Self::prepare_binop_registers(context, *a, *b)?;
                 code += "    mov rcx, 0\n";
                 code += "    cmp rax, rbx\n";
-                code += "    setle cl\n";
+                code += &format!("    {set} cl\n");
                 code += context.push("rcx").as_str();
                 Ok(code)
             },
             Self::Greater(a, b) => {
+                let set = if Self::is_unsigned(context, &a, &b) { "seta" } else { "setg" };
                 let mut code = Self::prepare_binop_registers(context, *a, *b)?;
                 code += "    mov rcx, 0\n";
                 code += "    cmp rax, rbx\n";
-                code += "    setg cl\n";
+                code += &format!("    {set} cl\n");
                 code += context.push("rcx").as_str();
                 Ok(code)
             },
             Self::GreaterEq(a, b) => {
+                let set = if Self::is_unsigned(context, &a, &b) { "setae" } else { "setge" };
                 let mut code = Self::prepare_binop_registers(context, *a, *b)?;
                 code += "    mov rcx, 0\n";
                 code += "    cmp rax, rbx\n";
-                code += "    setge cl\n";
+                code += &format!("    {set} cl\n");
                 code += context.push("rcx").as_str();
                 Ok(code)
             },
 
-            Self::IntegerLiteral(value) => Ok(context.push(value)),
+            Self::IntegerLiteral(value) => match value.parse::<i32>() {
+                Ok(_) => Ok(context.push(value)),
+                // `push` only takes a sign-extended 32-bit immediate
+                Err(_) => Ok(format!("    mov rax, {value}\n") + &context.push("rax")),
+            },
+            Self::StringLiteral(value) => {
+                let mut bytes = value.into_bytes();
+                bytes.push(0);
+                let label = context.add_data("str", bytes);
+                Ok(format!("    lea rax, [rel {label}]\n") + &context.push("rax"))
+            },
+            Self::Identifier(ident) if context.array_length(&ident).is_some() => Err(CodegenError::ArrayAsValue(ident)),
             Self::Identifier(ident) => Ok(
                 context.get_variable(&ident)
                     .ok_or(CodegenError::IdentifierNotDeclared(ident))?
             ),
+            array @ Self::Array(_) => Err(CodegenError::ArrayAsValue(array.to_string())),
+            Self::Index { identifier, index, location } => {
+                let mut code = index.codegen_x86(context)?;
+                code += &context.pop("rbx");
+                let (element, length) = context.element(&identifier, "rbx", false)?;
+                code += &Self::bounds_check(context, length, location);
+                code += &context.push(element);
+                Ok(code)
+            },
 
             Self::FunctionCall { name, args } => {
+                let arg_count = args.len() as u64;
                 let mut code = String::new();
                 code += args
                     .into_iter()
@@ -256,52 +693,153 @@ impl Codegen for Expr {
                     .reduce(|a, b| Ok(a? + &b?))
                     .unwrap_or(Ok(String::new()))?
                     .as_str();
-                code += format!("    call {name}\n").as_str();
+                // the callee's params frame owns the arguments from here on
+                context.release(arg_count);
+                match context.inline_body(&name) {
+                    Some(body) => {
+                        let end_label = context.create_label(format!("endinline{name}"));
+                        // stands in for the return address
+                        code += "    sub rsp, 8\n";
+                        code += &context.enter_inline_function(name, end_label)?;
+                        code += &body.codegen_x86(context)?;
+                        code += &context.exit_inline_function()?;
+                    },
+                    None => {
+                        // the return address and stack slots of both sides have to be the same size
+                        if let Some(caller) = context.current_function().map(String::from) {
+                            let bits = context.function_bits(&caller)?;
+                            let callee_bits = context.function_bits(&name)?;
+                            if bits != callee_bits {
+                                let reason = format!("it calls `{name}`, which is compiled for {} bits", callee_bits.number());
+                                return Err(CodegenError::UnsupportedBits { function: caller, bits: bits.number(), reason });
+                            }
+                        }
+                        code += format!("    call {name}\n").as_str();
+                    },
+                };
                 code += &context.push("rax");
                 Ok(code)
             }
-
-            Self::Block(stmts) => {
-                let mut code = context.enter();
-                code += stmts
+            Self::Intrinsic { intrinsic: Intrinsic::Spawn, args } => {
+                let name = match args.as_slice() {
+                    [Expr::Identifier(name)] => name.clone(),
+                    _ => return Err(CodegenError::InvalidThreadFunction(args.iter().map(Expr::to_string).collect())),
+                };
+                let function = context.type_registry().get_function(&name).ok_or(CodegenError::FunctionNotDeclared(name.clone()))?;
+                if !function.params.is_empty() {
+                    return Err(CodegenError::InvalidThreadFunction(name));
+                }
+                let mut code = format!("    lea rax, [rel {name}]\n");
+                code += &format!("    call {}\n", context.require_runtime(Routine::Spawn));
+                code += &context.push("rax");
+                Ok(code)
+            },
+            Self::Intrinsic { intrinsic: Intrinsic::Swap, args } => {
+                let (a, b) = match args.as_slice() {
+                    [Expr::Identifier(a), Expr::Identifier(b)] => (a.clone(), b.clone()),
+                    [Expr::Identifier(_), other] | [other, _] => return Err(CodegenError::NotAVariable(other.to_string())),
+                    _ => unreachable!("the parser checks the number of arguments"),
+                };
+                let mut code = context.get_variable(&a).ok_or(CodegenError::IdentifierNotDeclared(a.clone()))?;
+                code += &context.get_variable(&b).ok_or(CodegenError::IdentifierNotDeclared(b.clone()))?;
+                code += &context.pop("rax");
+                code += &context.pop("rbx");
+                code += &context.set_variable(&a, "rax")?;
+                code += &context.set_variable(&b, "rbx")?;
+                Ok(code)
+            },
+            // gives no value, it only ever stands as a statement of its own
+            Self::Intrinsic { intrinsic: Intrinsic::Trace, args } => {
+                let line = match args.as_slice() {
+                    [Expr::IntegerLiteral(line)] => line.clone(),
+                    _ => unreachable!("traces are inserted with the line they are on"),
+                };
+                let message = format!("{}:{line}\n", context.source_name());
+                let length = message.len();
+                let label = context.add_data("trace", message.into_bytes());
+                let mut code = format!("    lea rsi, [rel {label}]\n");
+                code += &format!("    mov rdx, {length}\n");
+                code += &format!("    call {}\n", context.require_runtime(Routine::Trace));
+                Ok(code)
+            },
+            Self::Intrinsic { intrinsic, args } => {
+                if intrinsic.is_unsafe() && !context.is_unsafe() {
+                    return Err(CodegenError::RequiresUnsafe(intrinsic.name().to_string()));
+                }
+                let definition = intrinsics::definition(intrinsic);
+                let unsigned = match args.as_slice() {
+                    [a, b] => definition.lower_unsigned.filter(|_| Self::is_unsigned(context, a, b)),
+                    _ => None,
+                };
+                let mut code = args
                     .into_iter()
-                    .map(|stmt| stmt.codegen_x86(context))
+                    .map(|arg| arg.codegen_x86(context))
                     .reduce(|a, b| Ok(a? + &b?))
-                    .unwrap_or(Ok(String::new()))?
-                    .as_str();
-                code += context.exit().as_str();
+                    .unwrap_or(Ok(String::new()))?;
+                let lower = unsigned.or(definition.lower).expect("generated on its own above");
+                code += &lower(context);
+                Ok(code)
+            },
+            Self::Neg(value) => {
+                let mut code = value.codegen_x86(context)?;
+                code += &context.pop("rax");
+                code += "    neg rax\n";
+                code += &context.push("rax");
+                Ok(code)
+            },
+            Self::Cast { value, ty } => {
+                let mut code = value.codegen_x86(context)?;
+                code += &context.pop("rax");
+                code += &width::truncate(ty);
+                code += &context.push("rax");
+                Ok(code)
+            },
+            Self::Try(value) => {
+                let ok_label = context.create_label("try");
+
+                let mut code = value.codegen_x86(context)?;
+                code += &context.pop("rax");
+                code += "    or rax, rax\n";
+                code += &format!("    jns {ok_label}\n");
+                code += &context.return_from_function();
+                code += &format!("{ok_label}:\n");
+                code += &context.push("rax");
                 Ok(code)
             }
+
+            // used as a value, the block's value is pushed once its scope is gone
+            Self::Block(stmts) => {
+                let mut code = Self::block_value(context, stmts, "{ ... }")?;
+                code += &context.push("rax");
+                Ok(code)
+            }
+            // used as a value, each branch leaves what it ends in in rax for the one push after both
             Self::If { check, body, els } => {
-                let if_label = context.create_label("if");
+                let name = format!("if ({check})");
+                let els = els.ok_or(CodegenError::MissingValue(name.clone()))?;
                 let else_label = context.create_label("else");
                 let endif_label = context.create_label("endif");
 
-                let mut code = format!("{if_label}:\n");
-                code += check.codegen_x86(context)?.as_str();
-                code += context.pop("rax").as_str();
+                let mut code = check.codegen_x86(context)?;
+                code += &context.pop("rax");
                 code += "    or rax, rax\n";
-                code += format!("    jz {else_label}\n").as_str();
-                code += context.enter().as_str();
-                code += body.codegen_x86(context)?.as_str();
-                code += context.exit().as_str();
-                code += format!("    jmp {endif_label}\n").as_str();
-                code += format!("{else_label}:\n").as_str();
-                if let Some(els) = els {
-                    code += context.enter().as_str();
-                    code += els.codegen_x86(context)?.as_str();
-                    code += context.exit().as_str();
-                }
-                code += format!("{endif_label}:\n").as_str();
+                code += &format!("    jz {else_label}\n");
+                code += &Self::branch_value(context, *body, &name)?;
+                code += &format!("    jmp {endif_label}\n");
+                code += &format!("{else_label}:\n");
+                code += &Self::branch_value(context, *els, &name)?;
+                code += &format!("{endif_label}:\n");
+                code += &context.push("rax");
                 Ok(code)
             },
-            Self::Loop { body } => {
+            Self::Loop { label, body } => {
                 let loop_label = context.create_label("loop");
                 let endloop_label = context.create_label("endloop");
 
                 context.enter_labelled_region(LabelFrame {
                     start:  loop_label.clone(),
                     end: endloop_label.clone(),
+                    name: label,
                 });
 
                 let mut code = format!("{loop_label}:\n");
@@ -313,7 +851,7 @@ impl Codegen for Expr {
 
                 Ok(code)
             }
-            Self::While { check, body } => {
+            Self::While { label, check, body } => {
                 let while_label = context.create_label("while");
                 let endwhile_label = context.create_label("endwhile");
 
@@ -321,6 +859,7 @@ impl Codegen for Expr {
                 context.enter_labelled_region(LabelFrame {
                     start:  while_label.clone(),
                     end: endwhile_label.clone(),
+                    name: label,
                 });
 
                 let mut code =  format!("{while_label}:\n");
@@ -336,7 +875,271 @@ impl Codegen for Expr {
 
                 Ok(code)
             }
+            Self::Unsafe(body) => {
+                context.enter_unsafe();
+                let code = body.codegen_x86(context)?;
+                context.exit_unsafe();
+                Ok(code)
+            }
         }
     }
 }
 
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+// the context is made for the program and then given to `configure` for the options a test needs
+#[cfg(test)]
+fn generate(source: &str, configure: fn(Context) -> Context) -> Result {
+    use crate::{parser::Parser, tokenize::Tokenize, type_registry::TypeRegistry};
+    let program = Parser::new(source.tokenize()).parse().expect("source parses");
+    let mut context = configure(Context::new(TypeRegistry::from(&program)));
+    program.codegen_x86(&mut context)
+}
+
+#[test]
+fn unreachable_functions_are_not_emitted() {
+    let code = generate("fn unused() exit(1); fn used() exit(2); fn _start() used();", |context| context).expect("codegen succeeds");
+    assert!(code.contains("\nused:\n"));
+    assert!(code.contains("\n_start:\n"));
+    assert!(!code.contains("\nunused:\n"));
+}
+
+#[test]
+fn keep_all_emits_unreachable_functions() {
+    let code = generate("fn unused() exit(1); fn _start() exit(0);", |context| context.with_keep_all(true)).expect("codegen succeeds");
+    assert!(code.contains("\nunused:\n"));
+}
+
+#[test]
+fn exported_functions_are_kept_and_global() {
+    let code = generate("#[export] fn api() exit(1); fn helper() exit(2); fn _start() exit(0);", |context| context).expect("codegen succeeds");
+    assert!(code.contains("global api\n"));
+    assert!(code.contains("\napi:\n"));
+    assert!(!code.contains("\nhelper:\n"));
+}
+
+#[test]
+fn dead_variables_share_stack_slots() {
+    let code = generate("fn _start() { let a = 1; let b = a + 1; let c = b + 1; exit(c); }", |context| context).expect("codegen succeeds");
+    assert!(code.contains("    add rsp, 8\n"));
+    assert!(!code.contains("    add rsp, 24\n"));
+}
+
+#[test]
+fn live_variables_keep_their_own_slots() {
+    let code = generate("fn _start() { let a = 1; let b = 2; let c = a + b; exit(c); }", |context| context).expect("codegen succeeds");
+    assert!(code.contains("    add rsp, 16\n"));
+}
+
+#[test]
+fn source_comments_annotate_statements() {
+    let code = generate("fn _start() { let x = 1; exit(x); }", |context| {
+        context.with_asm_format(format::AsmFormat { source_comments: true, ..Default::default() })
+    }).expect("codegen succeeds");
+    assert!(code.contains("    ; let x = 1;\n    push 1\n"));
+    assert!(code.contains("    ; exit(x);\n"));
+}
+
+#[test]
+fn unchecked_builds_drop_unreachable_traps() {
+    let code = generate("fn _start() { unreachable(); }", |context| context.with_checked(false)).expect("codegen succeeds");
+    assert!(!code.contains(Routine::Panic.label()));
+}
+
+#[test]
+fn entry_points_are_kept_and_global() {
+    let source = "fn kmain() exit(1); fn _start() exit(0);";
+    let code = generate(source, |context| context.with_entry(Some(String::from("kmain")))).expect("codegen succeeds");
+    assert!(code.contains("global kmain\n"));
+    assert!(code.contains("\nkmain:\n"));
+    assert!(matches!(
+        generate(source, |context| context.with_entry(Some(String::from("boot")))),
+        Err(CodegenError::FunctionNotDeclared(_)),
+    ));
+}
+
+#[test]
+fn narrow_functions_are_lowered() {
+    let code = generate("#[bits(16)] fn boot() { let x = 1; loop {} } fn _start() exit(0);", |context| context.with_keep_all(true)).expect("codegen succeeds");
+    assert!(code.contains("[bits 16]\nboot:\n"));
+    assert!(code.contains("    mov bx, [bp + 0]\n"));
+    assert!(code.contains("\n_start:\n    push 0\n    mov rax, 231\n"));
+    assert!(matches!(
+        generate("#[bits(32)] fn f() return g(); fn g() return 1;", |context| context.with_keep_all(true)),
+        Err(CodegenError::UnsupportedBits { bits: 32, .. }),
+    ));
+    assert!(matches!(
+        generate("#[bits(16)] fn f() { let x = 100000; loop {} }", |context| context.with_keep_all(true)),
+        Err(CodegenError::UnsupportedBits { bits: 16, .. }),
+    ));
+}
+
+#[test]
+fn volatile_accesses_survive_optimization() {
+    use crate::{optimize::optimize, parser::Parser, tokenize::Tokenize, type_registry::TypeRegistry};
+    let source = "fn _start() unsafe { let port = 4096; volatile_store(port, 1); volatile_store(port, 2); exit(volatile_load(port)); }";
+    let program = optimize(Parser::new(source.tokenize()).parse().expect("source parses"));
+    let mut context = Context::new(TypeRegistry::from(&program));
+    let code = program.codegen_x86(&mut context).expect("codegen succeeds");
+    assert_eq!(code.matches("    mov qword [rbx], rax\n").count(), 2);
+    assert!(code.contains("    pop rbx\n    push qword [rbx]\n"));
+}
+
+#[test]
+fn port_io_uses_dx() {
+    let code = generate("#[bits(16)] fn boot() unsafe { outb(1016, inb(1021) + 1); loop {} }", |context| context.with_keep_all(true)).expect("codegen succeeds");
+    assert!(code.contains("    pop dx\n    xor ax, ax\n    in al, dx\n"));
+    assert!(code.contains("    pop ax\n    pop dx\n    out dx, al\n"));
+}
+
+#[test]
+fn atomics_are_locked() {
+    let code = generate("fn _start() unsafe { let counter = 4096; atomic_add(counter, 1); exit(atomic_cas(counter, 1, 2)); }", |context| context).expect("codegen succeeds");
+    assert!(code.contains("    lock xadd qword [rbx], rax\n"));
+    assert!(code.contains("    lock cmpxchg qword [rbx], rcx\n    mov rcx, 0\n    sete cl\n"));
+}
+
+#[test]
+fn raw_accesses_need_an_unsafe_block() {
+    let codegen = |source: &str| generate(source, |context| context);
+    assert!(matches!(codegen("fn _start() exit(inb(96));"), Err(CodegenError::RequiresUnsafe(name)) if name == "inb"));
+    assert!(matches!(
+        codegen("#[inline] fn peek() return volatile_load(4096); fn _start() unsafe { exit(peek()); }"),
+        Err(CodegenError::RequiresUnsafe(_)),
+    ));
+    assert!(codegen("#[inline] fn peek() unsafe { return volatile_load(4096); } fn _start() exit(peek());").is_ok());
+    assert!(codegen("fn worker() return 1; fn _start() exit(join(spawn(worker)));").is_ok());
+}
+
+#[test]
+fn spawn_takes_a_function_without_parameters() {
+    let code = generate("fn worker() return 1; fn unused() return 2; fn _start() exit(join(spawn(worker)));", |context| context).expect("codegen succeeds");
+    assert!(code.contains("    lea rax, [rel worker]\n    call __beryllium_spawn\n"));
+    assert!(code.contains("    js __beryllium_spawn_failed\n"));
+    assert!(code.contains("\n__beryllium_panic:\n"));
+    assert!(code.contains("\nworker:\n"));
+    assert!(!code.contains("\nunused:\n"));
+    assert!(matches!(
+        generate("fn worker(n) return n; fn _start() exit(join(spawn(worker)));", |context| context),
+        Err(CodegenError::InvalidThreadFunction(_)),
+    ));
+}
+
+#[test]
+fn wide_literals_are_moved_through_a_register() {
+    let code = generate("fn _start() exit(4294967296);", |context| context).expect("codegen succeeds");
+    assert!(code.contains("    mov rax, 4294967296\n    push rax\n"));
+}
+
+#[test]
+fn variables_without_a_value_are_assigned_before_use() {
+    let code = generate("fn _start() { let mut x; x = 3; exit(x); }", |context| context).expect("codegen succeeds");
+    assert!(code.contains("    push 0\n"));
+    assert!(matches!(
+        generate("fn _start() { let mut x; if (1) x = 3; exit(x); }", |context| context),
+        Err(CodegenError::UnassignedVariable(name)) if name == "x",
+    ));
+}
+
+#[test]
+fn swap_exchanges_two_mutable_variables() {
+    let code = generate("fn _start() { let mut a = 1; let mut b = 2; swap(a, b); exit(a - b); }", |context| context).expect("codegen succeeds");
+    assert!(code.contains("    mov qword [rsp + 8], rax\n    mov qword [rsp + 0], rbx\n"));
+
+    let codegen = |source: &str| generate(source, |context| context);
+    assert!(matches!(codegen("fn _start() { let mut a = 1; let b = 2; swap(a, b); exit(a); }"), Err(CodegenError::ChangedImmutableVariable(name)) if name == "b"));
+    assert!(matches!(codegen("fn _start() { let mut a = 1; swap(a, 2); exit(a); }"), Err(CodegenError::NotAVariable(_))));
+}
+
+#[test]
+fn array_elements_are_indexed_from_the_lowest_slot() {
+    let code = generate("fn _start() { let mut a = [1, 2]; let i = 1; a[i] = 3; exit(a[0]); }", |context| context).expect("codegen succeeds");
+    assert!(code.contains("    sub rsp, 16\n"));
+    assert!(code.contains("    mov qword [rsp + 8], rax\n"));
+    assert!(code.contains("    cmp rbx, 2\n"));
+    assert!(code.contains("    mov qword [rsp + rbx*8 + 8], rax\n"));
+    assert!(code.contains("    push qword [rsp + rbx*8 + 8]\n"));
+
+    let codegen = |source: &str| generate(source, |context| context);
+    assert!(matches!(codegen("fn _start() { let a = [1]; exit(a); }"), Err(CodegenError::ArrayAsValue(name)) if name == "a"));
+    assert!(matches!(codegen("fn _start() { let a = 1; exit(a[0]); }"), Err(CodegenError::NotAnArray(name)) if name == "a"));
+}
+
+#[test]
+fn division_extends_the_dividend_by_signedness() {
+    let code = generate("fn f(a, b) return a * b / 2; fn _start() exit(f(-6, 1));", |context| context).expect("codegen succeeds");
+    assert!(code.contains("    mul rbx\n"));
+    assert!(code.contains("    cqo\n    idiv rbx\n"));
+    assert!(!code.contains("    div rbx\n"));
+    let code = generate("fn _start() { let mut x: u64 = 7; x %= 2; exit(x); }", |context| context).expect("codegen succeeds");
+    assert!(code.contains("    xor rdx, rdx\n    div rbx\n"));
+}
+
+#[test]
+fn min_max_and_abs_are_branchless() {
+    let code = generate("fn f(a, b) exit(min(a, b) + max(a, b) + abs(a)); fn _start() f(1, 2);", |context| context).expect("codegen succeeds");
+    assert!(code.contains("    cmp rax, rbx\n    cmovg rax, rbx\n"));
+    assert!(code.contains("    cmp rax, rbx\n    cmovl rax, rbx\n"));
+    assert!(code.contains("    mov rbx, rax\n    neg rbx\n    cmovns rax, rbx\n"));
+    let code = generate("fn f(a) exit(min(a as u64, 1) + max(a, 2 as u32)); fn _start() f(1);", |context| context).expect("codegen succeeds");
+    assert!(code.contains("    cmp rax, rbx\n    cmova rax, rbx\n"));
+    assert!(code.contains("    cmp rax, rbx\n    cmovb rax, rbx\n"));
+    let code = generate("fn f(a) exit(min(a as u64, 1)); fn _start() f(1);", |context| context.with_features(cpu::Cpu::I386.features())).expect("codegen succeeds");
+    assert!(code.contains("    jbe min"));
+}
+
+#[test]
+fn string_literals_are_emitted_as_read_only_data() {
+    let code = generate("fn _start() { let s = \"hi\"; exit(0); }", |context| context).expect("codegen succeeds");
+    assert!(code.contains("section .rodata\n"));
+    assert!(code.contains(": db 104, 105, 0\n"));
+    assert!(code.contains("    lea rax, [rel str"));
+}
+
+#[test]
+fn the_cpu_decides_which_extensions_are_used() {
+    use cpu::Cpu;
+    let source = "fn f(a, b) exit(min(a, b) + abs(a) + popcount(b)); fn _start() f(1, 2);";
+    let code = generate(source, |context| context.with_features(Cpu::I386.features())).expect("codegen succeeds");
+    assert!(!code.contains("cmov") && !code.contains("popcnt"));
+    let code = generate(source, |context| context.with_features(Cpu::X86_64.features())).expect("codegen succeeds");
+    assert!(code.contains("cmovg") && !code.contains("popcnt"));
+    let code = generate(source, |context| context.with_features(Cpu::X86_64V2.features())).expect("codegen succeeds");
+    assert!(code.contains("    popcnt rax, rax\n"));
+}
+
+#[test]
+fn detected_features_are_checked_at_runtime() {
+    let code = generate("fn f(a) exit(popcount(a)); fn _start() f(7);", |context| context.with_detect_features(true)).expect("codegen succeeds");
+    assert!(code.contains("_start:\n    call __beryllium_detect_features\n"));
+    assert!(code.contains("    test qword [rel __beryllium_features], 2\n"));
+    assert!(code.contains("    popcnt rax, rax\n"));
+    assert!(code.contains("    inc rcx\n"));
+    // `cmov` is part of the baseline, so `min` doesn't need a check
+    assert!(!code.contains("nocmov"));
+}
+
+#[test]
+fn the_entry_point_exits_instead_of_returning() {
+    let code = generate("fn f() return 1; fn _start() { f(); }", |context| context).expect("codegen succeeds");
+    assert!(code.contains("    xor rax, rax\n    add rsp, 0\nend_start00000000:\n    add rsp, 8\n    mov rdi, rax\n    mov rax, 231\n    syscall\n"));
+    assert!(code.contains("    push rbx\n    ret\n"));
+}
+
+#[test]
+fn the_stack_protector_guards_called_functions() {
+    let source = "#[inline(always)] fn g(a) return a; fn f(a) return g(a) + 1; fn _start() exit(f(2));";
+    let code = generate(source, |context| context.with_stack_protector(true)).expect("codegen succeeds");
+    assert!(code.contains("_start:\n    call __beryllium_seed_canary\n"));
+    assert!(code.contains("f:\n    mov rbx, [rel __beryllium_canary]\n    push rbx\n"));
+    assert!(code.contains("endf00000000:\n    pop rcx\n    cmp rcx, [rel __beryllium_canary]\n    je canary"));
+    assert!(code.contains("    call __beryllium_panic\ncanary00000001:\n    mov rbx, [rsp]\n"));
+    // inlined calls have no return address of their own
+    assert!(code.contains("endinlineg00000000:\n    add rsp, 16\n"));
+}
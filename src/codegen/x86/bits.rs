@@ -0,0 +1,186 @@
+use crate::ast::{Attribute, AttributeArg};
+
+
+// the operand size a function is compiled for. codegen always produces 64-bit code, narrower
+// functions are lowered from it afterwards, which only works for the subset that needs no system
+// calls or runtime routines
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Bits {
+    Bits16,
+    Bits32,
+    #[default]
+    Bits64,
+}
+
+impl Bits {
+    pub fn new(bits: u64) -> Option<Self> {
+        match bits {
+            16 => Some(Self::Bits16),
+            32 => Some(Self::Bits32),
+            64 => Some(Self::Bits64),
+            _ => None,
+        }
+    }
+
+    pub fn number(&self) -> u64 {
+        match self {
+            Self::Bits16 => 16,
+            Self::Bits32 => 32,
+            Self::Bits64 => 64,
+        }
+    }
+
+    fn word_size(&self) -> u64 {
+        self.number() / 8
+    }
+
+    // `Ok(None)` without a `#[bits(n)]` attribute, `Err(n)` if it names an unsupported size. the
+    // parser rejects one whose argument isn't an integer, so that is only left in a tree made by hand
+    pub fn from_attributes(attributes: &[Attribute]) -> Result<Option<Self>, u64> {
+        let Some(attribute) = attributes.iter().find(|attribute| attribute.name == "bits") else {
+            return Ok(None);
+        };
+        let bits = match attribute.args.as_slice() {
+            [AttributeArg::IntegerLiteral(bits)] => bits.parse().unwrap_or(0),
+            _ => 0,
+        };
+        Self::new(bits).map(Some).ok_or(bits)
+    }
+
+    fn register(&self, register: &str) -> Option<String> {
+        let name = match register {
+            "rax" | "rbx" | "rcx" | "rdx" | "rsi" | "rdi" | "rsp" | "rbp" => &register[1..],
+            _ => return None,
+        };
+        match self {
+            Self::Bits16 => Some(name.to_string()),
+            Self::Bits32 => Some(format!("e{name}")),
+            Self::Bits64 => Some(register.to_string()),
+        }
+    }
+
+    // rewrites the 64-bit code of a function for this mode, or gives the reason it cannot be
+    pub fn lower(&self, code: &str) -> Result<String, String> {
+        if *self == Self::Bits64 {
+            return Ok(code.to_string());
+        }
+        let mut lowered = format!("[bits {}]\n", self.number());
+        for line in code.lines() {
+            let Some(instruction) = line.strip_prefix("    ").filter(|instruction| !instruction.starts_with(';')) else {
+                lowered += &format!("{line}\n");
+                continue;
+            };
+            if instruction == "syscall" {
+                return Err(String::from("system calls such as `exit` and `panic` need 64-bit mode"));
+            }
+            if instruction.starts_with("call __beryllium_") {
+                return Err(String::from("runtime routines need 64-bit mode"));
+            }
+            if instruction.contains("*8") {
+                return Err(String::from("indexing arrays needs 64-bit mode"));
+            }
+            if *self == Self::Bits16 && instruction.contains("dword") {
+                return Err(String::from("32-bit variables need 32 or 64-bit mode"));
+            }
+            if let Some(value) = self.wide_immediate(instruction) {
+                return Err(format!("`{value}` doesn't fit in a {}-bit word", self.number()));
+            }
+            lowered += &self.lower_instruction(instruction);
+        }
+        lowered += "[bits 64]\n";
+        Ok(lowered)
+    }
+
+    // an immediate operand that would be cut down in this mode, memory operands are scaled instead
+    fn wide_immediate(&self, instruction: &str) -> Option<i64> {
+        let (_, operands) = instruction.split_once(' ')?;
+        operands.split(',')
+            .filter_map(|operand| operand.trim().rsplit(' ').next()?.parse::<i64>().ok())
+            .find(|&value| match self {
+                Self::Bits16 => i16::try_from(value).is_err(),
+                Self::Bits32 => i32::try_from(value).is_err(),
+                Self::Bits64 => false,
+            })
+    }
+
+    fn lower_instruction(&self, instruction: &str) -> String {
+        let scale = |bytes: &str| bytes.trim().parse::<u64>().map(|bytes| bytes / 8 * self.word_size());
+        for op in ["add", "sub"] {
+            if let Some(Ok(bytes)) = instruction.strip_prefix(&format!("{op} rsp, ")).map(scale) {
+                return format!("    {op} {}, {bytes}\n", self.register("rsp").unwrap());
+            }
+        }
+
+        // sign extending rax into rdx before a division
+        if instruction == "cqo" {
+            return String::from(match self {
+                Self::Bits16 => "    cwd\n",
+                _ => "    cdq\n",
+            });
+        }
+
+        let mut code = String::new();
+        // a 32-bit value fills the register already, there is nothing to sign extend
+        let mut instruction = match instruction.strip_prefix("movsxd ") {
+            Some(operands) => format!("mov {operands}"),
+            None => instruction.to_string(),
+        };
+        if let Some(start) = instruction.find("[rsp") {
+            let end = start + instruction[start..].find(']').unwrap();
+            let offset = instruction[start + 4..end].trim_start_matches([' ', '+']);
+            let offset = scale(offset).unwrap_or(0);
+            // 16-bit addressing cannot use the stack pointer as a base
+            let base = match self {
+                Self::Bits16 => {
+                    code += "    mov bp, sp\n";
+                    "bp"
+                },
+                _ => "esp",
+            };
+            instruction.replace_range(start..=end, &format!("[{base} + {offset}]"));
+        }
+
+        let mut lowered = String::new();
+        let mut word = String::new();
+        for c in instruction.chars().chain(['\n']) {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                word.push(c);
+                continue;
+            }
+            lowered += &match word.as_str() {
+                "qword" if *self == Self::Bits16 => String::from("word"),
+                "qword" => String::from("dword"),
+                _ => self.register(&word).unwrap_or(word.clone()),
+            };
+            lowered.push(c);
+            word.clear();
+        }
+        code + "    " + &lowered
+    }
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn stack_slots_shrink_with_the_mode() {
+    let code = "f:\n    ; return x;\n    push qword [rsp + 16]\n    pop rax\n    add rsp, 8\n    jmp endf00000000\n";
+    assert_eq!(
+        Bits::Bits32.lower(code).unwrap(),
+        "[bits 32]\nf:\n    ; return x;\n    push dword [esp + 8]\n    pop eax\n    add esp, 4\n    jmp endf00000000\n[bits 64]\n",
+    );
+    assert_eq!(Bits::Bits16.lower("    mov rbx, [rsp]\n").unwrap(), "[bits 16]\n    mov bp, sp\n    mov bx, [bp + 0]\n[bits 64]\n");
+    assert!(Bits::Bits16.lower("    mov rax, 60\n    pop rdi\n    syscall\n").is_err());
+    assert_eq!(Bits::Bits32.lower("    movsxd rax, dword [rsp + 8]\n").unwrap(), "[bits 32]\n    mov eax, dword [esp + 4]\n[bits 64]\n");
+    assert!(Bits::Bits16.lower("    mov dword [rsp], eax\n").is_err());
+    assert!(Bits::Bits32.lower("    push qword [rsp + rbx*8 + 8]\n").is_err());
+    assert!(Bits::Bits16.lower("    push 100000\n").is_err());
+    assert!(Bits::Bits32.lower("    mov rax, 5000000000\n").is_err());
+    assert!(Bits::Bits32.lower("    mov rax, -2147483649\n").is_err());
+    assert_eq!(Bits::Bits16.lower("    push -32768\n").unwrap(), "[bits 16]\n    push -32768\n[bits 64]\n");
+    assert_eq!(Bits::Bits16.lower("    cqo\n    idiv rbx\n").unwrap(), "[bits 16]\n    cwd\n    idiv bx\n[bits 64]\n");
+}
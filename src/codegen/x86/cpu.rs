@@ -0,0 +1,85 @@
+use std::collections::BTreeSet;
+
+
+// the instruction-set extensions codegen knows how to use, anything else is always lowered to
+// the instructions every x86 processor has
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Feature {
+    Cmov,
+    Popcnt,
+}
+
+impl Feature {
+    pub const ALL: [Self; 2] = [Self::Cmov, Self::Popcnt];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Cmov => "cmov",
+            Self::Popcnt => "popcnt",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|feature| feature.name() == name)
+    }
+
+    // the register and bit that `cpuid` leaf 1 reports the feature in
+    pub fn cpuid_bit(&self) -> (&'static str, u32) {
+        match self {
+            Self::Cmov => ("edx", 15),
+            Self::Popcnt => ("ecx", 23),
+        }
+    }
+
+    // the bit of the flags set by the feature detection routine at startup
+    pub fn flag(&self) -> u64 {
+        1 << Self::ALL.iter().position(|feature| feature == self).expect("every feature is listed")
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Cpu {
+    I386,
+    I686,
+    #[default]
+    X86_64,
+    X86_64V2,
+}
+
+impl Cpu {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::I386 => "i386",
+            Self::I686 => "i686",
+            Self::X86_64 => "x86-64",
+            Self::X86_64V2 => "x86-64-v2",
+        }
+    }
+
+    // `cmov` arrived with the Pentium Pro and is part of every 64-bit processor, `popcnt` is one
+    // of the additions of the v2 level
+    pub fn features(&self) -> BTreeSet<Feature> {
+        let features: &[Feature] = match self {
+            Self::I386 => &[],
+            Self::I686 | Self::X86_64 => &[Feature::Cmov],
+            Self::X86_64V2 => &[Feature::Cmov, Feature::Popcnt],
+        };
+        features.iter().copied().collect()
+    }
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn features_are_found_by_name() {
+    assert_eq!(Feature::from_name("popcnt"), Some(Feature::Popcnt));
+    assert_eq!(Feature::from_name("avx512"), None);
+    assert!(Cpu::X86_64V2.features().is_superset(&Cpu::X86_64.features()));
+    assert!(Cpu::I386.features().is_empty());
+}
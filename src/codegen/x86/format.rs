@@ -0,0 +1,88 @@
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LabelStyle {
+    // labels on a line of their own
+    #[default]
+    Flush,
+    // labels share a line with the instruction that follows them
+    Inline,
+}
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmFormat {
+    pub indent: usize,
+    pub label_style: LabelStyle,
+    pub group_statements: bool,
+    pub source_comments: bool,
+}
+
+impl Default for AsmFormat {
+    fn default() -> Self {
+        Self {
+            indent: 4,
+            label_style: LabelStyle::Flush,
+            group_statements: false,
+            source_comments: false,
+        }
+    }
+}
+
+// codegen always indents instructions by this much, `apply` rewrites it to the configured width
+const CODEGEN_INDENT: &str = "    ";
+
+impl AsmFormat {
+    pub fn apply(&self, code: &str) -> String {
+        let indent = " ".repeat(self.indent);
+        let mut formatted = String::new();
+        let mut pending_label: Option<&str> = None;
+        for line in code.lines() {
+            let instruction = line.strip_prefix(CODEGEN_INDENT);
+            match (self.label_style, pending_label.take(), instruction) {
+                (LabelStyle::Inline, Some(label), Some(instruction)) => {
+                    formatted += &format!("{label} {instruction}\n");
+                    continue;
+                },
+                (_, Some(label), _) => formatted += &format!("{label}\n"),
+                (_, None, _) => (),
+            }
+            match instruction {
+                Some(instruction) => formatted += &format!("{indent}{instruction}\n"),
+                None if self.label_style == LabelStyle::Inline && line.ends_with(':') => pending_label = Some(line),
+                None => formatted += &format!("{line}\n"),
+            }
+        }
+        if let Some(label) = pending_label {
+            formatted += &format!("{label}\n");
+        }
+        formatted
+    }
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn default_format_is_unchanged() {
+    let code = "_start:\n    mov rax, 60\n    syscall\n";
+    assert_eq!(AsmFormat::default().apply(code), code);
+}
+
+#[test]
+fn indent_width_is_applied() {
+    let format = AsmFormat { indent: 2, ..Default::default() };
+    assert_eq!(format.apply("_start:\n    syscall\n"), "_start:\n  syscall\n");
+}
+
+#[test]
+fn inline_labels_join_the_next_instruction() {
+    let format = AsmFormat { label_style: LabelStyle::Inline, ..Default::default() };
+    assert_eq!(
+        format.apply("a:\nb:\n    syscall\nc:\n"),
+        "a:\nb: syscall\nc:\n",
+    );
+}
@@ -0,0 +1,349 @@
+use crate::{ast::Intrinsic, context::Context};
+
+use super::{cpu::Feature, runtime::{Routine, FEATURES_LABEL}};
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Safety {
+    // computes its value from its arguments alone, so it can be evaluated at compile time
+    Pure,
+    Safe,
+    // reads or writes memory or ports at an arbitrary address, only allowed inside an `unsafe` block
+    Unsafe,
+}
+
+
+// everything the compiler knows about a builtin, so adding one means adding its variant and a row
+// here, and tools can list them
+#[derive(Clone, Copy, Debug)]
+pub struct IntrinsicDef {
+    pub intrinsic: Intrinsic,
+    pub name: &'static str,
+    pub arity: usize,
+    pub safety: Safety,
+    pub summary: &'static str,
+    // expands the intrinsic once its arguments are on the stack, the last one on top. `None` for
+    // those that take names rather than values and are generated on their own
+    pub(crate) lower: Option<fn(&mut Context) -> String>,
+    // the expansion when an argument is of an unsigned type, for those that compare them
+    pub(crate) lower_unsigned: Option<fn(&mut Context) -> String>,
+}
+
+impl IntrinsicDef {
+    // the intrinsics that can be called by name, `trace` is only ever inserted by the compiler
+    pub fn callable(&self) -> bool {
+        self.intrinsic != Intrinsic::Trace
+    }
+}
+
+
+pub const INTRINSICS: [IntrinsicDef; 18] = [
+    IntrinsicDef {
+        intrinsic: Intrinsic::VolatileLoad, name: "volatile_load", arity: 1, safety: Safety::Unsafe,
+        summary: "reads the 64-bit value at an address",
+        lower: Some(volatile_load),
+        lower_unsigned: None,
+    },
+    IntrinsicDef {
+        intrinsic: Intrinsic::VolatileStore, name: "volatile_store", arity: 2, safety: Safety::Unsafe,
+        summary: "writes a 64-bit value to an address and gives the value",
+        lower: Some(volatile_store),
+        lower_unsigned: None,
+    },
+    IntrinsicDef {
+        intrinsic: Intrinsic::Inb, name: "inb", arity: 1, safety: Safety::Unsafe,
+        summary: "reads a byte from an I/O port",
+        lower: Some(inb),
+        lower_unsigned: None,
+    },
+    IntrinsicDef {
+        intrinsic: Intrinsic::Inw, name: "inw", arity: 1, safety: Safety::Unsafe,
+        summary: "reads a 16-bit word from an I/O port",
+        lower: Some(inw),
+        lower_unsigned: None,
+    },
+    IntrinsicDef {
+        intrinsic: Intrinsic::Outb, name: "outb", arity: 2, safety: Safety::Unsafe,
+        summary: "writes a byte to an I/O port",
+        lower: Some(outb),
+        lower_unsigned: None,
+    },
+    IntrinsicDef {
+        intrinsic: Intrinsic::Outw, name: "outw", arity: 2, safety: Safety::Unsafe,
+        summary: "writes a 16-bit word to an I/O port",
+        lower: Some(outw),
+        lower_unsigned: None,
+    },
+    IntrinsicDef {
+        intrinsic: Intrinsic::AtomicLoad, name: "atomic_load", arity: 1, safety: Safety::Unsafe,
+        summary: "reads the 64-bit value at an address atomically",
+        lower: Some(atomic_load),
+        lower_unsigned: None,
+    },
+    IntrinsicDef {
+        intrinsic: Intrinsic::AtomicStore, name: "atomic_store", arity: 2, safety: Safety::Unsafe,
+        summary: "writes a 64-bit value to an address atomically",
+        lower: Some(atomic_store),
+        lower_unsigned: None,
+    },
+    IntrinsicDef {
+        intrinsic: Intrinsic::AtomicAdd, name: "atomic_add", arity: 2, safety: Safety::Unsafe,
+        summary: "adds to the value at an address atomically and gives the value from before",
+        lower: Some(atomic_add),
+        lower_unsigned: None,
+    },
+    IntrinsicDef {
+        intrinsic: Intrinsic::AtomicCas, name: "atomic_cas", arity: 3, safety: Safety::Unsafe,
+        summary: "replaces the value at an address if it is the expected one, giving 1 if it was",
+        lower: Some(atomic_cas),
+        lower_unsigned: None,
+    },
+    IntrinsicDef {
+        intrinsic: Intrinsic::Spawn, name: "spawn", arity: 1, safety: Safety::Safe,
+        summary: "runs a function without parameters on a new thread and gives its handle",
+        lower: None,
+        lower_unsigned: None,
+    },
+    IntrinsicDef {
+        intrinsic: Intrinsic::Join, name: "join", arity: 1, safety: Safety::Safe,
+        summary: "waits for a thread and gives the value its function returned",
+        lower: Some(join),
+        lower_unsigned: None,
+    },
+    IntrinsicDef {
+        intrinsic: Intrinsic::Swap, name: "swap", arity: 2, safety: Safety::Safe,
+        summary: "exchanges the values of two mutable variables",
+        lower: None,
+        lower_unsigned: None,
+    },
+    IntrinsicDef {
+        intrinsic: Intrinsic::Min, name: "min", arity: 2, safety: Safety::Pure,
+        summary: "the smaller of two values",
+        lower: Some(min),
+        lower_unsigned: Some(min_unsigned),
+    },
+    IntrinsicDef {
+        intrinsic: Intrinsic::Max, name: "max", arity: 2, safety: Safety::Pure,
+        summary: "the larger of two values",
+        lower: Some(max),
+        lower_unsigned: Some(max_unsigned),
+    },
+    IntrinsicDef {
+        intrinsic: Intrinsic::Abs, name: "abs", arity: 1, safety: Safety::Pure,
+        summary: "the absolute value, the smallest integer is its own",
+        lower: Some(abs),
+        lower_unsigned: None,
+    },
+    IntrinsicDef {
+        intrinsic: Intrinsic::Popcount, name: "popcount", arity: 1, safety: Safety::Pure,
+        summary: "the number of bits that are set",
+        lower: Some(popcount),
+        lower_unsigned: None,
+    },
+    IntrinsicDef {
+        intrinsic: Intrinsic::Trace, name: "trace", arity: 1, safety: Safety::Safe,
+        summary: "prints the source file and a line to stderr, inserted by `--instrument=trace`",
+        lower: None,
+        lower_unsigned: None,
+    },
+];
+
+
+pub fn definition(intrinsic: Intrinsic) -> &'static IntrinsicDef {
+    INTRINSICS.iter().find(|def| def.intrinsic == intrinsic).expect("every intrinsic has a definition")
+}
+
+
+// `fast` if the cpu is known to have the feature, otherwise `fallback`, or a choice between the
+// two made at runtime from the flags the startup routine set
+pub(super) fn with_feature(context: &mut Context, feature: Feature, fast: String, fallback: impl FnOnce(&mut Context) -> String) -> String {
+    if context.has_feature(feature) {
+        return fast;
+    }
+    if !context.detect_features() {
+        return fallback(context);
+    }
+    let fallback_label = context.create_label(format!("no{}", feature.name()));
+    let end_label = context.create_label(format!("end{}", feature.name()));
+    let mut code = format!("    test qword [rel {FEATURES_LABEL}], {}\n", feature.flag());
+    code += &format!("    jz {fallback_label}\n");
+    code += &fast;
+    code += &format!("    jmp {end_label}\n");
+    code += &format!("{fallback_label}:\n");
+    code += &fallback(context);
+    code + &format!("{end_label}:\n")
+}
+
+
+// addresses go through rbx as 16-bit code cannot use rax as a base
+fn volatile_load(context: &mut Context) -> String {
+    let mut code = context.pop("rbx");
+    code += &context.push("qword [rbx]");
+    code
+}
+
+fn volatile_store(context: &mut Context) -> String {
+    let mut code = context.pop("rax");
+    code += &context.pop("rbx");
+    code += "    mov qword [rbx], rax\n";
+    code += &context.push("rax");
+    code
+}
+
+// the port goes in dx, the value in al or ax, zero extended when read
+fn port_in(context: &mut Context, instruction: &str) -> String {
+    let mut code = context.pop("rdx");
+    code += "    xor rax, rax\n";
+    code += &format!("    {instruction}\n");
+    code += &context.push("rax");
+    code
+}
+
+fn inb(context: &mut Context) -> String {
+    port_in(context, "in al, dx")
+}
+
+fn inw(context: &mut Context) -> String {
+    port_in(context, "in ax, dx")
+}
+
+fn port_out(context: &mut Context, instruction: &str) -> String {
+    let mut code = context.pop("rax");
+    code += &context.pop("rdx");
+    code += &format!("    {instruction}\n");
+    code += &context.push("rax");
+    code
+}
+
+fn outb(context: &mut Context) -> String {
+    port_out(context, "out dx, al")
+}
+
+fn outw(context: &mut Context) -> String {
+    port_out(context, "out dx, ax")
+}
+
+// aligned loads are atomic on their own, `xchg` with memory is always locked, which also orders
+// the store before any later load
+fn atomic_load(context: &mut Context) -> String {
+    let mut code = context.pop("rbx");
+    code += "    mov rax, qword [rbx]\n";
+    code += &context.push("rax");
+    code
+}
+
+fn atomic_store(context: &mut Context) -> String {
+    let mut code = context.pop("rax");
+    code += &context.pop("rbx");
+    code += "    xchg qword [rbx], rax\n";
+    code += &context.push("rax");
+    code
+}
+
+fn atomic_add(context: &mut Context) -> String {
+    let mut code = context.pop("rax");
+    code += &context.pop("rbx");
+    code += "    lock xadd qword [rbx], rax\n";
+    code += &context.push("rax");
+    code
+}
+
+fn atomic_cas(context: &mut Context) -> String {
+    let mut code = context.pop("rcx");
+    code += &context.pop("rax");
+    code += &context.pop("rbx");
+    code += "    lock cmpxchg qword [rbx], rcx\n";
+    code += "    mov rcx, 0\n";
+    code += "    sete cl\n";
+    code += &context.push("rcx");
+    code
+}
+
+fn join(context: &mut Context) -> String {
+    let mut code = context.pop("rax");
+    code += &format!("    call {}\n", context.require_runtime(Routine::Join));
+    code += &context.push("rax");
+    code
+}
+
+// branchless where `cmov` is available, it takes the second operand only if the first is on the
+// wrong side. the conditions are those of a signed comparison, or an unsigned one
+fn select(context: &mut Context, name: &str, (cmov, keep): (&str, &str)) -> String {
+    let mut code = context.pop("rbx");
+    code += &context.pop("rax");
+    code += &with_feature(context, Feature::Cmov, format!("    cmp rax, rbx\n    {cmov} rax, rbx\n"), |context| {
+        let end_label = context.create_label(name);
+        let mut code = String::from("    cmp rax, rbx\n");
+        code += &format!("    {keep} {end_label}\n");
+        code += "    mov rax, rbx\n";
+        code + &format!("{end_label}:\n")
+    });
+    code += &context.push("rax");
+    code
+}
+
+fn min(context: &mut Context) -> String {
+    select(context, "min", ("cmovg", "jle"))
+}
+
+fn min_unsigned(context: &mut Context) -> String {
+    select(context, "min", ("cmova", "jbe"))
+}
+
+fn max(context: &mut Context) -> String {
+    select(context, "max", ("cmovl", "jge"))
+}
+
+fn max_unsigned(context: &mut Context) -> String {
+    select(context, "max", ("cmovb", "jae"))
+}
+
+// the negation is taken unless it is negative, `abs` of the smallest integer is itself
+fn abs(context: &mut Context) -> String {
+    let mut code = context.pop("rax");
+    code += &with_feature(context, Feature::Cmov, String::from("    mov rbx, rax\n    neg rbx\n    cmovns rax, rbx\n"), |context| {
+        let end_label = context.create_label("abs");
+        let mut code = String::from("    or rax, rax\n");
+        code += &format!("    jns {end_label}\n");
+        code += "    neg rax\n";
+        code + &format!("{end_label}:\n")
+    });
+    code += &context.push("rax");
+    code
+}
+
+// without `popcnt` the lowest set bit is cleared until none are left
+fn popcount(context: &mut Context) -> String {
+    let mut code = context.pop("rax");
+    code += &with_feature(context, Feature::Popcnt, String::from("    popcnt rax, rax\n"), |context| {
+        let loop_label = context.create_label("popcount");
+        let end_label = context.create_label("endpopcount");
+        let mut code = String::from("    mov rcx, 0\n");
+        code += &format!("{loop_label}:\n");
+        code += "    or rax, rax\n";
+        code += &format!("    jz {end_label}\n");
+        code += "    lea rbx, [rax - 1]\n";
+        code += "    and rax, rbx\n";
+        code += "    inc rcx\n";
+        code += &format!("    jmp {loop_label}\n");
+        code += &format!("{end_label}:\n");
+        code + "    mov rax, rcx\n"
+    });
+    code += &context.push("rax");
+    code
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn every_intrinsic_is_defined_once() {
+    for def in &INTRINSICS {
+        assert_eq!(INTRINSICS.iter().filter(|other| other.intrinsic == def.intrinsic || other.name == def.name).count(), 1, "{}", def.name);
+        assert_eq!(Intrinsic::from_name(def.name), Some(def.intrinsic).filter(|_| def.callable()));
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::BTreeSet;
+
+
+// the first instruction that refers to a label by its absolute address, with the function or
+// runtime routine it is in. position-independent code has to address data relative to `rip`,
+// which only 64-bit code can do, while jumps and calls are relative already
+pub fn absolute_reference(code: &str, functions: &BTreeSet<String>) -> Option<(String, String)> {
+    let labels: BTreeSet<&str> = code.lines()
+        .filter(|line| !line.starts_with(' '))
+        .filter_map(|line| line.split_once(':').map(|(label, _)| label))
+        .filter(|label| !label.contains(' '))
+        .collect();
+
+    let mut enclosing = "";
+    let mut bits_64 = true;
+    for line in code.lines() {
+        let instruction = match line.split_once(':') {
+            Some((label, rest)) if !line.starts_with(' ') && !label.contains(' ') => {
+                if functions.contains(label) || label.starts_with("__beryllium_") {
+                    enclosing = label;
+                }
+                rest.trim()
+            },
+            _ => line.trim(),
+        };
+        if let Some(bits) = instruction.strip_prefix("[bits ") {
+            bits_64 = bits == "64]";
+            continue;
+        }
+        let mnemonic = instruction.split_whitespace().next().unwrap_or_default();
+        let directive = matches!(mnemonic, "global" | "extern" | "section");
+        if instruction.is_empty() || instruction.starts_with(';') || directive || mnemonic.starts_with('j') || mnemonic == "call" {
+            continue;
+        }
+
+        let mut operands = instruction.to_string();
+        while let Some(start) = operands.find("[rel ").filter(|_| bits_64) {
+            let Some(length) = operands[start..].find(']') else { break };
+            operands.replace_range(start..=start + length, "");
+        }
+        let absolute = operands
+            .split(|character: char| !(character.is_ascii_alphanumeric() || character == '_' || character == '.'))
+            .any(|word| labels.contains(word));
+        if absolute {
+            return Some((enclosing.to_string(), instruction.to_string()));
+        }
+    }
+    None
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn only_rip_relative_data_references_are_position_independent() {
+    let functions = BTreeSet::from([String::from("f"), String::from("g")]);
+    let code = "global f\nf:\n    lea rax, [rel text00000000]\n    call g\n    jmp end00000000\nend00000000:\n    ret\ntext00000000: db 104, 0\n";
+    assert_eq!(absolute_reference(code, &functions), None);
+
+    let code = "f:\n    ret\n[bits 32]\ng:\n    lea eax, [rel text00000000]\n[bits 64]\ntext00000000: db 104, 0\n";
+    assert_eq!(absolute_reference(code, &functions), Some((String::from("g"), String::from("lea eax, [rel text00000000]"))));
+
+    let code = "f:\n    mov rax, g\n    ret\ng:\n    ret\n";
+    assert_eq!(absolute_reference(code, &functions), Some((String::from("f"), String::from("mov rax, g"))));
+}
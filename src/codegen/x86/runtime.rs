@@ -0,0 +1,276 @@
+use crate::profile::{FILE_NAME, TABLE_END_LABEL, TABLE_LABEL};
+
+use super::cpu::Feature;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Routine {
+    // writes `rdx` bytes from `rsi` to stderr, then exits with PANIC_EXIT_CODE
+    Panic,
+    // writes `rdx` bytes from `rsi` to stdout
+    Print,
+    // writes `rdx` bytes from `rsi` to stderr
+    Trace,
+    // writes the unsigned value of `rax` to stdout in decimal
+    PrintDecimal,
+    // runs the function at `rax` on a new thread with its own stack, returning a handle in `rax`.
+    // panics if the stack can't be mapped or the thread can't be started
+    Spawn,
+    // waits for the thread with the handle in `rax` to finish, frees its stack and returns the
+    // value its function returned in `rax`
+    Join,
+    // runs `cpuid` and stores a `Feature::flag` for each extension the processor has at
+    // FEATURES_LABEL, called once at the entry point
+    DetectFeatures,
+    // writes the profile table to FILE_NAME in the working directory, called before exiting
+    ProfileDump,
+    // stores the canary of `--stack-protector` at CANARY_LABEL, taken from the random bytes the
+    // kernel gives every process. called first thing at the entry point, as it finds them by
+    // walking the stack the process started with
+    SeedCanary,
+}
+
+pub const PANIC_EXIT_CODE: u8 = 101;
+
+// the canary if the kernel gives no random bytes. the zero, newline and carriage return bytes stop
+// most string copies from writing it back intact, a random canary keeps the zero byte for the same
+// reason
+const STACK_CANARY: u64 = 0xbe11_ca4a_ff0d_0a00;
+// the auxiliary vector entry holding the address of 16 random bytes
+const AT_RANDOM: u64 = 25;
+
+pub const FEATURES_LABEL: &str = "__beryllium_features";
+
+// what `--stack-protector` pushes under the return address, set once per process
+pub const CANARY_LABEL: &str = "__beryllium_canary";
+
+// each thread's stack is mapped on its own, the top 16 bytes hold the handle: the thread id,
+// which the kernel clears when the thread exits, and the value the thread's function returned
+const THREAD_STACK_SIZE: u64 = 0x100000;
+const CLONE_THREAD_FLAGS: u64 = 0x100 | 0x200 | 0x400 | 0x800 | 0x10000 | 0x40000 | 0x100000 | 0x200000;
+const SPAWN_FAILED_MESSAGE: &str = "failed to spawn a thread";
+
+impl Routine {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Panic => "__beryllium_panic",
+            Self::Print => "__beryllium_print",
+            Self::Trace => "__beryllium_trace",
+            Self::PrintDecimal => "__beryllium_print_decimal",
+            Self::Spawn => "__beryllium_spawn",
+            Self::Join => "__beryllium_join",
+            Self::DetectFeatures => "__beryllium_detect_features",
+            Self::ProfileDump => "__beryllium_profile_dump",
+            Self::SeedCanary => "__beryllium_seed_canary",
+        }
+    }
+
+    // the routines this one jumps to, which have to be emitted with it
+    pub fn requires(&self) -> &'static [Routine] {
+        match self {
+            Self::Spawn => &[Self::Panic],
+            _ => &[],
+        }
+    }
+
+    pub fn code(&self) -> String {
+        let label = self.label();
+        match self {
+            Self::Panic => format!(concat!(
+                "{label}:\n",
+                "    mov rax, 1\n",
+                "    mov rdi, 2\n",
+                "    syscall\n",
+                "    mov rax, 231\n",
+                "    mov rdi, {code}\n",
+                "    syscall\n",
+            ), label = label, code = PANIC_EXIT_CODE),
+            Self::Print => format!(concat!(
+                "{label}:\n",
+                "    mov rax, 1\n",
+                "    mov rdi, 1\n",
+                "    syscall\n",
+                "    ret\n",
+            ), label = label),
+            Self::Trace => format!(concat!(
+                "{label}:\n",
+                "    mov rax, 1\n",
+                "    mov rdi, 2\n",
+                "    syscall\n",
+                "    ret\n",
+            ), label = label),
+            Self::PrintDecimal => format!(concat!(
+                "{label}:\n",
+                "    mov rcx, rsp\n",
+                "    sub rsp, 32\n",
+                "    mov rbx, 10\n",
+                "{label}_digit:\n",
+                "    xor rdx, rdx\n",
+                "    div rbx\n",
+                "    add dl, 48\n",
+                "    dec rcx\n",
+                "    mov [rcx], dl\n",
+                "    or rax, rax\n",
+                "    jnz {label}_digit\n",
+                "    lea rdx, [rsp + 32]\n",
+                "    sub rdx, rcx\n",
+                "    mov rsi, rcx\n",
+                "    mov rdi, 1\n",
+                "    mov rax, 1\n",
+                "    syscall\n",
+                "    add rsp, 32\n",
+                "    ret\n",
+            ), label = label),
+            Self::Spawn => format!(concat!(
+                "{label}:\n",
+                "    push rax\n",
+                "    mov rax, 9\n",
+                "    xor rdi, rdi\n",
+                "    mov rsi, {size}\n",
+                "    mov rdx, 3\n",
+                "    mov r10, 34\n",
+                "    mov r8, -1\n",
+                "    xor r9, r9\n",
+                "    syscall\n",
+                // both syscalls give a negative errno when they fail
+                "    test rax, rax\n",
+                "    js {label}_failed\n",
+                // the new thread starts by popping the function off its stack
+                "    lea rsi, [rax + {size} - 24]\n",
+                "    pop rax\n",
+                "    mov [rsi], rax\n",
+                "    lea rdx, [rsi + 8]\n",
+                "    mov r10, rdx\n",
+                "    xor r8, r8\n",
+                "    mov rdi, {flags}\n",
+                "    mov rax, 56\n",
+                "    syscall\n",
+                "    test rax, rax\n",
+                "    jz {label}_thread\n",
+                "    js {label}_failed\n",
+                "    mov rax, rdx\n",
+                "    ret\n",
+                "{label}_thread:\n",
+                "    pop rax\n",
+                "    call rax\n",
+                "    mov [rsp + 8], rax\n",
+                "    xor rdi, rdi\n",
+                "    mov rax, 60\n",
+                "    syscall\n",
+                "{label}_failed:\n",
+                "    lea rsi, [rel {label}_message]\n",
+                "    mov rdx, {length}\n",
+                "    jmp {panic}\n",
+                "section .rodata\n",
+                "{label}_message: db \"{message}\", 10\n",
+                "section .text\n",
+            ), label = label, size = THREAD_STACK_SIZE, flags = CLONE_THREAD_FLAGS, panic = Self::Panic.label(),
+               message = SPAWN_FAILED_MESSAGE, length = SPAWN_FAILED_MESSAGE.len() + 1),
+            Self::Join => format!(concat!(
+                "{label}:\n",
+                "    mov rdi, rax\n",
+                "{label}_wait:\n",
+                "    mov edx, dword [rdi]\n",
+                "    or edx, edx\n",
+                "    jz {label}_done\n",
+                "    mov rax, 202\n",
+                "    xor rsi, rsi\n",
+                "    xor r10, r10\n",
+                "    syscall\n",
+                "    jmp {label}_wait\n",
+                "{label}_done:\n",
+                "    push qword [rdi + 8]\n",
+                "    lea rdi, [rdi + 16 - {size}]\n",
+                "    mov rsi, {size}\n",
+                "    mov rax, 11\n",
+                "    syscall\n",
+                "    pop rax\n",
+                "    ret\n",
+            ), label = label, size = THREAD_STACK_SIZE),
+            // `cpuid` overwrites rbx, which the caller may still hold a value in
+            Self::DetectFeatures => {
+                let mut code = format!(concat!(
+                    "{label}:\n",
+                    "    push rbx\n",
+                    "    mov eax, 1\n",
+                    "    cpuid\n",
+                    "    xor r8, r8\n",
+                ), label = label);
+                for feature in Feature::ALL {
+                    let (register, bit) = feature.cpuid_bit();
+                    code += &format!("    mov r9d, {register}\n");
+                    code += &format!("    shr r9d, {bit}\n");
+                    code += "    and r9, 1\n";
+                    code += &format!("    shl r9, {}\n", feature.flag().trailing_zeros());
+                    code += "    or r8, r9\n";
+                }
+                code += &format!(concat!(
+                    "    mov qword [rel {flags}], r8\n",
+                    "    pop rbx\n",
+                    "    ret\n",
+                    "section .bss\n",
+                    "{flags}: resq 1\n",
+                    "section .text\n",
+                ), flags = FEATURES_LABEL);
+                code
+            },
+            // open with O_WRONLY | O_CREAT | O_TRUNC and mode 0644, a profile that can't be
+            // written is skipped rather than failing the program
+            Self::ProfileDump => {
+                let path: Vec<String> = FILE_NAME.bytes().chain([0]).map(|byte| byte.to_string()).collect();
+                format!(concat!(
+                    "{label}:\n",
+                    "    mov rax, 2\n",
+                    "    lea rdi, [rel {label}_path]\n",
+                    "    mov rsi, 577\n",
+                    "    mov rdx, 420\n",
+                    "    syscall\n",
+                    "    test rax, rax\n",
+                    "    js {label}_end\n",
+                    "    mov rdi, rax\n",
+                    "    mov rax, 1\n",
+                    "    lea rsi, [rel {table}]\n",
+                    "    lea rdx, [rel {table_end}]\n",
+                    "    sub rdx, rsi\n",
+                    "    syscall\n",
+                    "    mov rax, 3\n",
+                    "    syscall\n",
+                    "{label}_end:\n",
+                    "    ret\n",
+                    "section .rodata\n",
+                    "{label}_path: db {path}\n",
+                    "section .text\n",
+                ), label = label, table = TABLE_LABEL, table_end = TABLE_END_LABEL, path = path.join(", "))
+            },
+            // the stack starts with argc, then argv and the environment each ended by a null, then
+            // the auxiliary vector as pairs of type and value ended by a zero type
+            Self::SeedCanary => format!(concat!(
+                "{label}:\n",
+                "    mov rcx, [rsp + 8]\n",
+                "    lea rsi, [rsp + rcx * 8 + 24]\n",
+                "{label}_environment:\n",
+                "    mov rcx, [rsi]\n",
+                "    add rsi, 8\n",
+                "    test rcx, rcx\n",
+                "    jnz {label}_environment\n",
+                "    mov rax, {fallback:#x}\n",
+                "{label}_auxiliary:\n",
+                "    mov rcx, [rsi]\n",
+                "    test rcx, rcx\n",
+                "    jz {label}_store\n",
+                "    add rsi, 16\n",
+                "    cmp rcx, {at_random}\n",
+                "    jne {label}_auxiliary\n",
+                "    mov rcx, [rsi - 8]\n",
+                "    mov rax, [rcx]\n",
+                "    xor al, al\n",
+                "{label}_store:\n",
+                "    mov [rel {canary}], rax\n",
+                "    ret\n",
+                "section .bss\n",
+                "{canary}: resq 1\n",
+                "section .text\n",
+            ), label = label, fallback = STACK_CANARY, at_random = AT_RANDOM, canary = CANARY_LABEL),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use crate::ast::Type;
+
+
+// every value is held in a 64-bit register or stack slot. a variable of a narrower type only ever
+// has its low bytes written, and is sign or zero extended to the full 64 bits when it is read
+
+// the size of a memory operand as wide as the type
+fn size(ty: Type) -> &'static str {
+    match ty.bits() {
+        Some(8) => "byte",
+        Some(16) => "word",
+        Some(32) => "dword",
+        _ => "qword",
+    }
+}
+
+// one of the 64-bit general purpose registers `rax` to `rdx`, cut to the width of the type
+fn register(register: &str, ty: Type) -> String {
+    let name = &register[1..2];
+    match ty.bits() {
+        Some(8) => format!("{name}l"),
+        Some(16) => format!("{name}x"),
+        Some(32) => format!("e{name}x"),
+        _ => register.to_string(),
+    }
+}
+
+// writing a 32-bit register already clears the upper half, so `mov` zero extends those
+fn extension(ty: Type) -> (&'static str, &'static str) {
+    match ty {
+        Type::I8 | Type::I16 => ("movsx", "rax"),
+        Type::I32 => ("movsxd", "rax"),
+        Type::U8 | Type::U16 => ("movzx", "eax"),
+        Type::U32 => ("mov", "eax"),
+        Type::I64 | Type::U64 | Type::Unit => ("mov", "rax"),
+    }
+}
+
+pub fn is_narrow(ty: Type) -> bool {
+    ty.bits().is_some_and(|bits| bits < 64)
+}
+
+// loads the value of the type at `address` into rax
+pub fn load(ty: Type, address: &str) -> String {
+    let (instruction, destination) = extension(ty);
+    format!("    {instruction} {destination}, {} {address}\n", size(ty))
+}
+
+// stores the value of the type in one of `rax` to `rdx` at `address`
+pub fn store(ty: Type, address: &str, source: &str) -> String {
+    format!("    mov {} {address}, {}\n", size(ty), register(source, ty))
+}
+
+// extends the value in rax from the width of the type, leaving what a variable of it would hold
+pub fn truncate(ty: Type) -> String {
+    if !is_narrow(ty) {
+        return String::new();
+    }
+    let (instruction, destination) = extension(ty);
+    format!("    {instruction} {destination}, {}\n", register("rax", ty))
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn narrow_values_are_extended_by_signedness() {
+    assert_eq!(load(Type::I8, "[rsp + 8]"), "    movsx rax, byte [rsp + 8]\n");
+    assert_eq!(load(Type::U16, "[rsp]"), "    movzx eax, word [rsp]\n");
+    assert_eq!(load(Type::I32, "[rsp]"), "    movsxd rax, dword [rsp]\n");
+    assert_eq!(store(Type::U8, "[rsp + 16]", "rdx"), "    mov byte [rsp + 16], dl\n");
+    assert_eq!(store(Type::U32, "[rsp]", "rbx"), "    mov dword [rsp], ebx\n");
+    assert_eq!(truncate(Type::U32), "    mov eax, eax\n");
+    assert_eq!(truncate(Type::I16), "    movsx rax, ax\n");
+    assert_eq!(truncate(Type::U64), "");
+}
@@ -0,0 +1,684 @@
+use std::collections::HashMap;
+
+use crate::{
+    CompileError,
+    ast::{Expr, Intrinsic, Item, Program, Statement, Type},
+    codegen::x86::runtime::PANIC_EXIT_CODE,
+    type_registry::TypeRegistry,
+    visit::{self, Visitor},
+};
+
+
+// bounds on the work done for a single call, past which it is left to run time
+const STEP_LIMIT: usize = 100_000;
+const DEPTH_LIMIT: usize = 64;
+
+
+// the `const fn`s of a program, which calls with constant arguments are evaluated against at
+// compile time, and the values of its top-level `const`s once they are known
+#[derive(Clone, Debug, Default)]
+pub struct ConstFunctions {
+    functions: HashMap<String, (Vec<String>, Option<Type>, Statement)>,
+    constants: HashMap<String, i64>,
+}
+
+impl ConstFunctions {
+    pub fn new(program: &Program) -> Self {
+        Self::collect(program, false)
+    }
+
+    // with `all`, every function rather than only the `const fn`s, to run a whole program
+    fn collect(program: &Program, all: bool) -> Self {
+        let functions = program.0
+            .iter()
+            .filter_map(|item| match item {
+                Item::Function { name, params, return_type, body, attributes: _, is_const } if *is_const || all => Some((
+                    name.clone(),
+                    (params.iter().map(|param| param.name.clone()).collect(), *return_type, body.clone()),
+                )),
+                Item::Function { .. } | Item::Const { .. } => None,
+            })
+            .collect();
+        Self { functions, constants: HashMap::new() }
+    }
+
+    // the type codegen gives the expression, so operations on it are signed or unsigned the same
+    // way. the variables here have no type of their own
+    fn int_type(&self, expr: &Expr) -> Option<Type> {
+        match expr {
+            Expr::FunctionCall { name, args: _ } => self.functions.get(name).and_then(|(_, return_type, _)| *return_type),
+            Expr::Add(a, b) | Expr::Sub(a, b) | Expr::Mul(a, b) | Expr::Div(a, b) | Expr::Mod(a, b)
+                => self.int_type(a).or_else(|| self.int_type(b)),
+            Expr::Neg(value) | Expr::Try(value) => self.int_type(value),
+            Expr::Cast { value: _, ty } => Some(*ty),
+            _ => None,
+        }
+    }
+
+    fn is_unsigned(&self, a: &Expr, b: &Expr) -> bool {
+        [a, b].into_iter().any(|expr| self.int_type(expr).is_some_and(|ty| ty != Type::Unit && !ty.is_signed()))
+    }
+
+    // `None` if the call cannot be evaluated, because it divides by zero, runs for too long or
+    // falls off the end of a function without returning
+    pub fn call(&self, name: &str, args: &[i64]) -> Option<i64> {
+        Evaluator { functions: self, steps: 0, depth: 0 }.call(name, args).ok()
+    }
+
+    // an expression of literals and calls to `const fn`s
+    pub fn evaluate(&self, expr: &Expr) -> Option<i64> {
+        Evaluator { functions: self, steps: 0, depth: 0 }.expr(expr, &mut [HashMap::new()]).ok()
+    }
+}
+
+
+// runs the program from its entry point instead of building it, giving the code it exits with.
+// `None` if it does something only the built binary can, such as using memory, strings or
+// threads, or runs for too long. the tree has to have had its constants inlined
+pub fn run(program: &Program, entry: &str) -> Option<i64> {
+    let functions = ConstFunctions::collect(program, true);
+    let (_, _, body) = functions.functions.get(entry)?;
+    let mut evaluator = Evaluator { functions: &functions, steps: 0, depth: 0 };
+    // an entry point that returns exits with the value, one that reaches its end with 0
+    match evaluator.statement(body, &mut vec![HashMap::new()]) {
+        Ok(Flow::Next) => Some(0),
+        Err(Stop::Return(code) | Stop::Exit(code)) => Some(code),
+        Ok(Flow::Break(_) | Flow::Continue(_)) | Err(Stop::Unsupported) => None,
+    }
+}
+
+
+// fails on the first `static_assert` whose condition is zero or not a constant expression
+pub fn check_static_asserts(program: &Program) -> Result<(), CompileError> {
+    let mut asserts = StaticAsserts { functions: ConstFunctions::new(program), error: None };
+    program.0.iter().for_each(|item| asserts.visit_item(item));
+    asserts.error.map_or(Ok(()), Err)
+}
+
+struct StaticAsserts {
+    functions: ConstFunctions,
+    error: Option<CompileError>,
+}
+
+impl Visitor for StaticAsserts {
+    fn visit_statement(&mut self, statement: &Statement) {
+        if let (Statement::StaticAssert { condition, message, location }, None) = (statement, &self.error) {
+            self.error = match self.functions.evaluate(condition) {
+                Some(0) => Some(CompileError::StaticAssertFailed { message: message.clone(), location: *location }),
+                Some(_) => None,
+                None => Some(CompileError::NotConstant(*location)),
+            };
+        }
+        visit::walk_statement(self, statement)
+    }
+}
+
+
+// replaces `const`s by their values, evaluated at compile time, so they need no stack slot.
+// top-level ones are evaluated in order, each can use those before it, and are then dropped
+pub fn inline_constants(program: Program) -> Result<Program, CompileError> {
+    let mut functions = ConstFunctions::new(&program);
+    for item in &program.0 {
+        if let Item::Const { identifier, value, location } = item {
+            let value = functions.evaluate(value).ok_or(CompileError::NotConstant(*location))?;
+            functions.constants.insert(identifier.clone(), value);
+        }
+    }
+    let globals: HashMap<String, Option<i64>> = functions.constants.iter().map(|(name, value)| (name.clone(), Some(*value))).collect();
+    program.0
+        .into_iter()
+        .filter(|item| matches!(item, Item::Function { .. }))
+        .map(|item| Constants { scopes: vec![globals.clone()], functions: &functions }.item(item))
+        .collect::<Result<_, _>>()
+        .map(Program)
+}
+
+struct Constants<'a> {
+    // `None` for variables, which hide constants of the same name
+    scopes: Vec<HashMap<String, Option<i64>>>,
+    functions: &'a ConstFunctions,
+}
+
+impl Constants<'_> {
+    fn item(&mut self, item: Item) -> Result<Item, CompileError> {
+        match item {
+            Item::Function { name, params, return_type, body, attributes, is_const } => {
+                self.scopes.push(params.iter().map(|param| (param.name.clone(), None)).collect());
+                let body = self.statement(body)?;
+                self.scopes.pop();
+                Ok(Item::Function { name, params, return_type, body, attributes, is_const })
+            },
+            Item::Const { .. } => Ok(item),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<i64> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name)).copied().flatten()
+    }
+
+    fn bind(&mut self, name: String, value: Option<i64>) {
+        self.scopes.last_mut().expect("a scope").insert(name, value);
+    }
+
+    fn scoped(&mut self, statement: Statement) -> Result<Statement, CompileError> {
+        self.scopes.push(HashMap::new());
+        let statement = self.statement(statement);
+        self.scopes.pop();
+        statement
+    }
+
+    fn statement(&mut self, statement: Statement) -> Result<Statement, CompileError> {
+        Ok(match statement {
+            Statement::Const { identifier, value, location } => {
+                let value = self.expr(value)?;
+                let value = self.functions.evaluate(&value).ok_or(CompileError::NotConstant(location))?;
+                self.bind(identifier, Some(value));
+                Statement::Expr(Expr::Block(Vec::new()))
+            },
+            Statement::Let { identifier, ty, value, is_mutable } => {
+                let value = value.map(|value| self.expr(value)).transpose()?;
+                self.bind(identifier.clone(), None);
+                Statement::Let { identifier, ty, value, is_mutable }
+            },
+            Statement::Exit { value } => Statement::Exit { value: self.expr(value)? },
+            Statement::Expr(value) => Statement::Expr(self.expr(value)?),
+            Statement::Return(value) => Statement::Return(value.map(|value| self.expr(value)).transpose()?),
+            Statement::StaticAssert { condition, message, location } =>
+                Statement::StaticAssert { condition: self.expr(condition)?, message, location },
+            statement @ (Statement::Panic { .. } | Statement::Unreachable { .. } | Statement::Break(_) | Statement::Continue(_)) => statement,
+        })
+    }
+
+    fn target(&self, identifier: String) -> Result<String, CompileError> {
+        match self.lookup(&identifier) {
+            Some(_) => Err(CompileError::ChangedImmutableVariable(identifier)),
+            None => Ok(identifier),
+        }
+    }
+
+    fn expr(&mut self, expr: Expr) -> Result<Expr, CompileError> {
+        Ok(match expr {
+            Expr::Identifier(name) => match self.lookup(&name) {
+                Some(value) => Expr::IntegerLiteral(value.to_string()),
+                None => Expr::Identifier(name),
+            },
+            Expr::IntegerLiteral(_) | Expr::StringLiteral(_) => expr,
+            Expr::Array(elements) => Expr::Array(elements.into_iter().map(|element| self.expr(element)).collect::<Result<_, _>>()?),
+            Expr::Index { identifier, index, location } => Expr::Index { identifier, index: self.boxed(*index)?, location },
+
+            Expr::Add(a, b) => Expr::Add(self.boxed(*a)?, self.boxed(*b)?),
+            Expr::Sub(a, b) => Expr::Sub(self.boxed(*a)?, self.boxed(*b)?),
+            Expr::Mul(a, b) => Expr::Mul(self.boxed(*a)?, self.boxed(*b)?),
+            Expr::Div(a, b) => Expr::Div(self.boxed(*a)?, self.boxed(*b)?),
+            Expr::Mod(a, b) => Expr::Mod(self.boxed(*a)?, self.boxed(*b)?),
+            Expr::Equality(a, b) => Expr::Equality(self.boxed(*a)?, self.boxed(*b)?),
+            Expr::NonEquality(a, b) => Expr::NonEquality(self.boxed(*a)?, self.boxed(*b)?),
+            Expr::Less(a, b) => Expr::Less(self.boxed(*a)?, self.boxed(*b)?),
+            Expr::LessEq(a, b) => Expr::LessEq(self.boxed(*a)?, self.boxed(*b)?),
+            Expr::Greater(a, b) => Expr::Greater(self.boxed(*a)?, self.boxed(*b)?),
+            Expr::GreaterEq(a, b) => Expr::GreaterEq(self.boxed(*a)?, self.boxed(*b)?),
+
+            Expr::Assign { identifier, value } => Expr::Assign { identifier: self.target(identifier)?, value: self.boxed(*value)? },
+            Expr::AddAssign { identifier, value } => Expr::AddAssign { identifier: self.target(identifier)?, value: self.boxed(*value)? },
+            Expr::SubAssign { identifier, value } => Expr::SubAssign { identifier: self.target(identifier)?, value: self.boxed(*value)? },
+            Expr::MulAssign { identifier, value } => Expr::MulAssign { identifier: self.target(identifier)?, value: self.boxed(*value)? },
+            Expr::DivAssign { identifier, value } => Expr::DivAssign { identifier: self.target(identifier)?, value: self.boxed(*value)? },
+            Expr::ModAssign { identifier, value } => Expr::ModAssign { identifier: self.target(identifier)?, value: self.boxed(*value)? },
+            Expr::IndexAssign { identifier, index, value, location } =>
+                Expr::IndexAssign { identifier: self.target(identifier)?, index: self.boxed(*index)?, value: self.boxed(*value)?, location },
+
+            Expr::FunctionCall { name, args } => Expr::FunctionCall {
+                name,
+                args: args.into_iter().map(|arg| self.expr(arg)).collect::<Result<_, _>>()?,
+            },
+            // a function name, which no constant may replace
+            Expr::Intrinsic { intrinsic: Intrinsic::Spawn, args: _ } => expr,
+            Expr::Intrinsic { intrinsic: Intrinsic::Swap, args } => Expr::Intrinsic {
+                intrinsic: Intrinsic::Swap,
+                args: args.into_iter().map(|arg| match arg {
+                    Expr::Identifier(name) => self.target(name).map(Expr::Identifier),
+                    arg => Ok(arg),
+                }).collect::<Result<_, _>>()?,
+            },
+            Expr::Intrinsic { intrinsic, args } => Expr::Intrinsic {
+                intrinsic,
+                args: args.into_iter().map(|arg| self.expr(arg)).collect::<Result<_, _>>()?,
+            },
+            Expr::Try(value) => Expr::Try(self.boxed(*value)?),
+            Expr::Neg(value) => Expr::Neg(self.boxed(*value)?),
+            Expr::Cast { value, ty } => Expr::Cast { value: self.boxed(*value)?, ty },
+
+            Expr::Block(stmts) => {
+                self.scopes.push(HashMap::new());
+                let mut block = Vec::new();
+                for stmt in stmts {
+                    // nothing is left of a `const` in its block
+                    let is_const = matches!(stmt, Statement::Const { .. });
+                    let stmt = self.statement(stmt)?;
+                    if !is_const {
+                        block.push(stmt);
+                    }
+                }
+                self.scopes.pop();
+                Expr::Block(block)
+            },
+            Expr::If { check, body, els } => Expr::If {
+                check: self.boxed(*check)?,
+                body: Box::new(self.scoped(*body)?),
+                els: els.map(|els| self.scoped(*els).map(Box::new)).transpose()?,
+            },
+            // loop bodies share the enclosing frame in codegen, so they don't get a scope here either
+            Expr::Loop { label, body } => Expr::Loop { label, body: Box::new(self.statement(*body)?) },
+            Expr::While { label, check, body } => Expr::While {
+                label,
+                check: self.boxed(*check)?,
+                body: Box::new(self.statement(*body)?),
+            },
+            Expr::Unsafe(body) => Expr::Unsafe(self.boxed(*body)?),
+        })
+    }
+
+    fn boxed(&mut self, expr: Expr) -> Result<Box<Expr>, CompileError> {
+        self.expr(expr).map(Box::new)
+    }
+}
+
+
+// `break` and `continue` carry the label of the loop they are for, if they have one
+enum Flow {
+    Next,
+    Break(Option<String>),
+    Continue(Option<String>),
+}
+
+impl Flow {
+    // what a loop with this label does once its body ended in this flow, `None` if it goes on
+    // looping. a `break` or `continue` for an outer loop is passed on to it
+    fn after_loop(self, label: &Option<String>) -> Option<Flow> {
+        match &self {
+            Flow::Break(Some(target)) | Flow::Continue(Some(target)) if label.as_ref() != Some(target) => Some(self),
+            Flow::Break(_) => Some(Flow::Next),
+            Flow::Next | Flow::Continue(_) => None,
+        }
+    }
+}
+
+enum Stop {
+    Unsupported,
+    Return(i64),
+    // the program exits, which a `const fn` never does as `check` rejects it
+    Exit(i64),
+}
+
+struct Evaluator<'a> {
+    functions: &'a ConstFunctions,
+    steps: usize,
+    depth: usize,
+}
+
+impl Evaluator<'_> {
+    fn tick(&mut self) -> Result<(), Stop> {
+        self.steps += 1;
+        match self.steps > STEP_LIMIT {
+            true => Err(Stop::Unsupported),
+            false => Ok(()),
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[i64]) -> Result<i64, Stop> {
+        self.tick()?;
+        let (params, return_type, body) = self.functions.functions.get(name).ok_or(Stop::Unsupported)?;
+        if params.len() != args.len() || self.depth == DEPTH_LIMIT {
+            return Err(Stop::Unsupported);
+        }
+        let mut scopes = vec![params.iter().cloned().zip(args.iter().copied()).collect()];
+        self.depth += 1;
+        let result = self.statement(body, &mut scopes);
+        self.depth -= 1;
+        // values are `i64`s here, so a `u64` one that doesn't fit is left to run time
+        match (result, return_type) {
+            (Err(Stop::Return(value)), Some(Type::U64)) if value < 0 => Err(Stop::Unsupported),
+            (Err(Stop::Return(value)), return_type) => Ok(return_type.map_or(value, |ty| ty.wrap(value))),
+            (Err(stop @ Stop::Exit(_)), _) => Err(stop),
+            (Ok(_) | Err(Stop::Unsupported), _) => Err(Stop::Unsupported),
+        }
+    }
+
+    fn scoped(&mut self, statement: &Statement, scopes: &mut Vec<HashMap<String, i64>>) -> Result<Flow, Stop> {
+        scopes.push(HashMap::new());
+        let flow = self.statement(statement, scopes);
+        scopes.pop();
+        flow
+    }
+
+    fn statement(&mut self, statement: &Statement, scopes: &mut Vec<HashMap<String, i64>>) -> Result<Flow, Stop> {
+        match statement {
+            // the variables here are all `i64`s, so those of another type are left to run time
+            Statement::Let { ty: Some(_), .. } => Err(Stop::Unsupported),
+            Statement::Let { identifier, ty: None, value, is_mutable: _ } => {
+                // like codegen, a variable without a value starts out as zero
+                let value = value.as_ref().map_or(Ok(0), |value| self.expr(value, scopes))?;
+                scopes.last_mut().expect("a scope").insert(identifier.clone(), value);
+                Ok(Flow::Next)
+            },
+            Statement::Const { identifier, value, location: _ } => {
+                let value = self.expr(value, scopes)?;
+                scopes.last_mut().expect("a scope").insert(identifier.clone(), value);
+                Ok(Flow::Next)
+            },
+            Statement::Return(Some(value)) => Err(Stop::Return(self.expr(value, scopes)?)),
+            // like codegen, a function that returns no value gives zero
+            Statement::Return(None) => Err(Stop::Return(0)),
+            Statement::Break(label) => Ok(Flow::Break(label.clone())),
+            Statement::Continue(label) => Ok(Flow::Continue(label.clone())),
+            Statement::StaticAssert { .. } => Ok(Flow::Next),
+            Statement::Exit { value } => Err(Stop::Exit(self.expr(value, scopes)?)),
+            Statement::Panic { .. } | Statement::Unreachable { .. } => Err(Stop::Exit(PANIC_EXIT_CODE.into())),
+
+            Statement::Expr(Expr::Block(stmts)) => {
+                scopes.push(HashMap::new());
+                let flow = stmts.iter().try_fold(Flow::Next, |flow, stmt| match flow {
+                    Flow::Next => self.statement(stmt, scopes),
+                    flow => Ok(flow),
+                });
+                scopes.pop();
+                flow
+            },
+            Statement::Expr(Expr::If { check, body, els }) => match (self.expr(check, scopes)?, els) {
+                (0, Some(els)) => self.scoped(els, scopes),
+                (0, None) => Ok(Flow::Next),
+                _ => self.scoped(body, scopes),
+            },
+            Statement::Expr(Expr::Loop { label, body }) => loop {
+                self.tick()?;
+                if let Some(flow) = self.scoped(body, scopes)?.after_loop(label) {
+                    return Ok(flow);
+                }
+            },
+            Statement::Expr(Expr::While { label, check, body }) => {
+                while self.expr(check, scopes)? != 0 {
+                    self.tick()?;
+                    if let Some(flow) = self.scoped(body, scopes)?.after_loop(label) {
+                        return Ok(flow);
+                    }
+                }
+                Ok(Flow::Next)
+            },
+
+            Statement::Expr(Expr::Assign { identifier, value }) => self.assign(identifier, value, scopes, |_, b| Some(b)),
+            Statement::Expr(Expr::AddAssign { identifier, value }) => self.assign(identifier, value, scopes, |a, b| Some(a.wrapping_add(b))),
+            Statement::Expr(Expr::SubAssign { identifier, value }) => self.assign(identifier, value, scopes, |a, b| Some(a.wrapping_sub(b))),
+            Statement::Expr(Expr::MulAssign { identifier, value }) => self.assign(identifier, value, scopes, |a, b| Some(a.wrapping_mul(b))),
+            Statement::Expr(Expr::DivAssign { identifier, value }) if self.functions.is_unsigned(&Expr::Identifier(identifier.clone()), value) =>
+                self.assign(identifier, value, scopes, |a, b| (a as u64).checked_div(b as u64).map(|q| q as i64)),
+            Statement::Expr(Expr::ModAssign { identifier, value }) if self.functions.is_unsigned(&Expr::Identifier(identifier.clone()), value) =>
+                self.assign(identifier, value, scopes, |a, b| (a as u64).checked_rem(b as u64).map(|r| r as i64)),
+            Statement::Expr(Expr::DivAssign { identifier, value }) => self.assign(identifier, value, scopes, i64::checked_div),
+            Statement::Expr(Expr::ModAssign { identifier, value }) => self.assign(identifier, value, scopes, i64::checked_rem),
+            Statement::Expr(value) => self.expr(value, scopes).map(|_| Flow::Next),
+        }
+    }
+
+    fn assign(
+        &mut self,
+        identifier: &str,
+        value: &Expr,
+        scopes: &mut [HashMap<String, i64>],
+        operator: fn(i64, i64) -> Option<i64>,
+    ) -> Result<Flow, Stop> {
+        let value = self.expr(value, scopes)?;
+        let variable = scopes.iter_mut().rev().find_map(|scope| scope.get_mut(identifier)).ok_or(Stop::Unsupported)?;
+        *variable = operator(*variable, value).ok_or(Stop::Unsupported)?;
+        Ok(Flow::Next)
+    }
+
+    // arithmetic wraps like the 64-bit instructions it stands in for, and dividing by zero or
+    // `i64::MIN / -1`, which fault at run time, is left to run time
+    fn expr(&mut self, expr: &Expr, scopes: &mut [HashMap<String, i64>]) -> Result<i64, Stop> {
+        let functions = self.functions;
+        let mut binary = |a: &Expr, b: &Expr, operator: fn(i64, i64) -> Option<i64>| {
+            let a = self.expr(a, scopes)?;
+            let b = self.expr(b, scopes)?;
+            operator(a, b).ok_or(Stop::Unsupported)
+        };
+        match expr {
+            Expr::IntegerLiteral(value) => value.parse().map_err(|_| Stop::Unsupported),
+            Expr::Identifier(name) => scopes.iter().rev()
+                .find_map(|scope| scope.get(name))
+                .or_else(|| self.functions.constants.get(name))
+                .copied()
+                .ok_or(Stop::Unsupported),
+
+            Expr::Add(a, b) => binary(a, b, |a, b| Some(a.wrapping_add(b))),
+            Expr::Sub(a, b) => binary(a, b, |a, b| Some(a.wrapping_sub(b))),
+            Expr::Mul(a, b) => binary(a, b, |a, b| Some(a.wrapping_mul(b))),
+            // an unsigned operation works on the same 64 bits read as a `u64`
+            Expr::Div(a, b) if functions.is_unsigned(a, b) => binary(a, b, |a, b| (a as u64).checked_div(b as u64).map(|q| q as i64)),
+            Expr::Mod(a, b) if functions.is_unsigned(a, b) => binary(a, b, |a, b| (a as u64).checked_rem(b as u64).map(|r| r as i64)),
+            Expr::Less(a, b) if functions.is_unsigned(a, b) => binary(a, b, |a, b| Some(((a as u64) < b as u64) as i64)),
+            Expr::LessEq(a, b) if functions.is_unsigned(a, b) => binary(a, b, |a, b| Some((a as u64 <= b as u64) as i64)),
+            Expr::Greater(a, b) if functions.is_unsigned(a, b) => binary(a, b, |a, b| Some((a as u64 > b as u64) as i64)),
+            Expr::GreaterEq(a, b) if functions.is_unsigned(a, b) => binary(a, b, |a, b| Some((a as u64 >= b as u64) as i64)),
+            Expr::Div(a, b) => binary(a, b, i64::checked_div),
+            Expr::Mod(a, b) => binary(a, b, i64::checked_rem),
+            Expr::Equality(a, b) => binary(a, b, |a, b| Some((a == b) as i64)),
+            Expr::NonEquality(a, b) => binary(a, b, |a, b| Some((a != b) as i64)),
+            Expr::Less(a, b) => binary(a, b, |a, b| Some((a < b) as i64)),
+            Expr::LessEq(a, b) => binary(a, b, |a, b| Some((a <= b) as i64)),
+            Expr::Greater(a, b) => binary(a, b, |a, b| Some((a > b) as i64)),
+            Expr::GreaterEq(a, b) => binary(a, b, |a, b| Some((a >= b) as i64)),
+
+            Expr::FunctionCall { name, args } => {
+                let args = args.iter().map(|arg| self.expr(arg, scopes)).collect::<Result<Vec<_>, _>>()?;
+                self.call(name, &args)
+            },
+            Expr::Neg(value) => Ok(self.expr(value, scopes)?.wrapping_neg()),
+            Expr::Cast { value, ty } => match self.expr(value, scopes)? {
+                value if *ty == Type::U64 && value < 0 => Err(Stop::Unsupported),
+                value => Ok(ty.wrap(value)),
+            },
+            Expr::Try(value) => match self.expr(value, scopes)? {
+                value if value < 0 => Err(Stop::Return(value)),
+                value => Ok(value),
+            },
+
+            // these produce no value
+            Expr::Assign { .. } | Expr::AddAssign { .. } | Expr::SubAssign { .. } | Expr::MulAssign { .. }
+                | Expr::DivAssign { .. } | Expr::ModAssign { .. }
+                | Expr::Block(_) | Expr::If { .. } | Expr::Loop { .. } | Expr::While { .. } => Err(Stop::Unsupported),
+            Expr::Intrinsic { intrinsic: Intrinsic::Min, args } if functions.is_unsigned(&args[0], &args[1]) =>
+                binary(&args[0], &args[1], |a, b| Some((a as u64).min(b as u64) as i64)),
+            Expr::Intrinsic { intrinsic: Intrinsic::Max, args } if functions.is_unsigned(&args[0], &args[1]) =>
+                binary(&args[0], &args[1], |a, b| Some((a as u64).max(b as u64) as i64)),
+            Expr::Intrinsic { intrinsic: Intrinsic::Min, args } => binary(&args[0], &args[1], |a, b| Some(a.min(b))),
+            Expr::Intrinsic { intrinsic: Intrinsic::Max, args } => binary(&args[0], &args[1], |a, b| Some(a.max(b))),
+            Expr::Intrinsic { intrinsic: Intrinsic::Abs, args } => Ok(self.expr(&args[0], scopes)?.wrapping_abs()),
+            Expr::Intrinsic { intrinsic: Intrinsic::Popcount, args } => Ok(self.expr(&args[0], scopes)?.count_ones().into()),
+            // the address of a string isn't known until link time
+            Expr::Intrinsic { .. } | Expr::Unsafe(_) | Expr::StringLiteral(_) => Err(Stop::Unsupported),
+            Expr::Array(_) | Expr::Index { .. } | Expr::IndexAssign { .. } => Err(Stop::Unsupported),
+        }
+    }
+}
+
+
+// the reason the body of a `const fn` could not be evaluated at compile time, if any: it may
+// only compute with its parameters and call other `const fn`s
+pub fn check(body: &Statement, registry: &TypeRegistry) -> Result<(), String> {
+    let mut checker = Checker { registry, reason: None };
+    checker.visit_statement(body);
+    checker.reason.map_or(Ok(()), Err)
+}
+
+struct Checker<'a> {
+    registry: &'a TypeRegistry,
+    reason: Option<String>,
+}
+
+impl Visitor for Checker<'_> {
+    fn visit_statement(&mut self, statement: &Statement) {
+        let reason = match statement {
+            Statement::Exit { .. } => "it calls `exit`",
+            Statement::Panic { .. } | Statement::Unreachable { .. } => "it can panic",
+            _ => return visit::walk_statement(self, statement),
+        };
+        self.reason.get_or_insert(String::from(reason));
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Intrinsic { intrinsic, args: _ } if !intrinsic.is_pure() => {
+                self.reason.get_or_insert(format!("it uses `{}`", intrinsic.name()));
+            },
+            Expr::Unsafe(_) => {
+                self.reason.get_or_insert(String::from("it contains an `unsafe` block"));
+            },
+            Expr::StringLiteral(_) => {
+                self.reason.get_or_insert(String::from("it uses a string literal, whose address is only known once linked"));
+            },
+            Expr::FunctionCall { name, args: _ } if !self.registry.get_function(name).is_some_and(|function| function.is_const) => {
+                self.reason.get_or_insert(format!("it calls `{name}`, which is not a `const fn`"));
+            },
+            _ => visit::walk_expr(self, expr),
+        }
+    }
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[cfg(test)]
+fn program(source: &str) -> Program {
+    use crate::{parser::Parser, tokenize::Tokenize};
+    Parser::new(source.tokenize()).parse().expect("source parses")
+}
+
+#[test]
+fn const_functions_are_evaluated() {
+    let functions = ConstFunctions::new(&program("
+        const fn square(x) return x * x;
+        const fn sum_of_squares(n) {
+            let mut total = 0;
+            let mut i = 1;
+            while (i <= n) {
+                total += square(i);
+                i += 1;
+            }
+            return total;
+        }
+        const fn checked(x) return x? + 1;
+        const fn first_pair(n) {
+            let mut found = 0;
+            'outer: for i in 1..n {
+                for j in 1..n {
+                    if (i * j == 12) {
+                        found = i * 10 + j;
+                        break 'outer;
+                    }
+                    if (j == i) continue 'outer;
+                }
+            }
+            return found;
+        }
+        const fn spin() loop {}
+        fn runtime() return 1;
+    "));
+    assert_eq!(functions.call("sum_of_squares", &[3]), Some(14));
+    assert_eq!(functions.call("checked", &[-2]), Some(-2));
+    assert_eq!(functions.call("checked", &[2]), Some(3));
+    assert_eq!(functions.call("first_pair", &[10]), Some(43));
+    assert_eq!(functions.call("spin", &[]), None);
+    assert_eq!(functions.call("runtime", &[]), None);
+    assert_eq!(functions.call("square", &[]), None);
+}
+
+#[test]
+fn unsigned_operands_are_compared_and_divided_unsigned() {
+    let functions = ConstFunctions::new(&program("
+        const fn lowest(x) return min(x as u64, -1);
+        const fn highest(x) return max(x as u64, -1);
+        const fn halve(x) return -2 / (x as u32);
+        const fn below(x) return x as u8 < -1;
+    "));
+    assert_eq!(functions.call("lowest", &[3]), Some(3));
+    assert_eq!(functions.call("highest", &[3]), Some(-1));
+    assert_eq!(functions.call("halve", &[2]), Some(i64::MAX));
+    assert_eq!(functions.call("below", &[3]), Some(1));
+}
+
+#[test]
+fn const_functions_must_be_pure() {
+    let program = program("const fn a() return b(); fn b() return 1; const fn c() return volatile_load(0); const fn d() return a() + 1;");
+    let registry = TypeRegistry::from(&program);
+    let reasons: Vec<_> = program.0
+        .iter()
+        .map(|item| match item {
+            Item::Function { body, .. } => check(body, &registry),
+            Item::Const { .. } => panic!("a function"),
+        })
+        .collect();
+    assert_eq!(reasons, vec![
+        Err(String::from("it calls `b`, which is not a `const fn`")),
+        Ok(()),
+        Err(String::from("it uses `volatile_load`")),
+        Ok(()),
+    ]);
+}
+
+#[test]
+fn static_asserts_are_checked() {
+    let check = |source: &str| check_static_asserts(&program(source));
+    assert!(check("const fn two() return 2; fn f() { static_assert(two() * 3 == 6, \"six\"); }").is_ok());
+    assert!(matches!(
+        check("fn f() { if (1) static_assert(1 < 0, \"ordered\"); }"),
+        Err(CompileError::StaticAssertFailed { message, location }) if message == "ordered" && location.column == 17,
+    ));
+    assert!(matches!(check("fn f(x) static_assert(x, \"constant\");"), Err(CompileError::NotConstant(_))));
+}
+
+#[test]
+fn constants_are_inlined() {
+    let inline = |source: &str| inline_constants(program(source)).map(|program| match &program.0[1] {
+        Item::Function { body, .. } => body.to_string(),
+        Item::Const { .. } => panic!("a function"),
+    });
+    assert_eq!(
+        inline("const fn kib(n) return n * 1024; fn f(a) { const size = kib(4); const half = size / 2; { let size = a; exit(size + half); } exit(size); }").unwrap(),
+        "{ { let size = a; exit(size + 2048); } exit(4096); }",
+    );
+    assert!(matches!(inline("fn g() {} fn f(a) { const size = a; exit(size); }"), Err(CompileError::NotConstant(_))));
+    assert!(matches!(inline("fn g() {} fn f() { const size = 1; size += 1; exit(size); }"), Err(CompileError::ChangedImmutableVariable(_))));
+}
+
+#[test]
+fn top_level_constants_are_inlined() {
+    let inline = |source: &str| inline_constants(program(source)).map(|program| program.0
+        .iter()
+        .map(|item| match item {
+            Item::Function { body, .. } => body.to_string(),
+            Item::Const { .. } => panic!("constants are dropped"),
+        })
+        .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        inline("const A = 3; const fn f(x) return x * A; const B = f(A) + 1; fn g(A) exit(A + B); fn h() exit(A + B);").unwrap(),
+        vec!["return x * 3;", "exit(A + 10);", "exit(3 + 10);"],
+    );
+    assert!(matches!(inline("const A = B; const B = 1;"), Err(CompileError::NotConstant(_))));
+    assert!(matches!(inline("const A = 1; fn f() { A = 2; }"), Err(CompileError::ChangedImmutableVariable(_))));
+}
+
+#[test]
+fn programs_run_without_being_built() {
+    let run = |source: &str| run(&inline_constants(program(source)).expect("constants are valid"), "_start");
+    assert_eq!(run("const N = 5; fn sum(n) { let mut total = 0; let mut i = n; while (i > 0) { total += i; i -= 1; } return total; } fn _start() exit(sum(N));"), Some(15));
+    assert_eq!(run("fn _start() { if (1) return 3; exit(4); }"), Some(3));
+    assert_eq!(run("fn _start() { let x = 1; }"), Some(0));
+    assert_eq!(run("fn _start() panic(\"no\");"), Some(101));
+    assert_eq!(run("fn _start() exit(volatile_load(0));"), None);
+    assert_eq!(run("fn main() exit(1);"), None);
+}
@@ -1,8 +1,11 @@
-use std::collections::HashMap;
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, sync::Arc};
 
 use crate::{
-    codegen::CodegenError,
+    ast::{self, InlineHint},
+    cfg::VariableId,
+    codegen::{CodegenError, x86::{bits::Bits, cpu::{Cpu, Feature}, format::AsmFormat, runtime::{Routine, CANARY_LABEL}, width}},
     iter::Reversed,
+    liveness::Interference,
     type_registry::TypeRegistry,
 };
 
@@ -11,6 +14,9 @@ use crate::{
 pub struct VariableMeta {
     stack_frame_offset: u64,
     is_mutable: bool,
+    ty: Option<ast::Type>,
+    // the number of elements of an array, which take a slot each from the variable's upwards
+    length: Option<u64>,
 }
 
 
@@ -18,6 +24,7 @@ pub struct VariableMeta {
 pub struct VariableFrame {
     stack_size: u64,
     variables: HashMap<String, VariableMeta>,
+    slots: Vec<(u64, VariableId)>,
 }
 
 impl VariableFrame {
@@ -25,8 +32,21 @@ impl VariableFrame {
         Self {
             stack_size: size,
             variables: HashMap::new(),
+            slots: Vec::new(),
         }
     }
+
+    // the lowest slot in this frame whose every occupant can share with a new variable
+    fn reusable_slot(&self, can_share: impl Fn(VariableId) -> bool) -> Option<u64> {
+        let mut offsets: Vec<u64> = self.slots.iter().map(|(offset, _)| *offset).collect();
+        offsets.sort();
+        offsets.dedup();
+        offsets.into_iter().find(|offset| self.slots
+            .iter()
+            .filter(|(other, _)| other == offset)
+            .all(|(_, id)| can_share(*id))
+        )
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -54,33 +74,45 @@ impl VariableStack {
         self.0.get_mut(last_index)
     }
 
-    pub fn declare_variable(&mut self, name: String, is_mutable: bool) {
+    pub fn declare_variable(&mut self, name: String, is_mutable: bool, ty: Option<ast::Type>) {
+        match self.peek() {
+            Some(frame) => Some(frame),
+            None => Some(self.push(VariableFrame::default())),
+        }.map(|frame|
+            frame.variables.insert(
+                name,
+                VariableMeta { stack_frame_offset: frame.stack_size, is_mutable, ty, length: None },
+            )
+        );
+    }
+
+    pub fn declare_variable_at(&mut self, name: String, is_mutable: bool, ty: Option<ast::Type>, offset: u64) {
         match self.peek() {
             Some(frame) => Some(frame),
             None => Some(self.push(VariableFrame::default())),
         }.map(|frame|
             frame.variables.insert(
                 name,
-                VariableMeta { stack_frame_offset: frame.stack_size, is_mutable },
+                VariableMeta { stack_frame_offset: offset, is_mutable, ty, length: None },
             )
         );
     }
 
-    pub fn declare_variable_at(&mut self, name: String, is_mutable: bool, offset: u64) {
+    pub fn declare_array_at(&mut self, name: String, is_mutable: bool, length: u64, offset: u64) {
         match self.peek() {
             Some(frame) => Some(frame),
             None => Some(self.push(VariableFrame::default())),
         }.map(|frame|
             frame.variables.insert(
                 name,
-                VariableMeta { stack_frame_offset: offset, is_mutable },
+                VariableMeta { stack_frame_offset: offset, is_mutable, ty: None, length: Some(length) },
             )
         );
     }
 
-    pub fn get_offset(&mut self, name: &String) -> Option<u64> {
+    pub fn get_offset(&mut self, name: &String, base: usize) -> Option<u64> {
         let mut offset = 0;
-        for frame in self.0.reversed() {
+        for frame in self.0.reversed().into_iter().take(self.0.len() - base) {
             match frame.variables.get(name) {
                 Some(meta) => return Some(frame.stack_size - meta.stack_frame_offset + offset),
                 None => offset += frame.stack_size,
@@ -89,14 +121,32 @@ impl VariableStack {
         None
     }
 
-    pub fn is_mutable(&mut self, name: &String) -> Option<bool> {
-        for frame in self.0.reversed() {
+    pub fn size_above(&self, index: usize) -> u64 {
+        self.0.iter().skip(index + 1).map(|frame| frame.stack_size).sum()
+    }
+
+    pub fn size(&self) -> u64 {
+        self.0.iter().map(|frame| frame.stack_size).sum()
+    }
+
+    pub fn is_mutable(&mut self, name: &String, base: usize) -> Option<bool> {
+        for frame in self.0.reversed().into_iter().take(self.0.len() - base) {
             if let Some(meta) = frame.variables.get(name) {
                 return Some(meta.is_mutable)
             }
         };
         None
     }
+
+    // the type a variable was declared with, `None` for those without one, which are `i64`
+    pub fn get_type(&self, name: &str, base: usize) -> Option<ast::Type> {
+        self.0.iter().skip(base).rev().find_map(|frame| frame.variables.get(name)).and_then(|meta| meta.ty)
+    }
+
+    // the number of elements of an array, `None` for variables that aren't one
+    pub fn get_length(&self, name: &str, base: usize) -> Option<u64> {
+        self.0.iter().skip(base).rev().find_map(|frame| frame.variables.get(name)).and_then(|meta| meta.length)
+    }
 }
 
 
@@ -104,35 +154,229 @@ impl VariableStack {
 pub struct LabelFrame {
     pub start: String,
     pub end: String,
+    // the label the loop was given in source, if any
+    pub name: Option<String>,
 }
 
 impl From<(String, String)> for LabelFrame {
     fn from((start, end): (String, String)) -> Self {
-        Self { start, end }
+        Self { start, end, name: None }
     }
 }
 
 
+#[derive(Clone, Debug)]
+pub struct FunctionFrame {
+    pub name: String,
+    pub end: String,
+    params_frame: usize,
+    label_depth: usize,
+    next_variable: VariableId,
+    // the `unsafe` blocks entered in this function, an inlined function starts again from none
+    unsafe_depth: usize,
+    // whether a stack canary sits between the return address and the function's variables
+    canary: bool,
+    // the label of the function's profile counters, when it is profiled
+    profile: Option<String>,
+}
+
+
 #[derive(Clone, Debug)]
 pub struct Context {
     stack_size: u64,
     variables: VariableStack,
-    label_counts: HashMap<String, u64>,
-    label_stack: Vec<LabelFrame>,
-    type_registry: TypeRegistry,
+    label_counts: BTreeMap<String, u64>,
+    // each loop's labels with the size of the stack on entering it
+    label_stack: Vec<(LabelFrame, u64)>,
+    function_frames: Vec<FunctionFrame>,
+    // shared rather than owned, so several contexts can generate code from one registry
+    type_registry: Arc<TypeRegistry>,
+    source_name: String,
+    test_harness: bool,
+    keep_all: bool,
+    asm_format: AsmFormat,
+    checked: bool,
+    entry: Option<String>,
+    bits: Bits,
+    features: BTreeSet<Feature>,
+    detect_features: bool,
+    verify_pic: bool,
+    stack_protector: bool,
+    profile: bool,
+    // each profiled function with the label of its counters
+    profiled: Vec<(String, String)>,
+    data: Vec<(String, Vec<u8>)>,
+    runtime: BTreeSet<Routine>,
+    interference: HashMap<String, Interference>,
 }
 
 impl Context {
-    pub fn new(type_registry: TypeRegistry) -> Self {
+    pub fn new(type_registry: impl Into<Arc<TypeRegistry>>) -> Self {
         Self {
             stack_size: 0,
             variables: VariableStack::new(),
-            label_counts: HashMap::new(),
+            label_counts: BTreeMap::new(),
             label_stack: Vec::new(),
-            type_registry,
+            function_frames: Vec::new(),
+            type_registry: type_registry.into(),
+            source_name: String::from("<unknown>"),
+            test_harness: false,
+            keep_all: false,
+            asm_format: AsmFormat::default(),
+            checked: true,
+            entry: None,
+            bits: Bits::default(),
+            features: Cpu::default().features(),
+            detect_features: false,
+            verify_pic: false,
+            stack_protector: false,
+            profile: false,
+            profiled: Vec::new(),
+            data: Vec::new(),
+            runtime: BTreeSet::new(),
+            interference: HashMap::new(),
         }
     }
 
+    pub fn with_source_name(mut self, source_name: impl Into<String>) -> Self {
+        self.source_name = source_name.into();
+        self
+    }
+
+    pub fn source_name(&self) -> &str {
+        &self.source_name
+    }
+
+    pub fn with_test_harness(mut self, test_harness: bool) -> Self {
+        self.test_harness = test_harness;
+        self
+    }
+
+    pub fn test_harness(&self) -> bool {
+        self.test_harness
+    }
+
+    pub fn with_keep_all(mut self, keep_all: bool) -> Self {
+        self.keep_all = keep_all;
+        self
+    }
+
+    pub fn keep_all(&self) -> bool {
+        self.keep_all
+    }
+
+    pub fn with_asm_format(mut self, asm_format: AsmFormat) -> Self {
+        self.asm_format = asm_format;
+        self
+    }
+
+    pub fn asm_format(&self) -> &AsmFormat {
+        &self.asm_format
+    }
+
+    pub fn with_checked(mut self, checked: bool) -> Self {
+        self.checked = checked;
+        self
+    }
+
+    pub fn checked(&self) -> bool {
+        self.checked
+    }
+
+    pub fn with_entry(mut self, entry: Option<String>) -> Self {
+        self.entry = entry;
+        self
+    }
+
+    pub fn entry(&self) -> Option<&str> {
+        self.entry.as_deref()
+    }
+
+    pub fn with_bits(mut self, bits: Bits) -> Self {
+        self.bits = bits;
+        self
+    }
+
+    pub fn with_features(mut self, features: BTreeSet<Feature>) -> Self {
+        self.features = features;
+        self
+    }
+
+    pub fn has_feature(&self, feature: Feature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    pub fn with_detect_features(mut self, detect_features: bool) -> Self {
+        self.detect_features = detect_features;
+        self
+    }
+
+    pub fn detect_features(&self) -> bool {
+        self.detect_features
+    }
+
+    pub fn with_verify_pic(mut self, verify_pic: bool) -> Self {
+        self.verify_pic = verify_pic;
+        self
+    }
+
+    pub fn verify_pic(&self) -> bool {
+        self.verify_pic
+    }
+
+    pub fn with_stack_protector(mut self, stack_protector: bool) -> Self {
+        self.stack_protector = stack_protector;
+        self
+    }
+
+    pub fn with_profile(mut self, profile: bool) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn stack_protector(&self) -> bool {
+        self.stack_protector
+    }
+
+    pub fn profile(&self) -> bool {
+        self.profile
+    }
+
+    pub fn profiled(&self) -> &[(String, String)] {
+        &self.profiled
+    }
+
+    // a `#[bits(n)]` attribute overrides the mode chosen for the whole file
+    pub fn function_bits(&self, name: &str) -> Result<Bits, CodegenError> {
+        let attributes = self.type_registry.get_function(name).map(|function| function.attributes.as_slice()).unwrap_or_default();
+        match Bits::from_attributes(attributes) {
+            Ok(bits) => Ok(bits.unwrap_or(self.bits)),
+            Err(bits) => Err(CodegenError::UnsupportedBits {
+                function: name.to_string(),
+                bits,
+                reason: String::from("only 16, 32 and 64 bits are supported"),
+            }),
+        }
+    }
+
+    // the function being emitted, which functions inlined into it are compiled as part of
+    pub fn current_function(&self) -> Option<&str> {
+        self.function_frames.first().map(|frame| frame.name.as_str())
+    }
+
+    // the function a statement belongs to, which is the inlined one while expanding a call
+    pub fn innermost_function(&self) -> Option<&str> {
+        self.function_frames.last().map(|frame| frame.name.as_str())
+    }
+
+    pub fn type_registry(&self) -> &TypeRegistry {
+        &self.type_registry
+    }
+
+    pub fn add_interference(&mut self, function: impl Into<String>, interference: Interference) {
+        self.interference.insert(function.into(), interference);
+    }
+
     pub fn push<S: Into<String>>(&mut self, value: S) -> String {
         self.stack_size += 1;
         match self.variables.peek() {
@@ -148,25 +392,110 @@ impl Context {
         format!("    pop {}\n", Into::<String>::into(value))
     }
 
-    pub fn declare_variable(&mut self, identifier: String, is_mutable: bool) {
-        self.variables.declare_variable(identifier, is_mutable)
+    // makes room for `count` values on the stack without writing them
+    pub fn reserve(&mut self, count: u64) -> String {
+        self.stack_size += count;
+        match self.variables.peek() {
+            Some(frame) => frame.stack_size += count,
+            None => { self.variables.push(VariableFrame::with_size(count)); },
+        }
+        format!("    sub rsp, {}\n", count * 8)
     }
 
+    pub fn release(&mut self, count: u64) {
+        self.stack_size -= count;
+        self.variables.peek().expect("trying to release from empty stack").stack_size -= count;
+    }
+
+    // declares a variable holding the value on top of the stack, moving it into a dead variable's
+    // slot when liveness allows
+    pub fn declare_variable(&mut self, identifier: String, is_mutable: bool, ty: Option<ast::Type>) -> String {
+        let Some(frame) = self.function_frames.last_mut() else {
+            self.variables.declare_variable(identifier, is_mutable, ty);
+            return String::new();
+        };
+        let id = frame.next_variable;
+        frame.next_variable += 1;
+
+        let slot = self.interference.get(&frame.name).and_then(|interference| self.variables
+            .peek()
+            .and_then(|frame| frame.reusable_slot(|other| !interference.interferes(id, other)))
+        );
+        let mut code = String::new();
+        let offset = match slot {
+            Some(offset) => {
+                code += &self.pop("rax");
+                let frame = self.variables.peek().expect("a variable frame");
+                code += &format!("    mov qword [rsp + {}], rax\n", (frame.stack_size - offset) * 8);
+                offset
+            },
+            None => self.variables.peek().expect("a variable frame").stack_size,
+        };
+        self.variables.declare_variable_at(identifier, is_mutable, ty, offset);
+        self.variables.peek().expect("a variable frame").slots.push((offset, id));
+        code
+    }
+
+    // declares an array whose elements are the `length` slots on top of the stack, the first one on
+    // top. its slots are never given to another variable
+    pub fn declare_array(&mut self, identifier: String, is_mutable: bool, length: u64) {
+        if let Some(frame) = self.function_frames.last_mut() {
+            frame.next_variable += 1;
+        }
+        let offset = match self.variables.peek() {
+            Some(frame) => frame.stack_size,
+            None => 0,
+        };
+        self.variables.declare_array_at(identifier, is_mutable, length, offset);
+    }
+
+    fn visible_frames(&self) -> usize {
+        self.function_frames.last().map(|frame| frame.params_frame).unwrap_or(0)
+    }
+
+    // variables of a narrower type are extended to 64 bits on the way
     pub fn get_variable(&mut self, identifier: &String) -> Option<String> {
-        self.variables.get_offset(identifier).map(|offset| {
-            self.push(format!("qword [rsp + {}]", offset * 8))
+        let base = self.visible_frames();
+        let ty = self.variables.get_type(identifier, base).filter(|ty| width::is_narrow(*ty));
+        self.variables.get_offset(identifier, base).map(|offset| match ty {
+            Some(ty) => width::load(ty, &format!("[rsp + {}]", offset * 8)) + &self.push("rax"),
+            None => self.push(format!("qword [rsp + {}]", offset * 8)),
         })
     }
 
+    pub fn variable_type(&self, identifier: &str) -> Option<ast::Type> {
+        self.variables.get_type(identifier, self.visible_frames())
+    }
+
+    pub fn array_length(&self, identifier: &str) -> Option<u64> {
+        self.variables.get_length(identifier, self.visible_frames())
+    }
+
+    // the memory operand of the element of an array at the index in `index`, and the array's length
+    pub fn element(&mut self, identifier: &String, index: &str, is_store: bool) -> Result<(String, u64), CodegenError> {
+        let base = self.visible_frames();
+        let offset = self.variables.get_offset(identifier, base).ok_or(CodegenError::IdentifierNotDeclared(identifier.clone()))?;
+        let length = self.variables.get_length(identifier, base).ok_or(CodegenError::NotAnArray(identifier.clone()))?;
+        if is_store && !self.variables.is_mutable(identifier, base).unwrap_or(false) {
+            return Err(CodegenError::ChangedImmutableVariable(identifier.clone()));
+        }
+        Ok((format!("qword [rsp + {index}*8 + {}]", offset * 8), length))
+    }
+
     pub fn set_variable(&mut self, identifier: &String, value: impl Into<String>) -> Result<String, CodegenError> {
-        if !self.variables.is_mutable(identifier)
+        let base = self.visible_frames();
+        if !self.variables.is_mutable(identifier, base)
                 .ok_or(CodegenError::IdentifierNotDeclared(identifier.clone()))? {
             return Err(CodegenError::ChangedImmutableVariable(identifier.clone()));
         }
-        self.variables.get_offset(identifier)
+        if self.variables.get_length(identifier, base).is_some() {
+            return Err(CodegenError::ArrayAsValue(identifier.clone()));
+        }
+        self.variables.get_offset(identifier, base)
             .ok_or(CodegenError::IdentifierNotDeclared(identifier.clone()))
-            .map(|offset| {
-                format!("    mov qword [rsp + {}], {}\n", offset * 8, Into::<String>::into(value))
+            .map(|offset| match self.variables.get_type(identifier, base) {
+                Some(ty) => width::store(ty, &format!("[rsp + {}]", offset * 8), &value.into()),
+                None => format!("    mov qword [rsp + {}], {}\n", offset * 8, Into::<String>::into(value)),
             })
     }
 
@@ -178,6 +507,34 @@ impl Context {
         format!("{tag}{index:08x}")
     }
 
+    pub fn add_data(&mut self, tag: impl Into<String>, bytes: Vec<u8>) -> String {
+        let label = self.create_label(tag);
+        self.data.push((label.clone(), bytes));
+        label
+    }
+
+    pub fn data_section(&self) -> String {
+        if self.data.is_empty() {
+            return String::new();
+        }
+        let mut code = String::from("section .rodata\n");
+        for (label, bytes) in &self.data {
+            let bytes: Vec<String> = bytes.iter().map(u8::to_string).collect();
+            code += &format!("{label}: db {}\n", bytes.join(", "));
+        }
+        code
+    }
+
+    pub fn require_runtime(&mut self, routine: Routine) -> &'static str {
+        self.runtime.insert(routine);
+        self.runtime.extend(routine.requires());
+        routine.label()
+    }
+
+    pub fn runtime_code(&self) -> String {
+        self.runtime.iter().map(Routine::code).collect()
+    }
+
     pub fn enter(&mut self) -> String {
         self.variables.push(VariableFrame::default());
         String::new()
@@ -189,39 +546,79 @@ impl Context {
     }
 
     pub fn enter_labelled_region(&mut self, frame: impl Into<LabelFrame>) {
-        self.label_stack.push(frame.into())
+        self.label_stack.push((frame.into(), self.variables.size()))
     }
 
     pub fn exit_labelled_region(&mut self) -> Option<LabelFrame> {
-        self.label_stack.pop()
+        self.label_stack.pop().map(|(frame, _)| frame)
     }
 
-    pub fn get_labelled_region(&mut self) -> Option<LabelFrame> {
-        let last_index = match self.label_stack.len() {
-            0 => return None,
-            l => l - 1,
-        };
-        self.label_stack.get(last_index).cloned()
+    // the innermost region of the current function, or the one with the given name
+    fn labelled_region(&self, name: Option<&str>) -> Option<&(LabelFrame, u64)> {
+        let depth = self.function_frames.last().map(|frame| frame.label_depth).unwrap_or(0);
+        self.label_stack[depth.min(self.label_stack.len())..]
+            .iter()
+            .rev()
+            .find(|(frame, _)| name.is_none() || frame.name.as_deref() == name)
+    }
+
+    pub fn get_labelled_region(&self, name: Option<&str>) -> Option<LabelFrame> {
+        self.labelled_region(name).map(|(frame, _)| frame.clone())
     }
 
-    pub fn enter_function(&mut self, name: impl Into<String>) -> Result<String, CodegenError> {
+    // drops whatever has been pushed since entering the region, as jumping to either of its labels
+    // skips the exits of the blocks in between, and of any loops inside it
+    pub fn unwind_labelled_region(&self, name: Option<&str>) -> String {
+        match self.labelled_region(name) {
+            Some((_, size)) if self.variables.size() > *size => format!("    add rsp, {}\n", (self.variables.size() - size) * 8),
+            _ => String::new(),
+        }
+    }
+
+    // only 64-bit functions get a canary or profile counters, as both rely on the runtime
+    pub fn enter_function(&mut self, name: impl Into<String>, end_label: impl Into<String>) -> Result<String, CodegenError> {
         let name: String = name.into();
+        let bits_64 = self.function_bits(&name)? == Bits::Bits64;
+        let profile = (self.profile && bits_64).then(|| {
+            let label = self.create_label("profile");
+            self.profiled.push((name.clone(), label.clone()));
+            label
+        });
+        self.enter_frames(name, end_label, self.stack_protector && bits_64, profile)
+    }
+
+    // an inlined function has no return address for a canary to guard, and is counted as part of
+    // its caller
+    pub fn enter_inline_function(&mut self, name: impl Into<String>, end_label: impl Into<String>) -> Result<String, CodegenError> {
+        self.enter_frames(name.into(), end_label, false, None)
+    }
+
+    fn enter_frames(&mut self, name: String, end_label: impl Into<String>, canary: bool, profile: Option<String>) -> Result<String, CodegenError> {
         let mut code = String::new();
 
-        let function = self.type_registry.get_function(name.clone()).cloned();
-        let function = function.ok_or(CodegenError::FunctionNotDeclared(name))?;
+        let params = self.type_registry.get_function(name.clone()).map(|function| function.params.clone());
+        let params = params.ok_or(CodegenError::FunctionNotDeclared(name.clone()))?;
 
         // push params frame
         code += &self.enter();
+        self.function_frames.push(FunctionFrame {
+            name,
+            end: end_label.into(),
+            params_frame: self.variables.0.len() - 1,
+            label_depth: self.label_stack.len(),
+            next_variable: params.len(),
+            unsafe_depth: 0,
+            canary,
+            profile: profile.clone(),
+        });
 
         // stack size + 1 for return address
         self.variables.peek().unwrap().stack_size += 1;
 
-        // declare params
-        let param_count = function.params.len() as u64;
+        // declare params, the first argument was pushed first so it sits furthest from the top
+        let param_count = params.len() as u64;
         self.variables.peek().unwrap().stack_size += param_count;
-        function
-            .params
+        params
             .into_iter()
             .enumerate()
             .map(|(i, p)| (i as u64, p))
@@ -229,20 +626,77 @@ impl Context {
                 self.variables.declare_variable_at(
                     param.name,
                     false,
-                    param_count - i
+                    None,
+                    i + 1
                 )
             )
         ;
 
+        if canary {
+            code += &format!("    mov rbx, [rel {CANARY_LABEL}]\n");
+            code += &self.push("rbx");
+        }
+        if let Some(label) = profile {
+            code += &format!("    inc qword [rel {label}]\n");
+            code += "    rdtsc\n";
+            code += "    shl rdx, 32\n";
+            code += "    or rax, rdx\n";
+            code += &self.push("rax");
+        }
+
         // push variables frame
         code += &self.enter();
         Ok(code)
     }
 
+    pub fn inline_body(&self, name: &String) -> Option<ast::Statement> {
+        if self.function_frames.iter().any(|frame| &frame.name == name) {
+            return None;
+        }
+        let function = self.type_registry.get_function(name.clone())?;
+        match function.inline() {
+            InlineHint::Always => function.body.clone(),
+            InlineHint::Default | InlineHint::Never => None,
+        }
+    }
+
+    pub fn enter_unsafe(&mut self) {
+        if let Some(frame) = self.function_frames.last_mut() {
+            frame.unsafe_depth += 1;
+        }
+    }
+
+    pub fn exit_unsafe(&mut self) {
+        if let Some(frame) = self.function_frames.last_mut() {
+            frame.unsafe_depth -= 1;
+        }
+    }
+
+    pub fn is_unsafe(&self) -> bool {
+        self.function_frames.last().is_some_and(|frame| frame.unsafe_depth > 0)
+    }
+
+    pub fn return_from_function(&mut self) -> String {
+        let FunctionFrame { name: _, end, params_frame, label_depth: _, next_variable: _, unsafe_depth: _, canary: _, profile: _ } = self.function_frames.last().cloned()
+            .expect("can't return from current context");
+        // unwind everything pushed since the params frame, leaving the return address on top
+        let unwind = self.variables.size_above(params_frame);
+        format!("    add rsp, {}\n    jmp {end}\n", unwind * 8)
+    }
+
     pub fn exit_function(&mut self) -> Result<String, CodegenError> {
+        let FunctionFrame { name, end, params_frame: _, label_depth: _, next_variable: _, unsafe_depth: _, canary, profile } = self.function_frames.pop()
+            .expect("can't exit from current context");
         let mut code = String::new();
         // pop variable frame
         code += &self.exit();
+        code += &format!("{end}:\n");
+        if let Some(label) = profile {
+            code += &self.stop_profile(&label);
+        }
+        if canary {
+            code += &self.check_canary(&name);
+        }
         // rbx <- [rsp]
         code += "    mov rbx, [rsp]\n";
         // pop params frame
@@ -252,5 +706,75 @@ impl Context {
         code += "    ret\n";
         Ok(code)
     }
+
+    // the entry point has nothing to return to, so the value it returns becomes the exit code
+    pub fn exit_entry_function(&mut self) -> Result<String, CodegenError> {
+        let FunctionFrame { name, end, params_frame: _, label_depth: _, next_variable: _, unsafe_depth: _, canary, profile } = self.function_frames.pop()
+            .expect("can't exit from current context");
+        let mut code = String::new();
+        // pop variable frame
+        code += &self.exit();
+        code += &format!("{end}:\n");
+        if let Some(label) = profile {
+            code += &self.stop_profile(&label);
+        }
+        if canary {
+            code += &self.check_canary(&name);
+        }
+        // pop params frame
+        code += &self.exit();
+        if self.profile {
+            code += "    mov rbx, rax\n";
+            code += &format!("    call {}\n", self.require_runtime(Routine::ProfileDump));
+            code += "    mov rax, rbx\n";
+        }
+        // exit_group rather than exit, so threads started with `spawn` end as well
+        code += "    mov rdi, rax\n";
+        code += "    mov rax, 231\n";
+        code += "    syscall\n";
+        Ok(code)
+    }
+
+    pub fn exit_inline_function(&mut self) -> Result<String, CodegenError> {
+        let FunctionFrame { name: _, end, params_frame: _, label_depth: _, next_variable: _, unsafe_depth: _, canary: _, profile: _ } = self.function_frames.pop()
+            .expect("can't exit from current context");
+        let mut code = String::new();
+        // pop variable frame
+        code += &self.exit();
+        code += &format!("{end}:\n");
+        // pop params frame, including the stand-in return address
+        code += &self.exit();
+        Ok(code)
+    }
+
+    // adds the cycles since the function was entered to its counters, keeping the return value
+    fn stop_profile(&mut self, label: &str) -> String {
+        let mut code = String::from("    mov rcx, rax\n");
+        code += "    rdtsc\n";
+        code += "    shl rdx, 32\n";
+        code += "    or rax, rdx\n";
+        code += &self.pop("rbx");
+        code += "    sub rax, rbx\n";
+        code += &format!("    add qword [rel {label} + 8], rax\n");
+        code += "    mov rax, rcx\n";
+        code
+    }
+
+    // pops the canary, panicking if whatever the function wrote has run over it. rax still holds
+    // the return value
+    fn check_canary(&mut self, function: &str) -> String {
+        let intact_label = self.create_label("canary");
+        let message = format!("stack smashing detected in `{function}`\n");
+        let length = message.len();
+        let message_label = self.add_data("panicmsg", message.into_bytes());
+        let mut code = self.pop("rcx");
+        code += &format!("    cmp rcx, [rel {CANARY_LABEL}]\n");
+        code += &format!("    je {intact_label}\n");
+        code += &format!("    lea rsi, [rel {message_label}]\n");
+        code += &format!("    mov rdx, {length}\n");
+        code += &format!("    call {}\n", self.require_runtime(Routine::Panic));
+        code += &format!("{intact_label}:\n");
+        code
+    }
 }
 
@@ -0,0 +1,355 @@
+use crate::{
+    messages,
+    node_map::Span,
+    parser::ParseWarning,
+    tokenize::Location,
+    CompileError, Lang,
+};
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+impl Level {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Note => "note",
+            Self::Help => "help",
+        }
+    }
+
+    fn style(&self) -> &'static str {
+        match self {
+            Self::Error => "\x1b[1;31m",
+            Self::Warning => "\x1b[1;33m",
+            Self::Note => "\x1b[1;36m",
+            Self::Help => "\x1b[1;32m",
+        }
+    }
+}
+
+
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+
+// a part of the source a diagnostic points at, the first one of a diagnostic is where it happened
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: Option<String>,
+}
+
+
+// replaces what the span covers, an empty span is an insertion
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edit {
+    pub span: Span,
+    pub replacement: String,
+}
+
+
+// edits that fix what a diagnostic is about, which `beryllium fix` and editors can make without
+// asking
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Suggestion {
+    pub message: String,
+    pub edits: Vec<Edit>,
+}
+
+impl Suggestion {
+    pub fn insert(message: impl Into<String>, at: Location, text: impl Into<String>) -> Self {
+        Self { message: message.into(), edits: vec![Edit { span: Span { start: at, end: at }, replacement: text.into() }] }
+    }
+
+    // the source with the edits made, they are made from the last so the spans of the others stay
+    // where they are
+    pub fn apply(&self, source: &str) -> String {
+        let mut chars: Vec<char> = source.chars().collect();
+        let mut edits: Vec<&Edit> = self.edits.iter().collect();
+        edits.sort_by_key(|edit| std::cmp::Reverse(edit.span.start.index));
+        for edit in edits {
+            let start = (edit.span.start.index as usize).min(chars.len());
+            let end = (edit.span.end.index as usize).clamp(start, chars.len());
+            chars.splice(start..end, edit.replacement.chars());
+        }
+        chars.into_iter().collect()
+    }
+}
+
+
+// what the compiler tells the user, whatever pass it comes from. the error enums stay how passes
+// fail, and are turned into these to be shown, written to a report or read by tools
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub labels: Vec<Label>,
+    // notes and helps that go with it, shown below it
+    pub children: Vec<Diagnostic>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    fn new(level: Level, message: impl Into<String>) -> Self {
+        Self { level, code: None, message: message.into(), labels: Vec::new(), children: Vec::new(), suggestions: Vec::new() }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Level::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Level::Warning, message)
+    }
+
+    pub fn note(message: impl Into<String>) -> Self {
+        Self::new(Level::Note, message)
+    }
+
+    pub fn help(message: impl Into<String>) -> Self {
+        Self::new(Level::Help, message)
+    }
+
+    pub fn localized(error: &CompileError, lang: Lang) -> Self {
+        let diagnostic = Self::error(error.localized(lang)).with_code(error.code());
+        let diagnostic = match span(error) {
+            Some(span) => diagnostic.with_span(span),
+            None => diagnostic,
+        };
+        // the closer is missing, so the opening one it pairs with is where to look
+        match error {
+            CompileError::UnclosedDelimiter { open, .. } => {
+                let message = messages::message(lang, "unclosed", &[("open", open.data.text())]);
+                diagnostic.with_label(Span { start: open.location, end: open.end() }, message)
+            },
+            _ => diagnostic,
+        }
+    }
+
+    pub fn localized_warning(warning: &ParseWarning, lang: Lang) -> Self {
+        match warning {
+            ParseWarning::DanglingElse(location) => {
+                let args = [("line", location.line.to_string()), ("column", location.column.to_string())];
+                Self::warning(messages::message(lang, "dangling-else", &args))
+                    .with_span(Span { start: *location, end: *location })
+                    .with_help(messages::message(lang, "dangling-else-help", &[]))
+            },
+        }
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.labels.push(Label { span, message: None });
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: Some(message.into()) });
+        self
+    }
+
+    pub fn with_note(mut self, message: impl Into<String>) -> Self {
+        self.children.push(Self::note(message));
+        self
+    }
+
+    pub fn with_help(mut self, message: impl Into<String>) -> Self {
+        self.children.push(Self::help(message));
+        self
+    }
+
+    pub fn with_suggestion(mut self, suggestion: Suggestion) -> Self {
+        self.suggestions.push(suggestion);
+        self
+    }
+
+    // the message already says where it happened, so only labels with a message of their own are
+    // shown, each with the children after them and then the suggestions
+    pub fn render(&self, color: bool) -> String {
+        let level = match self.code {
+            Some(code) => format!("{}[{code}]", self.level.name()),
+            None => self.level.name().to_string(),
+        };
+        let message = &self.message;
+        let mut text = match color {
+            true => format!("{}{level}{RESET}: {BOLD}{message}{RESET}\n", self.level.style()),
+            false => format!("{level}: {message}\n"),
+        };
+        for label in &self.labels {
+            if let Some(message) = &label.message {
+                text += &format!("  --> {}:{}: {message}\n", label.span.start.line, label.span.start.column);
+            }
+        }
+        for child in &self.children {
+            text += &format!("  = {}", child.render(color));
+        }
+        for suggestion in &self.suggestions {
+            text += &format!("  = {}", Self::help(suggestion.message.clone()).render(color));
+        }
+        text
+    }
+}
+
+// collects the diagnostics of a build, making warnings errors with `--fatal-warnings` and dropping
+// everything after `--max-errors` errors
+#[derive(Clone, Debug, Default)]
+pub struct DiagnosticSink {
+    max_errors: Option<usize>,
+    fatal_warnings: bool,
+    lang: Lang,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl DiagnosticSink {
+    // a limit of 0 is no limit
+    pub fn new(max_errors: Option<usize>, fatal_warnings: bool, lang: Lang) -> Self {
+        Self { max_errors: max_errors.filter(|max| *max > 0), fatal_warnings, lang, diagnostics: Vec::new() }
+    }
+
+    // the diagnostic as it is kept, `None` if it was dropped as the limit has been reached
+    pub fn emit(&mut self, mut diagnostic: Diagnostic) -> Option<&Diagnostic> {
+        if self.is_full() {
+            return None;
+        }
+        if self.fatal_warnings && diagnostic.level == Level::Warning {
+            diagnostic.level = Level::Error;
+            diagnostic = diagnostic.with_note(messages::message(self.lang, "fatal-warnings", &[]));
+        }
+        self.diagnostics.push(diagnostic);
+        self.diagnostics.last()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.max_errors.is_some_and(|max| self.error_count() >= max)
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.diagnostics.iter().filter(|diagnostic| diagnostic.level == Level::Error).count()
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+}
+
+// where in the source the error is, for those that know
+fn span(error: &CompileError) -> Option<Span> {
+    let at = |location: &Location| Some(Span { start: *location, end: *location });
+    match error {
+        CompileError::UnexpectedToken(token) => Some(Span { start: token.location, end: token.end() }),
+        CompileError::UnterminatedStringLiteral(location) | CompileError::UnterminatedBlockComment(location)
+            | CompileError::NonExhaustiveMatch(location) | CompileError::UnreachablePattern(location)
+            | CompileError::NotConstant(location) | CompileError::ChainedComparison(location)
+            | CompileError::TooDeep(location) | CompileError::UnexpectedEnd(location)
+            | CompileError::UnclosedDelimiter { location, .. }
+            | CompileError::InvalidEscape { location, .. } | CompileError::AbsoluteReference { location, .. }
+            | CompileError::StaticAssertFailed { location, .. } | CompileError::LiteralOutOfRange { location, .. }
+            | CompileError::LiteralOutOfType { location, .. } | CompileError::MissingSemicolon(location)
+            | CompileError::UndeclaredLabel { location, .. } => at(location),
+        CompileError::InvalidAttribute(attribute) => at(&attribute.location),
+        _ => None,
+    }
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn plain_rendering_has_no_escapes() {
+    assert_eq!(Diagnostic::warning("unused").render(false), "warning: unused\n");
+}
+
+#[test]
+fn codes_follow_the_level() {
+    let error = CompileError::ChangedImmutableVariable(String::from("x"));
+    assert_eq!(
+        Diagnostic::localized(&error, Lang::En).render(false),
+        "error[BE0003]: cannot assign to immutable variable `x`\n",
+    );
+}
+
+#[test]
+fn colored_rendering_styles_the_level() {
+    assert_eq!(
+        Diagnostic::note("see here").render(true),
+        "\x1b[1;36mnote\x1b[0m: \x1b[1msee here\x1b[0m\n",
+    );
+}
+
+#[test]
+fn errors_carry_their_span_and_children_render_below() {
+    use crate::tokenize::{Token, TokenData, Symbol};
+    let location = Location { index: 4, line: 1, column: 5 };
+    let error = CompileError::UnexpectedToken(Token { data: TokenData::Symbol(Symbol::FatArrow), location });
+    let diagnostic = Diagnostic::localized(&error, Lang::En);
+    assert_eq!(diagnostic.labels, vec![Label { span: Span { start: location, end: Location { index: 6, line: 1, column: 7 } }, message: None }]);
+    assert_eq!(
+        diagnostic.with_help("remove it").render(false),
+        "error[BE0005]: unexpected Symbol(FatArrow) at 1:5\n  = help: remove it\n",
+    );
+    let span = Span { start: location, end: location };
+    assert_eq!(
+        Diagnostic::warning("unused").with_label(span, "declared here").with_note("it is never read").render(false),
+        "warning: unused\n  --> 1:5: declared here\n  = note: it is never read\n",
+    );
+}
+
+#[test]
+fn unclosed_delimiters_are_labelled_where_they_open() {
+    use crate::tokenize::{Token, TokenData, Symbol};
+    let open = Token { data: TokenData::Symbol(Symbol::LBrace), location: Location { index: 12, line: 1, column: 13 } };
+    let error = CompileError::UnclosedDelimiter { open, location: Location { index: 30, line: 3, column: 1 } };
+    assert_eq!(
+        Diagnostic::localized(&error, Lang::En).render(false),
+        "error[BE0047]: expected `}` at 3:1 to close the `{` at 1:13\n  --> 1:13: this `{` is not closed\n",
+    );
+}
+
+#[test]
+fn sinks_promote_warnings_and_stop_at_the_limit() {
+    let mut sink = DiagnosticSink::new(None, false, Lang::En);
+    sink.emit(Diagnostic::warning("unused"));
+    assert_eq!(sink.error_count(), 0);
+
+    let mut sink = DiagnosticSink::new(Some(2), true, Lang::En);
+    for _ in 0..3 {
+        sink.emit(Diagnostic::warning("unused"));
+    }
+    assert!(sink.is_full());
+    assert_eq!(sink.diagnostics().len(), 2);
+    assert_eq!(sink.diagnostics()[0].render(false), "error: unused\n  = note: warnings are errors with `--fatal-warnings`\n");
+    assert!(!DiagnosticSink::new(Some(0), true, Lang::En).is_full());
+
+    let mut sink = DiagnosticSink::new(None, true, Lang::De);
+    let diagnostic = sink.emit(Diagnostic::warning("unused")).unwrap();
+    assert_eq!(diagnostic.render(false), "error: unused\n  = note: mit `--fatal-warnings` sind Warnungen Fehler\n");
+}
+
+#[test]
+fn suggestions_are_applied_from_the_last_edit() {
+    let at = |index| Location { index, line: 1, column: index + 1 };
+    let mut suggestion = Suggestion::insert("make `x` mutable", at(4), "mut ");
+    suggestion.edits.push(Edit { span: Span { start: at(8), end: at(9) }, replacement: String::from("2;") });
+    assert_eq!(suggestion.apply("let x = 1"), "let mut x = 2;");
+    assert_eq!(
+        Diagnostic::error("cannot assign").with_suggestion(suggestion).render(false),
+        "error: cannot assign\n  = help: make `x` mutable\n",
+    );
+}
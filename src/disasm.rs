@@ -0,0 +1,197 @@
+use std::{path::Path, process::Command};
+
+use crate::{
+    CompileError,
+    toolchain::Tool,
+    type_registry::TypeRegistry,
+};
+
+
+pub fn disassemble(binary: &Path) -> Result<String, CompileError> {
+    let objdump = Tool::Objdump.locate().ok_or_else(|| CompileError::ToolchainMissing {
+        tool: Tool::Objdump.name().to_string(),
+        hint: Tool::Objdump.install_hint(),
+    })?;
+    let output = Command::new(objdump)
+        .args(["--disassemble", "--no-show-raw-insn", "-M", "intel"])
+        .arg(binary)
+        .output()?;
+    if !output.status.success() {
+        return Err(CompileError::CommandFailed {
+            program: Tool::Objdump.name().to_string(),
+            status: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+// the symbols objdump starts a block of instructions with, `0000000000401000 <_start>:`
+pub fn symbols(disassembly: &str) -> Vec<&str> {
+    disassembly
+        .lines()
+        .filter_map(|line| line.split_once(" <")?.1.strip_suffix(">:"))
+        .collect()
+}
+
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SymbolCheck<'a> {
+    // registered functions without a symbol, because they were inlined or are unreachable
+    pub not_emitted: Vec<&'a str>,
+    // symbols that are neither a registered function nor something the backend generates
+    pub unknown: Vec<&'a str>,
+}
+
+impl<'a> SymbolCheck<'a> {
+    pub fn new(symbols: &[&'a str], registry: &'a TypeRegistry) -> Self {
+        let not_emitted = registry
+            .functions()
+            .map(|(name, _)| name.as_str())
+            .filter(|name| !symbols.contains(name))
+            .collect();
+        let unknown = symbols
+            .iter()
+            .copied()
+            .filter(|symbol| registry.get_function(*symbol).is_none() && !is_generated(symbol))
+            .collect();
+        Self { not_emitted, unknown }
+    }
+}
+
+// a run of instructions that starts at one address, under every label that points there
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Block<'a> {
+    labels: Vec<&'a str>,
+    instructions: usize,
+    // only 64-bit code is decoded the way it was written, the instructions of 16 and 32-bit
+    // functions are not counted
+    counted: bool,
+}
+
+// splits the `.text` of the emitted assembly into blocks, labels without an instruction after
+// them share the block of the next label
+fn emitted_blocks(asm: &str) -> Vec<Block<'_>> {
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut in_text = true;
+    let mut bits_64 = true;
+    for line in asm.lines() {
+        let mut instruction = line.trim();
+        if let Some(section) = instruction.strip_prefix("section ") {
+            in_text = section == ".text";
+            continue;
+        }
+        if let Some(bits) = instruction.strip_prefix("[bits ") {
+            bits_64 = bits == "64]";
+            continue;
+        }
+        if !in_text || instruction.is_empty() || instruction.starts_with(';') || instruction.starts_with("global ") {
+            continue;
+        }
+        if let Some((label, rest)) = instruction.split_once(':').filter(|(label, _)| !label.contains(char::is_whitespace)) {
+            match blocks.last_mut() {
+                Some(block) if block.instructions == 0 => block.labels.push(label),
+                _ => blocks.push(Block { labels: vec![label], instructions: 0, counted: bits_64 }),
+            }
+            instruction = rest.trim();
+        }
+        if !instruction.is_empty() && !instruction.starts_with(';') {
+            let block = blocks.last_mut().expect("instructions follow a label");
+            block.instructions += 1;
+            block.counted &= bits_64;
+        }
+    }
+    // a label at the very end has no address objdump would show
+    blocks.retain(|block| block.instructions > 0);
+    blocks
+}
+
+fn disassembled_blocks(disassembly: &str) -> Vec<(&str, usize)> {
+    let mut blocks: Vec<(&str, usize)> = Vec::new();
+    for line in disassembly.lines() {
+        if let Some(symbol) = line.split_once(" <").and_then(|(_, symbol)| symbol.strip_suffix(">:")) {
+            blocks.push((symbol, 0));
+        } else if line.starts_with(' ') && line.contains(":\t") {
+            if let Some((_, instructions)) = blocks.last_mut() {
+                *instructions += 1;
+            }
+        }
+    }
+    blocks
+}
+
+// checks that the binary holds the blocks and instruction counts of the assembly it was built
+// from, giving the number of instructions checked or the label and reason of the first mismatch
+pub fn round_trip(asm: &str, disassembly: &str) -> Result<usize, (String, String)> {
+    let emitted = emitted_blocks(asm);
+    let disassembled = disassembled_blocks(disassembly);
+    let mut checked = 0;
+    for (index, block) in emitted.iter().enumerate() {
+        let label = block.labels.join("/");
+        let Some((symbol, instructions)) = disassembled.get(index) else {
+            return Err((label, String::from("it is missing from the binary")));
+        };
+        if !block.labels.contains(symbol) {
+            return Err((label, format!("the binary has `{symbol}` in its place")));
+        }
+        if !block.counted {
+            continue;
+        }
+        if block.instructions != *instructions {
+            return Err((label, format!("{} instructions were emitted but {instructions} were assembled", block.instructions)));
+        }
+        checked += block.instructions;
+    }
+    match disassembled.get(emitted.len()) {
+        Some((symbol, _)) => Err((symbol.to_string(), String::from("it was not emitted"))),
+        None => Ok(checked),
+    }
+}
+
+// the test harness brings its own `_start`, runtime routines are prefixed, and every other label
+// comes from `Context::create_label` as a tag followed by eight hex digits
+fn is_generated(symbol: &str) -> bool {
+    let counter = symbol.len().saturating_sub(8);
+    symbol == "_start"
+        || symbol.starts_with("__beryllium_")
+        || (counter > 0 && symbol[counter..].bytes().all(|byte| byte.is_ascii_hexdigit()))
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn symbols_are_read_from_block_headers() {
+    let disassembly = "\nout:     file format elf64-x86-64\n\n\nDisassembly of section .text:\n\n\
+        0000000000401000 <_start>:\n  401000:\tcall   40101c <main>\n\n\
+        000000000040101c <main>:\n  40101c:\tret\n";
+    assert_eq!(symbols(disassembly), vec!["_start", "main"]);
+}
+
+#[test]
+fn round_trips_compare_blocks_and_counts() {
+    let asm = "global _start\n_start:\nif00000000:\n    push 1\n    jz end00000000\nend00000000:\n    ret\nlast00000000:\nsection .rodata\ntext00000000: db 1\n";
+    let disassembly = "0000000000401000 <_start>:\n  401000:\tpush   0x1\n  401002:\tje     401004 <end00000000>\n\n\
+        0000000000401004 <end00000000>:\n  401004:\tret\n";
+    assert_eq!(round_trip(asm, disassembly), Ok(3));
+
+    let disassembly = "0000000000401000 <_start>:\n  401000:\tpush   0x1\n\n0000000000401004 <end00000000>:\n  401004:\tret\n";
+    assert_eq!(round_trip(asm, disassembly), Err((
+        String::from("_start/if00000000"),
+        String::from("2 instructions were emitted but 1 were assembled"),
+    )));
+}
+
+#[test]
+fn symbols_are_checked_against_the_registry() {
+    use crate::{parser::Parser, tokenize::Tokenize};
+
+    let program = Parser::new("#[inline] fn one() return 1; fn main() exit(one()); fn _start() main();".tokenize()).parse().unwrap();
+    let registry = TypeRegistry::from(&program);
+    let check = SymbolCheck::new(&["_start", "end_start00000000", "main", "__beryllium_panic", "helper"], &registry);
+    assert_eq!(check, SymbolCheck { not_emitted: vec!["one"], unknown: vec!["helper"] });
+}
@@ -0,0 +1,733 @@
+// longer descriptions of each error code for `beryllium explain`, every code returned by
+// `CompileError::code` needs an entry here
+const EXPLANATIONS: [(&str, &str); 49] = [
+    ("BE0001", r#"A variable was used that is not declared in any enclosing scope.
+
+Variables are visible from the statement after their `let` until the end of the
+block that declares them, so this also happens when a variable is used before
+its declaration or after its block has ended.
+
+    fn _start() {
+        {
+            let x = 1;
+        }
+        exit(x); // `x` is out of scope here
+    }
+
+Declare the variable before its use, in a scope that encloses the use:
+
+    fn _start() {
+        let x = 1;
+        exit(x);
+    }
+"#),
+    ("BE0002", r#"A function was called that is not declared anywhere in the program.
+
+    fn _start() {
+        exit(answer());
+    }
+
+Declare the function, or correct the spelling of its name:
+
+    fn answer() {
+        return 42;
+    }
+
+    fn _start() {
+        exit(answer());
+    }
+"#),
+    ("BE0003", r#"A variable declared without `mut` was assigned to after its declaration.
+
+    fn _start() {
+        let x = 1;
+        x += 1;
+        exit(x);
+    }
+
+Declare the variable as mutable if it is meant to change:
+
+    fn _start() {
+        let mut x = 1;
+        x += 1;
+        exit(x);
+    }
+"#),
+    ("BE0004", r#"A function marked with `#[test]` takes parameters.
+
+The test harness calls each test function without arguments, so tests cannot
+have parameters.
+
+    #[test]
+    fn adds(x) {
+        exit(x + 1);
+    }
+
+Move the inputs into the body of the test:
+
+    #[test]
+    fn adds() {
+        let x = 1;
+        exit(x + 1);
+    }
+"#),
+    ("BE0005", r#"The parser found a token where it does not belong, such as a missing
+semicolon or an unbalanced parenthesis. The message names the token and where
+it was found.
+
+    fn _start() {
+        let x = 1
+        exit(x);
+    }
+
+Here `exit` was found where the `;` ending the `let` was expected:
+
+    fn _start() {
+        let x = 1;
+        exit(x);
+    }
+"#),
+    ("BE0006", r#"The source contains a character that is not part of the language, such as
+`$` or a lone `!`.
+
+    fn _start() {
+        exit(!0);
+    }
+
+Remove the character or replace it with a supported operator:
+
+    fn _start() {
+        exit(0 == 0);
+    }
+"#),
+    ("BE0007", r#"A string literal was opened with `"` but the file ended before it was
+closed.
+
+    fn _start() {
+        panic("oops);
+    }
+
+Close the string literal:
+
+    fn _start() {
+        panic("oops");
+    }
+"#),
+    ("BE0008", r#"An external tool needed to build the program, `nasm` or `ld`, could not be
+found on PATH. Beryllium checks for them before writing any output.
+
+Install the tool with your system package manager, for example
+`apt install nasm binutils`, or add the directory containing it to PATH.
+"#),
+    ("BE0009", r#"An external tool run during the build, `nasm` or `ld`, exited with an error.
+
+This usually points to a bug in the generated assembly. Run the build again
+with `--verbose` to see the tool's output, and keep the assembly with
+`--keep-all` to inspect it.
+"#),
+    ("BE0010", r#"A refactoring could not be applied without changing what the program does, or
+the arguments did not describe a valid refactoring.
+
+    beryllium refactor rename x y file.be
+
+fails if something called `y` is already declared, as uses of the two names
+could no longer be told apart. Extracting a function fails if the statements
+assign to variables declared outside them, `return`, `break` out of an
+enclosing loop, or declare variables that are used after them. Choose another
+name or a different range of lines.
+"#),
+    ("BE0011", r#"A file could not be read or written, for example because it does not exist or
+the directory is not writable. The message is the one given by the operating
+system.
+"#),
+    ("BE0012", r#"A file that was expected to be text is not valid UTF-8. Source files have to
+be saved as UTF-8.
+"#),
+    ("BE0013", r#"A build with `--reproducible` compiled the program twice and an output file
+came out different the second time.
+
+The compiler is meant to produce byte-for-byte identical output for the same
+source and options, so this is a bug in beryllium or in the installed `nasm` or
+`ld`. Please report it together with the source file and the options used.
+"#),
+    ("BE0014", r#"`beryllium inspect` was given a file without beryllium build information.
+
+Binaries built by beryllium record the compiler version and options in their
+`.comment` section. The file is either not an ELF64 binary, was built by
+another compiler or an older version of beryllium, or had the section removed,
+for example by `strip --remove-section=.comment`.
+"#),
+    ("BE0015", r#"`beryllium inspect --disasm` found a function symbol in the binary that the
+source file does not declare.
+
+Every symbol in the text section should be a function from the source, a
+runtime routine or a label generated by the backend. This usually means the
+binary was built from a different version of the source, in which case pass
+the right file with `--source`. Otherwise the backend emitted a symbol it
+should not have, which is a bug in beryllium.
+"#),
+    ("BE0016", r#"A function selected for 16- or 32-bit code, with `--bits` or a `#[bits(n)]`
+attribute, uses something only available in 64-bit mode.
+
+Narrower functions support arithmetic, variables, calls and control flow, but
+not `exit`, `panic`, `print` or checked `unreachable()`, which rely on Linux
+system calls. They can only call functions compiled for the same size, as the
+return address and every stack slot have to match.
+
+    #[bits(16)]
+    fn boot() {
+        exit(0);
+    }
+
+Loop instead of exiting, or move the call into a 64-bit function:
+
+    #[bits(16)]
+    fn boot() {
+        loop {}
+    }
+"#),
+    ("BE0017", r#"`spawn` was given something other than the name of a function without
+parameters. The new thread starts by calling that function, and `join` gives
+back what it returns.
+
+    fn worker(n) {
+        return n * 2;
+    }
+
+    fn _start() {
+        let thread = spawn(worker(21));
+        exit(join(thread));
+    }
+
+Pass the function by name and move its inputs into its body:
+
+    fn worker() {
+        return 21 * 2;
+    }
+
+    fn _start() {
+        let thread = spawn(worker);
+        exit(join(thread));
+    }
+"#),
+    ("BE0018", r#"A `match` does not end in an arm that matches every remaining value.
+
+Integer patterns can only list finitely many values, so the last arm has to be
+an unguarded `_`.
+
+    fn _start() {
+        match (3) {
+            0 => exit(1);
+            1..=9 => exit(2);
+        }
+    }
+
+Add a `_` arm for the values no other arm matches:
+
+    fn _start() {
+        match (3) {
+            0 => exit(1);
+            1..=9 => exit(2);
+            _ => exit(3);
+        }
+    }
+"#),
+    ("BE0019", r#"A `match` arm can never be taken, because the unguarded arms before it
+already match every value its pattern does. Arms with an `if` guard do not
+count, as the guard may be false. This also covers empty ranges such as `5..5`
+and anything after the final `_`.
+
+    fn _start() {
+        match (3) {
+            1..=9 => exit(1);
+            5 => exit(2);
+            _ => exit(0);
+        }
+    }
+
+Move the more specific arm first:
+
+    fn _start() {
+        match (3) {
+            5 => exit(2);
+            1..=9 => exit(1);
+            _ => exit(0);
+        }
+    }
+"#),
+    ("BE0020", r#"An intrinsic that reads or writes an arbitrary address was used outside an
+`unsafe` block. This covers `volatile_load`, `volatile_store`, the port
+intrinsics `inb`, `inw`, `outb` and `outw`, and the atomics. The compiler
+cannot check the addresses they are given, so their uses have to be marked.
+
+    fn _start() {
+        exit(volatile_load(4096));
+    }
+
+Wrap the code in an `unsafe` block after checking the address is valid:
+
+    fn _start() {
+        unsafe {
+            exit(volatile_load(4096));
+        }
+    }
+
+The block only covers the function it is written in. Functions called from it,
+inlined or not, need their own `unsafe` blocks.
+"#),
+    ("BE0021", r#"A function marked `const fn` does something that cannot happen at compile
+time.
+
+With optimizations enabled, calls to a `const fn` whose arguments are all
+constants are replaced by their result. The body may therefore only compute
+with its parameters, use control flow, and call other `const fn`s. It may not
+call `exit`, `panic`, `unreachable` or an intrinsic other than `min`, `max`,
+`abs` and `popcount`, and it may not call a function without `const`.
+
+    fn limit() {
+        return 10;
+    }
+
+    const fn clamp(x) {
+        if (x > limit()) return limit();
+        return x;
+    }
+
+Mark the callee `const` as well, or drop `const` from the caller:
+
+    const fn limit() {
+        return 10;
+    }
+"#),
+    ("BE0022", r#"The condition of a `static_assert` evaluated to zero. The message is the one
+given as the second argument.
+
+    const fn page_size() {
+        return 4096;
+    }
+
+    fn _start() {
+        static_assert(page_size() % 8192 == 0, "pages hold whole blocks");
+        exit(0);
+    }
+
+Static assertions are checked while compiling, for every function whether or
+not it is called, so either the assumption or the code it guards is wrong.
+"#),
+    ("BE0023", r#"The condition of a `static_assert` or the value of a `const` cannot be
+evaluated while compiling.
+
+It may only use integer literals, other `const`s, arithmetic, comparisons and
+calls to `const fn`s with constant arguments. Variables, including immutable
+ones, are not constants. Evaluation also fails if a `const fn` divides by zero,
+runs for too long or ends without returning.
+
+    fn _start() {
+        let size = 4096;
+        static_assert(size > 0, "size is positive");
+        exit(0);
+    }
+
+Declare the value as a `const` instead:
+
+    fn _start() {
+        const size = 4096;
+        static_assert(size > 0, "size is positive");
+        exit(0);
+    }
+"#),
+    ("BE0024", r#"An integer literal is larger than a 64-bit signed integer can hold, that is
+larger than 9223372036854775807.
+
+    fn _start() {
+        let mask = 18446744073709551615;
+        exit(mask);
+    }
+
+Integers are 64-bit and signed. To write a value by its unsigned bit pattern,
+build with `--literal-overflow=wrap`. Literals up to 18446744073709551615 are
+then read as the signed number with the same bits, so the one above is -1.
+Literals beyond that are still rejected.
+"#),
+    ("BE0025", r#"A variable declared without a value is read on a path where nothing has been
+assigned to it yet.
+
+`let mut x;` declares `x` without a value. Every path from the declaration to a
+read of `x` has to assign it first, with `=` rather than `+=` or the like,
+which read the old value.
+
+    fn _start() {
+        let mut code;
+        if (1 < 2) code = 1;
+        exit(code);
+    }
+
+Assign the variable on every branch, or give it a value where it is declared:
+
+    fn _start() {
+        let mut code;
+        if (1 < 2) code = 1;
+        else code = 2;
+        exit(code);
+    }
+"#),
+    ("BE0026", r#"A comparison was used as an operand of another comparison, as in `a < b < c`.
+
+Comparisons evaluate to 1 or 0, so this would compare the result of `a < b`
+with `c` instead of checking that `b` lies between `a` and `c`.
+
+    fn _start() {
+        let x = 5;
+        if (0 < x < 10) exit(1);
+        exit(0);
+    }
+
+Compare each pair on its own, for example with nested `if`s:
+
+    fn _start() {
+        let x = 5;
+        if (0 < x) if (x < 10) exit(1);
+        exit(0);
+    }
+"#),
+    ("BE0027", r#"`swap` was given something other than a variable. It writes to both of its
+arguments, so each has to be the name of a mutable variable.
+
+    fn _start() {
+        let mut x = 1;
+        swap(x, 2);
+        exit(x);
+    }
+
+Store the value in a variable first:
+
+    fn _start() {
+        let mut x = 1;
+        let mut y = 2;
+        swap(x, y);
+        exit(x);
+    }
+"#),
+    ("BE0028", r#"A block comment was opened with `/*` but the file ended before it was closed.
+
+Block comments nest, so every `/*` inside a comment needs its own `*/`.
+
+    fn _start() {
+        /* the exit code /* see below */
+        exit(0);
+    }
+
+Close each comment that was opened:
+
+    fn _start() {
+        /* the exit code /* see below */ */
+        exit(0);
+    }
+"#),
+    ("BE0029", r#"A string literal contains a backslash that doesn't start a known escape sequence.
+
+The escapes that can be used are `\n`, `\t`, `\0`, `\\`, `\"` and `\xNN`, where `NN`
+is two hex digits no greater than `7f`.
+
+    fn _start() {
+        panic("done\r\n");
+    }
+
+Spell the character with a hex escape instead:
+
+    fn _start() {
+        panic("done\x0d\n");
+    }
+"#),
+    ("BE0030", r#"With `--verify-pic`, the generated code refers to an address absolutely.
+
+Position-independent executables and shared libraries can be loaded anywhere,
+so data has to be addressed relative to the instruction pointer. Only 64-bit
+code can do that, which rules out string literals and `spawn` in functions
+compiled for 16 or 32 bits.
+
+    #[bits(32)]
+    fn greeting() {
+        return "hello";
+    }
+
+Keep the functions that address data in 64-bit mode:
+
+    fn greeting() {
+        return "hello";
+    }
+"#),
+    ("BE0031", r#"With `--verify`, the linked binary was disassembled and does not hold the code
+that was emitted.
+
+Every label of the emitted assembly has to start a block of the disassembly, in
+the same order and with as many instructions as were written under it. A
+mismatch means the assembler read some line differently than intended, which
+is a bug in the compiler rather than in the program. Compare the `.asm` file
+written next to the binary with `beryllium inspect --disasm` to find the line,
+and please report it.
+"#),
+    ("BE0032", r#"`beryllium prof report` was given a file that is not a profile.
+
+Binaries built with `--instrument=profile` write their function counters to
+`beryllium.prof` in the working directory when they exit. The file was either
+written by something else, cut short, or comes from a version of beryllium
+with a different profile layout. Run the binary again to write a fresh one.
+"#),
+    ("BE0033", r#"A file given to `--extern` is not a module interface.
+
+`--extern` takes the `.bemeta` file that `--emit-meta` writes next to a
+module's binary, which starts with a `beryllium-meta` header followed by one
+`fn` line per exported function. Rebuild the module with `--emit-meta` and
+pass the `.bemeta` file rather than its object file or source.
+"#),
+    ("BE0034", r#"An `if` or a block was used as a value, but one of its ways through gives none.
+
+A block gives the value of the expression it ends in, which may leave out its
+semicolon, and an `if` gives the value of its taken branch:
+
+    let x = { let y = 2; y * 3 };
+    let z = if (c) { 1 } else { 2 };
+
+Both branches are needed, so an `if` without an `else` cannot be used this
+way, and each block has to end in an expression rather than a `let` or other
+statement. A block that leaves with `return`, `exit`, `break`, `continue` or
+`panic` needs no value.
+"#),
+    ("BE0035", r#"The build was stopped before it finished.
+
+A program embedding the compiler cancelled the build through the token it
+passed in `CompileArgs::with_cancellation`, usually because the source
+changed and a newer build is on its way. Builds are stopped between passes,
+so the files of the passes that finished may be left behind. Nothing needs
+fixing in the source.
+"#),
+    ("BE0036", r#"Expressions or statements are nested more than 256 levels deep.
+
+Each pair of parentheses, block, or branch inside another counts as a level,
+and so does each operator of a chain such as `1 + 1 + 1 + ...`. Code this
+deep is almost always generated; split it up with `let` bindings:
+
+    let a = 1 + 1 + 1 + 1;
+    let b = a + 1 + 1 + 1;
+"#),
+    ("BE0037", r#"A `break` or `continue` names a label that no loop around it has.
+
+Labels are written before a `loop`, `while` or `for` and let a jump leave or
+go on with an outer loop rather than the innermost one:
+
+    'rows: for y in 0..h {
+        for x in 0..w {
+            if (grid(x, y) == 0) continue 'rows;
+        }
+    }
+
+A label can only be named inside the body of its own loop. Check the spelling,
+or move the jump into the loop it is meant for.
+"#),
+    ("BE0038", r#"A function uses both `return value;` and a bare `return;`.
+
+A bare `return;` leaves a function that gives its caller nothing, such as one
+that only writes to memory:
+
+    fn clear(address) {
+        if (address == 0) return;
+        unsafe { volatile_store(address, 0); }
+    }
+
+A function that returns a value somewhere has to return one everywhere, so its
+callers always get one. Return a value such as `return 0;` instead.
+"#),
+    ("BE0039", r#"A function declared `-> ()` returns a value.
+
+`-> ()` says that a function gives its caller nothing, so it can only leave
+with a bare `return;`:
+
+    fn clear(address) -> () {
+        if (address == 0) return;
+        unsafe { volatile_store(address, 0); }
+    }
+
+Declare the function `-> i64` if it is meant to give a value, or drop the
+value from the `return`.
+"#),
+    ("BE0040", r#"The value of a call to a function declared `-> ()` is used.
+
+Such a function gives its caller nothing, so it can only be called as a
+statement of its own:
+
+    clear(address);
+    let x = clear(address);   // BE0040
+
+Declare the function `-> i64` and return a value from it if one is needed.
+"#),
+    ("BE0041", r#"The build was stopped because warnings are treated as errors.
+
+With `--fatal-warnings` every warning, such as an `else` that could belong to
+more than one `if`, fails the build:
+
+    if (a) if (b) exit(1); else exit(2);
+
+Fix the code each warning points at, or build without `--fatal-warnings`.
+`--max-errors N` stops after the first N of them.
+"#),
+    ("BE0042", r#"An integer literal is given to a variable whose type can't hold it.
+
+    fn _start() {
+        let small: u8 = 256;
+        exit(small);
+    }
+
+`u8` holds 0 to 255, `i8` -128 to 127, and so on up to `i64` and `u64`. Use a
+wider type, or a literal that fits. Values computed at run time are cut down to
+the width of the variable instead, so `small += 1` on 255 gives 0.
+"#),
+    ("BE0043", r#"A statement is missing the `;` that ends it.
+
+    fn _start() {
+        let x = 1
+        exit(x);
+    }
+
+The error points just past the end of the statement, where the `;` goes, and
+parsing carries on as if it were there so that later errors are found too.
+`beryllium fix` inserts it.
+"#),
+    ("BE0044", r#"The build was stopped after parsing found more than one syntax error.
+
+Parsing carries on past a statement without its `;`, so every such statement
+and the error parsing finally stopped at, if any, are reported one after the
+other, each with its own code. Fix them from the first one.
+`--max-errors N` shows only the first N of them.
+"#),
+    ("BE0045", r#"A variable that is not an array was indexed or iterated over.
+
+    fn _start() {
+        let x = 1;
+        exit(x[0]);
+    }
+
+Only variables given an array such as `let a = [1, 2, 3];` can be indexed. The same goes for a
+`for x in a` loop, which iterates over an array declared in the same function; anything else is
+written as a range, `for i in 0..n`.
+"#),
+    ("BE0046", r#"An array was used where a single value is needed.
+
+    fn _start() {
+        let a = [1, 2, 3];
+        exit(a);
+    }
+
+An array takes one stack slot for each of its elements, so it can't be passed,
+returned or assigned as a whole. Use one of its elements, such as `a[0]`, or
+assign them one at a time with `a[i] = value`.
+"#),
+    ("BE0047", r#"A `(`, `{` or `[` was not closed.
+
+    fn _start() {
+        exit(0;
+    }
+
+The error is reported where the closing delimiter was expected, with a label
+on the one it should have closed. That is the innermost delimiter still open
+there, so when a closer goes missing further up, count the pairs from the
+label outwards to find it.
+"#),
+    ("BE0048", r#"The source ended part way through an item.
+
+    fn _start() exit(0)
+
+Nothing was left open, so it is the rest of the item that is missing, often
+the `;` after its last statement.
+"#),
+    ("BE0049", r#"An attribute is not one the compiler knows, or has the wrong arguments.
+
+    #[inlne]
+    fn one() return 1;
+
+The attributes are `#[bits(n)]`, `#[export]`, `#[inline]`, `#[inline(always)]`,
+`#[inline(never)]`, `#[noinline]` and `#[test]`. Any other is an error rather
+than ignored, so a misspelled one doesn't silently do nothing.
+"#),
+];
+
+
+pub fn explain(code: &str) -> Option<&'static str> {
+    EXPLANATIONS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(code))
+        .map(|(_, explanation)| *explanation)
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn every_error_code_is_explained() {
+    use crate::{CompileError, ast, tokenize::{Location, Symbol, Token, TokenData}};
+
+    let errors = [
+        CompileError::IdentifierNotDeclared(String::new()),
+        CompileError::FunctionNotDeclared(String::new()),
+        CompileError::ChangedImmutableVariable(String::new()),
+        CompileError::InvalidTestFunction(String::new()),
+        CompileError::UnexpectedToken(Token { data: TokenData::Symbol(Symbol::Semi), location: Location::default() }),
+        CompileError::UnrecognizedCharacter('$'),
+        CompileError::UnterminatedStringLiteral(Location::default()),
+        CompileError::ToolchainMissing { tool: String::new(), hint: String::new() },
+        CompileError::CommandFailed { program: String::new(), status: None, stderr: String::new() },
+        CompileError::RefactorFailed(String::new()),
+        CompileError::IOError(std::io::Error::other("")),
+        CompileError::FromUtf8Error(String::from_utf8(vec![0xff]).unwrap_err()),
+        CompileError::NotReproducible(std::path::PathBuf::new()),
+        CompileError::NoBuildInfo(std::path::PathBuf::new()),
+        CompileError::SymbolMismatch { symbol: String::new(), source_file: std::path::PathBuf::new() },
+        CompileError::UnsupportedBits { function: String::new(), bits: 16, reason: String::new() },
+        CompileError::InvalidThreadFunction(String::new()),
+        CompileError::NonExhaustiveMatch(Location::default()),
+        CompileError::UnreachablePattern(Location::default()),
+        CompileError::RequiresUnsafe(String::new()),
+        CompileError::InvalidConstFunction { function: String::new(), reason: String::new() },
+        CompileError::StaticAssertFailed { message: String::new(), location: Location::default() },
+        CompileError::NotConstant(Location::default()),
+        CompileError::LiteralOutOfRange { literal: String::new(), location: Location::default() },
+        CompileError::UnassignedVariable(String::new()),
+        CompileError::ChainedComparison(Location::default()),
+        CompileError::NotAVariable(String::new()),
+        CompileError::UnterminatedBlockComment(Location::default()),
+        CompileError::InvalidEscape { sequence: String::new(), location: Location::default() },
+        CompileError::AbsoluteReference { function: String::new(), instruction: String::new(), location: Location::default() },
+        CompileError::RoundTripMismatch { label: String::new(), reason: String::new() },
+        CompileError::InvalidProfile(std::path::PathBuf::new()),
+        CompileError::InvalidMeta(std::path::PathBuf::new()),
+        CompileError::MissingValue(String::new()),
+        CompileError::Cancelled,
+        CompileError::TooDeep(Location::default()),
+        CompileError::UndeclaredLabel { label: String::new(), location: Location::default() },
+        CompileError::MixedReturns(String::new()),
+        CompileError::UnexpectedReturnValue(String::new()),
+        CompileError::NoReturnValue(String::new()),
+        CompileError::FatalWarnings { count: 0, diagnostics: Vec::new() },
+        CompileError::LiteralOutOfType { literal: String::new(), ty: ast::Type::U8, location: Location::default() },
+        CompileError::MissingSemicolon(Location::default()),
+        CompileError::SyntaxErrors { count: 0, diagnostics: Vec::new() },
+        CompileError::NotAnArray(String::new()),
+        CompileError::ArrayAsValue(String::new()),
+        CompileError::UnclosedDelimiter { open: Token { data: TokenData::Symbol(Symbol::LParen), location: Location::default() }, location: Location::default() },
+        CompileError::UnexpectedEnd(Location::default()),
+        CompileError::InvalidAttribute(ast::Attribute { name: String::new(), args: Vec::new(), location: Location::default() }),
+    ];
+    let codes: Vec<&str> = errors.iter().map(CompileError::code).collect();
+    let known: Vec<&str> = EXPLANATIONS.iter().map(|(code, _)| *code).collect();
+    assert_eq!(codes, known);
+}
+
+#[test]
+fn codes_are_case_insensitive() {
+    assert_eq!(explain("be0003"), explain("BE0003"));
+    assert!(explain("BE9999").is_none());
+}
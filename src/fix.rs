@@ -0,0 +1,133 @@
+use fallible_iterator::FallibleIterator;
+
+use crate::{
+    CompileError,
+    codegen::x86::Codegen,
+    const_eval,
+    context::Context,
+    diagnostic::Suggestion,
+    messages::{self, Lang},
+    parser::Parser,
+    scope::{BindingKind, ScopeTree},
+    tokenize::{Keyword, Token, TokenData, Tokenize},
+    type_registry::{self, TypeRegistry},
+};
+
+
+// each fix can change what the next error is, so they are made one at a time. this bounds how many
+// in case one keeps undoing another
+const MAX_FIXES: usize = 100;
+
+
+#[derive(Debug)]
+pub struct Fixes {
+    pub source: String,
+    pub applied: usize,
+    // the first error that has no fix, if the source still doesn't build
+    pub remaining: Option<CompileError>,
+}
+
+
+// makes the first suggestion for the first error until the source builds or the error has none
+pub fn fix(source: &str) -> Fixes {
+    let mut source = source.to_string();
+    let mut applied = 0;
+    loop {
+        let Err(error) = check(&source) else {
+            return Fixes { source, applied, remaining: None };
+        };
+        match suggestions(&source, &error, Lang::default()).first() {
+            Some(suggestion) if applied < MAX_FIXES => {
+                source = suggestion.apply(&source);
+                applied += 1;
+            },
+            _ => return Fixes { source, applied, remaining: Some(error) },
+        }
+    }
+}
+
+// the passes a build runs up to and including codegen, with the default options
+fn check(source: &str) -> Result<(), CompileError> {
+    let tree = Parser::new(source.tokenize()).parse()?;
+    let tree = const_eval::inline_constants(tree)?;
+    const_eval::check_static_asserts(&tree)?;
+    let registry = TypeRegistry::from(&tree);
+    type_registry::check_return_types(&tree, &registry)?;
+    tree.codegen_x86(&mut Context::new(registry))?;
+    Ok(())
+}
+
+
+// the errors only carry a name or the token they stopped at, so what to change is found again from
+// the source. errors that could be fixed more than one way have no suggestion
+pub fn suggestions(source: &str, error: &CompileError, lang: Lang) -> Vec<Suggestion> {
+    let Ok(tokens) = source.tokenize().collect::<Vec<Token>>() else {
+        return Vec::new();
+    };
+    let suggestion = match error {
+        CompileError::ChangedImmutableVariable(name) => make_mutable(source, &tokens, name, lang),
+        CompileError::MissingSemicolon(at) => Some(Suggestion::insert(messages::message(lang, "insert-semicolon", &[]), *at, ";")),
+        CompileError::UnexpectedToken(token) => insert_semicolon(source, &tokens, token, lang),
+        _ => None,
+    };
+    suggestion.into_iter().collect()
+}
+
+fn make_mutable(source: &str, tokens: &[Token], name: &str, lang: Lang) -> Option<Suggestion> {
+    let (program, map) = Parser::new(source.tokenize()).parse_with_map().ok()?;
+    let scopes = ScopeTree::new(&program, &map);
+    let mut immutable = scopes.bindings()
+        .filter(|binding| binding.name == name && binding.kind == BindingKind::Variable { is_mutable: false });
+    let binding = immutable.next().filter(|_| immutable.next().is_none())?;
+    let start = map.span(binding.node)?.start;
+    // loops declare variables of their own, which have no `let` written in the source
+    match tokens.iter().position(|token| token.location == start).map(|i| &tokens[i..]) {
+        Some([Token { data: TokenData::Keyword(Keyword::Let), .. }, identifier, ..]) if identifier.data == TokenData::Identifier(name.to_string()) =>
+            Some(Suggestion::insert(messages::message(lang, "make-mutable", &[("name", name.to_string())]), identifier.location, "mut ")),
+        _ => None,
+    }
+}
+
+// only suggested if the parser gets past the token it stopped at once the `;` is there. missing `;`s
+// elsewhere are carried on past, so it is the last error that says where it stopped
+fn insert_semicolon(source: &str, tokens: &[Token], unexpected: &Token, lang: Lang) -> Option<Suggestion> {
+    let i = tokens.iter().position(|token| token.location == unexpected.location)?;
+    let previous = tokens.get(i.checked_sub(1)?)?;
+    let suggestion = Suggestion::insert(messages::message(lang, "insert-semicolon", &[]), previous.end(), ";");
+    let mut parser = Parser::new(suggestion.apply(source).tokenize());
+    let stopped = parser.parse().err().and_then(|_| parser.errors().last().cloned());
+    match stopped.map(CompileError::from) {
+        None | Some(CompileError::MissingSemicolon(_)) => Some(suggestion),
+        Some(CompileError::UnexpectedToken(token)) if token.location.index > unexpected.location.index + 1 => Some(suggestion),
+        Some(_) => None,
+    }
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn missing_mut_and_semicolons_are_fixed() {
+    let fixes = fix("fn _start() {\n    let x = 1\n    x += 2;\n    exit(x)\n}\n");
+    assert_eq!(fixes.source, "fn _start() {\n    let mut x = 1;\n    x += 2;\n    exit(x);\n}\n");
+    assert_eq!(fixes.applied, 3);
+    assert!(fixes.remaining.is_none());
+
+    let fixes = fix("fn _start() exit(y);");
+    assert_eq!(fixes.applied, 0);
+    assert!(matches!(fixes.remaining, Some(CompileError::IdentifierNotDeclared(_))));
+    // which of the two should be mutable can't be told
+    let source = "fn _start() { let x = 1; { let x = 2; x = 3; } exit(x); }";
+    assert!(suggestions(source, &CompileError::ChangedImmutableVariable(String::from("x")), Lang::En).is_empty());
+}
+
+#[test]
+fn suggestions_are_localized() {
+    let source = "fn _start() { let x = 1; x = 2; exit(x); }";
+    let suggestions = suggestions(source, &CompileError::ChangedImmutableVariable(String::from("x")), Lang::De);
+    assert_eq!(suggestions.iter().map(|suggestion| suggestion.message.as_str()).collect::<Vec<_>>(), ["`x` veränderlich machen"]);
+}
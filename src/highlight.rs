@@ -0,0 +1,178 @@
+use fallible_iterator::FallibleIterator;
+
+use crate::{
+    report::json_string,
+    tokenize::{Symbol, Token, TokenData, TokenizerError, Tokenize},
+};
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HighlightFormat {
+    Html,
+    Json,
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TokenClass {
+    Keyword,
+    Function,
+    Variable,
+    Number,
+    String,
+    Operator,
+    Punctuation,
+    Comment,
+    Whitespace,
+}
+
+impl TokenClass {
+    // the semantic token types in the order of the legend, punctuation and whitespace are not reported
+    const LEGEND: [Self; 7] = [
+        Self::Keyword, Self::Function, Self::Variable, Self::Number, Self::String, Self::Operator, Self::Comment,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Keyword => "keyword",
+            Self::Function => "function",
+            Self::Variable => "variable",
+            Self::Number => "number",
+            Self::String => "string",
+            Self::Operator => "operator",
+            Self::Punctuation => "punctuation",
+            Self::Comment => "comment",
+            Self::Whitespace => "whitespace",
+        }
+    }
+
+    fn classify(token: &Token, next: Option<&Token>) -> Self {
+        match &token.data {
+            TokenData::Keyword(_) => Self::Keyword,
+            TokenData::Identifier(_) => match next.map(|next| &next.data) {
+                Some(TokenData::Symbol(Symbol::LParen)) => Self::Function,
+                _ => Self::Variable,
+            },
+            TokenData::Label(_) => Self::Variable,
+            TokenData::IntegerLiteral(_) => Self::Number,
+            TokenData::StringLiteral(_) => Self::String,
+            TokenData::Symbol(
+                Symbol::LParen | Symbol::RParen | Symbol::LBrace | Symbol::RBrace
+                    | Symbol::LBracket | Symbol::RBracket
+                    | Symbol::Semi | Symbol::Colon | Symbol::Comma | Symbol::Hash
+            ) => Self::Punctuation,
+            TokenData::Symbol(_) => Self::Operator,
+            TokenData::Comment(_) => Self::Comment,
+            TokenData::Whitespace(_) => Self::Whitespace,
+        }
+    }
+}
+
+
+struct Span<'a> {
+    token: Token,
+    text: &'a [char],
+    class: TokenClass,
+}
+
+fn spans(source: &[char]) -> Result<Vec<Span<'_>>, TokenizerError> {
+    let tokens: Vec<Token> = source.to_vec().tokenize().with_trivia().collect()?;
+    let significant: Vec<usize> = (0..tokens.len())
+        .filter(|index| !matches!(tokens[*index].data, TokenData::Whitespace(_) | TokenData::Comment(_)))
+        .collect();
+
+    let mut spans = Vec::new();
+    for (index, token) in tokens.iter().enumerate() {
+        // with trivia kept the tokens cover the source, so each one runs up to the start of the next
+        let start = token.location.index as usize;
+        let end = tokens.get(index + 1).map(|next| next.location.index as usize).unwrap_or(source.len());
+        let next = significant.iter().find(|other| **other > index).map(|other| &tokens[*other]);
+        spans.push(Span {
+            token: token.clone(),
+            text: &source[start..end],
+            class: TokenClass::classify(token, next),
+        });
+    }
+    Ok(spans)
+}
+
+
+pub fn highlight(source: &str, format: HighlightFormat) -> Result<String, TokenizerError> {
+    let source: Vec<char> = source.chars().collect();
+    let spans = spans(&source)?;
+    Ok(match format {
+        HighlightFormat::Html => to_html(&spans),
+        HighlightFormat::Json => to_semantic_tokens(&spans),
+    })
+}
+
+fn to_html(spans: &[Span]) -> String {
+    let mut html = String::from("<pre class=\"beryllium\">");
+    for span in spans {
+        let text = escape_html(&span.text.iter().collect::<String>());
+        match span.class {
+            TokenClass::Whitespace => html += &text,
+            class => html += &format!("<span class=\"be-{}\">{text}</span>", class.name()),
+        }
+    }
+    html += "</pre>\n";
+    html
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// encodes the tokens the way an LSP `textDocument/semanticTokens` response does, as relative
+// line/column deltas with zero-based positions
+fn to_semantic_tokens(spans: &[Span]) -> String {
+    let mut data = Vec::new();
+    let (mut previous_line, mut previous_column) = (0, 0);
+    for span in spans {
+        let Some(kind) = TokenClass::LEGEND.iter().position(|class| *class == span.class) else {
+            continue;
+        };
+        let line = span.token.location.line - 1;
+        let column = span.token.location.column - 1;
+        let delta_column = match line == previous_line {
+            true => column - previous_column,
+            false => column,
+        };
+        data.push(format!("{}, {delta_column}, {}, {kind}, 0", line - previous_line, span.text.len()));
+        (previous_line, previous_column) = (line, column);
+    }
+
+    let legend: Vec<String> = TokenClass::LEGEND.iter().map(|class| json_string(class.name())).collect();
+    let mut json = String::from("{\n");
+    json += &format!("  \"legend\": {{\"tokenTypes\": [{}], \"tokenModifiers\": []}},\n", legend.join(", "));
+    json += &format!("  \"data\": [{}]\n", data.join(", "));
+    json += "}\n";
+    json
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn html_wraps_classified_tokens() {
+    let html = highlight("fn f() exit(1 < 2);", HighlightFormat::Html).unwrap();
+    assert_eq!(html, concat!(
+        "<pre class=\"beryllium\">",
+        "<span class=\"be-keyword\">fn</span> <span class=\"be-function\">f</span>",
+        "<span class=\"be-punctuation\">(</span><span class=\"be-punctuation\">)</span> ",
+        "<span class=\"be-keyword\">exit</span><span class=\"be-punctuation\">(</span>",
+        "<span class=\"be-number\">1</span> <span class=\"be-operator\">&lt;</span> ",
+        "<span class=\"be-number\">2</span><span class=\"be-punctuation\">)</span>",
+        "<span class=\"be-punctuation\">;</span></pre>\n",
+    ));
+}
+
+#[test]
+fn semantic_tokens_are_relative() {
+    let json = highlight("let x = 1;\n  exit(x);", HighlightFormat::Json).unwrap();
+    assert!(json.contains("\"data\": [0, 0, 3, 0, 0, 0, 4, 1, 2, 0, 0, 2, 1, 5, 0, 0, 2, 1, 3, 0, 1, 2, 4, 0, 0, 0, 5, 1, 2, 0]"));
+}
@@ -1,26 +1,69 @@
-mod ast;
+pub mod ast;
+mod assignment;
+mod build_info;
+mod callgraph;
+mod cfg;
 mod codegen;
+mod const_eval;
 mod context;
+mod diagnostic;
+mod disasm;
+mod explain;
+mod fix;
+mod highlight;
 mod iter;
-mod parser;
-mod tokenize;
+mod liveness;
+mod messages;
+pub mod node_map;
+mod optimize;
+pub mod parser;
+mod profile;
+mod refactor;
+mod report;
+pub mod scope;
+pub mod source_map;
+mod stats;
+pub mod syntax;
+pub mod tokenize;
+mod toolchain;
+mod trace;
 mod type_registry;
+mod visit;
 
 use std::{
+    collections::{BTreeSet, HashMap},
     fs::File,
     io::{Read, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
 };
 
 use crate::{
+    ast::Item,
+    callgraph::CallGraph,
     codegen::CodegenError,
-    parser::ParseError,
-    tokenize::{Token, TokenizerError},
+    parser::{Edition, LiteralOverflow, ParseError},
+    report::BuildReport,
+    tokenize::{Location, Token, TokenData, TokenizerError},
     type_registry::TypeRegistry,
 };
 
 use crate::context::Context;
 
+pub use crate::{
+    build_info::BuildInfo,
+    codegen::x86::{bits::Bits, cpu::{Cpu, Feature}, format::{AsmFormat, LabelStyle}, intrinsics::{IntrinsicDef, Safety, INTRINSICS}},
+    diagnostic::{Diagnostic, DiagnosticSink, Edit, Label, Level, Suggestion},
+    fix::Fixes,
+    highlight::HighlightFormat,
+    messages::Lang,
+};
+
+// the syntax api is covered by semver from here on, so tools can build on the tokenizer and
+// parser without depending on the rest of the compiler
+pub use fallible_iterator::FallibleIterator;
+
+
 
 trait RunCommand {
     fn run(&mut self) -> Result<(), CompileError>;
@@ -30,29 +73,338 @@ impl RunCommand for std::process::Command {
     fn run(&mut self) -> Result<(), CompileError> {
         let output = self.output()?;
         if !output.status.success() {
-            eprintln!("assembly failed with code {}", output.status);
-            eprintln!("{}", String::from_utf8(output.stderr)?);
-            eprintln!("{}", String::from_utf8(output.stdout)?);
-            panic!();
+            return Err(CompileError::CommandFailed {
+                program: self.get_program().to_string_lossy().into_owned(),
+                status: output.status.code(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
         }
         Ok(())
     }
 }
 
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Emit {
+    #[default]
+    Binary,
+    CallGraph,
+    Cfg,
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+}
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Target {
+    #[default]
+    X86_64Linux,
+}
+
+impl Target {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::X86_64Linux => "x86_64-linux",
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instrument {
+    Profile,
+    Trace,
+}
+
+impl Instrument {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Profile => "profile",
+            Self::Trace => "trace",
+        }
+    }
+}
+
+
+// lets another thread stop a build that is no longer wanted, which it does at the end of the pass
+// that is running
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+
 #[derive(Clone, Debug)]
 pub struct CompileArgs {
     pub source_file: PathBuf,
     pub target_file: Option<PathBuf>,
+    pub emit: Emit,
+    pub keep_all: bool,
+    pub asm_format: AsmFormat,
+    pub report: Option<ReportFormat>,
+    pub opt_level: u8,
+    pub debug_info: bool,
+    pub checked: bool,
+    pub target: Target,
+    pub reproducible: bool,
+    pub linker_script: Option<PathBuf>,
+    pub section_starts: Vec<(String, u64)>,
+    pub entry: Option<String>,
+    pub bits: Bits,
+    pub literal_overflow: LiteralOverflow,
+    pub edition: Edition,
+    // stops the build once this many errors have been reported, if set
+    pub max_errors: Option<usize>,
+    pub fatal_warnings: bool,
+    // the language of the diagnostics the build gives back
+    pub lang: Lang,
+    pub cpu: Cpu,
+    pub target_features: Vec<(Feature, bool)>,
+    pub detect_features: bool,
+    pub verify_pic: bool,
+    pub verify: bool,
+    pub stack_protector: bool,
+    pub instrument: Vec<Instrument>,
+    pub emit_meta: bool,
+    pub externs: Vec<PathBuf>,
+    pub cancellation: Option<CancellationToken>,
+}
+
+impl Default for CompileArgs {
+    fn default() -> Self {
+        Self {
+            source_file: PathBuf::new(),
+            target_file: None,
+            emit: Emit::default(),
+            keep_all: false,
+            asm_format: AsmFormat::default(),
+            report: None,
+            opt_level: 1,
+            debug_info: false,
+            checked: true,
+            target: Target::default(),
+            reproducible: false,
+            linker_script: None,
+            section_starts: Vec::new(),
+            entry: None,
+            bits: Bits::default(),
+            literal_overflow: LiteralOverflow::default(),
+            edition: Edition::default(),
+            max_errors: None,
+            fatal_warnings: false,
+            lang: Lang::default(),
+            cpu: Cpu::default(),
+            target_features: Vec::new(),
+            detect_features: false,
+            verify_pic: false,
+            verify: false,
+            stack_protector: false,
+            instrument: Vec::new(),
+            emit_meta: false,
+            externs: Vec::new(),
+            cancellation: None,
+        }
+    }
 }
 
 impl CompileArgs {
+    pub fn new(source_file: impl Into<PathBuf>) -> Self {
+        Self { source_file: source_file.into(), ..Default::default() }
+    }
+
+    pub fn with_target_file(mut self, target_file: impl Into<PathBuf>) -> Self {
+        self.target_file = Some(target_file.into());
+        self
+    }
+
+    pub fn with_emit(mut self, emit: Emit) -> Self {
+        self.emit = emit;
+        self
+    }
+
+    pub fn with_keep_all(mut self, keep_all: bool) -> Self {
+        self.keep_all = keep_all;
+        self
+    }
+
+    pub fn with_asm_format(mut self, asm_format: AsmFormat) -> Self {
+        self.asm_format = asm_format;
+        self
+    }
+
+    pub fn with_report(mut self, report: ReportFormat) -> Self {
+        self.report = Some(report);
+        self
+    }
+
+    pub fn with_opt_level(mut self, opt_level: u8) -> Self {
+        self.opt_level = opt_level;
+        self
+    }
+
+    pub fn with_debug_info(mut self, debug_info: bool) -> Self {
+        self.debug_info = debug_info;
+        self
+    }
+
+    pub fn with_checked(mut self, checked: bool) -> Self {
+        self.checked = checked;
+        self
+    }
+
+    pub fn with_target(mut self, target: Target) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn with_reproducible(mut self, reproducible: bool) -> Self {
+        self.reproducible = reproducible;
+        self
+    }
+
+    pub fn with_linker_script(mut self, linker_script: impl Into<PathBuf>) -> Self {
+        self.linker_script = Some(linker_script.into());
+        self
+    }
+
+    pub fn with_section_start(mut self, section: impl Into<String>, address: u64) -> Self {
+        self.section_starts.push((section.into(), address));
+        self
+    }
+
+    pub fn with_entry(mut self, entry: impl Into<String>) -> Self {
+        self.entry = Some(entry.into());
+        self
+    }
+
+    pub fn with_bits(mut self, bits: Bits) -> Self {
+        self.bits = bits;
+        self
+    }
+
+    pub fn with_literal_overflow(mut self, literal_overflow: LiteralOverflow) -> Self {
+        self.literal_overflow = literal_overflow;
+        self
+    }
+
+    pub fn with_edition(mut self, edition: Edition) -> Self {
+        self.edition = edition;
+        self
+    }
+
+    pub fn with_max_errors(mut self, max_errors: usize) -> Self {
+        self.max_errors = Some(max_errors);
+        self
+    }
+
+    pub fn with_lang(mut self, lang: Lang) -> Self {
+        self.lang = lang;
+        self
+    }
+
+    pub fn with_fatal_warnings(mut self, fatal_warnings: bool) -> Self {
+        self.fatal_warnings = fatal_warnings;
+        self
+    }
+
+    pub fn with_cpu(mut self, cpu: Cpu) -> Self {
+        self.cpu = cpu;
+        self
+    }
+
+    // turns a single feature on or off on top of those of the cpu, later calls win
+    pub fn with_target_feature(mut self, feature: Feature, enabled: bool) -> Self {
+        self.target_features.push((feature, enabled));
+        self
+    }
+
+    pub fn with_detect_features(mut self, detect_features: bool) -> Self {
+        self.detect_features = detect_features;
+        self
+    }
+
+    pub fn with_verify_pic(mut self, verify_pic: bool) -> Self {
+        self.verify_pic = verify_pic;
+        self
+    }
+
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    pub fn with_stack_protector(mut self, stack_protector: bool) -> Self {
+        self.stack_protector = stack_protector;
+        self
+    }
+
+    pub fn with_instrument(mut self, instrument: Instrument) -> Self {
+        if !self.instrument.contains(&instrument) {
+            self.instrument.push(instrument);
+        }
+        self
+    }
+
+    pub fn with_emit_meta(mut self, emit_meta: bool) -> Self {
+        self.emit_meta = emit_meta;
+        self
+    }
+
+    // the `.bemeta` interface of a separately compiled module, its object file is expected next to
+    // it and is linked in
+    pub fn with_extern(mut self, meta: impl Into<PathBuf>) -> Self {
+        self.externs.push(meta.into());
+        self
+    }
+
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
+    pub fn features(&self) -> BTreeSet<Feature> {
+        let mut features = self.cpu.features();
+        for (feature, enabled) in &self.target_features {
+            match enabled {
+                true => features.insert(*feature),
+                false => features.remove(feature),
+            };
+        }
+        features
+    }
+
     pub fn get_target_file(&self) -> PathBuf {
         match &self.target_file {
             Some(target_file) => target_file.clone(),
             None => self.source_file.with_extension(""),
         }
     }
+
+    pub fn get_test_target_file(&self) -> PathBuf {
+        match &self.target_file {
+            Some(target_file) => target_file.clone(),
+            None => {
+                let stem = self.source_file.file_stem().unwrap_or_default().to_string_lossy();
+                self.source_file.with_file_name(format!("{stem}_test"))
+            },
+        }
+    }
 }
 
 
@@ -61,15 +413,219 @@ pub enum CompileError {
     IdentifierNotDeclared(String),
     FunctionNotDeclared(String),
     ChangedImmutableVariable(String),
+    InvalidTestFunction(String),
+    InvalidThreadFunction(String),
+    RequiresUnsafe(String),
+    InvalidConstFunction { function: String, reason: String },
+    UnassignedVariable(String),
+    NotAVariable(String),
+    MissingValue(String),
+    MixedReturns(String),
+    UnexpectedReturnValue(String),
+    NoReturnValue(String),
+    FatalWarnings { count: usize, diagnostics: Vec<Diagnostic> },
+    Cancelled,
+    UnsupportedBits { function: String, bits: u64, reason: String },
     UnexpectedToken(Token),
     UnrecognizedCharacter(char),
+    UnterminatedStringLiteral(Location),
+    UnterminatedBlockComment(Location),
+    InvalidEscape { sequence: String, location: Location },
+    AbsoluteReference { function: String, instruction: String, location: Location },
+    RoundTripMismatch { label: String, reason: String },
+    InvalidProfile(PathBuf),
+    InvalidMeta(PathBuf),
+    NonExhaustiveMatch(Location),
+    UnreachablePattern(Location),
+    StaticAssertFailed { message: String, location: Location },
+    NotConstant(Location),
+    LiteralOutOfRange { literal: String, location: Location },
+    LiteralOutOfType { literal: String, ty: ast::Type, location: Location },
+    ChainedComparison(Location),
+    TooDeep(Location),
+    UndeclaredLabel { label: String, location: Location },
+    MissingSemicolon(Location),
+    // every error the parser found, of which the diagnostics are those within `--max-errors`
+    SyntaxErrors { count: usize, diagnostics: Vec<Diagnostic> },
+    NotAnArray(String),
+    ArrayAsValue(String),
+    UnclosedDelimiter { open: Token, location: Location },
+    UnexpectedEnd(Location),
+    InvalidAttribute(ast::Attribute),
+    ToolchainMissing { tool: String, hint: String },
+    CommandFailed { program: String, status: Option<i32>, stderr: String },
+    RefactorFailed(String),
+    NotReproducible(PathBuf),
+    NoBuildInfo(PathBuf),
+    SymbolMismatch { symbol: String, source_file: PathBuf },
     IOError(std::io::Error),
     FromUtf8Error(std::string::FromUtf8Error),
 }
 
 impl std::fmt::Display for CompileError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "{self:?}")
+        write!(f, "{}", self.localized(Lang::En))
+    }
+}
+
+impl CompileError {
+    // stable codes for `beryllium explain`, new variants get the next free code and codes are
+    // never reused
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::IdentifierNotDeclared(_) => "BE0001",
+            Self::FunctionNotDeclared(_) => "BE0002",
+            Self::ChangedImmutableVariable(_) => "BE0003",
+            Self::InvalidTestFunction(_) => "BE0004",
+            Self::UnexpectedToken(_) => "BE0005",
+            Self::UnrecognizedCharacter(_) => "BE0006",
+            Self::UnterminatedStringLiteral(_) => "BE0007",
+            Self::ToolchainMissing { .. } => "BE0008",
+            Self::CommandFailed { .. } => "BE0009",
+            Self::RefactorFailed(_) => "BE0010",
+            Self::IOError(_) => "BE0011",
+            Self::FromUtf8Error(_) => "BE0012",
+            Self::NotReproducible(_) => "BE0013",
+            Self::NoBuildInfo(_) => "BE0014",
+            Self::SymbolMismatch { .. } => "BE0015",
+            Self::UnsupportedBits { .. } => "BE0016",
+            Self::InvalidThreadFunction(_) => "BE0017",
+            Self::NonExhaustiveMatch(_) => "BE0018",
+            Self::UnreachablePattern(_) => "BE0019",
+            Self::RequiresUnsafe(_) => "BE0020",
+            Self::InvalidConstFunction { .. } => "BE0021",
+            Self::StaticAssertFailed { .. } => "BE0022",
+            Self::NotConstant(_) => "BE0023",
+            Self::LiteralOutOfRange { .. } => "BE0024",
+            Self::UnassignedVariable(_) => "BE0025",
+            Self::ChainedComparison(_) => "BE0026",
+            Self::NotAVariable(_) => "BE0027",
+            Self::UnterminatedBlockComment(_) => "BE0028",
+            Self::InvalidEscape { .. } => "BE0029",
+            Self::AbsoluteReference { .. } => "BE0030",
+            Self::RoundTripMismatch { .. } => "BE0031",
+            Self::InvalidProfile(_) => "BE0032",
+            Self::InvalidMeta(_) => "BE0033",
+            Self::MissingValue(_) => "BE0034",
+            Self::Cancelled => "BE0035",
+            Self::TooDeep(_) => "BE0036",
+            Self::UndeclaredLabel { .. } => "BE0037",
+            Self::MixedReturns(_) => "BE0038",
+            Self::UnexpectedReturnValue(_) => "BE0039",
+            Self::NoReturnValue(_) => "BE0040",
+            Self::FatalWarnings { .. } => "BE0041",
+            Self::LiteralOutOfType { .. } => "BE0042",
+            Self::MissingSemicolon(_) => "BE0043",
+            Self::SyntaxErrors { .. } => "BE0044",
+            Self::NotAnArray(_) => "BE0045",
+            Self::ArrayAsValue(_) => "BE0046",
+            Self::UnclosedDelimiter { .. } => "BE0047",
+            Self::UnexpectedEnd(_) => "BE0048",
+            Self::InvalidAttribute(_) => "BE0049",
+        }
+    }
+
+    pub fn localized(&self, lang: Lang) -> String {
+        let name = |name: &String| vec![("name", name.clone())];
+        let position = |location: &Location| vec![("line", location.line.to_string()), ("column", location.column.to_string())];
+        let (key, args) = match self {
+            Self::IdentifierNotDeclared(ident) | Self::FunctionNotDeclared(ident)
+                | Self::ChangedImmutableVariable(ident) | Self::InvalidTestFunction(ident)
+                | Self::InvalidThreadFunction(ident) | Self::RequiresUnsafe(ident)
+                | Self::UnassignedVariable(ident) | Self::NotAVariable(ident)
+                | Self::MissingValue(ident) | Self::MixedReturns(ident)
+                | Self::UnexpectedReturnValue(ident) | Self::NoReturnValue(ident)
+                | Self::NotAnArray(ident) | Self::ArrayAsValue(ident) => (self.code(), name(ident)),
+            Self::InvalidConstFunction { function, reason } =>
+                (self.code(), vec![("name", function.clone()), ("reason", reason.clone())]),
+            Self::UnsupportedBits { function, bits, reason } =>
+                (self.code(), vec![("name", function.clone()), ("bits", bits.to_string()), ("reason", reason.clone())]),
+            Self::UnexpectedToken(Token { data, location }) => {
+                let mut args = position(location);
+                args.push(("token", format!("{data:?}")));
+                (self.code(), args)
+            },
+            Self::UnrecognizedCharacter(c) => (self.code(), vec![("character", format!("{c:?}"))]),
+            Self::UnterminatedStringLiteral(location) | Self::UnterminatedBlockComment(location) | Self::NonExhaustiveMatch(location)
+                | Self::UnreachablePattern(location) | Self::NotConstant(location)
+                | Self::ChainedComparison(location) | Self::TooDeep(location)
+                | Self::MissingSemicolon(location) | Self::UnexpectedEnd(location) => (self.code(), position(location)),
+            Self::LiteralOutOfRange { literal, location } => {
+                let mut args = position(location);
+                args.push(("literal", literal.clone()));
+                (self.code(), args)
+            },
+            Self::LiteralOutOfType { literal, ty, location } => {
+                let mut args = position(location);
+                args.push(("literal", literal.clone()));
+                args.push(("type", ty.to_string()));
+                (self.code(), args)
+            },
+            Self::UnclosedDelimiter { open, location } => {
+                let mut args = position(location);
+                let close = match &open.data {
+                    TokenData::Symbol(symbol) => symbol.closing().map_or("", |close| close.as_str()),
+                    _ => "",
+                };
+                args.extend([
+                    ("open", open.data.text()), ("close", close.to_string()),
+                    ("open_line", open.location.line.to_string()), ("open_column", open.location.column.to_string()),
+                ]);
+                (self.code(), args)
+            },
+            Self::InvalidAttribute(attribute) => {
+                let mut args = position(&attribute.location);
+                args.push(("attribute", attribute.to_string()));
+                match attribute.usage() {
+                    Some(usage) => { args.push(("usage", usage)); (self.code(), args) },
+                    None => ("BE0049-unknown", args),
+                }
+            },
+            Self::UndeclaredLabel { label, location } => {
+                let mut args = position(location);
+                args.push(("label", label.clone()));
+                (self.code(), args)
+            },
+            Self::InvalidEscape { sequence, location } => {
+                let mut args = position(location);
+                args.push(("sequence", sequence.clone()));
+                (self.code(), args)
+            },
+            Self::RoundTripMismatch { label, reason } => (self.code(), vec![("name", label.clone()), ("reason", reason.clone())]),
+            Self::AbsoluteReference { function, instruction, location } => {
+                let mut args = position(location);
+                args.extend([("name", function.clone()), ("instruction", instruction.clone())]);
+                (self.code(), args)
+            },
+            Self::StaticAssertFailed { message, location } => {
+                let mut args = position(location);
+                args.push(("message", message.clone()));
+                (self.code(), args)
+            },
+            Self::ToolchainMissing { tool, hint } => (self.code(), vec![("tool", tool.clone()), ("hint", hint.clone())]),
+            Self::CommandFailed { program, status: Some(code), stderr: _ } =>
+                (self.code(), vec![("program", program.clone()), ("status", code.to_string())]),
+            Self::CommandFailed { program, status: None, stderr: _ } => ("BE0009-signal", vec![("program", program.clone())]),
+            Self::RefactorFailed(reason) => (self.code(), vec![("reason", reason.clone())]),
+            Self::Cancelled => (self.code(), Vec::new()),
+            Self::FatalWarnings { count, .. } | Self::SyntaxErrors { count, .. } => (self.code(), vec![("count", count.to_string())]),
+            Self::NotReproducible(path) | Self::NoBuildInfo(path) | Self::InvalidProfile(path)
+                | Self::InvalidMeta(path) =>
+                (self.code(), vec![("path", path.display().to_string())]),
+            Self::SymbolMismatch { symbol, source_file } =>
+                (self.code(), vec![("symbol", symbol.clone()), ("path", source_file.display().to_string())]),
+            Self::IOError(err) => (self.code(), vec![("error", err.to_string())]),
+            Self::FromUtf8Error(err) => (self.code(), vec![("error", err.to_string())]),
+        };
+        messages::message(lang, key, &args)
+    }
+
+    // the diagnostics found before the build stopped, to be shown ahead of the error itself
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        match self {
+            Self::FatalWarnings { count: _, diagnostics } | Self::SyntaxErrors { count: _, diagnostics } => diagnostics,
+            _ => &[],
+        }
     }
 }
 
@@ -81,6 +637,20 @@ impl From<CodegenError> for CompileError {
             CodegenError::IdentifierNotDeclared(ident) => Self::IdentifierNotDeclared(ident),
             CodegenError::ChangedImmutableVariable(ident) => Self::ChangedImmutableVariable(ident),
             CodegenError::FunctionNotDeclared(ident) => Self::FunctionNotDeclared(ident),
+            CodegenError::InvalidTestFunction(ident) => Self::InvalidTestFunction(ident),
+            CodegenError::InvalidThreadFunction(ident) => Self::InvalidThreadFunction(ident),
+            CodegenError::RequiresUnsafe(ident) => Self::RequiresUnsafe(ident),
+            CodegenError::InvalidConstFunction { function, reason } => Self::InvalidConstFunction { function, reason },
+            CodegenError::UnassignedVariable(ident) => Self::UnassignedVariable(ident),
+            CodegenError::NotAVariable(ident) => Self::NotAVariable(ident),
+            CodegenError::MissingValue(ident) => Self::MissingValue(ident),
+            CodegenError::MixedReturns(ident) => Self::MixedReturns(ident),
+            CodegenError::NotAnArray(ident) => Self::NotAnArray(ident),
+            CodegenError::ArrayAsValue(ident) => Self::ArrayAsValue(ident),
+            CodegenError::UnsupportedBits { function, bits, reason } => Self::UnsupportedBits { function, bits, reason },
+            // the location of the function is filled in by `run_passes`, which still has the source
+            CodegenError::AbsoluteReference { function, instruction } =>
+                Self::AbsoluteReference { function, instruction, location: Location::default() },
         }
     }
 }
@@ -89,6 +659,18 @@ impl From<ParseError> for CompileError {
     fn from(value: ParseError) -> Self {
         match value {
             ParseError::UnexpectedToken(tok) => Self::UnexpectedToken(tok),
+            ParseError::NonExhaustiveMatch(location) => Self::NonExhaustiveMatch(location),
+            ParseError::UnreachablePattern(location) => Self::UnreachablePattern(location),
+            ParseError::LiteralOutOfRange { literal, location } => Self::LiteralOutOfRange { literal, location },
+            ParseError::LiteralOutOfType { literal, ty, location } => Self::LiteralOutOfType { literal, ty, location },
+            ParseError::ChainedComparison(location) => Self::ChainedComparison(location),
+            ParseError::TooDeep(location) => Self::TooDeep(location),
+            ParseError::UndeclaredLabel { label, location } => Self::UndeclaredLabel { label, location },
+            ParseError::MissingSemicolon(location) => Self::MissingSemicolon(location),
+            ParseError::UnclosedDelimiter { open, location } => Self::UnclosedDelimiter { open, location },
+            ParseError::UnexpectedEnd(location) => Self::UnexpectedEnd(location),
+            ParseError::InvalidAttribute(attribute) => Self::InvalidAttribute(attribute),
+            ParseError::NotAnArray(identifier) => Self::NotAnArray(identifier),
             ParseError::TokenizerError(err) => err.into(),
         }
     }
@@ -98,6 +680,9 @@ impl From<TokenizerError> for CompileError {
     fn from(value: TokenizerError) -> Self {
         match value {
             TokenizerError::UnrecognizedCharacter(c) => Self::UnrecognizedCharacter(c),
+            TokenizerError::UnterminatedStringLiteral(location) => Self::UnterminatedStringLiteral(location),
+            TokenizerError::UnterminatedBlockComment(location) => Self::UnterminatedBlockComment(location),
+            TokenizerError::InvalidEscape { sequence, location } => Self::InvalidEscape { sequence, location },
         }
     }
 }
@@ -115,7 +700,203 @@ impl From<std::string::FromUtf8Error> for CompileError {
 }
 
 
-pub fn compile(args: &CompileArgs) -> Result<(), CompileError> {
+// what a build that went through leaves, the warnings are for the caller to show
+#[derive(Clone, Debug)]
+pub struct Compiled {
+    pub target_file: PathBuf,
+    pub warnings: Vec<Diagnostic>,
+}
+
+
+pub fn compile(args: &CompileArgs) -> Result<Compiled, CompileError> {
+    let target_file = args.get_target_file();
+    let warnings = build(args, &target_file, false)?;
+    Ok(Compiled { target_file, warnings })
+}
+
+
+pub fn compile_tests(args: &CompileArgs) -> Result<Compiled, CompileError> {
+    let target_file = args.get_test_target_file();
+    let warnings = build(args, &target_file, true)?;
+    Ok(Compiled { target_file, warnings })
+}
+
+
+// whether binaries can be built and run here, which needs the toolchain and an x86-64 linux host
+pub fn can_build_and_run() -> bool {
+    cfg!(all(target_os = "linux", target_arch = "x86_64")) && toolchain::preflight().is_ok()
+}
+
+
+// runs the program without building it, giving the code it would exit with, for hosts where
+// `can_build_and_run` is false. `None` if it does something only the built binary can
+pub fn interpret(args: &CompileArgs) -> Result<Option<i32>, CompileError> {
+    use crate::{parser::Parser, tokenize::Tokenize};
+
+    let mut source_code = String::new();
+    File::open(&args.source_file)?
+        .read_to_string(&mut source_code)?;
+    let tree = Parser::new(source_code.tokenize())
+        .with_literal_overflow(args.literal_overflow)
+        .with_edition(args.edition)
+        .parse()?;
+    let tree = const_eval::inline_constants(tree)?;
+    const_eval::check_static_asserts(&tree)?;
+    // the exit status only keeps the lowest byte
+    Ok(const_eval::run(&tree, args.entry.as_deref().unwrap_or("_start")).map(|code| (code & 0xff) as i32))
+}
+
+
+pub fn highlight(source_file: &Path, format: HighlightFormat) -> Result<String, CompileError> {
+    let mut source_code = String::new();
+    File::open(source_file)?
+        .read_to_string(&mut source_code)?;
+    Ok(highlight::highlight(&source_code, format)?)
+}
+
+
+pub fn inspect(binary: &Path) -> Result<BuildInfo, CompileError> {
+    let bytes = std::fs::read(binary)?;
+    BuildInfo::read(&bytes).ok_or(CompileError::NoBuildInfo(binary.to_path_buf()))
+}
+
+
+pub fn profile_report(profile: &Path) -> Result<String, CompileError> {
+    let bytes = std::fs::read(profile)?;
+    let profile = profile::Profile::parse(&bytes).ok_or(CompileError::InvalidProfile(profile.to_path_buf()))?;
+    Ok(profile.to_text())
+}
+
+
+// disassembles the binary and checks that every function symbol in it is declared by the source
+pub fn disassemble(binary: &Path, source_file: &Path) -> Result<String, CompileError> {
+    use crate::{parser::Parser, tokenize::Tokenize};
+
+    let mut source_code = String::new();
+    File::open(source_file)?
+        .read_to_string(&mut source_code)?;
+    let registry = TypeRegistry::from(&Parser::new(source_code.tokenize()).parse()?);
+
+    let disassembly = disasm::disassemble(binary)?;
+    let symbols = disasm::symbols(&disassembly);
+    let check = disasm::SymbolCheck::new(&symbols, &registry);
+    if let Some(symbol) = check.unknown.first() {
+        return Err(CompileError::SymbolMismatch { symbol: symbol.to_string(), source_file: source_file.to_path_buf() });
+    }
+    let mut text = disassembly.clone();
+    text += &format!("\nsymbols: {} checked against {}\n", symbols.len(), source_file.display());
+    for name in check.not_emitted {
+        text += &format!("not emitted: {name} (inlined or unreachable)\n");
+    }
+    Ok(text)
+}
+
+
+pub fn explain(code: &str) -> Option<&'static str> {
+    explain::explain(code)
+}
+
+
+pub fn stats(source_file: &Path) -> Result<String, CompileError> {
+    let mut source_code = String::new();
+    File::open(source_file)?
+        .read_to_string(&mut source_code)?;
+    Ok(stats::Stats::new(&source_code)?.to_text())
+}
+
+
+pub fn rename(source_file: &Path, old: &str, new: &str) -> Result<String, CompileError> {
+    let mut source_code = String::new();
+    File::open(source_file)?
+        .read_to_string(&mut source_code)?;
+    refactor::rename(&source_code, old, new)
+}
+
+
+// the fixes for an error in the source file, none if it can't be read any more
+pub fn suggestions(source_file: &Path, error: &CompileError, lang: Lang) -> Vec<Suggestion> {
+    std::fs::read_to_string(source_file)
+        .map(|source_code| fix::suggestions(&source_code, error, lang))
+        .unwrap_or_default()
+}
+
+
+pub fn fix(source_file: &Path) -> Result<Fixes, CompileError> {
+    let mut source_code = String::new();
+    File::open(source_file)?
+        .read_to_string(&mut source_code)?;
+    Ok(fix::fix(&source_code))
+}
+
+
+pub fn extract_function(source_file: &Path, name: &str, start_line: u64, end_line: u64) -> Result<String, CompileError> {
+    let mut source_code = String::new();
+    File::open(source_file)?
+        .read_to_string(&mut source_code)?;
+    refactor::extract_function(&source_code, name, start_line, end_line)
+}
+
+
+// the passes recurse over the tree, which can be as deep as the parser allows and is deeper still
+// once traces are inserted, so they get a stack of their own that is large enough for it
+const PASS_STACK_SIZE: usize = 64 * 1024 * 1024;
+
+// the warnings of the build if it went through
+fn build(args: &CompileArgs, target_file: &PathBuf, test_harness: bool) -> Result<Vec<Diagnostic>, CompileError> {
+    let mut report = BuildReport::new(&args.source_file, target_file, args.target);
+    let result = std::thread::scope(|scope| {
+        let passes = std::thread::Builder::new()
+            .stack_size(PASS_STACK_SIZE)
+            .spawn_scoped(scope, || {
+                let result = run_passes(args, target_file, test_harness, &mut report);
+                match (args.reproducible, result) {
+                    (true, Ok(())) => check_reproducible(args, target_file, test_harness, &report),
+                    (_, result) => result,
+                }
+            })?;
+        passes.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    });
+    if args.report == Some(ReportFormat::Json) {
+        if let Err(err) = &result {
+            report.add_error(err, suggestions(&args.source_file, err, args.lang), args.lang);
+        }
+        File::create(target_file.with_extension("report.json"))?
+            .write_all(report.to_json().as_bytes())?;
+    }
+    result.map(|_| report.diagnostics().to_vec())
+}
+
+
+// builds a second time into the same paths, as the object file records the name of the assembly,
+// and compares every artifact with the first build
+fn check_reproducible(args: &CompileArgs, target_file: &PathBuf, test_harness: bool, report: &BuildReport) -> Result<(), CompileError> {
+    let first = report.artifacts()
+        .iter()
+        .map(|path| Ok((path.clone(), std::fs::read(path)?)))
+        .collect::<Result<Vec<_>, CompileError>>()?;
+
+    println!("    rebuilding to check reproducibility");
+    let mut scratch = BuildReport::new(&args.source_file, target_file, args.target);
+    run_passes(args, target_file, test_harness, &mut scratch)?;
+    for (path, bytes) in first {
+        if std::fs::read(&path)? != bytes {
+            return Err(CompileError::NotReproducible(path));
+        }
+    }
+    Ok(())
+}
+
+
+// nothing is cancelled once the binary is being linked, the build is as good as done by then
+fn finish_pass(args: &CompileArgs, report: &mut BuildReport, pass: &'static str) -> Result<(), CompileError> {
+    report.finish_pass(pass);
+    match args.cancellation.as_ref().is_some_and(CancellationToken::is_cancelled) {
+        true => Err(CompileError::Cancelled),
+        false => Ok(()),
+    }
+}
+
+fn run_passes(args: &CompileArgs, target_file: &PathBuf, test_harness: bool, report: &mut BuildReport) -> Result<(), CompileError> {
     use crate::{
         parser::Parser,
         tokenize::Tokenize,
@@ -131,40 +912,181 @@ pub fn compile(args: &CompileArgs) -> Result<(), CompileError> {
     };
 
     println!("    lexing");
-    let tokens = source_code.tokenize();
+    let tokens = source_code.as_str().tokenize();
 
     println!("    parsing");
-    let mut parser = Parser::new(tokens);
-    let tree = parser.parse()?;
+    let mut parser = Parser::new(tokens)
+        .with_literal_overflow(args.literal_overflow)
+        .with_edition(args.edition);
+    let mut sink = DiagnosticSink::new(args.max_errors, args.fatal_warnings, args.lang);
+    let (tree, node_map) = match parser.parse_with_map() {
+        Ok(parsed) => parsed,
+        Err(err) if parser.errors().len() == 1 => return Err(err.into()),
+        // the parser carried on past a missing `;`, so everything it found is given back to be shown
+        Err(_) => {
+            for error in parser.errors() {
+                let error = CompileError::from(error.clone());
+                let diagnostic = fix::suggestions(&source_code, &error, args.lang)
+                    .into_iter()
+                    .fold(Diagnostic::localized(&error, args.lang), Diagnostic::with_suggestion);
+                let Some(diagnostic) = sink.emit(diagnostic) else { break };
+                report.add_diagnostic(diagnostic.clone());
+            }
+            let diagnostics = sink.diagnostics().to_vec();
+            return Err(CompileError::SyntaxErrors { count: parser.errors().len(), diagnostics });
+        },
+    };
+    // passes rewrite the tree, so errors found in the generated code are traced back to the
+    // function they are in by name
+    let function_locations: HashMap<String, Location> = tree.0.iter()
+        .zip(node_map.items())
+        .filter_map(|(item, id)| match item {
+            Item::Function { name, .. } => Some((name.clone(), node_map.span(id)?.start)),
+            Item::Const { .. } => None,
+        })
+        .collect();
+    for warning in parser.warnings() {
+        let Some(diagnostic) = sink.emit(Diagnostic::localized_warning(warning, args.lang)) else { break };
+        report.add_diagnostic(diagnostic.clone());
+    }
+    if sink.error_count() > 0 {
+        return Err(CompileError::FatalWarnings { count: sink.error_count(), diagnostics: sink.diagnostics().to_vec() });
+    }
+    finish_pass(args, report, "parsing")?;
+
+    let tree = match args.instrument.contains(&Instrument::Trace) {
+        true => {
+            println!("    inserting traces");
+            let tree = trace::insert_traces(tree, &node_map, args.bits);
+            finish_pass(args, report, "inserting traces")?;
+            tree
+        },
+        false => tree,
+    };
+
+    println!("    evaluating constants");
+    let tree = const_eval::inline_constants(tree)?;
+    finish_pass(args, report, "evaluating constants")?;
+
+    println!("    checking static assertions");
+    const_eval::check_static_asserts(&tree)?;
+    finish_pass(args, report, "checking static assertions")?;
+
+    let tree = match args.opt_level {
+        0 => tree,
+        _ => {
+            println!("    optimizing");
+            let tree = optimize::optimize(tree);
+            finish_pass(args, report, "optimizing")?;
+            tree
+        },
+    };
+
+    if args.emit == Emit::CallGraph {
+        println!("    writing call graph");
+        File::create(target_file.with_extension("callgraph.dot"))?
+            .write_all(CallGraph::from(&tree).to_dot().as_bytes())?;
+        report.add_artifact(target_file.with_extension("callgraph.dot"));
+        return Ok(());
+    }
+
+    if args.emit == Emit::Cfg {
+        println!("    writing control flow graph");
+        File::create(target_file.with_extension("cfg.dot"))?
+            .write_all(cfg::program_to_dot(&tree).as_bytes())?;
+        report.add_artifact(target_file.with_extension("cfg.dot"));
+        return Ok(());
+    }
 
     println!("    registering types");
-    let type_checker = TypeRegistry::from(&tree);
+    let mut type_checker = TypeRegistry::from(&tree);
+    for meta_file in &args.externs {
+        let meta = std::fs::read_to_string(meta_file)?;
+        let functions = TypeRegistry::parse_meta(&meta).ok_or(CompileError::InvalidMeta(meta_file.clone()))?;
+        functions.into_iter().for_each(|signature| type_checker.declare_extern(signature));
+    }
+    if args.emit_meta {
+        File::create(target_file.with_extension("bemeta"))?
+            .write_all(type_checker.to_meta().as_bytes())?;
+        report.add_artifact(target_file.with_extension("bemeta"));
+    }
+    type_registry::check_return_types(&tree, &type_checker)?;
+    finish_pass(args, report, "registering types")?;
 
     println!("    codegen");
     use crate::codegen::x86::Codegen;
-    let mut context = Context::new(type_checker);
-    let generated_code = tree.codegen_x86(&mut context)?;
+    let mut context = Context::new(type_checker)
+        .with_source_name(args.source_file.display().to_string())
+        .with_test_harness(test_harness)
+        .with_keep_all(args.keep_all)
+        .with_asm_format(args.asm_format.clone())
+        .with_checked(args.checked)
+        // the test harness is always entered through its own `_start`
+        .with_entry(args.entry.clone().filter(|_| !test_harness))
+        .with_bits(args.bits)
+        .with_features(args.features())
+        .with_detect_features(args.detect_features)
+        .with_verify_pic(args.verify_pic)
+        .with_stack_protector(args.stack_protector)
+        .with_profile(args.instrument.contains(&Instrument::Profile) && !test_harness);
+    let mut generated_code = tree.codegen_x86(&mut context).map_err(|err| match CompileError::from(err) {
+        CompileError::AbsoluteReference { function, instruction, location } => {
+            let location = function_locations.get(&function).copied().unwrap_or(location);
+            CompileError::AbsoluteReference { function, instruction, location }
+        },
+        err => err,
+    })?;
+    generated_code += &BuildInfo::new(args, test_harness).to_asm();
+    finish_pass(args, report, "codegen")?;
+
+    println!("    checking toolchain");
+    toolchain::preflight()?;
 
     println!("    writing");
-    let target_file = args.get_target_file();
     File::create(target_file.with_extension("asm"))?
         .write_all(generated_code.as_bytes())?;
+    report.add_artifact(target_file.with_extension("asm"));
 
     println!("    assembling");
     use std::process::Command;
     let mut command = Command::new("nasm");
-    command.arg("-felf64")
-           .arg(target_file.with_extension("asm"));
+    command.arg("-felf64");
+    if args.debug_info {
+        command.arg("-g").arg("-Fdwarf");
+    }
+    command.arg(target_file.with_extension("asm"));
     println!("        running `{:?}`", command);
     command.run()?;
+    report.add_artifact(target_file.with_extension("o"));
+    finish_pass(args, report, "assembling")?;
     
     println!("    linking");
     let mut command = Command::new("ld");
     command.arg(target_file.with_extension("o"))
+           .args(args.externs.iter().map(|meta_file| meta_file.with_extension("o")))
            .arg("-o").arg(target_file);
+    if let Some(linker_script) = &args.linker_script {
+        command.arg("-T").arg(linker_script);
+    }
+    for (section, address) in &args.section_starts {
+        command.arg(format!("--section-start={section}={address:#x}"));
+    }
+    if let Some(entry) = args.entry.as_ref().filter(|_| !test_harness) {
+        command.arg("-e").arg(entry);
+    }
     println!("        running `{:?}`", command);
     command.run()?;
+    report.add_artifact(target_file.clone());
+    report.finish_pass("linking");
+
+    if args.verify {
+        println!("    verifying");
+        let disassembly = disasm::disassemble(target_file)?;
+        let checked = disasm::round_trip(&generated_code, &disassembly)
+            .map_err(|(label, reason)| CompileError::RoundTripMismatch { label, reason })?;
+        println!("        {checked} instructions match the binary");
+        report.finish_pass("verifying");
+    }
 
     Ok(())
 }
-
@@ -0,0 +1,173 @@
+use std::collections::BTreeSet;
+
+use crate::{
+    ast::{Item, Statement},
+    cfg::{BlockId, Cfg, VariableId},
+    visit::{self, Visitor},
+};
+
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Liveness {
+    pub live_in: Vec<BTreeSet<VariableId>>,
+    pub live_out: Vec<BTreeSet<VariableId>>,
+}
+
+impl Liveness {
+    fn transfer(cfg: &Cfg, block: BlockId, live_out: &BTreeSet<VariableId>) -> BTreeSet<VariableId> {
+        let block_data = &cfg.blocks[block];
+        let mut live = live_out.clone();
+        live.extend(block_data.terminator.uses().iter().copied());
+        for node in block_data.nodes.iter().rev() {
+            node.defs.iter().for_each(|def| { live.remove(def); });
+            live.extend(node.uses.iter().copied());
+        }
+        live
+    }
+}
+
+impl From<&Cfg> for Liveness {
+    fn from(cfg: &Cfg) -> Self {
+        let count = cfg.blocks.len();
+        let mut liveness = Self {
+            live_in: vec![BTreeSet::new(); count],
+            live_out: vec![BTreeSet::new(); count],
+        };
+
+        // iterate backwards to a fixed point, visiting blocks in reverse order to converge quickly
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in (0..count).rev() {
+                let live_out: BTreeSet<VariableId> = cfg.blocks[block].terminator.successors()
+                    .into_iter()
+                    .flat_map(|successor| liveness.live_in[successor].iter().copied())
+                    .collect();
+                let live_in = Self::transfer(cfg, block, &live_out);
+                if live_in != liveness.live_in[block] || live_out != liveness.live_out[block] {
+                    liveness.live_in[block] = live_in;
+                    liveness.live_out[block] = live_out;
+                    changed = true;
+                }
+            }
+        }
+        liveness
+    }
+}
+
+
+
+// pairs of variables that are live at the same time and so can't share a stack slot
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Interference(BTreeSet<(VariableId, VariableId)>);
+
+impl Interference {
+    pub fn interferes(&self, a: VariableId, b: VariableId) -> bool {
+        a == b || self.0.contains(&(a.min(b), a.max(b)))
+    }
+
+    // codegen numbers variables by counting `let`s, which only lines up with the cfg when every
+    // `let` was lowered as a statement of its own
+    pub fn of(item: &Item) -> Option<Self> {
+        let cfg = Cfg::from(item);
+        let mut counter = LetCounter::default();
+        counter.visit_item(item);
+        let params = cfg.variables.iter().filter(|variable| variable.is_param).count();
+        (params + counter.0 == cfg.variables.len()).then(|| Self::from(&cfg))
+    }
+}
+
+impl From<&Cfg> for Interference {
+    fn from(cfg: &Cfg) -> Self {
+        let liveness = Liveness::from(cfg);
+        let mut edges = BTreeSet::new();
+        for (block, block_data) in cfg.blocks.iter().enumerate() {
+            let mut live = liveness.live_out[block].clone();
+            live.extend(block_data.terminator.uses().iter().copied());
+            for node in block_data.nodes.iter().rev() {
+                // a write clobbers the slot of anything live across it, even if the written value is dead
+                for def in &node.defs {
+                    edges.extend(live.iter().filter(|other| *other != def).map(|other| (*def.min(other), *def.max(other))));
+                }
+                node.defs.iter().for_each(|def| { live.remove(def); });
+                live.extend(node.uses.iter().copied());
+            }
+        }
+        Self(edges)
+    }
+}
+
+
+#[derive(Default)]
+struct LetCounter(usize);
+
+impl Visitor for LetCounter {
+    fn visit_statement(&mut self, statement: &Statement) {
+        if let Statement::Let { .. } = statement {
+            self.0 += 1;
+        }
+        visit::walk_statement(self, statement)
+    }
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[cfg(test)]
+use crate::cfg::ENTRY;
+
+#[cfg(test)]
+fn liveness_of(source: &str) -> (Cfg, Liveness) {
+    use crate::{parser::Parser, tokenize::Tokenize};
+    let program = Parser::new(source.tokenize()).parse().expect("source parses");
+    let cfg = Cfg::from(program.0.first().expect("an item"));
+    let liveness = Liveness::from(&cfg);
+    (cfg, liveness)
+}
+
+#[test]
+fn params_are_live_on_entry_until_last_use() {
+    let (_, liveness) = liveness_of("fn f(a, b) { let c = a + 1; return c; }");
+    assert_eq!(liveness.live_in[ENTRY], BTreeSet::from([0]));
+    assert_eq!(liveness.live_out[ENTRY], BTreeSet::new());
+}
+
+#[test]
+fn loop_carried_variables_stay_live() {
+    let (cfg, liveness) = liveness_of("fn f() { let mut x = 0; let y = 1; while (x < 10) x += y; return x; }");
+    let head = cfg.blocks[ENTRY].terminator.successors()[0];
+    assert_eq!(liveness.live_in[head], BTreeSet::from([0, 1]));
+    assert_eq!(liveness.live_out[ENTRY], BTreeSet::from([0, 1]));
+}
+
+#[test]
+fn branches_only_keep_their_own_uses_live() {
+    let (cfg, liveness) = liveness_of("fn f(a, b) { if (a) return b; return 0; }");
+    assert_eq!(liveness.live_in[ENTRY], BTreeSet::from([0, 1]));
+    match cfg.blocks[ENTRY].terminator {
+        crate::cfg::Terminator::Branch { then, els, .. } => {
+            assert_eq!(liveness.live_in[then], BTreeSet::from([1]));
+            assert_eq!(liveness.live_in[els], BTreeSet::new());
+        },
+        ref terminator => panic!("expected a branch, got {terminator:?}"),
+    }
+}
+
+#[test]
+fn overlapping_variables_interfere() {
+    let (cfg, _) = liveness_of("fn f() { let a = 1; let b = 2; let c = a + b; exit(c); }");
+    let interference = Interference::from(&cfg);
+    assert!(interference.interferes(0, 1));
+    assert!(!interference.interferes(0, 2));
+    assert!(!interference.interferes(1, 2));
+}
+
+#[test]
+fn dead_writes_still_interfere() {
+    let (cfg, _) = liveness_of("fn f() { let a = 1; let b = 2; exit(a); }");
+    assert!(Interference::from(&cfg).interferes(0, 1));
+}
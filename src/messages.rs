@@ -0,0 +1,182 @@
+// the message catalog: every user-facing error message is looked up by its key, the error code
+// with an optional suffix for variants of the same error, and filled in with named arguments.
+// a translation only needs a new catalog, missing entries fall back to English
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Lang {
+    #[default]
+    En,
+    De,
+}
+
+impl Lang {
+    fn catalog(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Self::En => &EN,
+            Self::De => &DE,
+        }
+    }
+}
+
+
+const EN: [(&str, &str); 57] = [
+    ("BE0001", "use of undeclared identifier `{name}`"),
+    ("BE0002", "call to undeclared function `{name}`"),
+    ("BE0003", "cannot assign to immutable variable `{name}`"),
+    ("BE0004", "test function `{name}` must not take parameters"),
+    ("BE0005", "unexpected {token} at {line}:{column}"),
+    ("BE0006", "unrecognized character {character}"),
+    ("BE0007", "unterminated string literal starting at {line}:{column}"),
+    ("BE0008", "`{tool}` was not found on PATH, {hint}"),
+    ("BE0009", "`{program}` failed with exit code {status}"),
+    ("BE0009-signal", "`{program}` was terminated by a signal"),
+    ("BE0010", "cannot refactor: {reason}"),
+    ("BE0011", "{error}"),
+    ("BE0012", "{error}"),
+    ("BE0013", "`{path}` differs between two builds of the same source"),
+    ("BE0014", "`{path}` is not a binary built by beryllium"),
+    ("BE0015", "the binary contains `{symbol}`, which is not a function in `{path}`"),
+    ("BE0016", "`{name}` cannot be compiled for {bits} bits: {reason}"),
+    ("BE0017", "`spawn` needs a function without parameters, found `{name}`"),
+    ("BE0018", "`match` at {line}:{column} does not cover every value, it needs a final `_` arm"),
+    ("BE0019", "the pattern at {line}:{column} is unreachable, the arms before it already match every value it does"),
+    ("BE0020", "`{name}` accesses memory or ports directly and can only be used inside an `unsafe` block"),
+    ("BE0021", "`{name}` cannot be a `const fn`, as {reason}"),
+    ("BE0022", "static assertion at {line}:{column} failed: {message}"),
+    ("BE0023", "the expression at {line}:{column} is not a constant expression"),
+    ("BE0024", "integer literal `{literal}` at {line}:{column} does not fit in 64 bits"),
+    ("BE0025", "`{name}` may be read before it is assigned a value"),
+    ("BE0026", "comparisons cannot be chained, the second one is at {line}:{column}, compare each pair on its own"),
+    ("BE0027", "`swap` exchanges two variables, but `{name}` is not a variable"),
+    ("BE0028", "unterminated block comment starting at {line}:{column}"),
+    ("BE0029", "unknown escape sequence `{sequence}` at {line}:{column}"),
+    ("BE0030", "`{instruction}` in `{name}` at {line}:{column} refers to an absolute address, which position-independent code cannot do"),
+    ("BE0031", "the binary does not match the assembly emitted for `{name}`: {reason}"),
+    ("BE0032", "`{path}` is not a profile written by a beryllium binary"),
+    ("BE0033", "`{path}` is not an interface written by `--emit-meta`"),
+    ("BE0034", "`{name}` is used as a value, but not every way through it ends in one"),
+    ("BE0035", "the build was cancelled"),
+    ("BE0036", "the code at {line}:{column} is nested too deeply"),
+    ("BE0037", "no enclosing loop is labelled `'{label}` at {line}:{column}"),
+    ("BE0038", "function `{name}` returns a value, so it cannot also `return;` without one"),
+    ("BE0039", "function `{name}` returns `()`, so it cannot `return` a value"),
+    ("BE0040", "function `{name}` returns `()`, so a call to it has no value to use"),
+    ("BE0041", "stopping because of {count} warning(s) treated as errors"),
+    ("BE0042", "integer literal `{literal}` at {line}:{column} does not fit in `{type}`"),
+    ("BE0043", "expected `;` after the statement ending at {line}:{column}"),
+    ("BE0044", "stopping because of {count} syntax error(s)"),
+    ("BE0045", "`{name}` is not an array, so it cannot be indexed"),
+    ("BE0046", "`{name}` is an array, only its elements can be used as values"),
+    ("BE0047", "expected `{close}` at {line}:{column} to close the `{open}` at {open_line}:{open_column}"),
+    ("BE0048", "the source ends at {line}:{column} in the middle of an item"),
+    ("BE0049", "`{attribute}` at {line}:{column} has the wrong arguments, it is written {usage}"),
+    ("BE0049-unknown", "unknown attribute `{attribute}` at {line}:{column}"),
+    ("unclosed", "this `{open}` is not closed"),
+    ("dangling-else", "this `else` belongs to the `if` at {line}:{column}, add braces to make clear which `if` it is meant for"),
+    ("dangling-else-help", "add braces around the inner `if`"),
+    ("fatal-warnings", "warnings are errors with `--fatal-warnings`"),
+    ("insert-semicolon", "insert missing `;`"),
+    ("make-mutable", "make `{name}` mutable"),
+];
+
+const DE: [(&str, &str); 55] = [
+    ("BE0001", "Verwendung des nicht deklarierten Bezeichners `{name}`"),
+    ("BE0002", "Aufruf der nicht deklarierten Funktion `{name}`"),
+    ("BE0003", "der unveränderlichen Variable `{name}` kann nichts zugewiesen werden"),
+    ("BE0004", "die Testfunktion `{name}` darf keine Parameter haben"),
+    ("BE0005", "unerwartetes {token} bei {line}:{column}"),
+    ("BE0006", "unbekanntes Zeichen {character}"),
+    ("BE0007", "nicht abgeschlossenes Zeichenkettenliteral ab {line}:{column}"),
+    ("BE0008", "`{tool}` wurde nicht im PATH gefunden, {hint}"),
+    ("BE0009", "`{program}` ist mit Exit-Code {status} fehlgeschlagen"),
+    ("BE0009-signal", "`{program}` wurde durch ein Signal beendet"),
+    ("BE0010", "Refactoring nicht möglich: {reason}"),
+    ("BE0013", "`{path}` unterscheidet sich zwischen zwei Builds derselben Quelle"),
+    ("BE0014", "`{path}` ist keine mit beryllium erstellte Binärdatei"),
+    ("BE0015", "die Binärdatei enthält `{symbol}`, das keine Funktion in `{path}` ist"),
+    ("BE0016", "`{name}` kann nicht für {bits} Bit übersetzt werden: {reason}"),
+    ("BE0017", "`spawn` braucht eine Funktion ohne Parameter, gefunden wurde `{name}`"),
+    ("BE0018", "`match` bei {line}:{column} deckt nicht jeden Wert ab, es fehlt ein abschließender `_`-Zweig"),
+    ("BE0019", "das Muster bei {line}:{column} ist unerreichbar, die Zweige davor passen bereits auf jeden seiner Werte"),
+    ("BE0020", "`{name}` greift direkt auf Speicher oder Ports zu und ist nur in einem `unsafe`-Block erlaubt"),
+    ("BE0021", "`{name}` kann keine `const fn` sein: {reason}"),
+    ("BE0022", "statische Zusicherung bei {line}:{column} fehlgeschlagen: {message}"),
+    ("BE0023", "der Ausdruck bei {line}:{column} ist kein konstanter Ausdruck"),
+    ("BE0024", "das Ganzzahlliteral `{literal}` bei {line}:{column} passt nicht in 64 Bit"),
+    ("BE0025", "`{name}` wird möglicherweise gelesen, bevor ihr ein Wert zugewiesen wurde"),
+    ("BE0026", "Vergleiche können nicht verkettet werden, der zweite steht bei {line}:{column}, vergleiche jedes Paar einzeln"),
+    ("BE0027", "`swap` vertauscht zwei Variablen, aber `{name}` ist keine Variable"),
+    ("BE0028", "nicht abgeschlossener Blockkommentar ab {line}:{column}"),
+    ("BE0029", "unbekannte Escape-Sequenz `{sequence}` bei {line}:{column}"),
+    ("BE0030", "`{instruction}` in `{name}` bei {line}:{column} verweist auf eine absolute Adresse, was positionsunabhängiger Code nicht kann"),
+    ("BE0031", "die Binärdatei passt nicht zum für `{name}` erzeugten Assembler: {reason}"),
+    ("BE0032", "`{path}` ist kein von einer beryllium-Binärdatei geschriebenes Profil"),
+    ("BE0033", "`{path}` ist keine von `--emit-meta` geschriebene Schnittstelle"),
+    ("BE0034", "`{name}` wird als Wert verwendet, aber nicht jeder Weg hindurch endet in einem"),
+    ("BE0035", "der Build wurde abgebrochen"),
+    ("BE0036", "der Code bei {line}:{column} ist zu tief verschachtelt"),
+    ("BE0037", "keine umgebende Schleife trägt das Label `'{label}` bei {line}:{column}"),
+    ("BE0038", "die Funktion `{name}` gibt einen Wert zurück und kann daher nicht auch `return;` ohne Wert verwenden"),
+    ("BE0039", "die Funktion `{name}` gibt `()` zurück und kann daher keinen Wert mit `return` zurückgeben"),
+    ("BE0040", "die Funktion `{name}` gibt `()` zurück, ein Aufruf von ihr hat daher keinen Wert"),
+    ("BE0041", "Abbruch wegen {count} als Fehler behandelter Warnung(en)"),
+    ("BE0042", "das Ganzzahlliteral `{literal}` bei {line}:{column} passt nicht in `{type}`"),
+    ("BE0043", "nach der Anweisung, die bei {line}:{column} endet, fehlt ein `;`"),
+    ("BE0044", "Abbruch wegen {count} Syntaxfehler(n)"),
+    ("BE0045", "`{name}` ist kein Array und kann daher nicht indiziert werden"),
+    ("BE0046", "`{name}` ist ein Array, nur seine Elemente können als Werte verwendet werden"),
+    ("BE0047", "bei {line}:{column} wird `{close}` erwartet, um das `{open}` bei {open_line}:{open_column} zu schließen"),
+    ("BE0048", "der Quelltext endet bei {line}:{column} mitten in einem Element"),
+    ("BE0049", "`{attribute}` bei {line}:{column} hat die falschen Argumente, es wird {usage} geschrieben"),
+    ("BE0049-unknown", "unbekanntes Attribut `{attribute}` bei {line}:{column}"),
+    ("unclosed", "dieses `{open}` wird nicht geschlossen"),
+    ("dangling-else", "dieses `else` gehört zum `if` bei {line}:{column}, Klammern machen deutlich, welches `if` gemeint ist"),
+    ("dangling-else-help", "Klammern um das innere `if` setzen"),
+    ("fatal-warnings", "mit `--fatal-warnings` sind Warnungen Fehler"),
+    ("insert-semicolon", "fehlendes `;` einfügen"),
+    ("make-mutable", "`{name}` veränderlich machen"),
+];
+
+
+fn template(lang: Lang, key: &str) -> Option<&'static str> {
+    lang.catalog().iter().find(|(known, _)| *known == key).map(|(_, template)| *template)
+}
+
+pub fn message(lang: Lang, key: &str, args: &[(&str, String)]) -> String {
+    let template = template(lang, key).or_else(|| template(Lang::En, key)).unwrap_or(key);
+    // a single pass, so braces inside the arguments are left alone
+    let mut message = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        message += &rest[..open];
+        let Some(close) = rest[open..].find('}').map(|close| open + close) else { break };
+        let name = &rest[open + 1..close];
+        match args.iter().find(|(arg, _)| *arg == name) {
+            Some((_, value)) => message += value,
+            None => message += &rest[open..=close],
+        }
+        rest = &rest[close + 1..];
+    }
+    message + rest
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn arguments_are_filled_in_once() {
+    let args = [("name", String::from("{name}"))];
+    assert_eq!(message(Lang::En, "BE0001", &args), "use of undeclared identifier `{name}`");
+    assert_eq!(message(Lang::De, "BE0003", &[("name", String::from("x"))]), "der unveränderlichen Variable `x` kann nichts zugewiesen werden");
+}
+
+#[test]
+fn missing_translations_fall_back_to_english() {
+    assert_eq!(message(Lang::De, "BE0011", &[("error", String::from("denied"))]), "denied");
+    assert_eq!(message(Lang::De, "unknown", &[]), "unknown");
+}
@@ -0,0 +1,211 @@
+use crate::{
+    ast::{Expr, Item, Program, Statement},
+    tokenize::Location,
+};
+
+
+/// nodes are numbered in post-order, children before their parents and left to right, which is the
+/// order the parser finishes them in, so the same source always gives the same ids
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(pub usize);
+
+
+/// the locations a node starts and ends at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub start: Location,
+    // just past the last token of the node
+    pub end: Location,
+}
+
+impl Span {
+    pub fn contains(&self, index: u64) -> bool {
+        self.start.index <= index && index < self.end.index
+    }
+
+    pub fn len(&self) -> u64 {
+        self.end.index - self.start.index
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+
+/// a reference to any node of a program, by what kind of node it is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NodeRef<'a> {
+    Item(&'a Item),
+    Statement(&'a Statement),
+    Expr(&'a Expr),
+}
+
+impl<'a> NodeRef<'a> {
+    pub fn children(&self) -> Vec<NodeRef<'a>> {
+        match *self {
+            Self::Item(Item::Function { body, .. }) => vec![Self::Statement(body)],
+            Self::Item(Item::Const { value, .. }) => vec![Self::Expr(value)],
+            Self::Statement(statement) => match statement {
+                Statement::Let { value, .. } | Statement::Return(value) => value.iter().map(Self::Expr).collect(),
+                Statement::Exit { value } | Statement::Expr(value)
+                    | Statement::Const { value, .. }
+                    | Statement::StaticAssert { condition: value, .. } => vec![Self::Expr(value)],
+                Statement::Panic { .. } | Statement::Unreachable { .. }
+                    | Statement::Break(_) | Statement::Continue(_) => vec![],
+            },
+            Self::Expr(expr) => match expr {
+                Expr::Add(a, b) | Expr::Sub(a, b) | Expr::Mul(a, b) | Expr::Div(a, b) | Expr::Mod(a, b)
+                    | Expr::Equality(a, b) | Expr::NonEquality(a, b)
+                    | Expr::Less(a, b) | Expr::LessEq(a, b)
+                    | Expr::Greater(a, b) | Expr::GreaterEq(a, b) => vec![Self::Expr(a), Self::Expr(b)],
+
+                Expr::Assign { value, .. } | Expr::AddAssign { value, .. } | Expr::SubAssign { value, .. }
+                    | Expr::MulAssign { value, .. } | Expr::DivAssign { value, .. }
+                    | Expr::ModAssign { value, .. } | Expr::Try(value) | Expr::Neg(value)
+                    | Expr::Cast { value, ty: _ } => vec![Self::Expr(value)],
+                Expr::Index { identifier: _, index, location: _ } => vec![Self::Expr(index)],
+                Expr::IndexAssign { identifier: _, index, value, location: _ } => vec![Self::Expr(index), Self::Expr(value)],
+
+                Expr::FunctionCall { name: _, args } | Expr::Intrinsic { intrinsic: _, args }
+                    | Expr::Array(args) => args.iter().map(Self::Expr).collect(),
+                Expr::IntegerLiteral(_) | Expr::StringLiteral(_) | Expr::Identifier(_) => vec![],
+
+                Expr::Block(stmts) => stmts.iter().map(Self::Statement).collect(),
+                Expr::If { check, body, els } => {
+                    let mut children = vec![Self::Expr(check), Self::Statement(body)];
+                    children.extend(els.iter().map(|els| Self::Statement(els)));
+                    children
+                },
+                Expr::Loop { label: _, body } => vec![Self::Statement(body)],
+                Expr::While { label: _, check, body } => vec![Self::Expr(check), Self::Statement(body)],
+                Expr::Unsafe(body) => vec![Self::Expr(body)],
+            },
+        }
+    }
+}
+
+
+/// every node of the program indexed by its id
+pub fn nodes(program: &Program) -> Vec<NodeRef<'_>> {
+    number(program).0
+}
+
+fn number(program: &Program) -> (Vec<NodeRef<'_>>, Vec<Option<NodeId>>) {
+    let mut nodes = Vec::new();
+    let mut parents = Vec::new();
+    for item in &program.0 {
+        number_node(NodeRef::Item(item), &mut nodes, &mut parents);
+    }
+    (nodes, parents)
+}
+
+fn number_node<'a>(node: NodeRef<'a>, nodes: &mut Vec<NodeRef<'a>>, parents: &mut Vec<Option<NodeId>>) -> NodeId {
+    let children: Vec<NodeId> = node.children()
+        .into_iter()
+        .map(|child| number_node(child, nodes, parents))
+        .collect();
+    let id = NodeId(nodes.len());
+    nodes.push(node);
+    parents.push(None);
+    children.into_iter().for_each(|child| parents[child.0] = Some(id));
+    id
+}
+
+
+/// side tables for the nodes of a parsed program, so that tools can refer to a node by its id
+/// without holding on to the tree
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeMap {
+    spans: Vec<Span>,
+    parents: Vec<Option<NodeId>>,
+}
+
+impl NodeMap {
+    pub(crate) fn new(program: &Program, spans: Vec<Span>) -> Self {
+        let (_, parents) = number(program);
+        debug_assert_eq!(spans.len(), parents.len(), "the parser records a span for every node");
+        Self { spans, parents }
+    }
+
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    pub fn span(&self, id: NodeId) -> Option<Span> {
+        self.spans.get(id.0).copied()
+    }
+
+    // the items are the only nodes without a parent, in the order they appear in
+    pub fn items(&self) -> impl Iterator<Item = NodeId> + '_ {
+        (0..self.len()).map(NodeId).filter(|id| self.parent(*id).is_none())
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.parents.get(id.0).copied().flatten()
+    }
+
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.parent(id), |id| self.parent(*id))
+    }
+
+    // the innermost node covering the offset; children come before their parents, so the first
+    // match with the shortest span wins
+    pub fn node_at(&self, index: u64) -> Option<NodeId> {
+        self.spans
+            .iter()
+            .enumerate()
+            .filter(|(_, span)| span.contains(index))
+            .min_by_key(|(_, span)| span.len())
+            .map(|(id, _)| NodeId(id))
+    }
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[cfg(test)]
+fn parse(source: &str) -> (Program, NodeMap) {
+    use crate::{parser::Parser, tokenize::Tokenize};
+    Parser::new(source.tokenize()).parse_with_map().expect("source parses")
+}
+
+#[test]
+fn spans_cover_each_node() {
+    let source = "fn _start() { let x = 1 + 2; exit(x); }";
+    let (program, map) = parse(source);
+    assert_eq!(nodes(&program).len(), map.len());
+    let texts: Vec<&str> = (0..map.len())
+        .map(|id| map.span(NodeId(id)).unwrap())
+        .map(|span| &source[span.start.index as usize..span.end.index as usize])
+        .collect();
+    assert_eq!(texts, vec![
+        "1", "2", "1 + 2", "let x = 1 + 2;", "x", "exit(x);",
+        "{ let x = 1 + 2; exit(x); }", "{ let x = 1 + 2; exit(x); }", source,
+    ]);
+}
+
+#[test]
+fn innermost_nodes_and_their_parents() {
+    let source = "fn _start() exit(1 + foo);";
+    let (program, map) = parse(source);
+    let nodes = nodes(&program);
+    let foo = map.node_at(source.find("foo").unwrap() as u64).unwrap();
+    assert_eq!(nodes[foo.0], NodeRef::Expr(&Expr::Identifier(String::from("foo"))));
+    let ancestors: Vec<_> = map.ancestors(foo).map(|id| nodes[id.0]).collect();
+    assert!(matches!(ancestors[..], [
+        NodeRef::Expr(Expr::Add(_, _)),
+        NodeRef::Statement(Statement::Exit { .. }),
+        NodeRef::Item(_),
+    ]));
+    assert_eq!(map.node_at(source.len() as u64), None);
+}
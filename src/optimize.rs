@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{Expr, Intrinsic, Item, Program, Statement},
+    const_eval::ConstFunctions,
+};
+
+
+pub fn optimize(program: Program) -> Program {
+    let functions = ConstFunctions::new(&program);
+    Program(program.0
+        .into_iter()
+        .map(|item| ConstantPropagation { scopes: Vec::new(), functions: &functions }.item(item))
+        .collect()
+    )
+}
+
+
+// substitutes immutable bindings to constants at their use sites and folds the arithmetic that
+// becomes constant as a result, including calls to `const fn`s with constant arguments
+struct ConstantPropagation<'a> {
+    scopes: Vec<HashMap<String, Option<Expr>>>,
+    functions: &'a ConstFunctions,
+}
+
+impl ConstantPropagation<'_> {
+    fn item(&mut self, item: Item) -> Item {
+        match item {
+            Item::Function { name, params, return_type, body, attributes, is_const } => {
+                self.scopes.push(HashMap::new());
+                let body = self.statement(body);
+                self.scopes.pop();
+                Item::Function { name, params, return_type, body, attributes, is_const }
+            },
+            Item::Const { .. } => item,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<Expr> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name)).cloned().flatten()
+    }
+
+    fn scoped(&mut self, statement: Statement) -> Statement {
+        self.scopes.push(HashMap::new());
+        let statement = self.statement(statement);
+        self.scopes.pop();
+        statement
+    }
+
+    fn statement(&mut self, statement: Statement) -> Statement {
+        match statement {
+            Statement::Let { identifier, ty, value, is_mutable } => {
+                let value = value.map(|value| self.expr(value));
+                // mutable bindings still shadow any outer constant of the same name. a narrower
+                // variable holds its value cut down, and unsigned ones are compared as unsigned,
+                // which a literal in their place wouldn't be
+                let constant = match (&value, is_mutable, ty) {
+                    (Some(value @ Expr::IntegerLiteral(_)), false, None) => Some(value.clone()),
+                    (Some(Expr::IntegerLiteral(value)), false, Some(ty)) if ty.is_signed() =>
+                        value.parse().ok().map(|value| Expr::IntegerLiteral(ty.wrap(value).to_string())),
+                    _ => None,
+                };
+                self.scopes.last_mut().expect("a scope").insert(identifier.clone(), constant);
+                Statement::Let { identifier, ty, value, is_mutable }
+            },
+            Statement::Exit { value } => Statement::Exit { value: self.expr(value) },
+            Statement::Expr(value) => match self.expr(value) {
+                // a branch that ends in `unreachable()` is never taken, so only the other one is
+                // left. a check that may have effects still has to run, so that is kept
+                Expr::If { check, body, els } if is_pure(&check) && reaches_unreachable(&body) =>
+                    els.map_or(Statement::Expr(Expr::Block(Vec::new())), |els| *els),
+                Expr::If { check, body, els: Some(els) } if is_pure(&check) && reaches_unreachable(&els) => *body,
+                value => Statement::Expr(value),
+            },
+            Statement::Return(value) => Statement::Return(value.map(|value| self.expr(value))),
+            statement @ (Statement::Panic { .. } | Statement::Unreachable { .. } | Statement::StaticAssert { .. } | Statement::Const { .. }
+                | Statement::Break(_) | Statement::Continue(_)) => statement,
+        }
+    }
+
+    fn expr(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Identifier(name) => self.lookup(&name).unwrap_or(Expr::Identifier(name)),
+            Expr::IntegerLiteral(_) | Expr::StringLiteral(_) => expr,
+            Expr::Array(elements) => Expr::Array(elements.into_iter().map(|element| self.expr(element)).collect()),
+            Expr::Index { identifier, index, location } => Expr::Index { identifier, index: self.boxed(*index), location },
+
+            Expr::Add(a, b) => fold(Expr::Add(self.boxed(*a), self.boxed(*b))),
+            Expr::Sub(a, b) => fold(Expr::Sub(self.boxed(*a), self.boxed(*b))),
+            Expr::Mul(a, b) => fold(Expr::Mul(self.boxed(*a), self.boxed(*b))),
+            Expr::Div(a, b) => fold(Expr::Div(self.boxed(*a), self.boxed(*b))),
+            Expr::Mod(a, b) => fold(Expr::Mod(self.boxed(*a), self.boxed(*b))),
+            Expr::Equality(a, b) => fold(Expr::Equality(self.boxed(*a), self.boxed(*b))),
+            Expr::NonEquality(a, b) => fold(Expr::NonEquality(self.boxed(*a), self.boxed(*b))),
+            Expr::Less(a, b) => fold(Expr::Less(self.boxed(*a), self.boxed(*b))),
+            Expr::LessEq(a, b) => fold(Expr::LessEq(self.boxed(*a), self.boxed(*b))),
+            Expr::Greater(a, b) => fold(Expr::Greater(self.boxed(*a), self.boxed(*b))),
+            Expr::GreaterEq(a, b) => fold(Expr::GreaterEq(self.boxed(*a), self.boxed(*b))),
+
+            Expr::Assign { identifier, value } => Expr::Assign { identifier, value: self.boxed(*value) },
+            Expr::AddAssign { identifier, value } => Expr::AddAssign { identifier, value: self.boxed(*value) },
+            Expr::SubAssign { identifier, value } => Expr::SubAssign { identifier, value: self.boxed(*value) },
+            Expr::MulAssign { identifier, value } => Expr::MulAssign { identifier, value: self.boxed(*value) },
+            Expr::DivAssign { identifier, value } => Expr::DivAssign { identifier, value: self.boxed(*value) },
+            Expr::ModAssign { identifier, value } => Expr::ModAssign { identifier, value: self.boxed(*value) },
+            Expr::IndexAssign { identifier, index, value, location } =>
+                Expr::IndexAssign { identifier, index: self.boxed(*index), value: self.boxed(*value), location },
+
+            Expr::FunctionCall { name, args } => {
+                let args = args.into_iter().map(|arg| self.expr(arg)).collect();
+                self.call(name, args)
+            },
+            // a function name or the variables to exchange, which no constant may replace
+            Expr::Intrinsic { intrinsic: Intrinsic::Spawn | Intrinsic::Swap, args: _ } => expr,
+            Expr::Intrinsic { intrinsic, args } => Expr::Intrinsic {
+                intrinsic,
+                args: args.into_iter().map(|arg| self.expr(arg)).collect(),
+            },
+            Expr::Try(value) => Expr::Try(self.boxed(*value)),
+            Expr::Neg(value) => fold(Expr::Neg(self.boxed(*value))),
+            Expr::Cast { value, ty } => fold(Expr::Cast { value: self.boxed(*value), ty }),
+
+            Expr::Block(stmts) => {
+                self.scopes.push(HashMap::new());
+                let mut stmts: Vec<_> = stmts.into_iter().map(|stmt| self.statement(stmt)).collect();
+                self.scopes.pop();
+                // nothing after an `unreachable()` ever runs
+                if let Some(end) = stmts.iter().position(reaches_unreachable) {
+                    stmts.truncate(end + 1);
+                }
+                Expr::Block(stmts)
+            },
+            Expr::If { check, body, els } => Expr::If {
+                check: self.boxed(*check),
+                body: Box::new(self.scoped(*body)),
+                els: els.map(|els| Box::new(self.scoped(*els))),
+            },
+            // loop bodies share the enclosing frame in codegen, so they don't get a scope here either
+            Expr::Loop { label, body } => Expr::Loop { label, body: Box::new(self.statement(*body)) },
+            Expr::While { label, check, body } => Expr::While {
+                label,
+                check: self.boxed(*check),
+                body: Box::new(self.statement(*body)),
+            },
+            Expr::Unsafe(body) => Expr::Unsafe(self.boxed(*body)),
+        }
+    }
+
+    fn call(&self, name: String, args: Vec<Expr>) -> Expr {
+        let result = args.iter().map(literal).collect::<Option<Vec<_>>>().and_then(|values| self.functions.call(&name, &values));
+        // `push` only takes a sign-extended 32-bit immediate
+        match result {
+            Some(value) if i32::try_from(value).is_ok() => Expr::IntegerLiteral(value.to_string()),
+            Some(_) | None => Expr::FunctionCall { name, args },
+        }
+    }
+
+    fn boxed(&mut self, expr: Expr) -> Box<Expr> {
+        Box::new(self.expr(expr))
+    }
+}
+
+
+fn literal(expr: &Expr) -> Option<i64> {
+    match expr {
+        Expr::IntegerLiteral(value) => value.parse().ok(),
+        _ => None,
+    }
+}
+
+// whether a statement always ends up at an `unreachable()`, once the blocks in it are pruned
+fn reaches_unreachable(statement: &Statement) -> bool {
+    match statement {
+        Statement::Unreachable { .. } => true,
+        Statement::Expr(Expr::Block(stmts)) => stmts.last().is_some_and(reaches_unreachable),
+        _ => false,
+    }
+}
+
+// whether leaving an expression out is unnoticeable, so no calls, nothing that may fault and
+// nothing that assigns
+fn is_pure(expr: &Expr) -> bool {
+    match expr {
+        Expr::IntegerLiteral(_) | Expr::Identifier(_) => true,
+        Expr::Neg(value) | Expr::Cast { value, ty: _ } => is_pure(value),
+        Expr::Add(a, b) | Expr::Sub(a, b) | Expr::Mul(a, b)
+            | Expr::Equality(a, b) | Expr::NonEquality(a, b)
+            | Expr::Less(a, b) | Expr::LessEq(a, b) | Expr::Greater(a, b) | Expr::GreaterEq(a, b) => is_pure(a) && is_pure(b),
+        _ => false,
+    }
+}
+
+// folds an operator over literals, matching the 64-bit semantics of the generated code
+fn fold(expr: Expr) -> Expr {
+    let folded = match &expr {
+        Expr::Neg(value) => literal(value).map(i64::wrapping_neg),
+        // an unsigned type also makes the comparisons it is in unsigned, which a literal wouldn't
+        Expr::Cast { value, ty } if ty.is_signed() => literal(value).map(|value| ty.wrap(value)),
+        Expr::Add(a, b) => literal(a).zip(literal(b)).map(|(a, b)| a.wrapping_add(b)),
+        Expr::Sub(a, b) => literal(a).zip(literal(b)).map(|(a, b)| a.wrapping_sub(b)),
+        Expr::Mul(a, b) => literal(a).zip(literal(b)).map(|(a, b)| a.wrapping_mul(b)),
+        Expr::Equality(a, b) => literal(a).zip(literal(b)).map(|(a, b)| (a == b) as i64),
+        Expr::NonEquality(a, b) => literal(a).zip(literal(b)).map(|(a, b)| (a != b) as i64),
+        Expr::Less(a, b) => literal(a).zip(literal(b)).map(|(a, b)| (a < b) as i64),
+        Expr::LessEq(a, b) => literal(a).zip(literal(b)).map(|(a, b)| (a <= b) as i64),
+        Expr::Greater(a, b) => literal(a).zip(literal(b)).map(|(a, b)| (a > b) as i64),
+        Expr::GreaterEq(a, b) => literal(a).zip(literal(b)).map(|(a, b)| (a >= b) as i64),
+        // division is left to run time so a zero divisor still faults where it did before
+        _ => None,
+    };
+    // `push` only takes a sign-extended 32-bit immediate
+    match folded {
+        Some(value) if i32::try_from(value).is_ok() => Expr::IntegerLiteral(value.to_string()),
+        Some(_) | None => expr,
+    }
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[cfg(test)]
+fn optimized_body(source: &str) -> String {
+    use crate::{parser::Parser, tokenize::Tokenize};
+    let program = Parser::new(source.tokenize()).parse().expect("source parses");
+    match optimize(program).0.remove(0) {
+        Item::Function { body, .. } => body.to_string(),
+        Item::Const { .. } => panic!("a function"),
+    }
+}
+
+#[test]
+fn immutable_constants_are_propagated_and_folded() {
+    assert_eq!(
+        optimized_body("fn f() { let x = 5; let y = x * 2; exit(y + 1); }"),
+        "{ let x = 5; let y = 10; exit(11); }",
+    );
+}
+
+#[test]
+fn mutable_bindings_are_not_propagated() {
+    assert_eq!(
+        optimized_body("fn f() { let mut x = 5; x += 1; exit(x); }"),
+        "{ let mut x = 5; x += 1; exit(x); }",
+    );
+}
+
+#[test]
+fn typed_bindings_are_propagated_as_they_are_held() {
+    assert_eq!(
+        optimized_body("fn f() { let x: i8 = 100; let y: i8 = x + x; let z: u8 = 1; exit(y + z); }"),
+        "{ let x: i8 = 100; let y: i8 = 200; let z: u8 = 1; exit(-56 + z); }",
+    );
+}
+
+#[test]
+fn shadowing_hides_outer_constants() {
+    assert_eq!(
+        optimized_body("fn f(a) { let x = 1; { let x = a; exit(x); } exit(x); }"),
+        "{ let x = 1; { let x = a; exit(x); } exit(1); }",
+    );
+}
+
+#[test]
+fn const_function_calls_are_evaluated() {
+    use crate::{parser::Parser, tokenize::Tokenize};
+    let source = "const fn double(x) return x * 2; fn f(a) { let x = 4; exit(double(x) + double(a) + double(1 / 0)); }";
+    let program = optimize(Parser::new(source.tokenize()).parse().expect("source parses"));
+    match &program.0[1] {
+        Item::Function { body, .. } => assert_eq!(body.to_string(), "{ let x = 4; exit((8 + double(a)) + double(1 / 0)); }"),
+        Item::Const { .. } => panic!("a function"),
+    }
+}
+
+#[test]
+fn statements_after_unreachable_are_dropped() {
+    assert_eq!(
+        optimized_body("fn f(a) { exit(a); unreachable(); exit(1); { unreachable(); } }"),
+        "{ exit(a); unreachable(); }",
+    );
+    assert_eq!(
+        optimized_body("fn f(a) { { exit(a); unreachable(); } exit(1); }"),
+        "{ { exit(a); unreachable(); } }",
+    );
+}
+
+#[test]
+fn branches_ending_in_unreachable_are_pruned() {
+    assert_eq!(
+        optimized_body("fn f(a) { if (a < 0) { exit(1); unreachable(); } else { exit(a); } }"),
+        "{ { exit(a); } }",
+    );
+    assert_eq!(
+        optimized_body("fn f(a) { if (a == 0) { exit(a); } else unreachable(); if (a > 1) unreachable(); exit(2); }"),
+        "{ { exit(a); } { } exit(2); }",
+    );
+    // the call in the check still has to be made
+    assert_eq!(
+        optimized_body("fn f(a) { if (g(a)) unreachable(); exit(a); }"),
+        "{ if (g(a)) unreachable(); exit(a); }",
+    );
+}
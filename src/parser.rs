@@ -1,24 +1,44 @@
-use std::collections::VecDeque;
-
 use fallible_iterator::FallibleIterator;
 
 use crate::{
     tokenize::{
+        self,
         Keyword, Symbol,
-        TokenStream, Token, TokenData,
-        TokenizerError,
+        Location, TokenCursor, TokenStream, Token, TokenData,
+        TokenizerError, unescape,
     },
     ast::{
-        Param, Expr,
-        Program, Statement, Item,
+        Attribute, AttributeArg, Param, Expr, Intrinsic,
+        Program, Statement, Item, Type,
     },
+    node_map::{NodeMap, Span},
 };
 
 
+/// why the parser stopped, or one of the errors it carried on past
 #[derive(Clone, Debug)]
+#[non_exhaustive]
 pub enum ParseError {
     TokenizerError(TokenizerError),
     UnexpectedToken(Token),
+    NonExhaustiveMatch(Location),
+    UnreachablePattern(Location),
+    LiteralOutOfRange { literal: String, location: Location },
+    LiteralOutOfType { literal: String, ty: Type, location: Location },
+    ChainedComparison(Location),
+    TooDeep(Location),
+    UndeclaredLabel { label: String, location: Location },
+    // where the `;` should have been, just past the end of the statement
+    MissingSemicolon(Location),
+    // the `(`, `{` or `[` that the token at the location should have closed, or the end of the
+    // source if it ends first
+    UnclosedDelimiter { open: Token, location: Location },
+    // the end of the source, reached part way through an item with nothing left open
+    UnexpectedEnd(Location),
+    // one the compiler doesn't know, or with the wrong arguments. parsing carries on past it
+    InvalidAttribute(Attribute),
+    // a `for` over a name that is neither the start of a range nor an array declared in the function
+    NotAnArray(String),
 }
 
 impl std::fmt::Display for ParseError {
@@ -36,17 +56,124 @@ impl From<TokenizerError> for ParseError {
 }
 
 
+/// what the parser accepts but is likely not what was meant
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseWarning {
+    // an `else` after an unbraced `if` that is itself the unbraced body of another `if`, the
+    // location is that of the inner `if`, which the `else` belongs to
+    DanglingElse(Location),
+}
+
+
+/// what to do with an integer literal that does not fit in 64 bits
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LiteralOverflow {
+    #[default]
+    Deny,
+    // literals up to `u64::MAX` stand for the same bits as a signed number, so
+    // `18446744073709551615` is -1
+    Wrap,
+}
+
+
+/// the version of the grammar a program is written in, so the syntax can change without breaking
+/// programs written for an older one. a `#version n` line before the first item picks it for its
+/// file, otherwise the parser is given one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum Edition {
+    // the conditions of `if` and `while` always need parentheses
+    V1,
+    #[default]
+    V2,
+}
+
+impl Edition {
+    pub fn from_number(number: u64) -> Option<Self> {
+        match number {
+            1 => Some(Self::V1),
+            2 => Some(Self::V2),
+            _ => None,
+        }
+    }
+}
+
+
+// every later pass walks the tree recursively, so how deeply it may nest is limited to keep them
+// all from running out of stack. each operator of a chain such as `1 + 1 + 1` counts as a level
+// too, as the chain nests to the left
+const MAX_DEPTH: usize = 256;
+
+
+/// a recursive descent parser over a stream of tokens, giving a `Program`
 pub struct Parser {
-    tokens: TokenStream,
-    buffer: VecDeque<Token>,
+    tokens: TokenCursor,
+    // the span of every node in the order they are finished, which is the numbering of `NodeId`
+    spans: Vec<Span>,
+    literal_overflow: LiteralOverflow,
+    edition: Edition,
+    warnings: Vec<ParseWarning>,
+    // every error found, in order. parsing carries on past a missing `;`, so there can be more than
+    // the one it stopped at
+    errors: Vec<ParseError>,
+    depth: usize,
+    // the labels of the loops being parsed, so a `break` or `continue` can only name one it is in
+    labels: Vec<String>,
+    // the variables visible in the function being parsed, innermost last, with the length of those
+    // that are arrays so `for x in a` knows how many elements to go through
+    variables: Vec<(String, Option<u64>)>,
 }
 
 impl Parser {
     pub fn new(tokens: TokenStream) -> Self {
-        Self { tokens, buffer: VecDeque::new() }
+        Self {
+            tokens: TokenCursor::new(tokens),
+            spans: Vec::new(),
+            literal_overflow: LiteralOverflow::default(),
+            edition: Edition::default(),
+            warnings: Vec::new(),
+            errors: Vec::new(),
+            depth: 0,
+            labels: Vec::new(),
+            variables: Vec::new(),
+        }
+    }
+
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    pub fn with_literal_overflow(mut self, literal_overflow: LiteralOverflow) -> Self {
+        self.literal_overflow = literal_overflow;
+        self
+    }
+
+    pub fn with_edition(mut self, edition: Edition) -> Self {
+        self.edition = edition;
+        self
     }
 
+    // gives the first error found, the rest are in `errors`
     pub fn parse(&mut self) -> Result<Program, ParseError> {
+        self.errors.clear();
+        let result = self.parse_items();
+        if let Err(err) = &result {
+            self.errors.push(err.clone());
+        }
+        match self.errors.first() {
+            Some(first) => Err(first.clone()),
+            None => result,
+        }
+    }
+
+    fn parse_items(&mut self) -> Result<Program, ParseError> {
+        self.parse_version()?;
         let mut program = Vec::new();
         while !self.is_empty()? {
             program.push(self.parse_item()?);
@@ -54,119 +181,320 @@ impl Parser {
         Ok(Program(program))
     }
 
+    pub fn parse_with_map(&mut self) -> Result<(Program, NodeMap), ParseError> {
+        self.spans.clear();
+        let program = self.parse()?;
+        let map = NodeMap::new(&program, std::mem::take(&mut self.spans));
+        Ok((program, map))
+    }
+
+    // `#version n`, which overrides the edition the parser was given for the rest of the file
+    fn parse_version(&mut self) -> Result<(), ParseError> {
+        let checkpoint = self.checkpoint();
+        match (self.consume()?, self.consume()?) {
+            (
+                Some(Token { data: TokenData::Symbol(Symbol::Hash), location: _ }),
+                Some(Token { data: TokenData::Identifier(name), location: _ }),
+            ) if name == "version" => (),
+            _ => {
+                self.rollback(checkpoint);
+                return Ok(());
+            },
+        }
+        let token = self.next_token()?;
+        let edition = match &token.data {
+            TokenData::IntegerLiteral(number) => number.parse().ok().and_then(Edition::from_number),
+            _ => None,
+        };
+        self.edition = edition.ok_or(ParseError::UnexpectedToken(token))?;
+        Ok(())
+    }
+
     fn parse_item(&mut self) -> Result<Item, ParseError> {
-        match self.peek()?.expect("a token") {
+        let start = self.start()?;
+        let attributes = self.parse_attributes()?;
+        let location = self.start()?;
+        let is_const = match self.peek()? {
+            Some(Token { data: TokenData::Keyword(Keyword::Const), location: _ }) => { self.consume()?; true },
+            Some(_) | None => false,
+        };
+        match self.peek_token()? {
+            // a constant for the whole program, it takes no attributes
+            Token { data: TokenData::Identifier(identifier), location: _ } if is_const && attributes.is_empty() => {
+                self.consume()?;
+                match self.next_token()? {
+                    Token { data: TokenData::Symbol(Symbol::Equals), location: _ } => (),
+                    tok => return Err(ParseError::UnexpectedToken(tok))
+                };
+                let value = self.parse_expression()?;
+                self.parse_semicolon()?;
+                Ok(self.node(start, Item::Const { identifier, value, location }))
+            },
             Token { data: TokenData::Keyword(Keyword::Fn), location: _ } => {
                 self.consume()?;
-                let name = match self.consume()?.expect("an identifier") {
+                let name = match self.next_token()? {
                     Token { data: TokenData::Identifier(ident), location: _ } => ident,
                     tok => return Err(ParseError::UnexpectedToken(tok)),
                 };
-                match self.consume()?.expect("a left parenthesis") {
+                match self.next_token()? {
                     Token { data: TokenData::Symbol(Symbol::LParen), location: _ } => (),
                     tok => return Err(ParseError::UnexpectedToken(tok))
                 };
                 let params = self.parse_params()?;
-                match self.consume()?.expect("a right parenthesis") {
-                    Token { data: TokenData::Symbol(Symbol::RParen), location: _ } => (),
-                    tok => return Err(ParseError::UnexpectedToken(tok))
+                self.parse_closing(Symbol::RParen)?;
+                self.variables = params.iter().map(|param| (param.name.clone(), None)).collect();
+                let return_type = match self.peek()? {
+                    Some(Token { data: TokenData::Symbol(Symbol::Arrow), location: _ }) => {
+                        self.consume()?;
+                        Some(self.parse_type()?)
+                    },
+                    Some(_) | None => None,
                 };
                 let body = self.parse_statement()?;
-                Ok(Item::Function { name, params, body })
+                Ok(self.node(start, Item::Function { name, params, return_type, body, attributes, is_const }))
+            },
+            tok => Err(ParseError::UnexpectedToken(tok)),
+        }
+    }
+
+    // `i64`, or `()` for no value
+    fn parse_type(&mut self) -> Result<Type, ParseError> {
+        match self.next_token()? {
+            Token { data: TokenData::Identifier(name), location } => match Type::from_name(&name) {
+                Some(ty) => Ok(ty),
+                None => Err(ParseError::UnexpectedToken(Token { data: TokenData::Identifier(name), location })),
             },
+            Token { data: TokenData::Symbol(Symbol::LParen), location: _ } => {
+                self.parse_closing(Symbol::RParen)?;
+                Ok(Type::Unit)
+            },
+            tok => Err(ParseError::UnexpectedToken(tok)),
+        }
+    }
+
+    // variables and casts always hold an integer, so `()` isn't one of their types
+    fn parse_integer_type(&mut self) -> Result<Type, ParseError> {
+        match self.peek_token()? {
+            Token { data: TokenData::Identifier(_), location: _ } => self.parse_type(),
             tok => Err(ParseError::UnexpectedToken(tok)),
         }
     }
 
+    fn parse_attributes(&mut self) -> Result<Vec<Attribute>, ParseError> {
+        let mut attributes = Vec::new();
+        while let Some(Token { data: TokenData::Symbol(Symbol::Hash), location }) = self.peek()? {
+            self.consume()?;
+            match self.next_token()? {
+                Token { data: TokenData::Symbol(Symbol::LBracket), location: _ } => (),
+                tok => return Err(ParseError::UnexpectedToken(tok)),
+            };
+            let name = match self.next_token()? {
+                Token { data: TokenData::Identifier(ident), location: _ } => ident,
+                tok => return Err(ParseError::UnexpectedToken(tok)),
+            };
+            let args = match self.peek_token()? {
+                Token { data: TokenData::Symbol(Symbol::LParen), location: _ } => {
+                    self.consume()?;
+                    let args = self.parse_attribute_args()?;
+                    self.parse_closing(Symbol::RParen)?;
+                    args
+                },
+                _ => Vec::new(),
+            };
+            self.parse_closing(Symbol::RBracket)?;
+            let attribute = Attribute { name, args, location };
+            if !attribute.is_valid() {
+                self.errors.push(ParseError::InvalidAttribute(attribute.clone()));
+            }
+            attributes.push(attribute);
+        }
+        Ok(attributes)
+    }
+
+    fn parse_attribute_args(&mut self) -> Result<Vec<AttributeArg>, ParseError> {
+        let arg = match self.peek_token()? {
+            Token { data: TokenData::Symbol(Symbol::RParen), location: _ } => return Ok(vec![]),
+            Token { data: TokenData::Identifier(ident), location: _ } => AttributeArg::Identifier(ident),
+            Token { data: TokenData::IntegerLiteral(lit), location: _ } => AttributeArg::IntegerLiteral(lit),
+            Token { data: TokenData::StringLiteral(lit), location: _ } => AttributeArg::StringLiteral(unescape(&lit)),
+            tok => return Err(ParseError::UnexpectedToken(tok)),
+        };
+        self.consume()?;
+        let mut args = vec![arg];
+        match self.peek_token()? {
+            Token { data: TokenData::Symbol(Symbol::RParen), location: _ } => (),
+            Token { data: TokenData::Symbol(Symbol::Comma), location: _ } => { self.consume()?; args.extend(self.parse_attribute_args()?); },
+            tok => return Err(self.unclosed_at(tok)),
+        };
+        Ok(args)
+    }
+
     fn parse_params(&mut self) -> Result<Vec<Param>, ParseError> {
-        let name = match self.peek()?.expect("an identifier or a right parenthesis") {
+        let name = match self.peek_token()? {
             Token { data: TokenData::Symbol(Symbol::RParen), location: _ } => return Ok(vec![]),
             Token { data: TokenData::Identifier(ident), location: _ } => ident,
             tok => return Err(ParseError::UnexpectedToken(tok)),
         };
         self.consume()?;
         let mut params = vec![Param { name }];
-        match self.peek()?.expect("a comma or a right parenthesis") {
+        match self.peek_token()? {
             Token { data: TokenData::Symbol(Symbol::RParen), location: _ } => (),
             Token { data: TokenData::Symbol(Symbol::Comma), location: _ } => { self.consume()?; params.extend(self.parse_params()?); },
-            tok => return Err(ParseError::UnexpectedToken(tok)),
+            tok => return Err(self.unclosed_at(tok)),
         };
         Ok(params)
     }
 
     fn parse_statement(&mut self) -> Result<Statement, ParseError> {
-        match self.peek()?.expect("a token") {
+        self.nested(Self::parse_single_statement)
+    }
+
+    fn parse_single_statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.start()?;
+        match self.peek_token()? {
             Token { data: TokenData::Keyword(kwd), location } => match kwd {
                 Keyword::Exit => {
                     self.consume()?;
-                    match self.consume()?.expect("a left parenthesis") {
+                    match self.next_token()? {
                         Token { data: TokenData::Symbol(Symbol::LParen), location: _ } => (),
                         tok => return Err(ParseError::UnexpectedToken(tok))
                     };
                     let value = self.parse_expression()?;
-                    match self.consume()?.expect("a right parenthesis") {
-                        Token { data: TokenData::Symbol(Symbol::RParen), location: _ } => (),
+                    self.parse_closing(Symbol::RParen)?;
+                    self.parse_semicolon()?;
+                    Ok(self.node(start, Statement::Exit { value }))
+                },
+                Keyword::Panic => {
+                    self.consume()?;
+                    match self.next_token()? {
+                        Token { data: TokenData::Symbol(Symbol::LParen), location: _ } => (),
+                        tok => return Err(ParseError::UnexpectedToken(tok))
+                    };
+                    let message = match self.next_token()? {
+                        Token { data: TokenData::StringLiteral(message), location: _ } => unescape(&message),
+                        tok => return Err(ParseError::UnexpectedToken(tok))
+                    };
+                    self.parse_closing(Symbol::RParen)?;
+                    self.parse_semicolon()?;
+                    Ok(self.node(start, Statement::Panic { message, location }))
+                },
+                Keyword::Unreachable => {
+                    self.consume()?;
+                    match self.next_token()? {
+                        Token { data: TokenData::Symbol(Symbol::LParen), location: _ } => (),
+                        tok => return Err(ParseError::UnexpectedToken(tok))
+                    };
+                    self.parse_closing(Symbol::RParen)?;
+                    self.parse_semicolon()?;
+                    Ok(self.node(start, Statement::Unreachable { location }))
+                },
+                Keyword::StaticAssert => {
+                    self.consume()?;
+                    match self.next_token()? {
+                        Token { data: TokenData::Symbol(Symbol::LParen), location: _ } => (),
+                        tok => return Err(ParseError::UnexpectedToken(tok))
+                    };
+                    let condition = self.parse_expression()?;
+                    match self.next_token()? {
+                        Token { data: TokenData::Symbol(Symbol::Comma), location: _ } => (),
                         tok => return Err(ParseError::UnexpectedToken(tok))
                     };
-                    match self.consume()?.expect("a semicolon") {
-                        Token { data: TokenData::Symbol(Symbol::Semi), location: _ } => (),
+                    let message = match self.next_token()? {
+                        Token { data: TokenData::StringLiteral(message), location: _ } => unescape(&message),
+                        tok => return Err(ParseError::UnexpectedToken(tok))
+                    };
+                    self.parse_closing(Symbol::RParen)?;
+                    self.parse_semicolon()?;
+                    Ok(self.node(start, Statement::StaticAssert { condition, message, location }))
+                },
+                Keyword::Const => {
+                    self.consume()?;
+                    let identifier = match self.next_token()? {
+                        Token { data: TokenData::Identifier(identifier), location: _ } => identifier,
+                        tok => return Err(ParseError::UnexpectedToken(tok)),
+                    };
+                    match self.next_token()? {
+                        Token { data: TokenData::Symbol(Symbol::Equals), location: _ } => (),
                         tok => return Err(ParseError::UnexpectedToken(tok))
                     };
-                    Ok(Statement::Exit { value })
+                    let value = self.parse_expression()?;
+                    self.parse_semicolon()?;
+                    Ok(self.node(start, Statement::Const { identifier, value, location }))
                 },
                 Keyword::Let => {
                     self.consume()?;
-                    let is_mutable = match self.peek()?.expect("an identifier or `mut`") {
+                    let is_mutable = match self.peek_token()? {
                         Token { data: TokenData::Keyword(Keyword::Mut), location: _ } => {
                             self.consume()?;
                             true
                         },
                         _ => false,
                     };
-                    let identifier = match self.consume()?.expect("an identifier") {
+                    let identifier = match self.next_token()? {
                         Token { data: TokenData::Identifier(identifier), location: _ } => identifier,
                         tok => return Err(ParseError::UnexpectedToken(tok)),
                     };
-                    match self.consume()?.expect("an equals sign") {
-                        Token { data: TokenData::Symbol(Symbol::Equals), location: _ } => (),
-                        tok => return Err(ParseError::UnexpectedToken(tok))
+                    let ty = match self.peek()? {
+                        Some(Token { data: TokenData::Symbol(Symbol::Colon), location: _ }) => {
+                            self.consume()?;
+                            Some(self.parse_integer_type()?)
+                        },
+                        _ => None,
                     };
-                    let value = self.parse_expression()?;
-                    match self.consume()?.expect("a semicolon") {
-                        Token { data: TokenData::Symbol(Symbol::Semi), location: _ } => (),
+                    let value = match self.next_token()? {
+                        Token { data: TokenData::Symbol(Symbol::Equals), location: _ } => {
+                            let value_start = self.start()?;
+                            let value = match self.peek()? {
+                                Some(Token { data: TokenData::Symbol(Symbol::LBracket), location: _ }) if ty.is_none() => self.parse_array()?,
+                                _ => self.parse_expression()?,
+                            };
+                            // a literal has the type of the variable it is given to, so it has to fit it
+                            if let (Some(ty), Expr::IntegerLiteral(literal)) = (ty, &value) {
+                                if !literal.parse().is_ok_and(|value| ty.fits(value)) {
+                                    return Err(ParseError::LiteralOutOfType { literal: literal.clone(), ty, location: value_start });
+                                }
+                            }
+                            value
+                        },
+                        Token { data: TokenData::Symbol(Symbol::Semi), location: _ } if is_mutable => {
+                            self.variables.push((identifier.clone(), None));
+                            return Ok(self.node(start, Statement::Let { identifier, ty, value: None, is_mutable }));
+                        },
                         tok => return Err(ParseError::UnexpectedToken(tok))
                     };
-                    Ok(Statement::Let { identifier, value, is_mutable })
+                    self.parse_semicolon()?;
+                    let length = match &value {
+                        Expr::Array(elements) => Some(elements.len() as u64),
+                        _ => None,
+                    };
+                    self.variables.push((identifier.clone(), length));
+                    Ok(self.node(start, Statement::Let { identifier, ty, value: Some(value), is_mutable }))
                 },
-                Keyword::If => self.parse_if().map(Statement::Expr),
-                Keyword::Loop => self.parse_loop().map(Statement::Expr),
-                Keyword::While => self.parse_while().map(Statement::Expr),
+                Keyword::If => self.parse_if().map(|expr| self.node(start, Statement::Expr(expr))),
+                Keyword::Loop => self.parse_loop(None).map(|expr| self.node(start, Statement::Expr(expr))),
+                Keyword::While => self.parse_while(None).map(|expr| self.node(start, Statement::Expr(expr))),
+                Keyword::For => self.parse_for(None).map(|expr| self.node(start, Statement::Expr(expr))),
+                Keyword::Unsafe => self.parse_unsafe().map(|expr| self.node(start, Statement::Expr(expr))),
 
                 Keyword::Break => {
                     self.consume()?;
-                    match self.consume()?.expect("a semicolon") {
-                        Token { data: TokenData::Symbol(Symbol::Semi), location: _ } => (),
-                        tok => return Err(ParseError::UnexpectedToken(tok))
-                    };
-                    Ok(Statement::Break)
+                    let label = self.parse_jump_label()?;
+                    Ok(self.node(start, Statement::Break(label)))
                 },
                 Keyword::Continue => {
                     self.consume()?;
-                    match self.consume()?.expect("a semicolon") {
-                        Token { data: TokenData::Symbol(Symbol::Semi), location: _ } => (),
-                        tok => return Err(ParseError::UnexpectedToken(tok))
-                    };
-                    Ok(Statement::Continue)
+                    let label = self.parse_jump_label()?;
+                    Ok(self.node(start, Statement::Continue(label)))
                 },
 
                 Keyword::Return => {
                     self.consume()?;
-                    let value = self.parse_expression()?;
-                    match self.consume()?.expect("a semicolon") {
-                        Token { data: TokenData::Symbol(Symbol::Semi), location: _ } => (),
-                        tok => return Err(ParseError::UnexpectedToken(tok))
+                    let value = match self.peek_token()? {
+                        Token { data: TokenData::Symbol(Symbol::Semi), location: _ } => None,
+                        _ => Some(self.parse_expression()?),
                     };
-                    Ok(Statement::Return(value))
+                    self.parse_semicolon()?;
+                    Ok(self.node(start, Statement::Return(value)))
                 }
 
                 kwd => Err(ParseError::UnexpectedToken(Token { data: TokenData::Keyword(kwd), location })),
@@ -174,302 +502,755 @@ impl Parser {
             Token {
                 data: TokenData::Symbol(Symbol::LBrace),
                 location: _,
-            } => self.parse_block().map(Statement::Expr),
+            } => self.parse_block().map(|expr| self.node(start, Statement::Expr(expr))),
+            Token { data: TokenData::Label(_), location: _ } => self.parse_labelled_loop().map(|expr| self.node(start, Statement::Expr(expr))),
+            _ if self.at_match()? => self.parse_match().map(|expr| self.node(start, Statement::Expr(expr))),
             _ => {
                 let expr = self.parse_expression()?;
-                match self.consume()?.expect("a semicolon `;`") {
-                    Token {
-                        data: TokenData::Symbol(Symbol::Semi),
-                        location: _
-                    } => Ok(Statement::Expr(expr)),
-                    tok => Err(ParseError::UnexpectedToken(tok)),
-                }
+                self.parse_semicolon()?;
+                Ok(self.node(start, Statement::Expr(expr)))
             },
         }
     }
 
     fn parse_expression(&mut self) -> Result<Expr, ParseError> {
-        self.parse_assign_expr()
+        self.nested(Self::parse_assign_expr)
+    }
+
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, ParseError>) -> Result<T, ParseError> {
+        let depth = self.depth;
+        let result = self.deeper().and_then(|_| parse(self));
+        self.depth = depth;
+        result
+    }
+
+    fn deeper(&mut self) -> Result<(), ParseError> {
+        self.depth += 1;
+        match self.depth > MAX_DEPTH {
+            true => Err(ParseError::TooDeep(self.start()?)),
+            false => Ok(()),
+        }
     }
 
+    // an identifier and an assignment operator, anything else is read again from the start as a
+    // comparison
     fn parse_assign_expr(&mut self) -> Result<Expr, ParseError> {
-        let identifier = match self.peek()?.expect("a token") {
-            Token { data: TokenData::Identifier(ident), location: _ } => ident,
-            _ => return self.parse_expression_cmp_part(),
-        };
-        let symbol = match self.peek_ahead(1)?.expect("an operator") {
-            Token { data:TokenData::Symbol(symbol), location: _ } => symbol,
-            _ => return self.parse_expression_cmp_part(),
-        };
-        match symbol {
-            Symbol::PlusEq => {
-                self.consume()?;
-                self.consume()?;
-                Ok(Expr::AddAssign {
-                    identifier,
-                    value: Box::new(self.parse_expression()?),
-                })
-            },
-            Symbol::MinusEq => {
-                self.consume()?;
-                self.consume()?;
-                Ok(Expr::SubAssign {
-                    identifier,
-                    value: Box::new(self.parse_expression()?),
-                })
-            },
-            Symbol::StarEq => {
-                self.consume()?;
-                self.consume()?;
-                Ok(Expr::MulAssign {
-                    identifier,
-                    value: Box::new(self.parse_expression()?),
-                })
-            },
-            Symbol::SlashEq => {
-                self.consume()?;
-                self.consume()?;
-                Ok(Expr::DivAssign {
-                    identifier,
-                    value: Box::new(self.parse_expression()?),
-                })
-            },
-            Symbol::PercentEq => {
-                self.consume()?;
-                self.consume()?;
-                Ok(Expr::ModAssign {
-                    identifier,
-                    value: Box::new(self.parse_expression()?),
-                })
+        let start = self.start()?;
+        let checkpoint = self.checkpoint();
+        let (identifier, location, symbol) = match (self.consume()?, self.consume()?) {
+            (
+                Some(Token { data: TokenData::Identifier(identifier), location }),
+                Some(Token { data: TokenData::Symbol(symbol), location: _ }),
+            ) => (identifier, location, symbol),
+            _ => {
+                self.rollback(checkpoint);
+                return self.parse_expression_cmp_part();
             },
-            _ => self.parse_expression_cmp_part(),
+        };
+        // `a[i] = v`, anything else that starts with `a[i]` is read as an expression
+        if symbol == Symbol::LBracket {
+            let index = Box::new(self.parse_index()?);
+            let Some(Token { data: TokenData::Symbol(Symbol::Equals), location: _ }) = self.consume()? else {
+                self.rollback(checkpoint);
+                return self.parse_expression_cmp_part();
+            };
+            let value = Box::new(self.parse_expression()?);
+            return Ok(self.node(start, Expr::IndexAssign { identifier, index, value, location }));
         }
+        let assign: fn(String, Box<Expr>) -> Expr = match symbol {
+            Symbol::Equals => |identifier, value| Expr::Assign { identifier, value },
+            Symbol::PlusEq => |identifier, value| Expr::AddAssign { identifier, value },
+            Symbol::MinusEq => |identifier, value| Expr::SubAssign { identifier, value },
+            Symbol::StarEq => |identifier, value| Expr::MulAssign { identifier, value },
+            Symbol::SlashEq => |identifier, value| Expr::DivAssign { identifier, value },
+            Symbol::PercentEq => |identifier, value| Expr::ModAssign { identifier, value },
+            _ => {
+                self.rollback(checkpoint);
+                return self.parse_expression_cmp_part();
+            },
+        };
+        let value = Box::new(self.parse_expression()?);
+        Ok(self.node(start, assign(identifier, value)))
     }
 
     fn parse_expression_cmp_part(&mut self) -> Result<Expr, ParseError> {
+        let start = self.start()?;
         let mut expr = self.parse_expression_add_part()?;
         if let Some(Token { data, location: _ }) = self.peek()? {
             match data {
                 TokenData::Symbol(Symbol::Equality) => {
                     self.consume()?;
-                    expr = Expr::Equality(
-                        Box::new(expr),
-                        Box::new(self.parse_expression_add_part()?)
-                    );
+                    let rhs = Box::new(self.parse_expression_add_part()?);
+                    expr = self.node(start, Expr::Equality(Box::new(expr), rhs));
                 },
                 TokenData::Symbol(Symbol::NonEquality) => {
                     self.consume()?;
-                    expr = Expr::NonEquality(
-                        Box::new(expr),
-                        Box::new(self.parse_expression_add_part()?)
-                    );
+                    let rhs = Box::new(self.parse_expression_add_part()?);
+                    expr = self.node(start, Expr::NonEquality(Box::new(expr), rhs));
                 },
                 TokenData::Symbol(Symbol::LAngle) => {
                     self.consume()?;
-                    expr = Expr::Less(
-                        Box::new(expr),
-                        Box::new(self.parse_expression_add_part()?)
-                    );
+                    let rhs = Box::new(self.parse_expression_add_part()?);
+                    expr = self.node(start, Expr::Less(Box::new(expr), rhs));
                 },
                 TokenData::Symbol(Symbol::LesserEqual) => {
                     self.consume()?;
-                    expr = Expr::LessEq(
-                        Box::new(expr),
-                        Box::new(self.parse_expression_add_part()?)
-                    );
+                    let rhs = Box::new(self.parse_expression_add_part()?);
+                    expr = self.node(start, Expr::LessEq(Box::new(expr), rhs));
                 },
                 TokenData::Symbol(Symbol::RAngle) => {
                     self.consume()?;
-                    expr = Expr::Greater(
-                        Box::new(expr),
-                        Box::new(self.parse_expression_add_part()?)
-                    );
+                    let rhs = Box::new(self.parse_expression_add_part()?);
+                    expr = self.node(start, Expr::Greater(Box::new(expr), rhs));
                 },
                 TokenData::Symbol(Symbol::GreaterEqual) => {
                     self.consume()?;
-                    expr = Expr::GreaterEq(
-                        Box::new(expr),
-                        Box::new(self.parse_expression_add_part()?)
-                    );
+                    let rhs = Box::new(self.parse_expression_add_part()?);
+                    expr = self.node(start, Expr::GreaterEq(Box::new(expr), rhs));
                 },
-                _ => (),
+                _ => return Ok(expr),
             }
         }
-        Ok(expr)
+        // `a < b < c` would compare the result of `a < b` with `c`, which is never what was meant
+        match self.peek()? {
+            Some(Token { data: TokenData::Symbol(symbol), location }) if symbol.is_comparison() => Err(ParseError::ChainedComparison(location)),
+            _ => Ok(expr),
+        }
     }
 
     fn parse_expression_add_part(&mut self) -> Result<Expr, ParseError> {
-        let expr = self.parse_expression_mul_part()?;
-        Ok(
-            if let Some(Token { data, location: _ }) = self.peek()? {
-                match data {
-                    TokenData::Symbol(Symbol::Plus) => {
-                        self.consume()?;
-                        match self.parse_expression_add_part()? {
-                            base @ Expr::Add(_, _) | base @ Expr::Sub(_, _)
-                                => base.map_left(|lhs| Expr::Add(Box::new(expr.clone()), lhs)),
-                            other => Expr::Add(Box::new(expr), Box::new(other)),
-                        }
-                    },
-                    TokenData::Symbol(Symbol::Minus) => {
-                        self.consume()?;
-                        match self.parse_expression_add_part()? {
-                            base @ Expr::Add(_, _) | base @ Expr::Sub(_, _)
-                                => base.map_left(|lhs| Expr::Sub(Box::new(expr.clone()), lhs)),
-                            other => Expr::Sub(Box::new(expr), Box::new(other)),
-                        }
-                    },
-                    _ => expr,
-                }
-            } else {
-                expr
-            }
-        )
+        let start = self.start()?;
+        let depth = self.depth;
+        let mut expr = self.parse_expression_mul_part()?;
+        while let Some(Token { data: TokenData::Symbol(symbol @ (Symbol::Plus | Symbol::Minus)), location: _ }) = self.peek()? {
+            self.deeper()?;
+            self.consume()?;
+            let rhs = Box::new(self.parse_expression_mul_part()?);
+            expr = match symbol {
+                Symbol::Plus => self.node(start, Expr::Add(Box::new(expr), rhs)),
+                _ => self.node(start, Expr::Sub(Box::new(expr), rhs)),
+            };
+        }
+        self.depth = depth;
+        Ok(expr)
     }
 
     fn parse_expression_mul_part(&mut self) -> Result<Expr, ParseError> {
-        let expr = self.parse_atom()?;
-        Ok(
-            if let Some(Token { data, location: _ }) = self.peek()? {
-                match data {
-                    TokenData::Symbol(Symbol::Star) => {
-                        self.consume()?;
-                        match self.parse_expression_mul_part()? {
-                            base @ Expr::Mul(_, _) | base @ Expr::Div(_, _) | base @ Expr::Mod(_, _)
-                                => base.map_left(|lhs| Expr::Mul(Box::new(expr.clone()), lhs)),
-                            other => Expr::Mul(Box::new(expr), Box::new(other)),
-                        }
-                    },
-                    TokenData::Symbol(Symbol::Slash) => {
-                        self.consume()?;
-                        match self.parse_expression_mul_part()? {
-                            base @ Expr::Mul(_, _) | base @ Expr::Div(_, _) | base @ Expr::Mod(_, _)
-                                => base.map_left(|lhs| Expr::Div(Box::new(expr.clone()), lhs)),
-                            other => Expr::Div(Box::new(expr), Box::new(other)),
-                        }
-                    },
-                    TokenData::Symbol(Symbol::Percent) => {
-                        self.consume()?;
-                        match self.parse_expression_mul_part()? {
-                            base @ Expr::Mul(_, _) | base @ Expr::Div(_, _) | base @ Expr::Mod(_, _)
-                                => base.map_left(|lhs| Expr::Mod(Box::new(expr.clone()), lhs)),
-                            other => Expr::Mod(Box::new(expr), Box::new(other)),
-                        }
-                    },
-                    _ => expr,
-                }
-            } else {
-                expr
+        let start = self.start()?;
+        let depth = self.depth;
+        let mut expr = self.parse_expression_cast_part()?;
+        while let Some(Token {
+            data: TokenData::Symbol(symbol @ (Symbol::Star | Symbol::Slash | Symbol::Percent)),
+            location: _,
+        }) = self.peek()? {
+            self.deeper()?;
+            self.consume()?;
+            let rhs = Box::new(self.parse_expression_cast_part()?);
+            expr = match symbol {
+                Symbol::Star => self.node(start, Expr::Mul(Box::new(expr), rhs)),
+                Symbol::Slash => self.node(start, Expr::Div(Box::new(expr), rhs)),
+                _ => self.node(start, Expr::Mod(Box::new(expr), rhs)),
+            };
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    // binds tighter than the binary operators and looser than `-`, so `-x as u8` casts `-x`
+    fn parse_expression_cast_part(&mut self) -> Result<Expr, ParseError> {
+        let start = self.start()?;
+        let depth = self.depth;
+        let mut expr = self.parse_expression_unary_part()?;
+        while self.at_contextual(Keyword::As)? {
+            self.deeper()?;
+            self.consume()?;
+            let ty = self.parse_integer_type()?;
+            expr = self.node(start, Expr::Cast { value: Box::new(expr), ty });
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    // binds looser than postfix operators, so `-x?` negates the value `x?` gives
+    fn parse_expression_unary_part(&mut self) -> Result<Expr, ParseError> {
+        let start = self.start()?;
+        let Some(Token { data: TokenData::Symbol(Symbol::Minus), location: _ }) = self.peek()? else {
+            return self.parse_expression_postfix_part();
+        };
+        self.consume()?;
+        // a negative literal is read as one, so the smallest integer fits
+        let checkpoint = self.checkpoint();
+        if let Some(Token { data: TokenData::IntegerLiteral(literal), location: _ }) = self.consume()? {
+            if !matches!(self.peek()?, Some(Token { data: TokenData::Symbol(Symbol::Question), location: _ })) {
+                let value = self.integer(format!("-{literal}"), start)?;
+                return Ok(self.node(start, Expr::IntegerLiteral(value.to_string())));
             }
-        )
+        }
+        self.rollback(checkpoint);
+        let value = Box::new(self.nested(Self::parse_expression_unary_part)?);
+        Ok(self.node(start, Expr::Neg(value)))
+    }
+
+    fn parse_expression_postfix_part(&mut self) -> Result<Expr, ParseError> {
+        let start = self.start()?;
+        let depth = self.depth;
+        let mut expr = self.parse_atom()?;
+        while let Some(Token { data: TokenData::Symbol(Symbol::Question), location: _ }) = self.peek()? {
+            self.deeper()?;
+            self.consume()?;
+            expr = self.node(start, Expr::Try(Box::new(expr)));
+        }
+        self.depth = depth;
+        Ok(expr)
     }
 
     fn parse_atom(&mut self) -> Result<Expr, ParseError> {
-        match self.peek()?.expect("a token") {
-            Token { data: TokenData::IntegerLiteral(lit), location: _ } => { self.consume()?; Ok(Expr::IntegerLiteral(lit)) },
-            Token { data: TokenData::Identifier(ident), location: _ } => {
+        if self.at_match()? {
+            return self.parse_match();
+        }
+        match self.peek_token()? {
+            Token { data: TokenData::IntegerLiteral(lit), location } => {
+                self.consume()?;
+                let value = self.integer(lit, location)?;
+                Ok(self.node(location, Expr::IntegerLiteral(value.to_string())))
+            },
+            Token { data: TokenData::StringLiteral(value), location } => {
+                self.consume()?;
+                Ok(self.node(location, Expr::StringLiteral(unescape(&value))))
+            },
+            Token { data: TokenData::Identifier(ident), location } => {
                 self.consume()?;
                 match self.peek()? {
                     Some(Token { data: TokenData::Symbol(Symbol::LParen), location: _ }) => {
                         self.consume()?;
                         let args = self.parse_args()?;
-                        match self.consume()?.expect("a right parenthesis `)`") {
-                            Token { data: TokenData::Symbol(Symbol::RParen), location: _ } => (),
-                            tok => return Err(ParseError::UnexpectedToken(tok)),
-                        };
-                        Ok(Expr::FunctionCall { name: ident, args })
+                        let rparen = self.parse_closing(Symbol::RParen)?;
+                        match Intrinsic::from_name(&ident) {
+                            Some(intrinsic) if args.len() != intrinsic.arity() => Err(ParseError::UnexpectedToken(rparen)),
+                            Some(intrinsic) => Ok(self.node(location, Expr::Intrinsic { intrinsic, args })),
+                            None => Ok(self.node(location, Expr::FunctionCall { name: ident, args })),
+                        }
                     },
-                    _ => Ok(Expr::Identifier(ident)),
+                    Some(Token { data: TokenData::Symbol(Symbol::LBracket), location: _ }) => {
+                        self.consume()?;
+                        let index = Box::new(self.parse_index()?);
+                        Ok(self.node(location, Expr::Index { identifier: ident, index, location }))
+                    },
+                    _ => Ok(self.node(location, Expr::Identifier(ident))),
                 }
             }
 
+            // grouping leaves no node of its own, the operators around it are nested to match
+            Token { data: TokenData::Symbol(Symbol::LParen), location: _ } => {
+                self.consume()?;
+                let value = self.parse_expression()?;
+                self.parse_closing(Symbol::RParen)?;
+                Ok(value)
+            },
             Token { data: TokenData::Symbol(Symbol::LBrace), location: _ } => self.parse_block(),
             Token { data: TokenData::Keyword(Keyword::If), location: _ } => self.parse_if(),
-            Token { data: TokenData::Keyword(Keyword::Loop), location: _ } => self.parse_loop(),
-            Token { data: TokenData::Keyword(Keyword::While), location: _ } => self.parse_while(),
+            Token { data: TokenData::Keyword(Keyword::Loop), location: _ } => self.parse_loop(None),
+            Token { data: TokenData::Keyword(Keyword::While), location: _ } => self.parse_while(None),
+            Token { data: TokenData::Keyword(Keyword::For), location: _ } => self.parse_for(None),
+            Token { data: TokenData::Label(_), location: _ } => self.parse_labelled_loop(),
+            Token { data: TokenData::Keyword(Keyword::Unsafe), location: _ } => self.parse_unsafe(),
             tok => Err(ParseError::UnexpectedToken(tok)),
         }
     }
 
+    // the index and closing bracket after the `[` of `a[i]`
+    fn parse_index(&mut self) -> Result<Expr, ParseError> {
+        let index = self.parse_expression()?;
+        self.parse_closing(Symbol::RBracket)?;
+        Ok(index)
+    }
+
+    // `[a, b, c]` with at least one element, only allowed as the value of a `let` without a type
+    fn parse_array(&mut self) -> Result<Expr, ParseError> {
+        let start = self.start()?;
+        match self.next_token()? {
+            Token { data: TokenData::Symbol(Symbol::LBracket), location: _ } => (),
+            tok => return Err(ParseError::UnexpectedToken(tok)),
+        };
+        let mut elements = vec![self.parse_expression()?];
+        while let Token { data: TokenData::Symbol(Symbol::Comma), location: _ } = self.peek_token()? {
+            self.consume()?;
+            elements.push(self.parse_expression()?);
+        }
+        self.parse_closing(Symbol::RBracket)?;
+        Ok(self.node(start, Expr::Array(elements)))
+    }
+
     fn parse_args(&mut self) -> Result<Vec<Expr>, ParseError> {
-        let expr = match self.peek()?.expect("an identifier or a right parenthesis") {
+        let expr = match self.peek_token()? {
             Token { data: TokenData::Symbol(Symbol::RParen), location: _ } => return Ok(vec![]),
             _ => self.parse_expression()?,
         };
         let mut args = vec![expr];
-        match self.peek()?.expect("a comma or a right parenthesis") {
+        match self.peek_token()? {
             Token { data: TokenData::Symbol(Symbol::RParen), location: _ } => (),
             Token { data: TokenData::Symbol(Symbol::Comma), location: _ } => { self.consume()?; args.extend(self.parse_args()?); },
-            tok => return Err(ParseError::UnexpectedToken(tok)),
+            tok => return Err(self.unclosed_at(tok)),
         };
         Ok(args)
     }
 
     fn parse_block(&mut self) -> Result<Expr, ParseError> {
-        match self.consume()?.expect("a left brace `{`") {
+        let start = self.start()?;
+        match self.next_token()? {
             Token { data: TokenData::Symbol(Symbol::LBrace), location: _ } => (),
             tok => return Err(ParseError::UnexpectedToken(tok)),
         };
         let mut stmts = Vec::new();
+        let declared = self.variables.len();
         loop {
-            match self.peek()?.expect("a statement or right brace `}`") {
+            match self.peek_token()? {
                 Token { data: TokenData::Symbol(Symbol::RBrace), location: _ } => { self.consume()?; break },
-                _ => stmts.push(self.parse_statement()?),
+                Token { data: TokenData::Keyword(_) | TokenData::Symbol(Symbol::LBrace) | TokenData::Label(_), location: _ } =>
+                    stmts.push(self.parse_statement()?),
+                _ if self.at_match()? => stmts.push(self.parse_statement()?),
+                _ => stmts.push(self.parse_block_expression()?),
             }
         };
-        Ok(Expr::Block(stmts))
+        self.variables.truncate(declared);
+        Ok(self.node(start, Expr::Block(stmts)))
+    }
+
+    // the last expression of a block may leave out its semicolon, as in `if (c) { 1 } else { 2 }`.
+    // it is the same statement either way, a block used as a value gives the value of its last one
+    fn parse_block_expression(&mut self) -> Result<Statement, ParseError> {
+        let start = self.start()?;
+        let expr = self.parse_expression()?;
+        if !matches!(self.peek()?, Some(Token { data: TokenData::Symbol(Symbol::RBrace), location: _ })) {
+            self.parse_semicolon()?;
+        }
+        Ok(self.node(start, Statement::Expr(expr)))
     }
 
     fn parse_if(&mut self) -> Result<Expr, ParseError> {
-        match self.consume()?.expect("keyword `if`") {
+        let start = self.start()?;
+        match self.next_token()? {
             Token { data: TokenData::Keyword(Keyword::If), location: _ } => (),
             tok => return Err(ParseError::UnexpectedToken(tok)),
         }
-        match self.consume()?.expect("a left parenthesis") {
-            Token { data: TokenData::Symbol(Symbol::LParen), location: _ } => (),
-            tok => return Err(ParseError::UnexpectedToken(tok))
-        };
-        let check = Box::new(self.parse_expression()?);
-        match self.consume()?.expect("a right parenthesis") {
-            Token { data: TokenData::Symbol(Symbol::RParen), location: _ } => (),
-            tok => return Err(ParseError::UnexpectedToken(tok))
-        };
-        let body = Box::new(self.parse_statement()?);
+        let (check, braced) = self.parse_condition()?;
+        let body_start = self.start()?;
+        let body = Box::new(self.parse_body(braced)?);
         let els = match self.peek()? {
             Some(Token { data: TokenData::Keyword(Keyword::Else), location: _ }) => {
                 self.consume()?;
-                Some(Box::new(self.parse_statement()?))
+                // `else if` is the only way to chain branches without nesting braces
+                match self.peek()? {
+                    Some(Token { data: TokenData::Keyword(Keyword::If), location: _ }) => Some(Box::new(self.parse_statement()?)),
+                    _ => Some(Box::new(self.parse_body(braced)?)),
+                }
             },
             Some(_) | None => None,
         };
-        Ok(Expr::If { check, body, els })
+        if let (Statement::Expr(Expr::If { els: Some(_), .. }), None) = (body.as_ref(), &els) {
+            self.warnings.push(ParseWarning::DanglingElse(body_start));
+        }
+        Ok(self.node(start, Expr::If { check, body, els }))
     }
 
-    fn parse_loop(&mut self) -> Result<Expr, ParseError> {
-        match self.consume()?.expect("keyword `loop`") {
+    fn parse_unsafe(&mut self) -> Result<Expr, ParseError> {
+        let start = self.start()?;
+        match self.next_token()? {
+            Token { data: TokenData::Keyword(Keyword::Unsafe), location: _ } => (),
+            tok => return Err(ParseError::UnexpectedToken(tok)),
+        }
+        let body = Box::new(self.parse_block()?);
+        Ok(self.node(start, Expr::Unsafe(body)))
+    }
+
+    // `'name: loop`, `'name: while` or `'name: for`, the label can be named by a `break` or
+    // `continue` anywhere in the body, including inside nested loops
+    fn parse_labelled_loop(&mut self) -> Result<Expr, ParseError> {
+        let label = match self.next_token()? {
+            Token { data: TokenData::Label(label), location: _ } => label,
+            tok => return Err(ParseError::UnexpectedToken(tok)),
+        };
+        match self.next_token()? {
+            Token { data: TokenData::Symbol(Symbol::Colon), location: _ } => (),
+            tok => return Err(ParseError::UnexpectedToken(tok)),
+        };
+        self.labels.push(label.clone());
+        let looped = match self.peek_token()? {
+            Token { data: TokenData::Keyword(Keyword::Loop), location: _ } => self.parse_loop(Some(label)),
+            Token { data: TokenData::Keyword(Keyword::While), location: _ } => self.parse_while(Some(label)),
+            Token { data: TokenData::Keyword(Keyword::For), location: _ } => self.parse_for(Some(label)),
+            tok => Err(ParseError::UnexpectedToken(tok)),
+        };
+        self.labels.pop();
+        looped
+    }
+
+    // the label after a `break` or `continue` if there is one, then the semicolon
+    fn parse_jump_label(&mut self) -> Result<Option<String>, ParseError> {
+        let label = match self.peek_token()? {
+            Token { data: TokenData::Label(label), location } => {
+                self.consume()?;
+                if !self.labels.contains(&label) {
+                    return Err(ParseError::UndeclaredLabel { label, location });
+                }
+                Some(label)
+            },
+            _ => None,
+        };
+        self.parse_semicolon()?;
+        Ok(label)
+    }
+
+    fn parse_loop(&mut self, label: Option<String>) -> Result<Expr, ParseError> {
+        let start = self.start()?;
+        match self.next_token()? {
             Token { data: TokenData::Keyword(Keyword::Loop), location: _ } => (),
             tok => return Err(ParseError::UnexpectedToken(tok)),
         }
         let body = Box::new(self.parse_statement()?);
-        Ok(Expr::Loop { body })
+        Ok(self.node(start, Expr::Loop { label, body }))
     }
 
-    fn parse_while(&mut self) -> Result<Expr, ParseError> {
-        match self.consume()?.expect("keyword `while`") {
+    fn parse_while(&mut self, label: Option<String>) -> Result<Expr, ParseError> {
+        let start = self.start()?;
+        match self.next_token()? {
             Token { data: TokenData::Keyword(Keyword::While), location: _ } => (),
             tok => return Err(ParseError::UnexpectedToken(tok)),
         }
-        match self.consume()?.expect("a left parenthesis") {
+        let (check, braced) = self.parse_condition()?;
+        let body = Box::new(self.parse_body(braced)?);
+        Ok(self.node(start, Expr::While { label, check, body }))
+    }
+
+    // the condition of an `if` or `while`, either in parentheses or bare, in which case the body
+    // has to be a block so it is clear where the condition ends. bare ones came with edition 2
+    fn parse_condition(&mut self) -> Result<(Box<Expr>, bool), ParseError> {
+        match self.peek_token()? {
+            Token { data: TokenData::Symbol(Symbol::LParen), location: _ } => {
+                self.consume()?;
+                let check = Box::new(self.parse_expression()?);
+                self.parse_closing(Symbol::RParen)?;
+                Ok((check, false))
+            },
+            tok if self.edition < Edition::V2 => Err(ParseError::UnexpectedToken(tok)),
+            _ => Ok((Box::new(self.parse_expression()?), true)),
+        }
+    }
+
+    fn parse_body(&mut self, braced: bool) -> Result<Statement, ParseError> {
+        match self.peek_token()? {
+            Token { data: TokenData::Symbol(Symbol::LBrace), location: _ } => self.parse_statement(),
+            tok if braced => Err(ParseError::UnexpectedToken(tok)),
+            _ => self.parse_statement(),
+        }
+    }
+
+    // `for x in start..end body` iterates over a half-open range by index, desugared to
+    //
+    //     {
+    //         let mut x#next = start;
+    //         let x#end = end;
+    //         while (x#next < x#end) {
+    //             let x = x#next;
+    //             x#next += 1;
+    //             body
+    //         }
+    //     }
+    //
+    // `for x in a body` over an array declared in the function goes from 0 to its length the same
+    // way, binding `let x = a[x#next];` instead. the hidden names cannot be written in source. nodes
+    // are made in the order of the desugared tree, the ones that have no source of their own span
+    // the `for` header. a label on the `for` goes on the `while`
+    fn parse_for(&mut self, label: Option<String>) -> Result<Expr, ParseError> {
+        let start = self.start()?;
+        match self.next_token()? {
+            Token { data: TokenData::Keyword(Keyword::For), location: _ } => (),
+            tok => return Err(ParseError::UnexpectedToken(tok)),
+        }
+        let variable = match self.next_token()? {
+            Token { data: TokenData::Identifier(variable), location: _ } => variable,
+            tok => return Err(ParseError::UnexpectedToken(tok)),
+        };
+        match self.next_token()? {
+            Token { data: TokenData::Identifier(name), location: _ } if name == Keyword::In.as_str() => (),
+            tok => return Err(ParseError::UnexpectedToken(tok)),
+        }
+        let next = format!("{variable}#next");
+        let end = format!("{variable}#end");
+        let array = self.parse_iterated_array()?;
+
+        let value = match &array {
+            Some(_) => self.node(start, Expr::IntegerLiteral(String::from("0"))),
+            None => self.parse_expression()?,
+        };
+        let is_range = matches!(self.peek()?, Some(Token { data: TokenData::Symbol(Symbol::DotDot), location: _ }));
+        if let (None, false, Expr::Identifier(identifier)) = (&array, is_range, &value) {
+            return Err(ParseError::NotAnArray(identifier.clone()));
+        }
+        let init_next = self.node(start, Statement::Let { identifier: next.clone(), ty: None, value: Some(value), is_mutable: true });
+        let value = match &array {
+            Some((_, _, length)) => self.node(start, Expr::IntegerLiteral(length.to_string())),
+            None => match self.next_token()? {
+                Token { data: TokenData::Symbol(Symbol::DotDot), location: _ } => self.parse_expression()?,
+                tok => return Err(ParseError::UnexpectedToken(tok)),
+            },
+        };
+        let init_end = self.node(start, Statement::Let { identifier: end.clone(), ty: None, value: Some(value), is_mutable: false });
+
+        let check = Expr::Less(
+            Box::new(self.node(start, Expr::Identifier(next.clone()))),
+            Box::new(self.node(start, Expr::Identifier(end))),
+        );
+        let check = Box::new(self.node(start, check));
+        let index = self.node(start, Expr::Identifier(next.clone()));
+        let value = match array {
+            Some((identifier, location, _)) => self.node(start, Expr::Index { identifier, index: Box::new(index), location }),
+            None => index,
+        };
+        let bind = self.node(start, Statement::Let { identifier: variable.clone(), ty: None, value: Some(value), is_mutable: false });
+        let step = Expr::AddAssign { identifier: next, value: Box::new(self.node(start, Expr::IntegerLiteral(String::from("1")))) };
+        let step = self.node(start, step);
+        let step = self.node(start, Statement::Expr(step));
+
+        let declared = self.variables.len();
+        self.variables.push((variable, None));
+        let body = self.parse_statement()?;
+        self.variables.truncate(declared);
+        let body = self.node(start, Expr::Block(vec![bind, step, body]));
+        let body = Box::new(self.node(start, Statement::Expr(body)));
+        let looped = self.node(start, Expr::While { label, check, body });
+        let looped = self.node(start, Statement::Expr(looped));
+        Ok(self.node(start, Expr::Block(vec![init_next, init_end, looped])))
+    }
+
+    // the name, location and length of the array after the `in` of a `for`, which is consumed. a
+    // name that starts a range, `a..b` or `a[0]..b`, is left for the range
+    fn parse_iterated_array(&mut self) -> Result<Option<(String, Location, u64)>, ParseError> {
+        let checkpoint = self.checkpoint();
+        if let Some(Token { data: TokenData::Identifier(identifier), location }) = self.consume()? {
+            let length = self.variables.iter().rev().find(|(name, _)| *name == identifier).and_then(|(_, length)| *length);
+            match (length, self.peek()?) {
+                (_, Some(Token { data: TokenData::Symbol(Symbol::DotDot | Symbol::LBracket), location: _ })) => (),
+                (Some(length), _) => return Ok(Some((identifier, location, length))),
+                (None, _) => (),
+            }
+        }
+        self.rollback(checkpoint);
+        Ok(None)
+    }
+
+    // `match (value) { pattern if guard => body ... }` over integers, where a pattern is a literal,
+    // an inclusive `1..=5` or half-open `1..6` range, or `_`. it is desugared to
+    //
+    //     {
+    //         let match#value = value;
+    //         let mut match#done = 0;
+    //         if (match#done == 0) if (match#value >= 1) if (match#value <= 5) if (guard) {
+    //             match#done += 1;
+    //             body
+    //         }
+    //         ...
+    //     }
+    //
+    // which loses the patterns, so they are checked here: the last arm has to be an unguarded `_`,
+    // and every arm has to match a value that no unguarded arm before it does
+    fn parse_match(&mut self) -> Result<Expr, ParseError> {
+        let start = self.start()?;
+        match self.next_token()? {
+            Token { data: TokenData::Identifier(name), location: _ } if name == Keyword::Match.as_str() => (),
+            tok => return Err(ParseError::UnexpectedToken(tok)),
+        }
+        match self.next_token()? {
             Token { data: TokenData::Symbol(Symbol::LParen), location: _ } => (),
             tok => return Err(ParseError::UnexpectedToken(tok))
         };
-        let check = Box::new(self.parse_expression()?);
-        match self.consume()?.expect("a right parenthesis") {
-            Token { data: TokenData::Symbol(Symbol::RParen), location: _ } => (),
-            tok => return Err(ParseError::UnexpectedToken(tok))
+        let value = self.parse_expression()?;
+        self.parse_closing(Symbol::RParen)?;
+        let scrutinee = String::from("match#value");
+        let done = String::from("match#done");
+        let init_value = self.node(start, Statement::Let { identifier: scrutinee.clone(), ty: None, value: Some(value), is_mutable: false });
+        let value = self.node(start, Expr::IntegerLiteral(String::from("0")));
+        let init_done = self.node(start, Statement::Let { identifier: done.clone(), ty: None, value: Some(value), is_mutable: true });
+        let mut stmts = vec![init_value, init_done];
+
+        match self.next_token()? {
+            Token { data: TokenData::Symbol(Symbol::LBrace), location: _ } => (),
+            tok => return Err(ParseError::UnexpectedToken(tok)),
         };
-        let body = Box::new(self.parse_statement()?);
-        Ok(Expr::While { check, body })
+        // the ranges of the unguarded arms so far, `None` once one of them was `_`
+        let mut covered = Some(Vec::new());
+        loop {
+            let pattern_start = match self.peek_token()? {
+                Token { data: TokenData::Symbol(Symbol::RBrace), location: _ } => { self.consume()?; break },
+                Token { data: _, location } => location,
+            };
+            let range = self.parse_pattern()?;
+            let reachable = match (&covered, range) {
+                (None, _) => false,
+                (Some(_), Some((low, high))) if low > high => false,
+                (Some(covered), Some(range)) => !is_covered(covered, range),
+                (Some(_), None) => true,
+            };
+            if !reachable {
+                return Err(ParseError::UnreachablePattern(pattern_start));
+            }
+
+            let mut checks = vec![Expr::Equality(
+                Box::new(self.node(pattern_start, Expr::Identifier(done.clone()))),
+                Box::new(self.node(pattern_start, Expr::IntegerLiteral(String::from("0")))),
+            )];
+            if let Some((low, high)) = range {
+                checks.push(Expr::GreaterEq(
+                    Box::new(self.node(pattern_start, Expr::Identifier(scrutinee.clone()))),
+                    Box::new(self.node(pattern_start, Expr::IntegerLiteral(low.to_string()))),
+                ));
+                checks.push(Expr::LessEq(
+                    Box::new(self.node(pattern_start, Expr::Identifier(scrutinee.clone()))),
+                    Box::new(self.node(pattern_start, Expr::IntegerLiteral(high.to_string()))),
+                ));
+            }
+            let mut checks: Vec<Expr> = checks.into_iter().map(|check| self.node(pattern_start, check)).collect();
+            let guarded = match self.peek()? {
+                Some(Token { data: TokenData::Keyword(Keyword::If), location: _ }) => {
+                    self.consume()?;
+                    checks.push(self.parse_expression()?);
+                    true
+                },
+                Some(_) | None => false,
+            };
+            match self.next_token()? {
+                Token { data: TokenData::Symbol(Symbol::FatArrow), location: _ } => (),
+                tok => return Err(ParseError::UnexpectedToken(tok)),
+            };
+            if !guarded {
+                match (&mut covered, range) {
+                    (Some(covered), Some(range)) => covered.push(range),
+                    (covered, _) => *covered = None,
+                }
+            }
+
+            let mark = Expr::AddAssign { identifier: done.clone(), value: Box::new(self.node(pattern_start, Expr::IntegerLiteral(String::from("1")))) };
+            let mark = self.node(pattern_start, mark);
+            let mark = self.node(pattern_start, Statement::Expr(mark));
+            let body = self.parse_statement()?;
+            let body = self.node(pattern_start, Expr::Block(vec![mark, body]));
+            let mut arm = self.node(pattern_start, Statement::Expr(body));
+            for check in checks.into_iter().rev() {
+                let branch = self.node(pattern_start, Expr::If { check: Box::new(check), body: Box::new(arm), els: None });
+                arm = self.node(pattern_start, Statement::Expr(branch));
+            }
+            stmts.push(arm);
+        }
+        if covered.is_some() {
+            return Err(ParseError::NonExhaustiveMatch(start));
+        }
+        Ok(self.node(start, Expr::Block(stmts)))
+    }
+
+    // a literal or range as the inclusive bounds it matches, `None` for `_`
+    fn parse_pattern(&mut self) -> Result<Option<(i128, i128)>, ParseError> {
+        match self.peek_token()? {
+            Token { data: TokenData::Identifier(ident), location: _ } if ident == "_" => {
+                self.consume()?;
+                return Ok(None);
+            },
+            _ => (),
+        }
+        let low = self.parse_bound()?;
+        let high = match self.peek()? {
+            Some(Token { data: TokenData::Symbol(Symbol::DotDotEq), location: _ }) => { self.consume()?; self.parse_bound()? },
+            Some(Token { data: TokenData::Symbol(Symbol::DotDot), location: _ }) => { self.consume()?; self.parse_bound()? - 1 },
+            Some(_) | None => low,
+        };
+        Ok(Some((low, high)))
+    }
+
+    fn parse_bound(&mut self) -> Result<i128, ParseError> {
+        match self.next_token()? {
+            Token { data: TokenData::IntegerLiteral(value), location } => self.integer(value, location).map(i128::from),
+            tok => Err(ParseError::UnexpectedToken(tok)),
+        }
+    }
+
+    // the value of an integer literal, which is at most 64 bits wide
+    fn integer(&self, literal: String, location: Location) -> Result<i64, ParseError> {
+        match (literal.parse::<i128>().ok(), self.literal_overflow) {
+            (Some(value), _) if i64::try_from(value).is_ok() => Ok(value as i64),
+            (Some(value), LiteralOverflow::Wrap) if u64::try_from(value).is_ok() => Ok(value as u64 as i64),
+            _ => Err(ParseError::LiteralOutOfRange { literal, location }),
+        }
+    }
+
+    // records the span of a finished node, from `start` to the end of the last token consumed
+    fn node<T>(&mut self, start: Location, node: T) -> T {
+        self.spans.push(Span { start, end: self.tokens.last_end() });
+        node
+    }
+
+    fn start(&mut self) -> Result<Location, TokenizerError> {
+        Ok(self.peek()?.map(|token| token.location).unwrap_or(self.tokens.last_end()))
+    }
+
+    // whether the next token is the given contextual keyword, which is lexed as an identifier
+    fn at_contextual(&mut self, keyword: Keyword) -> Result<bool, TokenizerError> {
+        Ok(matches!(self.peek()?, Some(Token { data: TokenData::Identifier(name), location: _ }) if name == keyword.as_str()))
+    }
+
+    // whether a `match` starts here rather than a call to or a variable called `match`. the two
+    // only part ways after the parenthesised value, which a `match` follows with a brace
+    fn at_match(&mut self) -> Result<bool, TokenizerError> {
+        if !self.at_contextual(Keyword::Match)? {
+            return Ok(false);
+        }
+        let checkpoint = self.checkpoint();
+        self.consume()?;
+        let mut parens = 0;
+        let is_match = loop {
+            match self.consume()?.map(|token| token.data) {
+                Some(TokenData::Symbol(Symbol::LParen)) => parens += 1,
+                Some(TokenData::Symbol(Symbol::RParen)) if parens == 1 =>
+                    break matches!(self.peek()?, Some(Token { data: TokenData::Symbol(Symbol::LBrace), location: _ })),
+                Some(TokenData::Symbol(Symbol::RParen)) if parens > 1 => parens -= 1,
+                Some(_) if parens > 0 => (),
+                Some(_) | None => break false,
+            }
+        };
+        self.rollback(checkpoint);
+        Ok(is_match)
+    }
+
+    // the `;` that ends a statement. a missing one is reported just past the end of the statement
+    // and read as if it were there, as long as the token in its place starts another statement
+    fn parse_semicolon(&mut self) -> Result<(), ParseError> {
+        match self.peek_token()? {
+            Token { data: TokenData::Symbol(Symbol::Semi), location: _ } => { self.consume()?; },
+            Token {
+                data: TokenData::Identifier(_) | TokenData::Label(_) | TokenData::Symbol(Symbol::LBrace | Symbol::Hash)
+                    | TokenData::Keyword(Keyword::Let | Keyword::Exit | Keyword::Return | Keyword::If | Keyword::Loop
+                        | Keyword::While | Keyword::For | Keyword::Const | Keyword::Break | Keyword::Continue
+                        | Keyword::Panic | Keyword::Unreachable | Keyword::StaticAssert | Keyword::Unsafe | Keyword::Fn),
+                location: _,
+            } => self.errors.push(ParseError::MissingSemicolon(self.tokens.last_end())),
+            tok => return Err(ParseError::UnexpectedToken(tok)),
+        }
+        Ok(())
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            tokens: self.tokens.checkpoint(),
+            spans: self.spans.len(),
+            warnings: self.warnings.len(),
+            errors: self.errors.len(),
+        }
+    }
+
+    // goes back to where the checkpoint was taken, forgetting the nodes, warnings and errors made since
+    fn rollback(&mut self, checkpoint: Checkpoint) {
+        self.tokens.rollback(checkpoint.tokens);
+        self.spans.truncate(checkpoint.spans);
+        self.warnings.truncate(checkpoint.warnings);
+        self.errors.truncate(checkpoint.errors);
     }
 
     fn is_empty(&mut self) -> Result<bool, TokenizerError> {
@@ -477,26 +1258,79 @@ impl Parser {
     }
 
     fn peek(&mut self) -> Result<Option<Token>, TokenizerError> {
-        self.peek_ahead(0)
+        Ok(self.tokens.peek()?.cloned())
     }
 
-    fn peek_ahead(&mut self, count: usize) -> Result<Option<Token>, TokenizerError> {
-        while self.buffer.len() < count + 1 {
-            match self.tokens.next()? {
-                Some(token) => self.buffer.push_back(token),
-                None => return Ok(None),
-            };
-        };
-        Ok(self.buffer.get(count).cloned())
+    fn consume(&mut self) -> Result<Option<Token>, TokenizerError> {
+        self.tokens.next()
+    }
 
+    // the next token where the source can't end yet
+    fn peek_token(&mut self) -> Result<Token, ParseError> {
+        match self.peek()? {
+            Some(token) => Ok(token),
+            None => Err(self.unexpected_end()),
+        }
     }
 
-    fn consume(&mut self) -> Result<Option<Token>, TokenizerError> {
-        if self.buffer.is_empty() {
-            self.tokens.next()
-        } else {
-            Ok(self.buffer.pop_front())
+    fn next_token(&mut self) -> Result<Token, ParseError> {
+        match self.consume()? {
+            Some(token) => Ok(token),
+            None => Err(self.unexpected_end()),
         }
     }
+
+    fn unexpected_end(&self) -> ParseError {
+        let location = self.tokens.last_end();
+        match self.tokens.unclosed() {
+            Some(open) => ParseError::UnclosedDelimiter { open, location },
+            None => ParseError::UnexpectedEnd(location),
+        }
+    }
+
+    // a token other than the closer is blamed on the delimiter it should have closed rather than
+    // on itself, as that is usually where the mistake is
+    fn parse_closing(&mut self, close: Symbol) -> Result<Token, ParseError> {
+        match self.peek_token()? {
+            token if token.data == TokenData::Symbol(close) => { self.consume()?; Ok(token) },
+            token => Err(self.unclosed_at(token)),
+        }
+    }
+
+    // for a token that isn't read yet, in place of where a closer could have been
+    fn unclosed_at(&self, token: Token) -> ParseError {
+        match self.tokens.unclosed() {
+            Some(open) => ParseError::UnclosedDelimiter { open, location: token.location },
+            None => ParseError::UnexpectedToken(token),
+        }
+    }
+}
+
+
+// a point the parser can go back to, to read the tokens after it another way
+#[derive(Clone, Copy)]
+struct Checkpoint {
+    tokens: tokenize::Checkpoint,
+    spans: usize,
+    warnings: usize,
+    errors: usize,
 }
 
+// whether the inclusive ranges in `covered` together contain all of `range`
+fn is_covered(covered: &[(i128, i128)], range: (i128, i128)) -> bool {
+    let mut covered = covered.to_vec();
+    covered.sort();
+    let (mut low, high) = range;
+    for (start, end) in covered {
+        if start > low {
+            break;
+        }
+        if end >= low {
+            low = end + 1;
+        }
+        if low > high {
+            return true;
+        }
+    }
+    false
+}
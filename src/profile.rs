@@ -0,0 +1,106 @@
+pub const FILE_NAME: &str = "beryllium.prof";
+
+pub const TABLE_LABEL: &str = "__beryllium_profile";
+pub const TABLE_END_LABEL: &str = "__beryllium_profile_end";
+
+const MAGIC: &[u8; 8] = b"BEPROF01";
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub calls: u64,
+    // cycles from entering the function until it returns, including those spent in its callees
+    pub cycles: u64,
+}
+
+
+// the counters an `--instrument=profile` binary writes to FILE_NAME at exit. the table is written
+// as it sits in memory: MAGIC, the number of functions, then for each function its calls, cycles,
+// the length of its name and the name itself, all little-endian
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Profile {
+    pub functions: Vec<FunctionProfile>,
+}
+
+impl Profile {
+    // the writable table for the functions, given with the label of their counters
+    pub fn table_asm(functions: &[(String, String)]) -> String {
+        let bytes = |bytes: &[u8]| bytes.iter().map(u8::to_string).collect::<Vec<_>>().join(", ");
+        let mut code = format!("section .data\n{TABLE_LABEL}:\n");
+        code += &format!("    db {}\n", bytes(MAGIC));
+        code += &format!("    dq {}\n", functions.len());
+        for (name, label) in functions {
+            code += &format!("{label}:\n");
+            code += &format!("    dq 0, 0, {}\n", name.len());
+            code += &format!("    db {}\n", bytes(name.as_bytes()));
+        }
+        code += &format!("{TABLE_END_LABEL}:\n");
+        code
+    }
+
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.get(..8)? != MAGIC {
+            return None;
+        }
+        let count = read_u64(bytes, 8)?;
+        let mut offset = 16;
+        let mut functions = Vec::new();
+        for _ in 0..count {
+            let calls = read_u64(bytes, offset)?;
+            let cycles = read_u64(bytes, offset + 8)?;
+            let length = read_u64(bytes, offset + 16)? as usize;
+            offset += 24;
+            let name = bytes.get(offset..offset.checked_add(length)?)?;
+            offset += length;
+            functions.push(FunctionProfile { name: String::from_utf8(name.to_vec()).ok()?, calls, cycles });
+        }
+        Some(Self { functions })
+    }
+
+    // the functions that were called, those that took the most cycles first
+    pub fn to_text(&self) -> String {
+        let mut functions: Vec<&FunctionProfile> = self.functions.iter().filter(|function| function.calls > 0).collect();
+        functions.sort_by(|a, b| b.cycles.cmp(&a.cycles).then_with(|| a.name.cmp(&b.name)));
+        let width = functions.iter().map(|function| function.name.len()).max().unwrap_or(0).max("function".len());
+        let mut text = format!("{:<width$}  {:>10}  {:>14}  {:>14}\n", "function", "calls", "cycles", "cycles/call");
+        for function in functions {
+            text += &format!(
+                "{:<width$}  {:>10}  {:>14}  {:>14}\n",
+                function.name, function.calls, function.cycles, function.cycles / function.calls,
+            );
+        }
+        text
+    }
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(bytes.get(offset..offset.checked_add(8)?)?.try_into().ok()?))
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn profiles_are_read_from_the_table_layout() {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(2u64.to_le_bytes());
+    for (calls, cycles, name) in [(3u64, 900u64, "f"), (0, 0, "_start")] {
+        bytes.extend(calls.to_le_bytes());
+        bytes.extend(cycles.to_le_bytes());
+        bytes.extend((name.len() as u64).to_le_bytes());
+        bytes.extend(name.as_bytes());
+    }
+    let profile = Profile::parse(&bytes).expect("profile parses");
+    assert_eq!(profile.functions[0], FunctionProfile { name: String::from("f"), calls: 3, cycles: 900 });
+    assert_eq!(profile.to_text(), concat!(
+        "function       calls          cycles     cycles/call\n",
+        "f                  3             900             300\n",
+    ));
+    assert_eq!(Profile::parse(&bytes[..bytes.len() - 1]), None);
+    assert_eq!(Profile::parse(b"BEPROF00"), None);
+}
@@ -0,0 +1,266 @@
+use std::collections::HashSet;
+
+use fallible_iterator::FallibleIterator;
+
+use crate::{
+    CompileError,
+    ast::{Expr, Intrinsic, Program, Statement},
+    node_map::{nodes, NodeId, NodeMap, NodeRef, Span},
+    parser::Parser,
+    scope::{BindingKind, ScopeTree},
+    source_map::LineIndex,
+    syntax::{Delimiter, SyntaxElement, SyntaxTree},
+    tokenize::{Keyword, Symbol, TokenData, Tokenize},
+};
+
+
+fn refuse(reason: impl Into<String>) -> CompileError {
+    CompileError::RefactorFailed(reason.into())
+}
+
+fn check_identifier(name: &str) -> Result<(), CompileError> {
+    let tokens: Vec<_> = name.tokenize().collect()?;
+    match &tokens[..] {
+        [token] if token.data == TokenData::Identifier(name.to_string()) => Ok(()),
+        _ => Err(refuse(format!("`{name}` is not a valid identifier"))),
+    }
+}
+
+// the result of a refactoring has to be a valid program, anything else is a bug in the refactoring
+fn reparse(source: String) -> Result<String, CompileError> {
+    match Parser::new(source.clone().tokenize()).parse() {
+        Ok(_) => Ok(source),
+        Err(err) => Err(refuse(format!("the rewritten source does not parse ({})", CompileError::from(err)))),
+    }
+}
+
+
+// renames every binding called `old`, which is only safe while nothing is called `new` yet, as
+// then no use of either name can be captured by the other
+pub fn rename(source: &str, old: &str, new: &str) -> Result<String, CompileError> {
+    check_identifier(new)?;
+    // renaming goes by tokens, which can't tell the variable from the keyword
+    if Keyword::CONTEXTUAL.iter().any(|keyword| keyword.as_str() == old) {
+        return Err(refuse(format!("`{old}` is also a keyword")));
+    }
+    let (program, map) = Parser::new(source.tokenize()).parse_with_map()?;
+    let scopes = ScopeTree::new(&program, &map);
+    if !scopes.bindings().any(|binding| binding.name == old) {
+        return Err(refuse(format!("nothing called `{old}` is declared")));
+    }
+    if scopes.bindings().any(|binding| binding.name == new) {
+        return Err(refuse(format!("`{new}` is already declared")));
+    }
+
+    let mut tree = SyntaxTree::parse(source)?;
+    rename_tokens(&mut tree.children, old, new);
+    reparse(tree.to_string())
+}
+
+fn rename_tokens(elements: &mut [SyntaxElement], old: &str, new: &str) {
+    let mut after_hash = false;
+    for element in elements {
+        match element {
+            SyntaxElement::Token(token) => {
+                if token.token.data == TokenData::Identifier(old.to_string()) {
+                    token.token.data = TokenData::Identifier(new.to_string());
+                    token.text = new.to_string();
+                }
+                after_hash = token.token.data == TokenData::Symbol(Symbol::Hash);
+            },
+            // attribute names are not bindings
+            SyntaxElement::Group(group) if after_hash && group.delimiter == Delimiter::Bracket => after_hash = false,
+            SyntaxElement::Group(group) => {
+                rename_tokens(&mut group.children, old, new);
+                after_hash = false;
+            },
+        }
+    }
+}
+
+
+// moves the statements on lines `start..=end` into a new function `name`, passing the variables
+// they read as arguments; statements that assign to outer variables, return, break out of the
+// selection, or declare variables used after it cannot be moved without changing their meaning
+pub fn extract_function(source: &str, name: &str, start: u64, end: u64) -> Result<String, CompileError> {
+    check_identifier(name)?;
+    let (program, map) = Parser::new(source.tokenize()).parse_with_map()?;
+    let scopes = ScopeTree::new(&program, &map);
+    if scopes.bindings().any(|binding| binding.name == name && matches!(binding.kind, BindingKind::Function { .. })) {
+        return Err(refuse(format!("a function called `{name}` already exists")));
+    }
+    let nodes = nodes(&program);
+    let chars: Vec<char> = source.chars().collect();
+    let lines = LineIndex::new(source);
+    let range = lines.index(start, 1).ok_or_else(|| refuse(format!("line {start} is out of range")))?
+        ..lines.index(end + 1, 1).unwrap_or(chars.len() as u64);
+
+    let selected = select_statements(&program, &map, &range)?;
+    let first = map.span(selected[0]).unwrap();
+    let last = map.span(*selected.last().unwrap()).unwrap();
+    let inside = |id: NodeId| selected.iter().any(|statement| *statement == id || map.ancestors(id).any(|ancestor| ancestor == *statement));
+
+    let mut params: Vec<String> = Vec::new();
+    for (id, node) in nodes.iter().enumerate().map(|(id, node)| (NodeId(id), node)).filter(|(id, _)| inside(*id)) {
+        let span = map.span(id).unwrap();
+        match node {
+            NodeRef::Statement(Statement::Return(_)) => return Err(refuse("the statements contain a `return`")),
+            // `?` returns early too, from the function it would be moved into
+            NodeRef::Expr(Expr::Try(_)) => return Err(refuse("the statements contain a `?`")),
+            NodeRef::Statement(Statement::Break(target) | Statement::Continue(target)) => {
+                let in_loop = map.ancestors(id)
+                    .take_while(|ancestor| inside(*ancestor))
+                    .any(|ancestor| match nodes[ancestor.0] {
+                        NodeRef::Expr(Expr::Loop { label, .. } | Expr::While { label, .. }) => target.is_none() || label == target,
+                        _ => false,
+                    });
+                if !in_loop {
+                    return Err(refuse("the statements `break` or `continue` out of an enclosing loop"));
+                }
+            },
+            NodeRef::Expr(
+                Expr::Assign { identifier, .. } | Expr::AddAssign { identifier, .. } | Expr::SubAssign { identifier, .. }
+                    | Expr::MulAssign { identifier, .. }
+                    | Expr::DivAssign { identifier, .. } | Expr::ModAssign { identifier, .. }
+            ) => match scopes.resolve(identifier, span.start.index) {
+                Some(binding) if !inside(binding.node) =>
+                    return Err(refuse(format!("the statements assign to `{identifier}`, which is declared outside them"))),
+                _ => (),
+            },
+            // an array can't be passed to the new function
+            NodeRef::Expr(Expr::Index { identifier, .. } | Expr::IndexAssign { identifier, .. }) => match scopes.resolve(identifier, span.start.index) {
+                Some(binding) if !inside(binding.node) =>
+                    return Err(refuse(format!("the statements index `{identifier}`, which is declared outside them"))),
+                _ => (),
+            },
+            NodeRef::Expr(Expr::Intrinsic { intrinsic: Intrinsic::Swap, args }) => for arg in args {
+                if let Expr::Identifier(identifier) = arg {
+                    if scopes.resolve(identifier, span.start.index).is_some_and(|binding| !inside(binding.node)) {
+                        return Err(refuse(format!("the statements assign to `{identifier}`, which is declared outside them")));
+                    }
+                }
+            },
+            NodeRef::Expr(Expr::Identifier(identifier)) => match scopes.resolve(identifier, span.start.index) {
+                Some(binding) if !inside(binding.node) && !params.contains(identifier) => params.push(identifier.clone()),
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+
+    // variables declared by the statements must not be needed after them
+    let function = map.ancestors(selected[0]).last().unwrap();
+    let function_end = map.span(function).unwrap().end.index;
+    for (id, node) in nodes.iter().enumerate().map(|(id, node)| (NodeId(id), node)) {
+        let (NodeRef::Expr(Expr::Identifier(identifier)), Some(span)) = (node, map.span(id)) else { continue };
+        if span.start.index < last.end.index || span.start.index >= function_end {
+            continue;
+        }
+        if let Some(binding) = scopes.resolve(identifier, span.start.index) {
+            if inside(binding.node) {
+                return Err(refuse(format!("`{identifier}` is declared by the statements but used after them")));
+            }
+        }
+    }
+
+    let text = |from: u64, to: u64| -> String { chars[from as usize..to as usize].iter().collect() };
+    let line_start = lines.index(first.start.line, 1).unwrap();
+    let body = reindent(&text(line_start, last.end.index));
+    let function_text = format!("\n\nfn {name}({}) {{\n{body}}}", params.join(", "));
+    let call = format!("{name}({});", params.join(", "));
+
+    reparse(
+        text(0, first.start.index) + &call
+            + &text(last.end.index, function_end) + &function_text
+            + &text(function_end, chars.len() as u64),
+    )
+}
+
+// the outermost statements within the range, which have to be neighbours in the same block
+fn select_statements(program: &Program, map: &NodeMap, range: &std::ops::Range<u64>) -> Result<Vec<NodeId>, CompileError> {
+    let nodes = nodes(program);
+    let within = |span: Span| range.start <= span.start.index && span.end.index <= range.end;
+    let selected: Vec<NodeId> = (0..nodes.len())
+        .map(NodeId)
+        .filter(|id| matches!(nodes[id.0], NodeRef::Statement(_)) && within(map.span(*id).unwrap()))
+        .filter(|id| !map.ancestors(*id).any(|ancestor| matches!(nodes[ancestor.0], NodeRef::Statement(_)) && within(map.span(ancestor).unwrap())))
+        .collect();
+
+    let parents: Vec<Option<NodeId>> = selected.iter().map(|id| map.parent(*id)).collect::<HashSet<_>>().into_iter().collect();
+    let block = match parents[..] {
+        [Some(block)] if matches!(nodes[block.0], NodeRef::Expr(Expr::Block(_))) => block,
+        _ => return Err(refuse("the lines do not hold a run of statements from a single block")),
+    };
+    let siblings: Vec<NodeId> = (0..nodes.len()).map(NodeId).filter(|id| map.parent(*id) == Some(block)).collect();
+    let position = siblings.iter().position(|id| *id == selected[0]).unwrap();
+    match siblings[position..].starts_with(&selected) {
+        true => Ok(selected),
+        false => Err(refuse("the lines do not hold a run of statements from a single block")),
+    }
+}
+
+fn reindent(text: &str) -> String {
+    let indent = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    text.lines()
+        .map(|line| match line.trim().is_empty() {
+            true => String::from("\n"),
+            false => format!("    {}\n", &line[indent..]),
+        })
+        .collect()
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn renaming_keeps_trivia_and_attributes() {
+    let source = "#[test]\nfn test() {\n    let test = 1; // the test\n    exit(test);\n}\n";
+    assert_eq!(
+        rename(source, "test", "check").unwrap(),
+        "#[test]\nfn check() {\n    let check = 1; // the test\n    exit(check);\n}\n",
+    );
+}
+
+#[test]
+fn renaming_refuses_to_capture() {
+    let source = "fn _start() { let x = 1; let y = 2; exit(x + y); }";
+    assert!(matches!(rename(source, "x", "y"), Err(CompileError::RefactorFailed(_))));
+    assert!(matches!(rename(source, "z", "w"), Err(CompileError::RefactorFailed(_))));
+    assert!(matches!(rename(source, "x", "let"), Err(CompileError::RefactorFailed(_))));
+    let source = "fn _start() { let in = 1; for i in 0..in exit(i); }";
+    assert!(matches!(rename(source, "in", "n"), Err(CompileError::RefactorFailed(_))));
+}
+
+#[test]
+fn extracting_passes_outer_variables() {
+    let source = "fn _start() {\n    let a = 1;\n    let b = 2;\n    if (a < b) {\n        exit(a + b);\n    }\n    exit(0);\n}\n";
+    assert_eq!(extract_function(source, "finish", 4, 6).unwrap(), concat!(
+        "fn _start() {\n    let a = 1;\n    let b = 2;\n    finish(a, b);\n    exit(0);\n}\n\n",
+        "fn finish(a, b) {\n    if (a < b) {\n        exit(a + b);\n    }\n}\n",
+    ));
+}
+
+#[test]
+fn extracting_refuses_to_change_meaning() {
+    let source = "fn _start() {\n    let mut a = 1;\n    a += 1;\n    let b = a;\n    exit(b);\n}\n";
+    assert!(matches!(extract_function(source, "bump", 3, 3), Err(CompileError::RefactorFailed(_))));
+    assert!(matches!(extract_function(source, "copy", 4, 4), Err(CompileError::RefactorFailed(_))));
+    assert!(matches!(extract_function(source, "_start", 5, 5), Err(CompileError::RefactorFailed(_))));
+    assert!(extract_function(source, "finish", 5, 5).is_ok());
+
+    let source = "fn _start() {\n    'outer: loop {\n        loop {\n            break 'outer;\n        }\n    }\n    exit(0);\n}\n";
+    assert!(matches!(extract_function(source, "inner", 3, 5), Err(CompileError::RefactorFailed(_))));
+    assert!(extract_function(source, "outer", 2, 6).is_ok());
+
+    let source = "fn g(x) return x;\nfn f(x) {\n    g(x?);\n    return 0;\n}\nfn _start() exit(f(1));\n";
+    assert!(matches!(extract_function(source, "checked", 3, 3), Err(CompileError::RefactorFailed(reason)) if reason.contains('?')));
+}
@@ -0,0 +1,170 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{CompileError, Diagnostic, Lang, Level, Suggestion, Target};
+
+
+#[derive(Clone, Debug)]
+pub struct BuildReport {
+    input: PathBuf,
+    output: PathBuf,
+    target: String,
+    passes: Vec<(&'static str, Duration)>,
+    diagnostics: Vec<Diagnostic>,
+    artifacts: Vec<PathBuf>,
+    mark: Instant,
+}
+
+impl BuildReport {
+    pub fn new(input: &Path, output: &Path, target: Target) -> Self {
+        Self {
+            input: input.to_path_buf(),
+            output: output.to_path_buf(),
+            target: target.name().to_string(),
+            passes: Vec::new(),
+            diagnostics: Vec::new(),
+            artifacts: Vec::new(),
+            mark: Instant::now(),
+        }
+    }
+
+    // records the time since the previous pass finished
+    pub fn finish_pass(&mut self, name: &'static str) {
+        let now = Instant::now();
+        self.passes.push((name, now - self.mark));
+        self.mark = now;
+    }
+
+    pub fn add_artifact(&mut self, path: PathBuf) {
+        self.artifacts.push(path);
+    }
+
+    pub fn artifacts(&self) -> &[PathBuf] {
+        &self.artifacts
+    }
+
+    pub fn add_error(&mut self, error: &CompileError, suggestions: Vec<Suggestion>, lang: Lang) {
+        self.diagnostics.push(suggestions.into_iter().fold(Diagnostic::localized(error, lang), Diagnostic::with_suggestion));
+    }
+
+    pub fn add_diagnostic(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn to_json(&self) -> String {
+        let passes: Vec<String> = self.passes
+            .iter()
+            .map(|(name, duration)| format!(
+                "{{\"name\": {}, \"seconds\": {}}}",
+                json_string(name), duration.as_secs_f64(),
+            ))
+            .collect();
+        let diagnostics: Vec<String> = self.diagnostics.iter().map(diagnostic_json).collect();
+        let artifacts: Vec<String> = self.artifacts
+            .iter()
+            .map(|path| format!(
+                "{{\"path\": {}, \"bytes\": {}}}",
+                json_string(&path.display().to_string()),
+                path.metadata().map(|meta| meta.len().to_string()).unwrap_or(String::from("null")),
+            ))
+            .collect();
+
+        let mut json = String::from("{\n");
+        json += &format!("  \"input\": {},\n", json_string(&self.input.display().to_string()));
+        json += &format!("  \"output\": {},\n", json_string(&self.output.display().to_string()));
+        json += &format!("  \"target\": {},\n", json_string(&self.target));
+        json += &format!("  \"success\": {},\n", self.diagnostics.iter().all(|diagnostic| diagnostic.level != Level::Error));
+        json += &format!("  \"passes\": [{}],\n", passes.join(", "));
+        json += &format!("  \"diagnostics\": [{}],\n", diagnostics.join(", "));
+        json += &format!("  \"artifacts\": [{}]\n", artifacts.join(", "));
+        json += "}\n";
+        json
+    }
+}
+
+
+// spans are given by the line and column they start and end at
+fn diagnostic_json(diagnostic: &Diagnostic) -> String {
+    let labels: Vec<String> = diagnostic.labels
+        .iter()
+        .map(|label| format!(
+            "{{\"start\": [{}, {}], \"end\": [{}, {}], \"message\": {}}}",
+            label.span.start.line, label.span.start.column, label.span.end.line, label.span.end.column,
+            label.message.as_deref().map(json_string).unwrap_or(String::from("null")),
+        ))
+        .collect();
+    let children: Vec<String> = diagnostic.children.iter().map(diagnostic_json).collect();
+    let suggestions: Vec<String> = diagnostic.suggestions
+        .iter()
+        .map(|suggestion| {
+            let edits: Vec<String> = suggestion.edits
+                .iter()
+                .map(|edit| format!(
+                    "{{\"start\": [{}, {}], \"end\": [{}, {}], \"replacement\": {}}}",
+                    edit.span.start.line, edit.span.start.column, edit.span.end.line, edit.span.end.column,
+                    json_string(&edit.replacement),
+                ))
+                .collect();
+            format!("{{\"message\": {}, \"edits\": [{}]}}", json_string(&suggestion.message), edits.join(", "))
+        })
+        .collect();
+    format!(
+        "{{\"level\": {}, \"code\": {}, \"message\": {}, \"labels\": [{}], \"children\": [{}], \"suggestions\": [{}]}}",
+        json_string(diagnostic.level.name()),
+        diagnostic.code.map(json_string).unwrap_or(String::from("null")),
+        json_string(&diagnostic.message),
+        labels.join(", "),
+        children.join(", "),
+        suggestions.join(", "),
+    )
+}
+
+pub fn json_string(text: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => escaped += "\\\"",
+            '\\' => escaped += "\\\\",
+            '\n' => escaped += "\\n",
+            '\r' => escaped += "\\r",
+            '\t' => escaped += "\\t",
+            c if (c as u32) < 0x20 => escaped += &format!("\\u{:04x}", c as u32),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn strings_are_escaped() {
+    assert_eq!(json_string("a \"b\"\n\\"), "\"a \\\"b\\\"\\n\\\\\"");
+}
+
+#[test]
+fn failed_builds_report_their_errors() {
+    let mut report = BuildReport::new(Path::new("in.be"), Path::new("out"), Target::default());
+    report.finish_pass("parsing");
+    report.add_error(&CompileError::IdentifierNotDeclared(String::from("x")), Vec::new(), Lang::En);
+    let json = report.to_json();
+    assert!(json.contains("\"success\": false"));
+    assert!(json.contains("\"code\": \"BE0001\", \"message\": \"use of undeclared identifier `x`\""));
+    let location = crate::tokenize::Location { index: 10, line: 2, column: 3 };
+    report.add_error(&CompileError::NotConstant(location), vec![Suggestion::insert("insert missing `;`", location, ";")], Lang::En);
+    assert!(report.to_json().contains("\"labels\": [{\"start\": [2, 3], \"end\": [2, 3], \"message\": null}], \"children\": []"));
+    assert!(report.to_json().contains("\"suggestions\": [{\"message\": \"insert missing `;`\", \"edits\": [{\"start\": [2, 3], \"end\": [2, 3], \"replacement\": \";\"}]}]"));
+    assert!(json.contains("{\"name\": \"parsing\", \"seconds\": "));
+}
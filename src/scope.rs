@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{Expr, Item, Program, Statement},
+    node_map::{nodes, NodeId, NodeMap, NodeRef, Span},
+    tokenize::Location,
+};
+
+
+/// the index of a scope in its `ScopeTree`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ScopeId(pub usize);
+
+
+/// what a name is bound to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BindingKind {
+    Function { params: usize },
+    Parameter,
+    Variable { is_mutable: bool },
+    Constant,
+}
+
+
+/// a name made visible by a declaration, with the node that declares it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Binding {
+    pub name: String,
+    pub kind: BindingKind,
+    // the item for functions and parameters, the `let` or `const` statement for variables
+    pub node: NodeId,
+    // the offset from which the name can be used, a variable is not visible in its own initialiser
+    pub visible_from: u64,
+}
+
+
+/// a frame of names, inside the scope it is nested in
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Scope {
+    pub parent: Option<ScopeId>,
+    pub span: Span,
+    pub bindings: Vec<Binding>,
+}
+
+
+/// the scopes of a program, following the same rules as codegen: functions are visible everywhere,
+/// each function has a frame for its parameters, and blocks and the branches of an `if` open a new
+/// frame while loop bodies do not
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScopeTree {
+    scopes: Vec<Scope>,
+}
+
+impl ScopeTree {
+    pub const ROOT: ScopeId = ScopeId(0);
+
+    pub fn new(program: &Program, map: &NodeMap) -> Self {
+        let nodes = nodes(program);
+        let end = (0..map.len())
+            .filter_map(|id| map.span(NodeId(id)))
+            .map(|span| span.end)
+            .max_by_key(|end| end.index)
+            .unwrap_or_default();
+        let mut tree = Self {
+            scopes: vec![Scope { parent: None, span: Span { start: Location::default(), end }, bindings: Vec::new() }],
+        };
+
+        // parents always have higher ids than their children, so walking the ids backwards opens
+        // each scope before anything inside it
+        let mut opened: HashMap<NodeId, ScopeId> = HashMap::new();
+        for (id, node) in nodes.iter().enumerate().rev() {
+            let id = NodeId(id);
+            let Some(span) = map.span(id) else { continue };
+            let parent = map.ancestors(id)
+                .find_map(|ancestor| opened.get(&ancestor).copied())
+                .unwrap_or(Self::ROOT);
+            let parent_node = map.parent(id).map(|parent| nodes[parent.0]);
+
+            match node {
+                NodeRef::Item(Item::Function { name, params, .. }) => {
+                    tree.bind(Self::ROOT, Binding {
+                        name: name.clone(),
+                        kind: BindingKind::Function { params: params.len() },
+                        node: id,
+                        visible_from: 0,
+                    });
+                    let scope = tree.open(Self::ROOT, span);
+                    for param in params {
+                        tree.bind(scope, Binding {
+                            name: param.name.clone(),
+                            kind: BindingKind::Parameter,
+                            node: id,
+                            visible_from: span.start.index,
+                        });
+                    }
+                    opened.insert(id, scope);
+                },
+                NodeRef::Item(Item::Const { identifier, .. }) => tree.bind(Self::ROOT, Binding {
+                    name: identifier.clone(),
+                    kind: BindingKind::Constant,
+                    node: id,
+                    visible_from: 0,
+                }),
+                NodeRef::Expr(Expr::Block(_)) => { opened.insert(id, tree.open(parent, span)); },
+                NodeRef::Statement(_) if matches!(parent_node, Some(NodeRef::Expr(Expr::If { .. }))) => {
+                    opened.insert(id, tree.open(parent, span));
+                },
+                _ => (),
+            }
+
+            // a `let` that is itself the branch of an `if` is declared in the branch's own frame
+            let binding = match node {
+                NodeRef::Statement(Statement::Let { identifier, is_mutable, .. }) =>
+                    Some((identifier, BindingKind::Variable { is_mutable: *is_mutable })),
+                NodeRef::Statement(Statement::Const { identifier, .. }) => Some((identifier, BindingKind::Constant)),
+                _ => None,
+            };
+            if let Some((identifier, kind)) = binding {
+                tree.bind(opened.get(&id).copied().unwrap_or(parent), Binding {
+                    name: identifier.clone(),
+                    kind,
+                    node: id,
+                    visible_from: span.end.index,
+                });
+            }
+        }
+
+        tree.scopes.iter_mut().for_each(|scope| scope.bindings.sort_by_key(|binding| binding.visible_from));
+        tree
+    }
+
+    fn open(&mut self, parent: ScopeId, span: Span) -> ScopeId {
+        self.scopes.push(Scope { parent: Some(parent), span, bindings: Vec::new() });
+        ScopeId(self.scopes.len() - 1)
+    }
+
+    fn bind(&mut self, scope: ScopeId, binding: Binding) {
+        self.scopes[scope.0].bindings.push(binding);
+    }
+
+    pub fn len(&self) -> usize {
+        self.scopes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scopes.is_empty()
+    }
+
+    pub fn scope(&self, id: ScopeId) -> Option<&Scope> {
+        self.scopes.get(id.0)
+    }
+
+    pub fn bindings(&self) -> impl Iterator<Item = &Binding> {
+        self.scopes.iter().flat_map(|scope| &scope.bindings)
+    }
+
+    // the innermost scope covering the offset; scopes are opened after their parents, so going
+    // backwards picks the deeper of two scopes with the same span
+    pub fn scope_at(&self, index: u64) -> ScopeId {
+        self.scopes
+            .iter()
+            .enumerate()
+            .skip(1)
+            .rev()
+            .filter(|(_, scope)| scope.span.contains(index))
+            .min_by_key(|(_, scope)| scope.span.len())
+            .map(|(id, _)| ScopeId(id))
+            .unwrap_or(Self::ROOT)
+    }
+
+    // every name that can be used at the offset, innermost first, leaving out shadowed bindings
+    pub fn visible_at(&self, index: u64) -> Vec<&Binding> {
+        let mut visible: Vec<&Binding> = Vec::new();
+        let mut scope = Some(self.scope_at(index));
+        while let Some(id) = scope {
+            let current = &self.scopes[id.0];
+            for binding in current.bindings.iter().rev() {
+                if binding.visible_from <= index && visible.iter().all(|other| other.name != binding.name) {
+                    visible.push(binding);
+                }
+            }
+            scope = current.parent;
+        }
+        visible
+    }
+
+    pub fn resolve(&self, name: &str, index: u64) -> Option<&Binding> {
+        self.visible_at(index).into_iter().find(|binding| binding.name == name)
+    }
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[cfg(test)]
+fn scopes(source: &str) -> ScopeTree {
+    use crate::{parser::Parser, tokenize::Tokenize};
+    let (program, map) = Parser::new(source.tokenize()).parse_with_map().expect("source parses");
+    ScopeTree::new(&program, &map)
+}
+
+#[cfg(test)]
+fn names(bindings: Vec<&Binding>) -> Vec<&str> {
+    bindings.into_iter().map(|binding| binding.name.as_str()).collect()
+}
+
+#[test]
+fn variables_are_visible_after_their_let() {
+    let source = "fn f(a) { let x = a; let y = x; exit(y); }";
+    let tree = scopes(source);
+    assert_eq!(names(tree.visible_at(source.find("a;").unwrap() as u64)), vec!["a", "f"]);
+    assert_eq!(names(tree.visible_at(source.find("exit").unwrap() as u64)), vec!["y", "x", "a", "f"]);
+    assert_eq!(tree.resolve("x", source.find("x;").unwrap() as u64).unwrap().kind, BindingKind::Variable { is_mutable: false });
+}
+
+#[test]
+fn blocks_and_branches_scope_their_variables() {
+    let source = "fn _start() { let x = 1; if (x == 1) { let mut x = 2; x += 1; } else let y = 3; loop { let z = x; break; } exit(x); }";
+    let tree = scopes(source);
+    let inner = tree.resolve("x", source.find("x += 1").unwrap() as u64).unwrap();
+    assert_eq!(inner.kind, BindingKind::Variable { is_mutable: true });
+    let outer = tree.resolve("x", source.find("exit").unwrap() as u64).unwrap();
+    assert_eq!(outer.kind, BindingKind::Variable { is_mutable: false });
+    assert_eq!(names(tree.visible_at(source.find("exit").unwrap() as u64)), vec!["x", "_start"]);
+    assert_eq!(names(tree.visible_at(source.find("break").unwrap() as u64)), vec!["z", "x", "_start"]);
+}
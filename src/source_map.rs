@@ -0,0 +1,76 @@
+use crate::tokenize::Location;
+
+
+/// converts between the character offsets in `Location::index` and one-based line/column pairs,
+/// using the same counting as the tokenizer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineIndex {
+    line_starts: Vec<u64>,
+    len: u64,
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> Self {
+        let mut line_starts = vec![0];
+        let mut len = 0;
+        for character in source.chars() {
+            len += 1;
+            if character == '\n' {
+                line_starts.push(len);
+            }
+        }
+        Self { line_starts, len }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    pub fn location(&self, index: u64) -> Option<Location> {
+        if index > self.len {
+            return None;
+        }
+        let line = self.line_starts.partition_point(|start| *start <= index);
+        let start = self.line_starts[line - 1];
+        Some(Location { index, line: line as u64, column: index - start + 1 })
+    }
+
+    pub fn index(&self, line: u64, column: u64) -> Option<u64> {
+        let start = *self.line_starts.get(line.checked_sub(1)? as usize)?;
+        // a line ends at the start of the next one, or at the end of the source for the last line
+        let end = self.line_starts.get(line as usize).copied().unwrap_or(self.len + 1);
+        let index = start + column.checked_sub(1)?;
+        (index < end).then_some(index)
+    }
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn locations_match_the_tokenizer() {
+    use fallible_iterator::FallibleIterator;
+    use crate::tokenize::Tokenize;
+
+    let source = "fn _start() {\n    let x = 1;\n\n    exit(x);\n}\n";
+    let index = LineIndex::new(source);
+    let tokens: Vec<_> = source.tokenize().collect().expect("source tokenizes");
+    for token in tokens {
+        assert_eq!(index.location(token.location.index), Some(token.location));
+        assert_eq!(index.index(token.location.line, token.location.column), Some(token.location.index));
+    }
+}
+
+#[test]
+fn out_of_range_positions_are_rejected() {
+    let index = LineIndex::new("ab\ncd");
+    assert_eq!(index.line_count(), 2);
+    assert_eq!(index.location(6), None);
+    assert_eq!(index.index(1, 4), None);
+    assert_eq!(index.index(3, 1), None);
+    assert_eq!(index.index(2, 3), Some(5));
+}
@@ -0,0 +1,136 @@
+use fallible_iterator::FallibleIterator;
+
+use crate::{
+    CompileError,
+    ast::{Expr, Item},
+    node_map::{nodes, NodeId, NodeRef},
+    parser::Parser,
+    tokenize::{Token, Tokenize},
+    visit::{self, Visitor},
+};
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionStats {
+    pub name: String,
+    pub tokens: usize,
+    // how deeply `if`, `loop` and `while` bodies nest, straight-line code is 0
+    pub depth: usize,
+    // one more than the number of branches: each `if`, `while` and `?`
+    pub complexity: usize,
+}
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stats {
+    pub tokens: usize,
+    pub functions: Vec<FunctionStats>,
+}
+
+impl Stats {
+    pub fn new(source: &str) -> Result<Self, CompileError> {
+        let tokens: Vec<Token> = source.tokenize().collect()?;
+        let (program, map) = Parser::new(source.tokenize()).parse_with_map()?;
+
+        let functions = nodes(&program)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(id, node)| match node {
+                NodeRef::Item(item) => Some((map.span(NodeId(id))?, item)),
+                _ => None,
+            })
+            .filter_map(|(span, item)| {
+                let Item::Function { name, .. } = item else { return None };
+                let mut counter = Counter::default();
+                counter.visit_item(item);
+                Some(FunctionStats {
+                    name: name.clone(),
+                    tokens: tokens.iter().filter(|token| span.contains(token.location.index)).count(),
+                    depth: counter.max_depth,
+                    complexity: counter.branches + 1,
+                })
+            })
+            .collect();
+        Ok(Self { tokens: tokens.len(), functions })
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.functions.iter().map(|function| function.depth).max().unwrap_or(0)
+    }
+
+    pub fn to_text(&self) -> String {
+        let width = self.functions.iter().map(|function| function.name.len()).max().unwrap_or(0).max("function".len());
+        let mut text = format!("functions: {}\n", self.functions.len());
+        text += &format!("tokens: {}\n", self.tokens);
+        text += &format!("max nesting depth: {}\n", self.max_depth());
+        if self.functions.is_empty() {
+            return text;
+        }
+        text += &format!("\n{:<width$}  {:>6}  {:>5}  {:>10}\n", "function", "tokens", "depth", "complexity");
+        for function in &self.functions {
+            text += &format!(
+                "{:<width$}  {:>6}  {:>5}  {:>10}\n",
+                function.name, function.tokens, function.depth, function.complexity,
+            );
+        }
+        text
+    }
+}
+
+
+#[derive(Default)]
+struct Counter {
+    depth: usize,
+    max_depth: usize,
+    branches: usize,
+}
+
+impl Visitor for Counter {
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::If { .. } | Expr::While { .. } | Expr::Try(_) => self.branches += 1,
+            _ => (),
+        }
+        match expr {
+            Expr::If { .. } | Expr::Loop { .. } | Expr::While { .. } => {
+                self.depth += 1;
+                self.max_depth = self.max_depth.max(self.depth);
+                visit::walk_expr(self, expr);
+                self.depth -= 1;
+            },
+            _ => visit::walk_expr(self, expr),
+        }
+    }
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn functions_are_measured() {
+    let source = "fn f(x) return x?;\nfn _start() { while (1) { if (f(1) == 1) break; else loop {} } exit(0); }";
+    let stats = Stats::new(source).unwrap();
+    assert_eq!(stats.tokens, 41);
+    assert_eq!(stats.functions, vec![
+        FunctionStats { name: String::from("f"), tokens: 9, depth: 0, complexity: 2 },
+        FunctionStats { name: String::from("_start"), tokens: 32, depth: 3, complexity: 3 },
+    ]);
+    assert_eq!(stats.max_depth(), 3);
+}
+
+#[test]
+fn text_lines_up_columns() {
+    let stats = Stats::new("fn _start() exit(0);").unwrap();
+    assert_eq!(stats.to_text(), concat!(
+        "functions: 1\n",
+        "tokens: 9\n",
+        "max nesting depth: 0\n",
+        "\n",
+        "function  tokens  depth  complexity\n",
+        "_start         9      0           1\n",
+    ));
+}
@@ -0,0 +1,250 @@
+use std::iter::Peekable;
+
+use fallible_iterator::FallibleIterator;
+
+use crate::tokenize::{Symbol, Token, TokenData, TokenizerError, Tokenize};
+
+
+/// the kinds of text that do not change what a program means
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TriviaKind {
+    Whitespace,
+    Comment,
+}
+
+
+/// whitespace or a comment, with its source text
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+}
+
+
+/// a significant token with the source text and trivia around it; trailing trivia runs up to the
+/// end of the token's line, everything else leads the next token
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxToken {
+    pub token: Token,
+    pub text: String,
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+}
+
+
+/// the pairs of symbols that nest a `SyntaxGroup`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Delimiter {
+    Paren,
+    Brace,
+    Bracket,
+}
+
+impl Delimiter {
+    fn open(symbol: Symbol) -> Option<Self> {
+        match symbol {
+            Symbol::LParen => Some(Self::Paren),
+            Symbol::LBrace => Some(Self::Brace),
+            Symbol::LBracket => Some(Self::Bracket),
+            _ => None,
+        }
+    }
+
+    fn close(&self) -> Symbol {
+        match self {
+            Self::Paren => Symbol::RParen,
+            Self::Brace => Symbol::RBrace,
+            Self::Bracket => Symbol::RBracket,
+        }
+    }
+}
+
+
+/// the tokens between a pair of delimiters, with the delimiters themselves
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxGroup {
+    pub delimiter: Delimiter,
+    pub open: SyntaxToken,
+    pub children: Vec<SyntaxElement>,
+    // missing when the file ends before the group is closed
+    pub close: Option<SyntaxToken>,
+}
+
+
+/// a token or a group of tokens in a `SyntaxTree`
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SyntaxElement {
+    Token(SyntaxToken),
+    Group(SyntaxGroup),
+}
+
+
+/// a lossless tree of the source, nesting tokens by their delimiters and keeping all trivia, so that
+/// printing it reproduces the file exactly
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxTree {
+    pub children: Vec<SyntaxElement>,
+    pub end: Vec<Trivia>,
+}
+
+impl SyntaxTree {
+    pub fn parse(source: &str) -> Result<Self, TokenizerError> {
+        let (tokens, end) = attach_trivia(source)?;
+        let mut tokens = tokens.into_iter().peekable();
+        let (children, _) = group(&mut tokens, None);
+        Ok(Self { children, end })
+    }
+
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        let mut tokens = Vec::new();
+        collect_tokens(&self.children, &mut tokens);
+        tokens
+    }
+}
+
+fn collect_tokens<'a>(elements: &'a [SyntaxElement], tokens: &mut Vec<&'a SyntaxToken>) {
+    for element in elements {
+        match element {
+            SyntaxElement::Token(token) => tokens.push(token),
+            SyntaxElement::Group(group) => {
+                tokens.push(&group.open);
+                collect_tokens(&group.children, tokens);
+                tokens.extend(&group.close);
+            },
+        }
+    }
+}
+
+fn attach_trivia(source: &str) -> Result<(Vec<SyntaxToken>, Vec<Trivia>), TokenizerError> {
+    let chars: Vec<char> = source.chars().collect();
+    let raw: Vec<Token> = source.tokenize().with_trivia().collect()?;
+
+    let mut tokens: Vec<SyntaxToken> = Vec::new();
+    let mut pending = Vec::new();
+    let mut same_line = false;
+    for (index, token) in raw.iter().enumerate() {
+        let start = token.location.index as usize;
+        let end = raw.get(index + 1).map(|next| next.location.index as usize).unwrap_or(chars.len());
+        let text: String = chars[start..end].iter().collect();
+        match (&token.data, tokens.last_mut()) {
+            (TokenData::Whitespace(_), Some(last)) if same_line => {
+                let (trailing, leading) = text.split_at(text.find('\n').unwrap_or(text.len()));
+                if !trailing.is_empty() {
+                    last.trailing.push(Trivia { kind: TriviaKind::Whitespace, text: trailing.to_string() });
+                }
+                if !leading.is_empty() {
+                    pending.push(Trivia { kind: TriviaKind::Whitespace, text: leading.to_string() });
+                    same_line = false;
+                }
+            },
+            (TokenData::Comment(_), Some(last)) if same_line => {
+                last.trailing.push(Trivia { kind: TriviaKind::Comment, text });
+            },
+            (TokenData::Whitespace(_), _) => pending.push(Trivia { kind: TriviaKind::Whitespace, text }),
+            (TokenData::Comment(_), _) => pending.push(Trivia { kind: TriviaKind::Comment, text }),
+            _ => {
+                tokens.push(SyntaxToken {
+                    token: token.clone(),
+                    text,
+                    leading: std::mem::take(&mut pending),
+                    trailing: Vec::new(),
+                });
+                same_line = true;
+            },
+        }
+    }
+    Ok((tokens, pending))
+}
+
+fn group(
+    tokens: &mut Peekable<impl Iterator<Item = SyntaxToken>>,
+    close: Option<Symbol>,
+) -> (Vec<SyntaxElement>, Option<SyntaxToken>) {
+    let mut children = Vec::new();
+    while let Some(token) = tokens.next() {
+        match token.token.data {
+            TokenData::Symbol(symbol) if Some(symbol) == close => return (children, Some(token)),
+            TokenData::Symbol(symbol) => match Delimiter::open(symbol) {
+                Some(delimiter) => {
+                    let (inner, closing) = group(tokens, Some(delimiter.close()));
+                    children.push(SyntaxElement::Group(SyntaxGroup {
+                        delimiter,
+                        open: token,
+                        children: inner,
+                        close: closing,
+                    }));
+                },
+                None => children.push(SyntaxElement::Token(token)),
+            },
+            _ => children.push(SyntaxElement::Token(token)),
+        }
+    }
+    (children, None)
+}
+
+
+impl std::fmt::Display for SyntaxToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.leading.iter().try_for_each(|trivia| write!(f, "{}", trivia.text))?;
+        write!(f, "{}", self.text)?;
+        self.trailing.iter().try_for_each(|trivia| write!(f, "{}", trivia.text))
+    }
+}
+
+impl std::fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.tokens().into_iter().try_for_each(|token| write!(f, "{token}"))?;
+        self.end.iter().try_for_each(|trivia| write!(f, "{}", trivia.text))
+    }
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn printing_is_lossless() {
+    let source = "// entry point\nfn _start() {\n    let x = 1;  // one\n\n    exit(x);\n}\n";
+    assert_eq!(SyntaxTree::parse(source).unwrap().to_string(), source);
+}
+
+#[test]
+fn trivia_attaches_to_the_nearest_line() {
+    let tree = SyntaxTree::parse("// lead\nexit; // trail\n").unwrap();
+    let tokens = tree.tokens();
+    assert_eq!(tokens[0].leading, vec![
+        Trivia { kind: TriviaKind::Comment, text: String::from("// lead") },
+        Trivia { kind: TriviaKind::Whitespace, text: String::from("\n") },
+    ]);
+    assert_eq!(tokens[1].trailing, vec![
+        Trivia { kind: TriviaKind::Whitespace, text: String::from(" ") },
+        Trivia { kind: TriviaKind::Comment, text: String::from("// trail") },
+    ]);
+    assert_eq!(tree.end, vec![Trivia { kind: TriviaKind::Whitespace, text: String::from("\n") }]);
+}
+
+#[test]
+fn delimiters_nest() {
+    let tree = SyntaxTree::parse("f(a, [b]) { }").unwrap();
+    assert_eq!(tree.children.len(), 3);
+    match &tree.children[1] {
+        SyntaxElement::Group(group) => {
+            assert_eq!(group.delimiter, Delimiter::Paren);
+            assert!(matches!(&group.children[2], SyntaxElement::Group(SyntaxGroup { delimiter: Delimiter::Bracket, .. })));
+        },
+        element => panic!("expected a group, got {element:?}"),
+    }
+}
+
+#[test]
+fn unclosed_groups_are_kept() {
+    let source = "{ (";
+    let tree = SyntaxTree::parse(source).unwrap();
+    assert_eq!(tree.to_string(), source);
+}
@@ -3,6 +3,7 @@ use std::collections::VecDeque;
 use fallible_iterator::FallibleIterator;
 
 
+/// splits source text into tokens, read one at a time from the `TokenStream` it gives
 pub trait Tokenize {
     fn tokenize(self) -> TokenStream;
 }
@@ -26,6 +27,7 @@ impl Tokenize for Vec<char> {
 }
 
 
+/// where a token is in the source, `index` counting characters from the start
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Location {
     pub index: u64,
@@ -44,6 +46,7 @@ impl Default for Location {
 }
 
 
+/// a token and the location it starts at
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Token {
     pub data: TokenData,
@@ -51,32 +54,52 @@ pub struct Token {
 }
 
 
+/// what a token is, with the value it carries
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum TokenData {
     Identifier(String),
     IntegerLiteral(String),
+    StringLiteral(String),
+    // a loop label such as `'outer`, without its quote
+    Label(String),
     Keyword(Keyword),
     Symbol(Symbol),
+    // only produced when trivia is kept
+    Whitespace(String),
+    Comment(String),
 }
 
+/// the words that cannot be used as identifiers
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Keyword {
-    Exit,
+    Exit, Panic, Unreachable, StaticAssert,
     Let, Mut,
-    If, Else,
-    Loop, While,
+    If, Else, Match,
+    Loop, While, For, In,
     Break, Continue,
-    Fn, Return,
+    Fn, Return, Const,
+    Unsafe,
+    As,
 }
 
+/// operators and punctuation
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Symbol {
     LParen, RParen,
     LBrace, RBrace,
+    LBracket, RBracket,
     LAngle, RAngle,
     Semi,
+    Colon,
     Comma,
     Equals,
+    Question,
+    Hash,
+    DotDot, DotDotEq,
+    FatArrow, Arrow,
     Plus, Minus, Star, Slash, Percent,
     PlusEq, MinusEq, StarEq, SlashEq, PercentEq,
     Equality, NonEquality,
@@ -84,9 +107,135 @@ pub enum Symbol {
 }
 
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+impl Keyword {
+    // only keywords where the parser expects them and identifiers everywhere else, so they can
+    // be added without breaking programs that already use the name. the tokenizer leaves them as
+    // identifiers
+    pub const CONTEXTUAL: [Keyword; 3] = [Self::Match, Self::In, Self::As];
+
+    pub fn is_contextual(&self) -> bool {
+        Self::CONTEXTUAL.contains(self)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Exit => "exit",
+            Self::Panic => "panic",
+            Self::Unreachable => "unreachable",
+            Self::StaticAssert => "static_assert",
+            Self::Let => "let",
+            Self::Mut => "mut",
+            Self::If => "if",
+            Self::Else => "else",
+            Self::Match => "match",
+            Self::Loop => "loop",
+            Self::While => "while",
+            Self::For => "for",
+            Self::In => "in",
+            Self::Break => "break",
+            Self::Continue => "continue",
+            Self::Fn => "fn",
+            Self::Return => "return",
+            Self::Const => "const",
+            Self::Unsafe => "unsafe",
+            Self::As => "as",
+        }
+    }
+}
+
+impl Symbol {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::LParen => "(",
+            Self::RParen => ")",
+            Self::LBrace => "{",
+            Self::RBrace => "}",
+            Self::LBracket => "[",
+            Self::RBracket => "]",
+            Self::LAngle => "<",
+            Self::RAngle => ">",
+            Self::Semi => ";",
+            Self::Colon => ":",
+            Self::Comma => ",",
+            Self::Equals => "=",
+            Self::Question => "?",
+            Self::Hash => "#",
+            Self::DotDot => "..",
+            Self::DotDotEq => "..=",
+            Self::FatArrow => "=>",
+            Self::Arrow => "->",
+            Self::Plus => "+",
+            Self::Minus => "-",
+            Self::Star => "*",
+            Self::Slash => "/",
+            Self::Percent => "%",
+            Self::PlusEq => "+=",
+            Self::MinusEq => "-=",
+            Self::StarEq => "*=",
+            Self::SlashEq => "/=",
+            Self::PercentEq => "%=",
+            Self::Equality => "==",
+            Self::NonEquality => "!=",
+            Self::GreaterEqual => ">=",
+            Self::LesserEqual => "<=",
+        }
+    }
+
+    // the symbol that closes this one, for the opening ones of the pairs that have to be balanced
+    pub fn closing(&self) -> Option<Symbol> {
+        match self {
+            Self::LParen => Some(Self::RParen),
+            Self::LBrace => Some(Self::RBrace),
+            Self::LBracket => Some(Self::RBracket),
+            _ => None,
+        }
+    }
+
+    pub fn is_comparison(&self) -> bool {
+        matches!(self,
+            Self::Equality | Self::NonEquality | Self::LAngle | Self::LesserEqual | Self::RAngle | Self::GreaterEqual)
+    }
+}
+
+impl TokenData {
+    // the token as it is spelled in the source
+    pub fn text(&self) -> String {
+        match self {
+            Self::Identifier(text) | Self::IntegerLiteral(text)
+                | Self::Whitespace(text) | Self::Comment(text) => text.clone(),
+            Self::StringLiteral(text) => format!("\"{text}\""),
+            Self::Label(name) => format!("'{name}"),
+            Self::Keyword(keyword) => keyword.as_str().to_string(),
+            Self::Symbol(symbol) => symbol.as_str().to_string(),
+        }
+    }
+}
+
+impl Token {
+    // the location just past the end of the token
+    pub fn end(&self) -> Location {
+        let mut end = self.location;
+        for character in self.data.text().chars() {
+            end.index += 1;
+            end.column += 1;
+            if character == '\n' {
+                end.line += 1;
+                end.column = 1;
+            }
+        }
+        end
+    }
+}
+
+
+/// why the source could not be split into tokens
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum TokenizerError {
     UnrecognizedCharacter(char),
+    UnterminatedStringLiteral(Location),
+    UnterminatedBlockComment(Location),
+    InvalidEscape { sequence: String, location: Location },
 }
 
 impl std::fmt::Display for TokenizerError {
@@ -98,14 +247,74 @@ impl std::fmt::Display for TokenizerError {
 impl std::error::Error for TokenizerError {}
 
 
+// decodes the escape following a backslash, or gives back the sequence as written if it isn't
+// one; `\x` only reaches up to 7f so that every escape is a single character
+fn escaped(mut sequence: impl Iterator<Item = char>) -> Result<char, String> {
+    match sequence.next() {
+        Some('n') => Ok('\n'),
+        Some('t') => Ok('\t'),
+        Some('0') => Ok('\0'),
+        Some('\\') => Ok('\\'),
+        Some('"') => Ok('"'),
+        Some('x') => {
+            let digits: String = sequence.take(2).collect();
+            match u8::from_str_radix(&digits, 16) {
+                Ok(byte) if digits.len() == 2 && byte.is_ascii() => Ok(char::from(byte)),
+                _ => Err(format!("\\x{digits}")),
+            }
+        },
+        Some(character) => Err(format!("\\{character}")),
+        None => Err(String::from("\\")),
+    }
+}
+
+// the value of a string literal, whose escapes have already been checked by the lexer. only the
+// tokens the lexer gives are passed in, so it is kept to the crate
+pub(crate) fn unescape(text: &str) -> String {
+    let mut characters = text.chars();
+    let mut value = String::new();
+    while let Some(character) = characters.next() {
+        match character {
+            '\\' => value.push(escaped(&mut characters).expect("escapes are checked when lexing")),
+            character => value.push(character),
+        }
+    }
+    value
+}
+
+/// spells a value as a string literal, escaping what has to be
+pub fn quoted(value: &str) -> String {
+    let mut text = String::from("\"");
+    for character in value.chars() {
+        match character {
+            '\n' => text += "\\n",
+            '\t' => text += "\\t",
+            '\0' => text += "\\0",
+            '\\' => text += "\\\\",
+            '"' => text += "\\\"",
+            character if character.is_ascii_control() => text += &format!("\\x{:02x}", character as u8),
+            character => text.push(character),
+        }
+    }
+    text + "\""
+}
+
+
+/// the tokens of a source, lexed as they are asked for
 pub struct TokenStream {
     source: VecDeque<char>,
     location: Location,
+    keep_trivia: bool,
 }
 
 impl TokenStream {
     pub fn new(source: VecDeque<char>) -> Self {
-        TokenStream { source, location: Location::default() }
+        TokenStream { source, location: Location::default(), keep_trivia: false }
+    }
+
+    pub fn with_trivia(mut self) -> Self {
+        self.keep_trivia = true;
+        self
     }
 
     fn peek(&self) -> Option<char> {
@@ -137,7 +346,10 @@ impl TokenStream {
             buffer.push(self.consume().unwrap());
         };
         let data = match buffer.as_str() {
-            "exit" => TokenData::Keyword(Keyword::Exit),
+            "exit"  => TokenData::Keyword(Keyword::Exit),
+            "panic" => TokenData::Keyword(Keyword::Panic),
+            "unreachable" => TokenData::Keyword(Keyword::Unreachable),
+            "static_assert" => TokenData::Keyword(Keyword::StaticAssert),
 
             "let" => TokenData::Keyword(Keyword::Let),
             "mut" => TokenData::Keyword(Keyword::Mut),
@@ -147,18 +359,34 @@ impl TokenStream {
 
             "loop"  => TokenData::Keyword(Keyword::Loop),
             "while" => TokenData::Keyword(Keyword::While),
+            "for"   => TokenData::Keyword(Keyword::For),
 
             "break"    => TokenData::Keyword(Keyword::Break),
             "continue" => TokenData::Keyword(Keyword::Continue),
 
             "fn"     => TokenData::Keyword(Keyword::Fn),
             "return" => TokenData::Keyword(Keyword::Return),
+            "const"  => TokenData::Keyword(Keyword::Const),
+
+            "unsafe" => TokenData::Keyword(Keyword::Unsafe),
             
             _ => TokenData::Identifier(buffer),
         };
         Token { data, location }
     }
 
+    fn lex_label(&mut self) -> Token {
+        let location = self.location;
+        self.consume();
+        // keywords are free to use as labels, `'loop` is no more ambiguous than `'outer`
+        let name = match self.lex_identifier().data {
+            TokenData::Identifier(name) => name,
+            TokenData::Keyword(keyword) => keyword.as_str().to_string(),
+            _ => unreachable!("identifiers lex as identifiers or keywords"),
+        };
+        Token { data: TokenData::Label(name), location }
+    }
+
     fn lex_number(&mut self) -> Token {
         let mut buffer = String::new();
         let location = self.location;
@@ -174,6 +402,97 @@ impl TokenStream {
         }
     }
 
+    fn lex_whitespace(&mut self) -> Token {
+        let mut buffer = String::new();
+        let location = self.location;
+        while let Some(character) = self.peek() {
+            if !character.is_whitespace() {
+                break;
+            }
+            buffer.push(self.consume().unwrap());
+        };
+        Token {
+            data: TokenData::Whitespace(buffer),
+            location,
+        }
+    }
+
+    fn lex_comment(&mut self) -> Token {
+        let mut buffer = String::new();
+        let location = self.location;
+        while let Some(character) = self.peek() {
+            if character == '\n' {
+                break;
+            }
+            buffer.push(self.consume().unwrap());
+        };
+        Token {
+            data: TokenData::Comment(buffer),
+            location,
+        }
+    }
+
+    // `/* ... */`, which may contain further block comments, so a region that already contains
+    // one can still be commented out
+    fn lex_block_comment(&mut self) -> Result<Token, TokenizerError> {
+        let mut buffer = String::new();
+        let location = self.location;
+        let mut depth = 0;
+        loop {
+            let delimiter = match (self.source.front(), self.source.get(1)) {
+                (Some('/'), Some('*')) => Some(1),
+                (Some('*'), Some('/')) => Some(-1),
+                _ => None,
+            };
+            if let Some(change) = delimiter {
+                buffer.push(self.consume().unwrap());
+                buffer.push(self.consume().unwrap());
+                depth += change;
+                if depth == 0 {
+                    break;
+                }
+                continue;
+            }
+            match self.consume() {
+                Some(character) => buffer.push(character),
+                None => return Err(TokenizerError::UnterminatedBlockComment(location)),
+            }
+        }
+        Ok(Token {
+            data: TokenData::Comment(buffer),
+            location,
+        })
+    }
+
+    fn lex_string(&mut self) -> Result<Token, TokenizerError> {
+        let mut buffer = String::new();
+        let location = self.location;
+        self.consume();
+        loop {
+            let character_location = self.location;
+            match self.consume() {
+                Some('"') => break,
+                // the token keeps the escape as it is spelled, `unescape` decodes it
+                Some('\\') => {
+                    buffer.push('\\');
+                    let sequence = std::iter::from_fn(|| self.consume().inspect(|character| buffer.push(*character)));
+                    if let Err(sequence) = escaped(sequence) {
+                        return Err(match self.peek() {
+                            Some(_) => TokenizerError::InvalidEscape { sequence, location: character_location },
+                            None => TokenizerError::UnterminatedStringLiteral(location),
+                        });
+                    }
+                },
+                Some(character) => buffer.push(character),
+                None => return Err(TokenizerError::UnterminatedStringLiteral(location)),
+            }
+        };
+        Ok(Token {
+            data: TokenData::StringLiteral(buffer),
+            location,
+        })
+    }
+
     fn lex_symbol(&mut self) -> Result<Symbol, TokenizerError> {
         let character = self.consume()
             .ok_or(TokenizerError::UnrecognizedCharacter(0 as char))?;
@@ -182,6 +501,8 @@ impl TokenStream {
             ')' => Ok(Symbol::RParen),
             '{' => Ok(Symbol::LBrace),
             '}' => Ok(Symbol::RBrace),
+            '[' => Ok(Symbol::LBracket),
+            ']' => Ok(Symbol::RBracket),
             '<' => match self.peek().unwrap_or(0 as char) {
                 '=' => { self.consume(); Ok(Symbol::LesserEqual) },
                 _ => Ok(Symbol::LAngle)
@@ -197,10 +518,24 @@ impl TokenStream {
             }
             '=' => match self.peek().unwrap_or(0 as char) {
                 '=' => { self.consume(); Ok(Symbol::Equality) },
+                '>' => { self.consume(); Ok(Symbol::FatArrow) },
                 _ => Ok(Symbol::Equals)
             },
             ';' => Ok(Symbol::Semi),
+            ':' => Ok(Symbol::Colon),
             ',' => Ok(Symbol::Comma),
+            '?' => Ok(Symbol::Question),
+            '#' => Ok(Symbol::Hash),
+            '.' => match self.peek().unwrap_or(0 as char) {
+                '.' => {
+                    self.consume();
+                    match self.peek().unwrap_or(0 as char) {
+                        '=' => { self.consume(); Ok(Symbol::DotDotEq) },
+                        _ => Ok(Symbol::DotDot),
+                    }
+                },
+                _ => Err(TokenizerError::UnrecognizedCharacter('.')),
+            },
 
             '+' => match self.peek().unwrap_or(0 as char) {
                 '=' => { self.consume(); Ok(Symbol::PlusEq) },
@@ -208,6 +543,7 @@ impl TokenStream {
             }
             '-' => match self.peek().unwrap_or(0 as char) {
                 '=' => { self.consume(); Ok(Symbol::MinusEq) },
+                '>' => { self.consume(); Ok(Symbol::Arrow) },
                 _ => Ok(Symbol::Minus),
             }
             '*' => match self.peek().unwrap_or(0 as char) {
@@ -239,6 +575,24 @@ impl FallibleIterator for TokenStream {
                 Ok(Some(self.lex_identifier()))
             } else if character.is_numeric() {
                 Ok(Some(self.lex_number()))
+            } else if character == '"' {
+                Ok(Some(self.lex_string()?))
+            } else if character == '\'' && self.source.get(1).is_some_and(|next| next.is_alphabetic() || *next == '_') {
+                Ok(Some(self.lex_label()))
+            } else if character == '/' && self.source.get(1) == Some(&'/') {
+                let comment = self.lex_comment();
+                match self.keep_trivia {
+                    true => Ok(Some(comment)),
+                    false => continue,
+                }
+            } else if character == '/' && self.source.get(1) == Some(&'*') {
+                let comment = self.lex_block_comment()?;
+                match self.keep_trivia {
+                    true => Ok(Some(comment)),
+                    false => continue,
+                }
+            } else if character.is_whitespace() && self.keep_trivia {
+                Ok(Some(self.lex_whitespace()))
             } else if character.is_whitespace() {
                 self.consume();
                 continue;
@@ -255,6 +609,77 @@ impl FallibleIterator for TokenStream {
 }
 
 
+/// the tokens of a stream with a position that can be saved and gone back to, so a parser can try
+/// one reading of an ambiguous construct and fall back to another. tokens are kept once they are
+/// read, the stream is only asked for those not read yet
+pub struct TokenCursor {
+    stream: TokenStream,
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+/// a position of a `TokenCursor` to go back to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checkpoint(usize);
+
+impl TokenCursor {
+    pub fn new(stream: TokenStream) -> Self {
+        Self { stream, tokens: Vec::new(), position: 0 }
+    }
+
+    pub fn peek(&mut self) -> Result<Option<&Token>, TokenizerError> {
+        if self.position == self.tokens.len() {
+            match self.stream.next()? {
+                Some(token) => self.tokens.push(token),
+                None => return Ok(None),
+            }
+        }
+        Ok(self.tokens.get(self.position))
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.position)
+    }
+
+    pub fn rollback(&mut self, checkpoint: Checkpoint) {
+        self.position = checkpoint.0;
+    }
+
+    // the location just past the last token read, the start of the source before any are
+    pub fn last_end(&self) -> Location {
+        self.position.checked_sub(1).map_or(Location::default(), |last| self.tokens[last].end())
+    }
+
+    // the innermost `(`, `{` or `[` read so far that hasn't been closed yet. a closer that doesn't
+    // match the innermost one is passed over, the parser has already reported it
+    pub fn unclosed(&self) -> Option<Token> {
+        let mut open: Vec<(&Token, Symbol)> = Vec::new();
+        for token in &self.tokens[..self.position] {
+            let TokenData::Symbol(symbol) = token.data else { continue };
+            if let Some(closing) = symbol.closing() {
+                open.push((token, closing));
+            } else if open.last().is_some_and(|(_, closing)| *closing == symbol) {
+                open.pop();
+            }
+        }
+        open.pop().map(|(token, _)| token.clone())
+    }
+}
+
+impl FallibleIterator for TokenCursor {
+    type Item = Token;
+    type Error = TokenizerError;
+
+    fn next(&mut self) -> Result<Option<Self::Item>, Self::Error> {
+        let token = self.peek()?.cloned();
+        if token.is_some() {
+            self.position += 1;
+        }
+        Ok(token)
+    }
+}
+
+
 /********************************************************/
 /*                                                      */
 /*                         TESTS                        */
@@ -267,12 +692,16 @@ macro_rules! test_keyword_tokenizes {
         #[allow(non_snake_case)]
         fn $kwd() {
             use crate::tokenize::*;
-            let tokens: Result<Vec<_>, _> = stringify!($kwd).to_lowercase().tokenize().collect();
+            let tokens: Result<Vec<_>, _> = Keyword::$kwd.as_str().tokenize().collect();
             assert!(tokens.is_ok());
             let tokens = tokens.unwrap();
             assert_eq!(tokens.len(), 1);
             let token = tokens.get(0).unwrap().clone().data;
-            assert_eq!(token, TokenData::Keyword(Keyword::$kwd));
+            let expected = match Keyword::$kwd.is_contextual() {
+                true => TokenData::Identifier(Keyword::$kwd.as_str().into()),
+                false => TokenData::Keyword(Keyword::$kwd),
+            };
+            assert_eq!(token, expected);
         }
     };
 }
@@ -322,6 +751,106 @@ fn many_integer_literals_tokenize() {
     assert_eq!(token, TokenData::IntegerLiteral("789".into()));
 }
 
+#[test]
+fn string_literal_tokenizes() {
+    let tokens: Result<Vec<_>, _> = "\"hello, world\"".tokenize().collect();
+    assert!(tokens.is_ok());
+    let tokens = tokens.unwrap();
+    assert_eq!(tokens.len(), 1);
+    let token = tokens.first().unwrap().clone().data;
+    assert_eq!(token, TokenData::StringLiteral("hello, world".into()));
+}
+
+#[test]
+fn unterminated_string_literal_fails() {
+    let tokens: Result<Vec<_>, _> = "\"hello".tokenize().collect();
+    assert_eq!(tokens, Err(TokenizerError::UnterminatedStringLiteral(Location::default())));
+}
+
+#[test]
+fn string_escapes_are_decoded() {
+    let tokens: Result<Vec<_>, _> = r#""a\tb\n\x41\0\\\"""#.tokenize().collect();
+    let TokenData::StringLiteral(text) = tokens.unwrap().remove(0).data else { panic!("a string literal") };
+    assert_eq!(text, r#"a\tb\n\x41\0\\\""#);
+    assert_eq!(unescape(&text), "a\tb\nA\0\\\"");
+    assert_eq!(quoted(&unescape(&text)), r#""a\tb\nA\0\\\"""#);
+
+    let tokens: Result<Vec<_>, _> = r#"1 "a\qb""#.tokenize().collect();
+    assert_eq!(tokens, Err(TokenizerError::InvalidEscape { sequence: r"\q".into(), location: Location { index: 4, line: 1, column: 5 } }));
+    let tokens: Result<Vec<_>, _> = r#""\x80""#.tokenize().collect();
+    assert!(matches!(tokens, Err(TokenizerError::InvalidEscape { sequence, .. }) if sequence == r"\x80"));
+}
+
+#[test]
+fn loop_labels_tokenize() {
+    let tokens: Result<Vec<_>, _> = "'outer: loop break 'outer;".tokenize().collect();
+    let tokens: Vec<_> = tokens.unwrap().into_iter().map(|token| token.data).collect();
+    assert_eq!(tokens, vec![
+        TokenData::Label("outer".into()),
+        TokenData::Symbol(Symbol::Colon),
+        TokenData::Keyword(Keyword::Loop),
+        TokenData::Keyword(Keyword::Break),
+        TokenData::Label("outer".into()),
+        TokenData::Symbol(Symbol::Semi),
+    ]);
+}
+
+#[test]
+fn cursor_rolls_back_to_checkpoints() {
+    let mut cursor = TokenCursor::new("a = 1".tokenize());
+    assert_eq!(cursor.last_end(), Location::default());
+    let checkpoint = cursor.checkpoint();
+    assert_eq!(cursor.next().unwrap().map(|token| token.data), Some(TokenData::Identifier("a".into())));
+    assert_eq!(cursor.next().unwrap().map(|token| token.data), Some(TokenData::Symbol(Symbol::Equals)));
+    assert_eq!(cursor.last_end().index, 3);
+    cursor.rollback(checkpoint);
+    assert_eq!(cursor.last_end(), Location::default());
+    assert_eq!(cursor.peek().unwrap().map(|token| token.data.clone()), Some(TokenData::Identifier("a".into())));
+    assert_eq!(cursor.count().unwrap(), 3);
+}
+
+#[test]
+fn whitespace_is_kept_as_trivia() {
+    let tokens: Result<Vec<_>, _> = "let  x".tokenize().with_trivia().collect();
+    let tokens: Vec<_> = tokens.unwrap().into_iter().map(|token| token.data).collect();
+    assert_eq!(tokens, vec![
+        TokenData::Keyword(Keyword::Let),
+        TokenData::Whitespace("  ".into()),
+        TokenData::Identifier("x".into()),
+    ]);
+}
+
+#[test]
+fn comments_are_skipped() {
+    let tokens: Result<Vec<_>, _> = "1 // one\n2".tokenize().collect();
+    let tokens: Vec<_> = tokens.unwrap().into_iter().map(|token| token.data).collect();
+    assert_eq!(tokens, vec![
+        TokenData::IntegerLiteral("1".into()),
+        TokenData::IntegerLiteral("2".into()),
+    ]);
+}
+
+#[test]
+fn comments_are_kept_as_trivia() {
+    let tokens: Result<Vec<_>, _> = "// one\n".tokenize().with_trivia().collect();
+    let tokens: Vec<_> = tokens.unwrap().into_iter().map(|token| token.data).collect();
+    assert_eq!(tokens, vec![
+        TokenData::Comment("// one".into()),
+        TokenData::Whitespace("\n".into()),
+    ]);
+}
+
+#[test]
+fn block_comments_nest() {
+    let tokens: Result<Vec<_>, _> = "1 /* a /* b */ c */ 2".tokenize().with_trivia().collect();
+    let tokens: Vec<_> = tokens.unwrap().into_iter().map(|token| token.data).collect();
+    assert_eq!(tokens[2], TokenData::Comment("/* a /* b */ c */".into()));
+    assert_eq!(tokens[4], TokenData::IntegerLiteral("2".into()));
+
+    let tokens: Result<Vec<_>, _> = "1\n /* a /* b */".tokenize().collect();
+    assert_eq!(tokens, Err(TokenizerError::UnterminatedBlockComment(Location { index: 3, line: 2, column: 2 })));
+}
+
 #[test]
 fn identifier_tokenizes() {
     let tokens: Result<Vec<_>, _> = "main".tokenize().collect();
@@ -351,27 +880,43 @@ fn many_identifiers_tokenize() {
 
 mod keyword {
     test_keyword_tokenizes!(Exit);
+    test_keyword_tokenizes!(Panic);
+    test_keyword_tokenizes!(Unreachable);
+    test_keyword_tokenizes!(StaticAssert);
 
     test_keyword_tokenizes!(Let);
     test_keyword_tokenizes!(Mut);
 
     test_keyword_tokenizes!(If);
     test_keyword_tokenizes!(Else);
+    test_keyword_tokenizes!(Match);
 
     test_keyword_tokenizes!(Loop);
     test_keyword_tokenizes!(While);
+    test_keyword_tokenizes!(For);
+    test_keyword_tokenizes!(In);
 
     test_keyword_tokenizes!(Break);
     test_keyword_tokenizes!(Continue);
 
     test_keyword_tokenizes!(Fn);
     test_keyword_tokenizes!(Return);
+    test_keyword_tokenizes!(Const);
+
+    test_keyword_tokenizes!(Unsafe);
+    test_keyword_tokenizes!(As);
 }
 
 mod symbol {
     test_symbol_tokenizes!(Comma, ",");
     test_symbol_tokenizes!(Semi, ";");
     test_symbol_tokenizes!(Equals, "=");
+    test_symbol_tokenizes!(Question, "?");
+    test_symbol_tokenizes!(Hash, "#");
+    test_symbol_tokenizes!(DotDot, "..");
+    test_symbol_tokenizes!(DotDotEq, "..=");
+    test_symbol_tokenizes!(FatArrow, "=>");
+    test_symbol_tokenizes!(Arrow, "->");
 
     test_symbol_tokenizes!(Plus, "+");
     test_symbol_tokenizes!(Minus, "-");
@@ -408,7 +953,7 @@ fn maths_expression_tokenizes() {
 
 #[test]
 fn brackets_tokenize() {
-    let tokens: Result<Vec<_>, _> = "( ) { }".tokenize().collect();
+    let tokens: Result<Vec<_>, _> = "( ) { } [ ]".tokenize().collect();
     assert!(tokens.is_ok());
     let tokens = tokens.unwrap();
 
@@ -417,6 +962,8 @@ fn brackets_tokenize() {
         TokenData::Symbol(Symbol::RParen),
         TokenData::Symbol(Symbol::LBrace),
         TokenData::Symbol(Symbol::RBrace),
+        TokenData::Symbol(Symbol::LBracket),
+        TokenData::Symbol(Symbol::RBracket),
     ];
 
     assert_eq!(tokens.len(), expected_tokens.len());
@@ -466,3 +1013,9 @@ fn assignment_operators_tokenize() {
     }
 }
 
+#[test]
+fn token_ends_follow_their_text() {
+    let tokens: Vec<_> = "let x\n  = \"a\nb\";".tokenize().collect().unwrap();
+    let ends: Vec<_> = tokens.iter().map(|token| (token.end().index, token.end().line, token.end().column)).collect();
+    assert_eq!(ends, vec![(3, 1, 4), (5, 1, 6), (9, 2, 4), (15, 3, 3), (16, 3, 4)]);
+}
@@ -0,0 +1,90 @@
+use std::path::{Path, PathBuf};
+
+use crate::CompileError;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tool {
+    Nasm,
+    Ld,
+    Objdump,
+}
+
+impl Tool {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Nasm => "nasm",
+            Self::Ld => "ld",
+            Self::Objdump => "objdump",
+        }
+    }
+
+    pub fn install_hint(&self) -> String {
+        let package = match self {
+            Self::Nasm => "nasm",
+            Self::Ld | Self::Objdump => "binutils",
+        };
+        if cfg!(target_os = "macos") {
+            format!("install it with `brew install {package}`")
+        } else if cfg!(target_os = "windows") {
+            format!("install it inside WSL with `sudo apt install {package}`")
+        } else {
+            format!("install it with your package manager, e.g. `sudo apt install {package}` or `sudo dnf install {package}`")
+        }
+    }
+
+    pub fn locate(&self) -> Option<PathBuf> {
+        find_executable(self.name(), &std::env::var_os("PATH")?)
+    }
+}
+
+
+fn find_executable(name: &str, path: &std::ffi::OsStr) -> Option<PathBuf> {
+    std::env::split_paths(path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| is_executable(candidate))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+
+pub fn preflight() -> Result<(), CompileError> {
+    for tool in [Tool::Nasm, Tool::Ld] {
+        if tool.locate().is_none() {
+            return Err(CompileError::ToolchainMissing {
+                tool: tool.name().to_string(),
+                hint: tool.install_hint(),
+            });
+        }
+    }
+    Ok(())
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn missing_executables_are_not_found() {
+    let path = std::env::temp_dir();
+    assert_eq!(find_executable("beryllium-no-such-tool", path.as_os_str()), None);
+}
+
+#[cfg(unix)]
+#[test]
+fn executables_are_found_on_the_path() {
+    let path = std::ffi::OsString::from("/nonexistent:/bin:/usr/bin");
+    assert!(find_executable("sh", &path).is_some());
+}
@@ -0,0 +1,156 @@
+use crate::{
+    ast::{Expr, Intrinsic, Item, Program, Statement},
+    codegen::x86::bits::Bits,
+    node_map::{nodes, NodeId, NodeMap, NodeRef},
+};
+
+
+// puts a `trace` before each statement that runs, giving the line it starts on. the tree has to be
+// the one the map was made for, as statements are matched to their spans by their ids. functions
+// that aren't compiled for 64 bits have no runtime to print with and `const` functions are run by
+// the compiler, so both are left as they are
+pub fn insert_traces(program: Program, map: &NodeMap, bits: Bits) -> Program {
+    let lines: Vec<u64> = nodes(&program)
+        .into_iter()
+        .enumerate()
+        .filter(|(_, node)| matches!(node, NodeRef::Statement(_)))
+        .map(|(id, _)| map.span(NodeId(id)).map_or(0, |span| span.start.line))
+        .collect();
+    let mut lines = lines.into_iter();
+
+    let items = program.0
+        .into_iter()
+        .map(|item| match item {
+            Item::Function { name, params, return_type, body, attributes, is_const } => {
+                let bits = Bits::from_attributes(&attributes).ok().flatten().unwrap_or(bits);
+                let mut tracer = Tracer { lines: &mut lines, enabled: bits == Bits::Bits64 && !is_const };
+                let body = tracer.body(body);
+                Item::Function { name, params, return_type, body, attributes, is_const }
+            },
+            Item::Const { identifier, value, location } => {
+                let value = Tracer { lines: &mut lines, enabled: false }.expr(value);
+                Item::Const { identifier, value, location }
+            },
+        })
+        .collect();
+    Program(items)
+}
+
+
+struct Tracer<'a, I: Iterator<Item = u64>> {
+    lines: &'a mut I,
+    enabled: bool,
+}
+
+impl<I: Iterator<Item = u64>> Tracer<'_, I> {
+    // statements are numbered after the statements inside them, so their lines are taken in the
+    // same order
+    fn statement(&mut self, statement: Statement) -> (Statement, Option<u64>) {
+        let statement = match statement {
+            Statement::Let { identifier, ty, value, is_mutable } => Statement::Let { identifier, ty, value: value.map(|value| self.expr(value)), is_mutable },
+            Statement::Exit { value } => Statement::Exit { value: self.expr(value) },
+            Statement::Expr(value) => Statement::Expr(self.expr(value)),
+            Statement::Return(value) => Statement::Return(value.map(|value| self.expr(value))),
+            Statement::Const { identifier, value, location } => Statement::Const { identifier, value: self.expr(value), location },
+            Statement::StaticAssert { condition, message, location } => Statement::StaticAssert { condition: self.expr(condition), message, location },
+            statement @ (Statement::Panic { .. } | Statement::Unreachable { .. } | Statement::Break(_) | Statement::Continue(_)) => statement,
+        };
+        let line = self.lines.next().unwrap_or(0);
+        let traced = match &statement {
+            Statement::Const { .. } | Statement::StaticAssert { .. }
+                | Statement::Expr(Expr::Block(_) | Expr::Unsafe(_)) => false,
+            _ => self.enabled,
+        };
+        (statement, traced.then_some(line))
+    }
+
+    // a statement that stands on its own, such as the body of an `if`, is put in a block with its
+    // trace. a lone `let` would go out of scope with the block, but it also can't be used
+    fn body(&mut self, body: Statement) -> Statement {
+        match self.statement(body) {
+            (body, None) | (body @ Statement::Let { .. }, _) => body,
+            (body, Some(line)) => Statement::Expr(Expr::Block(vec![trace(line), body])),
+        }
+    }
+
+    fn expr(&mut self, expr: Expr) -> Expr {
+        let mut operand = |expr: Box<Expr>| Box::new(self.expr(*expr));
+        match expr {
+            Expr::Add(a, b) => { let a = operand(a); Expr::Add(a, operand(b)) },
+            Expr::Sub(a, b) => { let a = operand(a); Expr::Sub(a, operand(b)) },
+            Expr::Mul(a, b) => { let a = operand(a); Expr::Mul(a, operand(b)) },
+            Expr::Div(a, b) => { let a = operand(a); Expr::Div(a, operand(b)) },
+            Expr::Mod(a, b) => { let a = operand(a); Expr::Mod(a, operand(b)) },
+            Expr::Equality(a, b) => { let a = operand(a); Expr::Equality(a, operand(b)) },
+            Expr::NonEquality(a, b) => { let a = operand(a); Expr::NonEquality(a, operand(b)) },
+            Expr::Less(a, b) => { let a = operand(a); Expr::Less(a, operand(b)) },
+            Expr::LessEq(a, b) => { let a = operand(a); Expr::LessEq(a, operand(b)) },
+            Expr::Greater(a, b) => { let a = operand(a); Expr::Greater(a, operand(b)) },
+            Expr::GreaterEq(a, b) => { let a = operand(a); Expr::GreaterEq(a, operand(b)) },
+
+            Expr::Assign { identifier, value } => Expr::Assign { identifier, value: operand(value) },
+            Expr::AddAssign { identifier, value } => Expr::AddAssign { identifier, value: operand(value) },
+            Expr::SubAssign { identifier, value } => Expr::SubAssign { identifier, value: operand(value) },
+            Expr::MulAssign { identifier, value } => Expr::MulAssign { identifier, value: operand(value) },
+            Expr::DivAssign { identifier, value } => Expr::DivAssign { identifier, value: operand(value) },
+            Expr::ModAssign { identifier, value } => Expr::ModAssign { identifier, value: operand(value) },
+            Expr::IndexAssign { identifier, index, value, location } => {
+                let index = operand(index);
+                Expr::IndexAssign { identifier, index, value: operand(value), location }
+            },
+            Expr::Try(value) => Expr::Try(operand(value)),
+            Expr::Neg(value) => Expr::Neg(operand(value)),
+            Expr::Cast { value, ty } => Expr::Cast { value: operand(value), ty },
+
+            Expr::FunctionCall { name, args } => Expr::FunctionCall { name, args: args.into_iter().map(|arg| self.expr(arg)).collect() },
+            Expr::Intrinsic { intrinsic, args } => Expr::Intrinsic { intrinsic, args: args.into_iter().map(|arg| self.expr(arg)).collect() },
+            expr @ (Expr::IntegerLiteral(_) | Expr::StringLiteral(_) | Expr::Identifier(_)) => expr,
+            Expr::Array(elements) => Expr::Array(elements.into_iter().map(|element| self.expr(element)).collect()),
+            Expr::Index { identifier, index, location } => Expr::Index { identifier, index: operand(index), location },
+
+            Expr::Block(stmts) => Expr::Block(stmts
+                .into_iter()
+                .flat_map(|statement| match self.statement(statement) {
+                    (statement, Some(line)) => vec![trace(line), statement],
+                    (statement, None) => vec![statement],
+                })
+                .collect()
+            ),
+            Expr::If { check, body, els } => {
+                let check = operand(check);
+                let body = Box::new(self.body(*body));
+                let els = els.map(|els| Box::new(self.body(*els)));
+                Expr::If { check, body, els }
+            },
+            Expr::Loop { label, body } => Expr::Loop { label, body: Box::new(self.body(*body)) },
+            Expr::While { label, check, body } => {
+                let check = operand(check);
+                Expr::While { label, check, body: Box::new(self.body(*body)) }
+            },
+            Expr::Unsafe(body) => Expr::Unsafe(operand(body)),
+        }
+    }
+}
+
+fn trace(line: u64) -> Statement {
+    Statement::Expr(Expr::Intrinsic { intrinsic: Intrinsic::Trace, args: vec![Expr::IntegerLiteral(line.to_string())] })
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn statements_are_traced_with_their_line() {
+    use crate::{parser::Parser, tokenize::Tokenize};
+    let source = "fn _start() {\n    let x = 1;\n    if (x > 0)\n        exit(x);\n    exit(0);\n}\n\n#[bits(32)]\nfn f() return 1;\n";
+    let (program, map) = Parser::new(source.tokenize()).parse_with_map().expect("source parses");
+    let program = insert_traces(program, &map, Bits::Bits64);
+    let Item::Function { body, .. } = &program.0[0] else { panic!("a function") };
+    assert_eq!(body.to_string(), "{ trace(2); let x = 1; trace(3); if (x > 0) { trace(4); exit(x); } trace(5); exit(0); }");
+    let Item::Function { body, .. } = &program.0[1] else { panic!("a function") };
+    assert_eq!(body.to_string(), "return 1;");
+}
@@ -1,6 +1,11 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
-use crate::ast;
+use crate::{
+    CompileError,
+    ast,
+    callgraph::CallGraph,
+    visit::{walk_expr, walk_item, walk_statement, Visitor},
+};
 
 
 #[derive(Clone, Debug)]
@@ -9,27 +14,157 @@ pub struct Param {
 }
 
 
+// a function as an interface written by `--emit-meta` declares it
+#[derive(Clone, Debug)]
+pub struct Signature {
+    pub name: String,
+    pub params: Vec<Param>,
+    pub return_type: Option<ast::Type>,
+}
+
+
 #[derive(Clone, Debug)]
 pub struct Function {
     pub params: Vec<Param>,
+    pub attributes: Vec<ast::Attribute>,
+    pub body: Option<ast::Statement>,
+    pub is_const: bool,
+    // defined by a separately compiled module and resolved when linking
+    pub is_extern: bool,
+    // as written after `->`, if it was
+    pub return_type: Option<ast::Type>,
+    pub returns: ReturnKind,
+}
+
+
+// whether a function gives its caller a value, one that never does can leave early with a bare
+// `return;`. falling off the end gives no value either way
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReturnKind {
+    Value,
+    Void,
+}
+
+impl ReturnKind {
+    fn of(body: &ast::Statement) -> Self {
+        struct Returns(bool);
+        impl Visitor for Returns {
+            fn visit_statement(&mut self, statement: &ast::Statement) {
+                self.0 |= matches!(statement, ast::Statement::Return(Some(_)));
+                walk_statement(self, statement);
+            }
+        }
+        let mut returns = Returns(false);
+        returns.visit_statement(body);
+        match returns.0 {
+            true => Self::Value,
+            false => Self::Void,
+        }
+    }
+}
+
+impl Function {
+    pub fn get_attribute(&self, name: &str) -> Option<&ast::Attribute> {
+        self.attributes.iter().find(|attribute| attribute.name == name)
+    }
+
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.get_attribute(name).is_some()
+    }
+
+    pub fn inline(&self) -> ast::InlineHint {
+        ast::InlineHint::from(self.attributes.as_slice())
+    }
 }
 
 
 #[derive(Clone, Debug)]
 pub struct TypeRegistry {
-    functions: HashMap<String, Function>,
+    // ordered so that anything generated by walking the functions comes out the same every build
+    functions: BTreeMap<String, Function>,
 }
 
 impl TypeRegistry {
     pub fn get_function(&self, name: impl Into<String>) -> Option<&Function> {
         self.functions.get(&name.into())
     }
+
+    pub fn functions(&self) -> impl Iterator<Item = (&String, &Function)> {
+        self.functions.iter()
+    }
+
+    // a function of the program itself takes precedence over one of the same name elsewhere
+    pub fn declare_extern(&mut self, Signature { name, params, return_type }: Signature) {
+        self.functions.entry(name).or_insert(Function {
+            params,
+            attributes: Vec::new(),
+            body: None,
+            is_const: false,
+            is_extern: true,
+            return_type,
+            returns: match return_type {
+                Some(ast::Type::Unit) => ReturnKind::Void,
+                _ => ReturnKind::Value,
+            },
+        });
+    }
+
+    // the interface of the functions this module exports, one per line after the header, e.g.
+    // `fn add a b -> i32`. a function that gives no value is written `-> ()` even if the source
+    // doesn't say so, one without a type that does has no `->`
+    pub fn to_meta(&self) -> String {
+        let mut meta = format!("{META_HEADER}\n");
+        for (name, function) in self.functions.iter().filter(|(_, function)| function.has_attribute("export")) {
+            meta += &format!("fn {name}");
+            function.params.iter().for_each(|param| meta += &format!(" {}", param.name));
+            match (function.return_type, function.returns) {
+                (Some(ty), _) => meta += &format!(" -> {ty}"),
+                (None, ReturnKind::Void) => meta += &format!(" -> {}", ast::Type::Unit),
+                (None, ReturnKind::Value) => (),
+            }
+            meta += "\n";
+        }
+        meta
+    }
+
+    pub fn parse_meta(meta: &str) -> Option<Vec<Signature>> {
+        let mut lines = meta.lines();
+        if lines.next()? != META_HEADER {
+            return None;
+        }
+        lines
+            .map(|line| {
+                let (signature, return_type) = match line.split_once(" -> ") {
+                    Some((signature, "()")) => (signature, Some(ast::Type::Unit)),
+                    Some((signature, ty)) => (signature, Some(ast::Type::from_name(ty)?)),
+                    None => (line, None),
+                };
+                let mut words = signature.split(' ');
+                if words.next()? != "fn" {
+                    return None;
+                }
+                let name = words.next()?.to_string();
+                Some(Signature { name, params: words.map(|name| Param { name: name.to_string() }).collect(), return_type })
+            })
+            .collect()
+    }
 }
 
+// bumped whenever the format changes, so an interface written by an older compiler is rejected
+// rather than misread
+const META_HEADER: &str = "beryllium-meta 2";
+
 impl From<&ast::Program> for TypeRegistry {
     fn from(program: &ast::Program) -> Self {
-        let mut registry = Self { functions: HashMap::new() };
+        let mut registry = Self { functions: BTreeMap::new() };
         program.register_types(&mut registry);
+
+        // recursive functions are always called rather than expanded in place
+        let call_graph = CallGraph::from(program);
+        registry.functions
+            .iter_mut()
+            .filter(|(name, _)| call_graph.is_recursive(name))
+            .for_each(|(_, function)| function.body = None);
         registry
     }
 }
@@ -48,15 +183,192 @@ impl TypeHolder for ast::Program {
 impl TypeHolder for ast::Item {
     fn register_types(&self, registry: &mut TypeRegistry) {
         match self {
-            Self::Function { name, params, body: _ } => registry.functions.insert(
+            Self::Function { name, params, return_type, body, attributes, is_const } => registry.functions.insert(
                 name.clone(),
                 Function {
                     params: params.iter()
                           .map(|param| Param { name: param.name.clone() })
-                          .collect()
+                          .collect(),
+                    attributes: attributes.clone(),
+                    body: match ast::InlineHint::from(attributes.as_slice()) {
+                        ast::InlineHint::Always => Some(body.clone()),
+                        ast::InlineHint::Default | ast::InlineHint::Never => None,
+                    },
+                    is_const: *is_const,
+                    is_extern: false,
+                    return_type: *return_type,
+                    returns: match return_type {
+                        Some(ast::Type::Unit) => ReturnKind::Void,
+                        Some(_) => ReturnKind::Value,
+                        None => ReturnKind::of(body),
+                    },
                 }
             ),
+            Self::Const { .. } => None,
         };
     }
 }
 
+
+// checks the program against the `-> ()` it declares: such a function can't `return` a value and
+// its calls can't be used as one. a bare `return;` in an `-> i64` function is found by codegen,
+// as for any function that returns a value
+pub fn check_return_types(program: &ast::Program, registry: &TypeRegistry) -> Result<(), CompileError> {
+    let mut checker = ReturnTypes { registry, function: None, error: None };
+    program.0.iter().for_each(|item| checker.visit_item(item));
+    checker.error.map_or(Ok(()), Err)
+}
+
+struct ReturnTypes<'a> {
+    registry: &'a TypeRegistry,
+    // the function being checked, if it is declared `-> ()`
+    function: Option<String>,
+    error: Option<CompileError>,
+}
+
+impl ReturnTypes<'_> {
+    fn returns_unit(&self, name: &str) -> bool {
+        self.registry.get_function(name).is_some_and(|function| function.return_type == Some(ast::Type::Unit))
+    }
+
+    // the statement's value is used, as the last one of a block or a branch that gives a value
+    fn visit_value(&mut self, statement: &ast::Statement) {
+        match statement {
+            ast::Statement::Expr(value) => self.visit_expr(value),
+            statement => self.visit_statement(statement),
+        }
+    }
+
+    // an expression standing on its own, whose value is dropped
+    fn visit_discarded(&mut self, expr: &ast::Expr) {
+        match expr {
+            ast::Expr::FunctionCall { name: _, args } => args.iter().for_each(|arg| self.visit_expr(arg)),
+            ast::Expr::Block(stmts) => stmts.iter().for_each(|statement| self.visit_statement(statement)),
+            ast::Expr::If { check, body, els } => {
+                self.visit_expr(check);
+                self.visit_statement(body);
+                els.iter().for_each(|els| self.visit_statement(els));
+            },
+            ast::Expr::Unsafe(body) => self.visit_discarded(body),
+            expr => self.visit_expr(expr),
+        }
+    }
+}
+
+impl Visitor for ReturnTypes<'_> {
+    fn visit_item(&mut self, item: &ast::Item) {
+        self.function = match item {
+            ast::Item::Function { name, .. } if self.returns_unit(name) => Some(name.clone()),
+            ast::Item::Function { .. } | ast::Item::Const { .. } => None,
+        };
+        walk_item(self, item)
+    }
+
+    fn visit_statement(&mut self, statement: &ast::Statement) {
+        match (statement, &self.function) {
+            (ast::Statement::Return(Some(_)), Some(function)) => {
+                self.error.get_or_insert(CompileError::UnexpectedReturnValue(function.clone()));
+            },
+            (ast::Statement::Expr(value), _) => return self.visit_discarded(value),
+            _ => (),
+        }
+        walk_statement(self, statement)
+    }
+
+    fn visit_expr(&mut self, expr: &ast::Expr) {
+        match expr {
+            ast::Expr::FunctionCall { name, args: _ } if self.returns_unit(name) => {
+                self.error.get_or_insert(CompileError::NoReturnValue(name.clone()));
+            },
+            ast::Expr::Block(stmts) => {
+                if let Some((last, stmts)) = stmts.split_last() {
+                    stmts.iter().for_each(|statement| self.visit_statement(statement));
+                    self.visit_value(last);
+                }
+                return;
+            },
+            ast::Expr::If { check, body, els } => {
+                self.visit_expr(check);
+                self.visit_value(body);
+                els.iter().for_each(|els| self.visit_value(els));
+                return;
+            },
+            _ => (),
+        }
+        walk_expr(self, expr)
+    }
+}
+
+
+/********************************************************/
+/*                                                      */
+/*                         TESTS                        */
+/*                                                      */
+/********************************************************/
+
+#[test]
+fn exported_functions_round_trip_through_meta() {
+    use crate::{parser::Parser, tokenize::Tokenize};
+    let source = "#[export] fn add(a, b) return a + b; #[export] fn low(a) -> u8 return a; #[export] fn log(a) { } fn helper() return 1;";
+    let program = Parser::new(source.tokenize()).parse().expect("source parses");
+    let meta = TypeRegistry::from(&program).to_meta();
+    assert_eq!(meta, "beryllium-meta 2\nfn add a b\nfn log a -> ()\nfn low a -> u8\n");
+
+    let mut registry = TypeRegistry::from(&Parser::new("fn _start() exit(add(1, 2));".tokenize()).parse().expect("source parses"));
+    TypeRegistry::parse_meta(&meta).expect("meta parses").into_iter().for_each(|signature| registry.declare_extern(signature));
+    let add = registry.get_function("add").expect("add is declared");
+    assert!(add.is_extern);
+    assert_eq!(add.params.len(), 2);
+    assert_eq!((add.return_type, add.returns), (None, ReturnKind::Value));
+    let low = registry.get_function("low").expect("low is declared");
+    assert_eq!((low.return_type, low.returns), (Some(ast::Type::U8), ReturnKind::Value));
+    let log = registry.get_function("log").expect("log is declared");
+    assert_eq!((log.return_type, log.returns), (Some(ast::Type::Unit), ReturnKind::Void));
+    assert!(TypeRegistry::parse_meta("fn add a b\n").is_none());
+    assert!(TypeRegistry::parse_meta("beryllium-meta 1\nfn add a b\n").is_none());
+    assert!(TypeRegistry::parse_meta("beryllium-meta 2\nfn add a b -> f64\n").is_none());
+}
+
+#[test]
+fn inline_hints_follow_their_argument() {
+    use crate::{ast::InlineHint, parser::Parser, tokenize::Tokenize};
+    let source = "#[inline] fn a() return 1; #[inline(always)] fn b() return 1; #[inline(never)] fn c() return 1; #[noinline] fn d() return 1;";
+    let registry = TypeRegistry::from(&Parser::new(source.tokenize()).parse().expect("source parses"));
+    let hint = |name: &str| registry.get_function(name).expect("the function is declared").inline();
+    assert_eq!([hint("a"), hint("b"), hint("c"), hint("d")], [InlineHint::Always, InlineHint::Always, InlineHint::Never, InlineHint::Never]);
+}
+
+#[test]
+fn registries_are_shared_between_threads() {
+    use crate::{parser::Parser, tokenize::Tokenize};
+    use std::sync::Arc;
+    fn shareable<T: Send + Sync>() {}
+    shareable::<crate::Diagnostic>();
+
+    let registry = Arc::new(TypeRegistry::from(&Parser::new("fn f(a) return a;".tokenize()).parse().expect("source parses")));
+    let threads: Vec<_> = (0..2)
+        .map(|_| {
+            let registry = Arc::clone(&registry);
+            std::thread::spawn(move || registry.get_function("f").map(|function| function.params.len()))
+        })
+        .collect();
+    for thread in threads {
+        assert_eq!(thread.join().expect("thread finishes"), Some(1));
+    }
+}
+
+#[test]
+fn return_types_are_checked() {
+    use crate::{parser::Parser, tokenize::Tokenize};
+    let check = |source: &str| {
+        let program = Parser::new(source.tokenize()).parse().expect("source parses");
+        check_return_types(&program, &TypeRegistry::from(&program))
+    };
+    assert!(check("fn f(x) -> () { if (x) return; } fn g() -> i64 { f(1); { f(2); } return 1; } fn _start() exit(g());").is_ok());
+    assert!(matches!(check("fn f() -> () return 1;"), Err(CompileError::UnexpectedReturnValue(name)) if name == "f"));
+    assert!(matches!(check("fn f() -> () {} fn _start() exit(f());"), Err(CompileError::NoReturnValue(name)) if name == "f"));
+    assert!(matches!(check("fn f() -> () {} fn g(x) return if (x) { 1 } else { f() };"), Err(CompileError::NoReturnValue(_))));
+    assert_eq!(TypeRegistry::from(&Parser::new("fn f() -> i64 exit(1);".tokenize()).parse().expect("source parses"))
+        .get_function("f")
+        .map(|function| function.returns), Some(ReturnKind::Value));
+}
@@ -0,0 +1,80 @@
+use crate::ast::{Expr, Item, Statement};
+
+
+pub trait Visitor {
+    fn visit_item(&mut self, item: &Item) {
+        walk_item(self, item)
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        walk_statement(self, statement)
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr)
+    }
+}
+
+
+pub fn walk_item<V: Visitor + ?Sized>(visitor: &mut V, item: &Item) {
+    match item {
+        Item::Function { name: _, params: _, return_type: _, body, attributes: _, is_const: _ } => visitor.visit_statement(body),
+        Item::Const { identifier: _, value, location: _ } => visitor.visit_expr(value),
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &Statement) {
+    match statement {
+        Statement::Exit { value } => visitor.visit_expr(value),
+        Statement::Expr(value) => visitor.visit_expr(value),
+        Statement::Let { identifier: _, ty: _, value, is_mutable: _ } => value.iter().for_each(|value| visitor.visit_expr(value)),
+        Statement::Return(value) => value.iter().for_each(|value| visitor.visit_expr(value)),
+        Statement::Const { identifier: _, value, location: _ } => visitor.visit_expr(value),
+        Statement::StaticAssert { condition, message: _, location: _ } => visitor.visit_expr(condition),
+        Statement::Panic { .. } | Statement::Unreachable { .. }
+            | Statement::Break(_) | Statement::Continue(_) => (),
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match expr {
+        Expr::Add(a, b) | Expr::Sub(a, b) | Expr::Mul(a, b) | Expr::Div(a, b) | Expr::Mod(a, b)
+            | Expr::Equality(a, b) | Expr::NonEquality(a, b)
+            | Expr::Less(a, b) | Expr::LessEq(a, b)
+            | Expr::Greater(a, b) | Expr::GreaterEq(a, b) => {
+            visitor.visit_expr(a);
+            visitor.visit_expr(b);
+        },
+
+        Expr::Assign { identifier: _, value } | Expr::AddAssign { identifier: _, value } | Expr::SubAssign { identifier: _, value }
+            | Expr::MulAssign { identifier: _, value } | Expr::DivAssign { identifier: _, value }
+            | Expr::ModAssign { identifier: _, value } => visitor.visit_expr(value),
+
+        Expr::IndexAssign { identifier: _, index, value, location: _ } => {
+            visitor.visit_expr(index);
+            visitor.visit_expr(value);
+        },
+
+        Expr::FunctionCall { name: _, args } | Expr::Intrinsic { intrinsic: _, args } | Expr::Array(args) =>
+            args.iter().for_each(|arg| visitor.visit_expr(arg)),
+        Expr::Try(value) | Expr::Neg(value) | Expr::Cast { value, ty: _ }
+            | Expr::Index { identifier: _, index: value, location: _ } => visitor.visit_expr(value),
+
+        Expr::IntegerLiteral(_) | Expr::StringLiteral(_) | Expr::Identifier(_) => (),
+
+        Expr::Block(stmts) => stmts.iter().for_each(|stmt| visitor.visit_statement(stmt)),
+        Expr::If { check, body, els } => {
+            visitor.visit_expr(check);
+            visitor.visit_statement(body);
+            if let Some(els) = els {
+                visitor.visit_statement(els);
+            }
+        },
+        Expr::Loop { label: _, body } => visitor.visit_statement(body),
+        Expr::Unsafe(body) => visitor.visit_expr(body),
+        Expr::While { label: _, check, body } => {
+            visitor.visit_expr(check);
+            visitor.visit_statement(body);
+        },
+    }
+}
@@ -0,0 +1,236 @@
+use beryllium::{
+    FallibleIterator,
+    ast::{Expr, Intrinsic, Item, Statement},
+    node_map::{nodes, NodeRef},
+    parser::{Edition, LiteralOverflow, Parser, ParseError, ParseWarning},
+    tokenize::{Keyword, TokenData, Tokenize},
+};
+
+
+#[test]
+fn tokens_are_available_to_tools() {
+    let tokens: Vec<_> = "let x = 1;".tokenize().collect().expect("source tokenizes");
+    assert_eq!(tokens[0].data, TokenData::Keyword(Keyword::Let));
+    assert_eq!(tokens[1].location.column, 5);
+}
+
+#[test]
+fn programs_are_available_to_tools() {
+    let program = Parser::new("fn _start() exit(1 + 2);".tokenize()).parse().expect("source parses");
+    let Item::Function { name, body, .. } = &program.0[0] else { panic!("a function") };
+    assert_eq!(name, "_start");
+    match body {
+        Statement::Exit { value: Expr::Add(_, _) } => (),
+        body => panic!("unexpected body {body:?}"),
+    }
+    assert_eq!(body.to_string(), "exit(1 + 2);");
+}
+
+#[test]
+fn mixed_operators_associate_left() {
+    let program = Parser::new("fn _start() exit(8 - 4 + 2 - 1 * 6 / 3 % 2);".tokenize()).parse().expect("source parses");
+    let Item::Function { body, .. } = &program.0[0] else { panic!("a function") };
+    assert_eq!(body.to_string(), "exit(((8 - 4) + 2) - (((1 * 6) / 3) % 2));");
+}
+
+#[test]
+fn nodes_can_be_found_by_offset() {
+    let source = "fn _start() {\n    let x = 1;\n    exit(x);\n}\n";
+    let (program, map) = Parser::new(source.tokenize()).parse_with_map().expect("source parses");
+    let id = map.node_at(source.find("exit").unwrap() as u64).expect("a node");
+    assert!(matches!(nodes(&program)[id.0], NodeRef::Statement(Statement::Exit { .. })));
+    let span = map.span(id).unwrap();
+    assert_eq!((span.start.line, span.start.column, span.end.column), (3, 5, 13));
+}
+
+#[test]
+fn intrinsics_are_parsed_by_name() {
+    let program = Parser::new("fn _start() exit(volatile_load(4096));".tokenize()).parse().expect("source parses");
+    let Item::Function { body, .. } = &program.0[0] else { panic!("a function") };
+    match body {
+        Statement::Exit { value: Expr::Intrinsic { intrinsic: Intrinsic::VolatileLoad, args } } => assert_eq!(args.len(), 1),
+        body => panic!("unexpected body {body:?}"),
+    }
+    assert!(Parser::new("fn _start() volatile_store(4096);".tokenize()).parse().is_err());
+}
+
+#[test]
+fn for_loops_desugar_to_while() {
+    let source = "fn _start() for i in 0..3 exit(i);";
+    let (program, map) = Parser::new(source.tokenize()).parse_with_map().expect("source parses");
+    let Item::Function { body, .. } = &program.0[0] else { panic!("a function") };
+    assert_eq!(
+        body.to_string(),
+        "{ let mut i#next = 0; let i#end = 3; while (i#next < i#end) { let i = i#next; i#next += 1; exit(i); } }",
+    );
+    assert_eq!(map.len(), nodes(&program).len());
+    let id = map.node_at(source.find("exit").unwrap() as u64).expect("a node");
+    assert!(matches!(nodes(&program)[id.0], NodeRef::Statement(Statement::Exit { .. })));
+}
+
+#[test]
+fn match_desugars_to_guarded_ifs() {
+    let source = "fn _start() match (3) { 1..=2 if 1 => exit(1); _ => exit(2); }";
+    let (program, map) = Parser::new(source.tokenize()).parse_with_map().expect("source parses");
+    let Item::Function { body, .. } = &program.0[0] else { panic!("a function") };
+    assert_eq!(
+        body.to_string(),
+        "{ let match#value = 3; let mut match#done = 0; \
+        if (match#done == 0) if (match#value >= 1) if (match#value <= 2) if (1) { match#done += 1; exit(1); } \
+        if (match#done == 0) { match#done += 1; exit(2); } }",
+    );
+    assert_eq!(map.len(), nodes(&program).len());
+}
+
+#[test]
+fn match_patterns_are_checked() {
+    let parse = |source: &str| Parser::new(source.tokenize()).parse();
+    assert!(matches!(parse("fn _start() match (3) { 0..3 => exit(1); }"), Err(ParseError::NonExhaustiveMatch(_))));
+    assert!(matches!(parse("fn _start() match (3) { 0 if 1 => exit(1); _ if 1 => exit(2); }"), Err(ParseError::NonExhaustiveMatch(_))));
+    assert!(matches!(parse("fn _start() match (3) { 0..3 => exit(1); 1..=2 => exit(2); _ => exit(3); }"), Err(ParseError::UnreachablePattern(_))));
+    assert!(matches!(parse("fn _start() match (3) { 5..5 => exit(1); _ => exit(2); }"), Err(ParseError::UnreachablePattern(_))));
+    assert!(matches!(parse("fn _start() match (3) { _ => exit(1); 0 => exit(2); }"), Err(ParseError::UnreachablePattern(_))));
+    assert!(parse("fn _start() match (3) { 0..3 if 1 => exit(1); 0..=1 => exit(2); 2 => exit(3); _ => exit(4); }").is_ok());
+}
+
+#[test]
+fn contextual_keywords_can_be_names() {
+    let source = "fn match(in) return in; fn _start() { let match = match(2); for in in 0..match match (in) { _ => exit(match(in)); } }";
+    let program = Parser::new(source.tokenize()).parse().expect("source parses");
+    let Item::Function { params, .. } = &program.0[0] else { panic!("a function") };
+    assert_eq!(params[0].name, "in");
+    let Item::Function { body, .. } = &program.0[1] else { panic!("a function") };
+    let Statement::Expr(Expr::Block(stmts)) = body else { panic!("a block") };
+    assert_eq!(stmts[0].to_string(), "let match = match(2);");
+    assert!(stmts[1].to_string().contains("let match#value = in;"));
+}
+
+#[test]
+fn literals_must_fit_in_64_bits() {
+    let parse = |source: &str, overflow| Parser::new(source.tokenize()).with_literal_overflow(overflow).parse().map(|program| format!("{program:?}"));
+    assert!(parse("fn _start() exit(9223372036854775807);", LiteralOverflow::Deny).is_ok());
+    assert!(matches!(parse("fn _start() exit(9223372036854775808);", LiteralOverflow::Deny), Err(ParseError::LiteralOutOfRange { .. })));
+    assert!(parse("fn _start() exit(18446744073709551615);", LiteralOverflow::Wrap).unwrap().contains("IntegerLiteral(\"-1\")"));
+    assert!(matches!(parse("fn _start() exit(18446744073709551616);", LiteralOverflow::Wrap), Err(ParseError::LiteralOutOfRange { .. })));
+}
+
+#[test]
+fn minus_negates_literals_and_expressions() {
+    let parse = |source: &str| Parser::new(source.tokenize()).parse().map(|program| format!("{program:?}"));
+    assert!(parse("fn _start() exit(-9223372036854775808);").unwrap().contains("IntegerLiteral(\"-9223372036854775808\")"));
+    assert!(matches!(parse("fn _start() exit(-9223372036854775809);"), Err(ParseError::LiteralOutOfRange { .. })));
+    assert!(parse("fn f(x) return -x * 2;").unwrap().contains("Mul(Neg(Identifier(\"x\")), IntegerLiteral(\"2\"))"));
+    assert!(parse("fn f(x) return -x?;").unwrap().contains("Neg(Try(Identifier(\"x\")))"));
+    assert!(parse("fn f(x) return x - -1;").unwrap().contains("Sub(Identifier(\"x\"), IntegerLiteral(\"-1\"))"));
+}
+
+#[test]
+fn parentheses_group_expressions() {
+    let program = Parser::new("fn f(x) return (x + 1) * -(2 - x);".tokenize()).parse().expect("source parses");
+    let Item::Function { body, .. } = &program.0[0] else { panic!("a function") };
+    assert!(matches!(body, Statement::Return(Some(Expr::Mul(a, b))) if matches!(**a, Expr::Add(_, _)) && matches!(**b, Expr::Neg(_))));
+    assert_eq!(body.to_string(), "return (x + 1) * -(2 - x);");
+
+    // printed expressions read back the same
+    let source = format!("fn _start() {};", "exit(((8 - 4) + 2) - (((1 * 6) / 3) % 2))");
+    let program = Parser::new(source.as_str().tokenize()).parse().expect("source parses");
+    let Item::Function { body, .. } = &program.0[0] else { panic!("a function") };
+    assert_eq!(body.to_string(), "exit(((8 - 4) + 2) - (((1 * 6) / 3) % 2));");
+    assert!(Parser::new("fn f(x) return (x + 1;".tokenize()).parse().is_err());
+}
+
+#[test]
+fn the_last_expression_of_a_block_needs_no_semicolon() {
+    let program = Parser::new("fn f(c) { let x = if (c) { 1 } else { 2 }; return x; }".tokenize()).parse().expect("source parses");
+    let Item::Function { body, .. } = &program.0[0] else { panic!("a function") };
+    assert_eq!(body.to_string(), "{ let x = if (c) { 1; } else { 2; }; return x; }");
+    assert!(Parser::new("fn f(c) { let x = 1 return x; }".tokenize()).parse().is_err());
+}
+
+#[test]
+fn only_mutable_variables_can_be_declared_without_a_value() {
+    let parse = |source: &str| Parser::new(source.tokenize()).parse();
+    assert!(parse("fn _start() { let mut x; x = 1; exit(x); }").is_ok());
+    assert!(matches!(parse("fn _start() { let x; exit(0); }"), Err(ParseError::UnexpectedToken(_))));
+}
+
+#[test]
+fn comparisons_cannot_be_chained() {
+    let parse = |source: &str| Parser::new(source.tokenize()).parse();
+    assert!(matches!(
+        parse("fn _start() if (0 < 5 < 10) exit(1);"),
+        Err(ParseError::ChainedComparison(location)) if location.column == 23,
+    ));
+    assert!(matches!(parse("fn _start() exit(1 == 1 != 0);"), Err(ParseError::ChainedComparison(_))));
+    assert!(parse("fn _start() exit(1 + 2 < 4);").is_ok());
+}
+
+#[test]
+fn conditions_without_parentheses_need_braces() {
+    let parse = |source: &str| Parser::new(source.tokenize()).parse();
+    let braced = parse("fn _start() { while 1 < 2 { exit(1); } if 1 { exit(2); } else if (0) exit(3); else { exit(4); } }");
+    let parenthesized = parse("fn _start() { while (1 < 2) { exit(1); } if (1) { exit(2); } else if (0) exit(3); else { exit(4); } }");
+    assert_eq!(braced.map(|program| format!("{program:?}")).ok(), parenthesized.map(|program| format!("{program:?}")).ok());
+    assert!(matches!(parse("fn _start() if 1 exit(2);"), Err(ParseError::UnexpectedToken(_))));
+    assert!(matches!(parse("fn _start() if 1 { exit(2); } else exit(3);"), Err(ParseError::UnexpectedToken(_))));
+}
+
+#[test]
+fn editions_select_the_grammar() {
+    let parse = |source: &str, edition| Parser::new(source.tokenize()).with_edition(edition).parse();
+    assert!(parse("fn _start() if 1 { exit(2); }", Edition::V2).is_ok());
+    assert!(matches!(parse("fn _start() if 1 { exit(2); }", Edition::V1), Err(ParseError::UnexpectedToken(_))));
+    assert!(parse("fn _start() if (1) { exit(2); }", Edition::V1).is_ok());
+    assert!(matches!(parse("#version 1\nfn _start() while 1 { exit(2); }", Edition::V2), Err(ParseError::UnexpectedToken(_))));
+    assert!(parse("#version 2\nfn _start() while 1 { exit(2); }", Edition::V1).is_ok());
+    assert!(matches!(parse("#version 3\nfn _start() exit(2);", Edition::V2), Err(ParseError::UnexpectedToken(_))));
+}
+
+#[test]
+fn deeply_nested_code_is_rejected() {
+    // unoptimized, the parser takes several times the stack per level that it does in release
+    let parse = |source: String| std::thread::Builder::new()
+        .stack_size(32 * 1024 * 1024)
+        .spawn(move || Parser::new(source.as_str().tokenize()).parse())
+        .expect("thread spawns")
+        .join()
+        .expect("parser finishes");
+    let chain = |terms: usize| format!("fn _start() exit({});", vec!["1"; terms].join(" + "));
+    assert!(parse(chain(200)).is_ok());
+    assert!(matches!(parse(chain(300)), Err(ParseError::TooDeep(location)) if location.column == 1036));
+    let parens = format!("fn _start() exit({}1{});", "(".repeat(300), ")".repeat(300));
+    assert!(matches!(parse(parens), Err(ParseError::TooDeep(_))));
+    let blocks = format!("fn _start() {}exit(1);{}", "{ ".repeat(300), " }".repeat(300));
+    assert!(matches!(parse(blocks), Err(ParseError::TooDeep(_))));
+}
+
+#[test]
+fn dangling_else_is_warned_about() {
+    let warnings = |source: &str| {
+        let mut parser = Parser::new(source.tokenize());
+        parser.parse().expect("source parses");
+        parser.warnings().to_vec()
+    };
+    assert!(matches!(warnings("fn f(a, b) if (a) if (b) exit(1); else exit(2);").as_slice(), [ParseWarning::DanglingElse(location)] if location.column == 19));
+    assert!(warnings("fn f(a, b) if (a) { if (b) exit(1); } else exit(2);").is_empty());
+    assert!(warnings("fn f(a, b) if (a) if (b) exit(1); else exit(2); else exit(3);").is_empty());
+}
+
+#[test]
+fn intrinsics_can_be_listed() {
+    let popcount = beryllium::INTRINSICS.iter().find(|def| def.name == "popcount").expect("popcount is listed");
+    assert_eq!((popcount.arity, popcount.safety), (1, beryllium::Safety::Pure));
+    assert!(beryllium::INTRINSICS.iter().filter(|def| def.safety == beryllium::Safety::Unsafe).all(|def| !def.summary.is_empty()));
+}
+
+#[test]
+fn cancelled_builds_stop_between_passes() {
+    let target = std::env::temp_dir().join("beryllium_api_cancelled");
+    let cancellation = beryllium::CancellationToken::new();
+    let args = beryllium::CompileArgs::new("examples/grouping.be")
+        .with_target_file(&target)
+        .with_cancellation(cancellation.clone());
+    cancellation.cancel();
+    assert!(matches!(beryllium::compile(&args), Err(beryllium::CompileError::Cancelled)));
+    assert!(!target.with_extension("asm").exists());
+}
@@ -0,0 +1,421 @@
+use std::process::Command;
+
+
+fn cli() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_beryllium_cli"))
+}
+
+// these tests assemble and link, which needs the toolchain and an x86-64 linux host
+fn can_build() -> bool {
+    let can_build = beryllium::can_build_and_run();
+    if !can_build {
+        println!("    needs a built binary, skipped");
+    }
+    can_build
+}
+
+
+#[test]
+fn compile_errors_exit_nonzero_with_their_fix() {
+    let target = std::env::temp_dir().join("beryllium_cli_compile_error");
+    let output = cli()
+        .arg("compile")
+        .arg("examples/variable_mutability_invalid.be")
+        .arg(&target)
+        .output()
+        .expect("cli runs");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).expect("utf-8 stderr");
+    assert_eq!(stderr.lines().count(), 2);
+    assert!(stderr.starts_with("error[BE0003]: cannot assign to immutable variable"));
+    assert_eq!(stderr.lines().nth(1), Some("  = help: make `x` mutable"));
+}
+
+#[test]
+fn verbose_errors_include_detail() {
+    let target = std::env::temp_dir().join("beryllium_cli_verbose_error");
+    let output = cli()
+        .arg("compile")
+        .arg("--verbose")
+        .arg("examples/variable_mutability_invalid.be")
+        .arg(&target)
+        .output()
+        .expect("cli runs");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).expect("utf-8 stderr");
+    assert!(stderr.contains("ChangedImmutableVariable"));
+}
+
+#[test]
+fn color_always_styles_errors() {
+    let target = std::env::temp_dir().join("beryllium_cli_color_error");
+    let output = cli()
+        .arg("compile")
+        .arg("--color=always")
+        .arg("examples/variable_mutability_invalid.be")
+        .arg(&target)
+        .output()
+        .expect("cli runs");
+    let stderr = String::from_utf8(output.stderr).expect("utf-8 stderr");
+    assert!(stderr.starts_with("\x1b[1;31merror[BE0003]\x1b[0m: "));
+}
+
+#[test]
+fn lang_translates_errors() {
+    let target = std::env::temp_dir().join("beryllium_cli_lang_error");
+    let output = cli()
+        .arg("compile")
+        .arg("--lang=de")
+        .arg("examples/variable_mutability_invalid.be")
+        .arg(&target)
+        .output()
+        .expect("cli runs");
+    let stderr = String::from_utf8(output.stderr).expect("utf-8 stderr");
+    assert!(stderr.starts_with("error[BE0003]: der unveränderlichen Variable"));
+}
+
+#[test]
+fn highlight_prints_html() {
+    let output = cli()
+        .arg("highlight")
+        .arg("examples/exit_simple.be")
+        .output()
+        .expect("cli runs");
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).expect("utf-8 stdout");
+    assert!(stdout.starts_with("<pre class=\"beryllium\">"));
+    assert!(stdout.contains("<span class=\"be-keyword\">exit</span>"));
+}
+
+#[test]
+fn refactor_rename_prints_with_dry_run() {
+    let output = cli()
+        .args(["refactor", "rename", "--dry-run", "x", "code", "examples/exit_variable.be"])
+        .output()
+        .expect("cli runs");
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).expect("utf-8 stdout");
+    assert!(stdout.contains("exit(code);"));
+}
+
+#[test]
+fn stats_lists_each_function() {
+    let output = cli()
+        .arg("stats")
+        .arg("examples/function_call_args.be")
+        .output()
+        .expect("cli runs");
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).expect("utf-8 stdout");
+    assert!(stdout.starts_with("functions: 2\n"));
+    assert!(stdout.contains("\nmain "));
+}
+
+#[test]
+fn explain_describes_error_codes() {
+    let output = cli().args(["explain", "BE0003"]).output().expect("cli runs");
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).expect("utf-8 stdout");
+    assert!(stdout.contains("let mut x = 1;"));
+
+    let output = cli().args(["explain", "BE9999"]).output().expect("cli runs");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn reproducible_builds_match() {
+    if !can_build() {
+        return;
+    }
+    let target = std::env::temp_dir().join("beryllium_cli_reproducible");
+    let output = cli()
+        .arg("compile")
+        .arg("--reproducible")
+        .arg("--debug-info")
+        .arg("examples/function_inline.be")
+        .arg(&target)
+        .output()
+        .expect("cli runs");
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).expect("utf-8 stdout");
+    assert!(stdout.contains("rebuilding to check reproducibility"));
+}
+
+#[test]
+fn inspect_reads_build_info() {
+    if !can_build() {
+        return;
+    }
+    let target = std::env::temp_dir().join("beryllium_cli_inspect");
+    let output = cli()
+        .args(["compile", "-O", "0", "examples/exit_simple.be"])
+        .arg(&target)
+        .output()
+        .expect("cli runs");
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = cli().arg("inspect").arg(&target).output().expect("cli runs");
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).expect("utf-8 stdout");
+    assert!(stdout.starts_with(&format!("version: {}\n", env!("CARGO_PKG_VERSION"))));
+    assert!(stdout.contains("opt-level: 0\n"));
+
+    let output = cli().arg("inspect").arg("examples/exit_simple.be").output().expect("cli runs");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn inspect_disasm_checks_symbols() {
+    if !can_build() {
+        return;
+    }
+    let target = std::env::temp_dir().join("beryllium_cli_disasm");
+    let output = cli().args(["compile", "examples/function_call.be"]).arg(&target).output().expect("cli runs");
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = cli()
+        .args(["inspect", "--disasm", "--source", "examples/function_call.be"])
+        .arg(&target)
+        .output()
+        .expect("cli runs");
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).expect("utf-8 stdout");
+    assert!(stdout.contains("<_start>:"));
+    assert!(stdout.contains("checked against examples/function_call.be"));
+
+    let output = cli()
+        .args(["inspect", "--disasm", "--source", "examples/exit_simple.be"])
+        .arg(&target)
+        .output()
+        .expect("cli runs");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("error[BE0015]"));
+}
+
+#[test]
+fn entry_and_section_start_reach_the_linker() {
+    if !can_build() {
+        return;
+    }
+    let dir = std::env::temp_dir();
+    let source = dir.join("beryllium_cli_entry.be");
+    std::fs::write(&source, "fn kmain() exit(3);\nfn _start() exit(2);\n").expect("source is written");
+    let target = dir.join("beryllium_cli_entry");
+    let output = cli()
+        .args(["compile", "--entry", "kmain", "--section-start", ".text=0x800000"])
+        .arg(&source)
+        .arg(&target)
+        .output()
+        .expect("cli runs");
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(Command::new(&target).status().expect("binary runs").code(), Some(3));
+
+    let output = cli().args(["inspect", "--disasm", "--source"]).arg(&source).arg(&target).output().expect("cli runs");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("0000000000800000 <"));
+}
+
+#[test]
+fn sixteen_bit_functions_assemble() {
+    if !can_build() {
+        return;
+    }
+    let dir = std::env::temp_dir();
+    let source = dir.join("beryllium_cli_bits.be");
+    std::fs::write(&source, "fn add(a, b) return a + b;\nfn boot() { let mut x = add(1, 2); while (x < 10) x += 1; loop {} }\n")
+        .expect("source is written");
+    let target = dir.join("beryllium_cli_bits");
+    let output = cli()
+        .args(["compile", "--bits", "16", "--entry", "boot", "--section-start", ".text=0x7c00"])
+        .arg(&source)
+        .arg(&target)
+        .output()
+        .expect("cli runs");
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    let asm = std::fs::read_to_string(target.with_extension("asm")).expect("assembly is kept");
+    assert!(asm.contains("[bits 16]\nboot:\n"));
+
+    let output = cli().args(["inspect"]).arg(&target).output().expect("cli runs");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("bits: 16\n"));
+}
+
+#[test]
+fn detected_features_give_the_same_result() {
+    if !can_build() {
+        return;
+    }
+    let target = std::env::temp_dir().join("beryllium_cli_detect_features");
+    let output = cli()
+        .args(["compile", "-O", "0", "--cpu", "i386", "--detect-features", "examples/intrinsic_popcount.be"])
+        .arg(&target)
+        .output()
+        .expect("cli runs");
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(Command::new(&target).status().expect("binary runs").code(), Some(110));
+
+    let output = cli().args(["inspect"]).arg(&target).output().expect("cli runs");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("detect-features: true\n"));
+}
+
+#[test]
+fn verify_pic_reports_absolute_references() {
+    if !can_build() {
+        return;
+    }
+    let dir = std::env::temp_dir();
+    let source = dir.join("beryllium_cli_pic.be");
+    std::fs::write(&source, "fn _start() exit(0);\n\n#[export]\n#[bits(32)]\nfn greeting() return \"hi\";\n").expect("source is written");
+    let output = cli().args(["compile", "--verify-pic"]).arg(&source).arg(dir.join("beryllium_cli_pic")).output().expect("cli runs");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("error[BE0030]: `lea eax, [rel str00000000]` in `greeting` at 3:1"));
+
+    let output = cli()
+        .args(["compile", "--verify-pic", "examples/string_literal.be"])
+        .arg(dir.join("beryllium_cli_pic"))
+        .output()
+        .expect("cli runs");
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn verify_checks_the_binary_against_the_assembly() {
+    if !can_build() {
+        return;
+    }
+    let target = std::env::temp_dir().join("beryllium_cli_verify");
+    let output = cli().args(["compile", "--verify", "examples/if_else_true.be"]).arg(&target).output().expect("cli runs");
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("instructions match the binary\n"));
+}
+
+#[test]
+fn stack_protector_keeps_programs_working() {
+    if !can_build() {
+        return;
+    }
+    let target = std::env::temp_dir().join("beryllium_cli_stack_protector");
+    let output = cli()
+        .args(["compile", "-O", "0", "--stack-protector", "examples/entry_return.be"])
+        .arg(&target)
+        .output()
+        .expect("cli runs");
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(Command::new(&target).status().expect("binary runs").code(), Some(12));
+
+    let output = cli().args(["inspect"]).arg(&target).output().expect("cli runs");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("stack-protector: true\n"));
+}
+
+#[test]
+fn profiles_count_calls_per_function() {
+    if !can_build() {
+        return;
+    }
+    let dir = std::env::temp_dir().join("beryllium_cli_profile");
+    std::fs::create_dir_all(&dir).expect("directory is created");
+    let target = dir.join("function_return_loop");
+    let output = cli()
+        .args(["compile", "-O", "0", "--instrument=profile", "examples/function_return_loop.be"])
+        .arg(&target)
+        .output()
+        .expect("cli runs");
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(Command::new(&target).current_dir(&dir).status().expect("binary runs").code(), Some(7));
+
+    let output = cli().args(["prof", "report"]).arg(dir.join("beryllium.prof")).output().expect("cli runs");
+    let report = String::from_utf8_lossy(&output.stdout);
+    assert!(report.starts_with("function       calls          cycles     cycles/call\n"), "{report}");
+    assert!(report.lines().any(|line| line.starts_with("find  ") && line.split_whitespace().nth(1) == Some("1")), "{report}");
+
+    let output = cli().args(["prof", "report"]).arg(&target).output().expect("cli runs");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is not a profile written by a beryllium binary"));
+}
+
+#[test]
+fn traces_print_each_statement_as_it_runs() {
+    if !can_build() {
+        return;
+    }
+    let target = std::env::temp_dir().join("beryllium_cli_trace");
+    let output = cli()
+        .args(["compile", "--instrument=trace", "examples/function_return_loop.be"])
+        .arg(&target)
+        .output()
+        .expect("cli runs");
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    let output = Command::new(&target).output().expect("binary runs");
+    assert_eq!(output.status.code(), Some(7));
+    let trace = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = trace.lines().map(|line| line.trim_start_matches("examples/function_return_loop.be:")).collect();
+    assert_eq!(lines[..6], ["4", "8", "9", "10", "11", "12"]);
+    assert_eq!(lines.iter().filter(|line| **line == "13").count(), 1);
+}
+
+#[test]
+fn modules_call_each_other_through_their_meta() {
+    if !can_build() {
+        return;
+    }
+    let dir = std::env::temp_dir().join("beryllium_cli_meta");
+    std::fs::create_dir_all(&dir).expect("directory is created");
+    std::fs::write(dir.join("lib.be"), "#[export]\nfn add(a, b) return a + b;\n#[export]\nfn log(a) -> () { }\n").expect("source is written");
+    std::fs::write(dir.join("main.be"), "fn _start() exit(add(40, 2));\n").expect("source is written");
+
+    let output = cli().args(["compile", "--emit-meta"]).arg(dir.join("lib.be")).output().expect("cli runs");
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    let meta = std::fs::read_to_string(dir.join("lib.bemeta")).expect("meta is written");
+    assert_eq!(meta, "beryllium-meta 2\nfn add a b\nfn log a -> ()\n");
+
+    let output = cli().args(["compile", "--extern"]).arg(dir.join("lib.bemeta")).arg(dir.join("main.be")).output().expect("cli runs");
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(Command::new(dir.join("main")).status().expect("binary runs").code(), Some(42));
+
+    std::fs::write(dir.join("log.be"), "fn _start() exit(log(1));\n").expect("source is written");
+    let output = cli().args(["compile", "--extern"]).arg(dir.join("lib.bemeta")).arg(dir.join("log.be")).output().expect("cli runs");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("error[BE0040]"));
+
+    let output = cli().args(["compile", "--extern"]).arg(dir.join("lib.be")).arg(dir.join("main.be")).output().expect("cli runs");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is not an interface written by `--emit-meta`"));
+}
+
+#[test]
+fn fatal_warnings_fail_the_build() {
+    let source = std::env::temp_dir().join("beryllium_cli_fatal_warnings.be");
+    std::fs::write(&source, "fn _start() {\n    let a = 1;\n    let b = 0;\n    if (a) if (b) exit(1); else exit(2);\n    exit(0);\n}\n").expect("source is written");
+    let target = std::env::temp_dir().join("beryllium_cli_fatal_warnings");
+    let output = cli().arg("compile").arg("--fatal-warnings").arg(&source).arg(&target).output().expect("cli runs");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).expect("utf-8 stderr");
+    assert!(stderr.starts_with("error: "), "{stderr}");
+    assert!(stderr.contains("= note: warnings are errors with `--fatal-warnings`"));
+    assert!(stderr.contains("error[BE0041]"));
+}
+
+#[test]
+fn missing_semicolons_are_all_reported() {
+    let source = std::env::temp_dir().join("beryllium_cli_missing_semicolons.be");
+    std::fs::write(&source, "fn _start() {\n    let x = 1\n    let y = x + 1\n    exit(y);\n}\n").expect("source is written");
+    let target = std::env::temp_dir().join("beryllium_cli_missing_semicolons");
+    let output = cli().arg("compile").arg(&source).arg(&target).output().expect("cli runs");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).expect("utf-8 stderr");
+    let errors: Vec<&str> = stderr.lines().filter(|line| line.starts_with("error")).collect();
+    assert_eq!(errors, [
+        "error[BE0043]: expected `;` after the statement ending at 2:14",
+        "error[BE0043]: expected `;` after the statement ending at 3:18",
+        "error[BE0044]: stopping because of 2 syntax error(s)",
+    ]);
+}
+
+#[test]
+fn fix_applies_suggestions() {
+    let source = std::env::temp_dir().join("beryllium_cli_fix.be");
+    std::fs::write(&source, "fn _start() {\n    let x = 0\n    x += 1;\n    exit(x);\n}\n").expect("source is written");
+    let output = cli().arg("fix").arg(&source).output().expect("cli runs");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stderr).expect("utf-8 stderr"), "applied 2 fix(es)\n");
+    let fixed = std::fs::read_to_string(&source).expect("source is readable");
+    assert_eq!(fixed, "fn _start() {\n    let mut x = 0;\n    x += 1;\n    exit(x);\n}\n");
+}
@@ -1,6 +1,44 @@
 
+// the header at the top of an example, lines such as `//! exit: 20` saying what running it should
+// give. each `stdout` line is a line the example prints, without them its output isn't checked
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Header {
+    exit: Option<i32>,
+    stdout: Option<String>,
+}
+
+impl Header {
+    fn read(source: &str) -> Self {
+        let mut header = Self::default();
+        for line in source.lines().map_while(|line| line.strip_prefix("//!")) {
+            let Some((key, value)) = line.split_once(':') else { continue };
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match key.trim() {
+                "exit" => header.exit = Some(value.trim().parse().expect("the exit code is a number")),
+                "stdout" => header.stdout.get_or_insert_with(String::new).push_str(&format!("{value}\n")),
+                key => panic!("unknown header `{key}`"),
+            }
+        }
+        header
+    }
+}
+
+
+// examples are run by the interpreter where they can't be built and run, or when
+// BERYLLIUM_INTERPRET is set. it can't check their output, and skips those that need the binary
+fn interpret() -> bool {
+    std::env::var_os("BERYLLIUM_INTERPRET").is_some() || !beryllium::can_build_and_run()
+}
+
+
 macro_rules! valid_example {
+    ($name:ident) => {
+        valid_example!(@run $name, None);
+    };
     ($name:ident, $exit_code:tt) => {
+        valid_example!(@run $name, Some($exit_code));
+    };
+    (@run $name:ident, $exit_code:expr) => {
         #[test]
         fn $name() {
             let examples_dir = PathBuf::from("examples");
@@ -19,19 +57,84 @@ macro_rules! valid_example {
             let target_file = build_dir.join(example);
             println!("{example}");
             assert!(example_file.exists());
+            let header = Header::read(&read_to_string(&example_file).expect("example is readable"));
+            let exit_code: Option<i32> = $exit_code;
+            let exit_code = exit_code.or(header.exit).expect("an exit code in the header");
             let compile_args = beryllium::CompileArgs {
                 source_file: example_file,
                 target_file: Some(target_file.clone()),
+                ..Default::default()
             };
             let compile_result = beryllium::compile(&compile_args);
             println!("        {compile_result:?}");
+            if interpret() {
+                // the toolchain is only looked for once codegen is done, so that is checked either way
+                assert!(matches!(compile_result, Ok(_) | Err(beryllium::CompileError::ToolchainMissing { .. })));
+                println!("    interpreting");
+                match beryllium::interpret(&compile_args).expect("example is valid") {
+                    Some(code) => assert_eq!(code, exit_code),
+                    None => println!("        can't be interpreted, skipped"),
+                }
+                return;
+            }
             assert!(compile_result.is_ok());
             println!("    runnning");
             let output = Command::new(target_file).output().expect("executable runs correctly");
             let code = output.status.code();
             println!("        {code:?}");
             assert!(code.is_some());
+            assert_eq!(code.unwrap(), exit_code);
+            if let Some(stdout) = header.stdout {
+                assert_eq!(String::from_utf8_lossy(&output.stdout), stdout);
+            }
+            println!("    SUCCESS\n");
+        }
+    };
+}
+
+
+macro_rules! test_harness_example {
+    ($name:ident, $exit_code:tt) => {
+        #[test]
+        fn $name() {
+            let examples_dir = PathBuf::from("examples");
+            let build_dir = PathBuf::from("examples/build");
+            if !build_dir.exists() {
+                match create_dir(&build_dir) {
+                    Ok(_) => (),
+                    Err(err) => if !matches!(err.kind(), std::io::ErrorKind::AlreadyExists) {
+                        Result::<(), std::io::Error>::Err(err).unwrap()
+                    }
+                }
+            }
+
+            let example = stringify!($name.be);
+            let example_file = examples_dir.join(example);
+            let target_file = build_dir.join(format!("{}_test", stringify!($name)));
+            println!("{example}");
+            assert!(example_file.exists());
+            let header = Header::read(&read_to_string(&example_file).expect("example is readable"));
+            let compile_args = beryllium::CompileArgs {
+                source_file: example_file,
+                target_file: Some(target_file.clone()),
+                ..Default::default()
+            };
+            if interpret() {
+                println!("    needs a built binary, skipped");
+                return;
+            }
+            let compile_result = beryllium::compile_tests(&compile_args);
+            println!("        {compile_result:?}");
+            assert!(compile_result.is_ok());
+            println!("    runnning");
+            let output = Command::new(target_file).output().expect("test harness runs correctly");
+            let code = output.status.code();
+            println!("        {code:?}");
+            assert!(code.is_some());
             assert_eq!(code.unwrap(), $exit_code);
+            if let Some(stdout) = header.stdout {
+                assert_eq!(String::from_utf8_lossy(&output.stdout), stdout);
+            }
             println!("    SUCCESS\n");
         }
     };
@@ -41,11 +144,16 @@ macro_rules! valid_example {
 macro_rules! invalid_example {
     ($name:ident, $err:pat) => {
         #[test]
-        fn invalid_variable_mutability_fails() {
+        fn $name() {
             let examples_dir = PathBuf::from("examples");
             let build_dir = PathBuf::from("examples/build");
             if !build_dir.exists() {
-                create_dir(&build_dir).expect("failed to create build dir");
+                match create_dir(&build_dir) {
+                    Ok(_) => (),
+                    Err(err) => if !matches!(err.kind(), std::io::ErrorKind::AlreadyExists) {
+                        Result::<(), std::io::Error>::Err(err).unwrap()
+                    }
+                }
             }
 
             let example = stringify!($name.be);
@@ -56,6 +164,7 @@ macro_rules! invalid_example {
             let compile_args = beryllium::CompileArgs {
                 source_file: example_file,
                 target_file: Some(target_file),
+                ..Default::default()
             };
             let compile_result = beryllium::compile(&compile_args);
             println!("        {compile_result:?}");
@@ -71,60 +180,136 @@ macro_rules! invalid_example {
 
 mod example {
     use std::{
-        fs::create_dir,
+        fs::{create_dir, read_to_string},
         path::PathBuf,
         process::Command,
     };
 
-    valid_example!(exit_failure, 20);
-    valid_example!(exit_simple, 0);
-    valid_example!(exit_variable, 20);
-    valid_example!(let_simple, 0);
-    valid_example!(let_variable_value, 10);
-    valid_example!(maths_add_simple, 3);
-    valid_example!(maths_add_three_way, 6);
-    valid_example!(maths_add_variables, 6);
-    valid_example!(maths_div_remainder, 5);
-    valid_example!(maths_div_simple, 5);
-    valid_example!(maths_mod_simple, 0);
-    valid_example!(maths_mul_simple, 6);
-    valid_example!(maths_mul_variables, 8);
-    valid_example!(maths_sub_simple, 1);
-    valid_example!(maths_sub_three_way, 0);
-    valid_example!(maths_sub_variables, 2);
-    valid_example!(if_simple_true, 1);
-    valid_example!(if_simple_false, 0);
-    valid_example!(if_else_true, 0);
-    valid_example!(if_else_false, 1);
-    valid_example!(variable_scoping, 5);
-    valid_example!(variable_scoping_multiple, 3);
-    valid_example!(block, 3);
-    valid_example!(block_if, 3);
-    valid_example!(comparison_equality_true, 0);
-    valid_example!(comparison_equality_false, 1);
-    valid_example!(comparison_nonequality_true, 0);
-    valid_example!(comparison_nonequality_false, 1);
-    valid_example!(comparison_lesser_true, 0);
-    valid_example!(comparison_lesser_false_eq, 1);
-    valid_example!(comparison_lesser_false, 1);
-    valid_example!(comparison_lesser_equal_true, 0);
-    valid_example!(comparison_lesser_equal_true_eq, 0);
-    valid_example!(comparison_lesser_equal_false, 1);
-    valid_example!(comparison_greater_true, 0);
-    valid_example!(comparison_greater_false_eq, 1);
-    valid_example!(comparison_greater_false, 1);
-    valid_example!(comparison_greater_equal_true, 0);
-    valid_example!(comparison_greater_equal_true_eq, 0);
-    valid_example!(comparison_greater_equal_false, 1);
-    valid_example!(variable_mutability_valid, 1);
-    valid_example!(iteration_while, 10);
-    valid_example!(iteration_loop, 10);
-    valid_example!(iteration_continue, 10);
-    valid_example!(function_call, 1);
-    valid_example!(function_call_arg, 2);
-    valid_example!(function_call_args, 3);
-    valid_example!(function_return, 4);
+    use super::{interpret, Header};
+
+    valid_example!(exit_failure);
+    valid_example!(exit_simple);
+    valid_example!(exit_variable);
+    valid_example!(let_simple);
+    valid_example!(let_variable_value);
+    valid_example!(let_deferred);
+    valid_example!(assign_overwrite);
+    valid_example!(maths_add_simple);
+    valid_example!(maths_add_three_way);
+    valid_example!(maths_add_variables);
+    valid_example!(maths_div_remainder);
+    valid_example!(maths_div_signed);
+    valid_example!(maths_div_simple);
+    valid_example!(maths_mod_simple);
+    valid_example!(maths_mul_simple);
+    valid_example!(maths_mul_variables);
+    valid_example!(maths_sub_simple);
+    valid_example!(maths_sub_three_way);
+    valid_example!(maths_sub_variables);
+    valid_example!(if_simple_true);
+    valid_example!(if_without_parens);
+    valid_example!(if_simple_false);
+    valid_example!(if_else_true);
+    valid_example!(if_else_false);
+    valid_example!(variable_scoping);
+    valid_example!(variable_scoping_multiple);
+    valid_example!(block);
+    valid_example!(block_if);
+    valid_example!(comparison_equality_true);
+    valid_example!(comparison_equality_false);
+    valid_example!(comparison_nonequality_true);
+    valid_example!(comparison_nonequality_false);
+    valid_example!(comparison_lesser_true);
+    valid_example!(comparison_lesser_false_eq);
+    valid_example!(comparison_lesser_false);
+    valid_example!(comparison_lesser_equal_true);
+    valid_example!(comparison_lesser_equal_true_eq);
+    valid_example!(comparison_lesser_equal_false);
+    valid_example!(comparison_greater_true);
+    valid_example!(comparison_greater_false_eq);
+    valid_example!(comparison_greater_false);
+    valid_example!(comparison_greater_equal_true);
+    valid_example!(comparison_greater_equal_true_eq);
+    valid_example!(comparison_greater_equal_false);
+    valid_example!(variable_mutability_valid);
+    valid_example!(iteration_while);
+    valid_example!(iteration_loop);
+    valid_example!(iteration_continue);
+    valid_example!(iteration_for);
+    valid_example!(iteration_for_array);
+    valid_example!(iteration_labelled);
+    valid_example!(match_ranges);
+    valid_example!(function_call);
+    valid_example!(function_call_arg);
+    valid_example!(function_call_args);
+    valid_example!(function_call_arg_order);
+    valid_example!(function_return);
+    valid_example!(function_return_loop);
+    valid_example!(function_return_void);
+    valid_example!(function_return_type);
+    valid_example!(cast_widths);
+    valid_example!(let_typed);
+    valid_example!(function_inline);
+    valid_example!(function_inline_recursive);
+    valid_example!(function_attributes);
+    valid_example!(function_const);
+    valid_example!(const_local);
+    valid_example!(const_global);
+    valid_example!(try_success);
+    valid_example!(try_error);
+    valid_example!(panic_message);
+    valid_example!(array_index);
+    valid_example!(array_index_out_of_bounds);
+    valid_example!(unreachable_trap);
+    valid_example!(unreachable_reached);
+    valid_example!(test_harness, 3);
+    valid_example!(stack_slot_reuse);
+    valid_example!(thread_spawn);
+    valid_example!(intrinsic_swap);
+    valid_example!(intrinsic_min_max);
+    valid_example!(intrinsic_min_max_unsigned);
+    valid_example!(comments);
+    valid_example!(string_literal);
+    valid_example!(intrinsic_popcount);
+    valid_example!(entry_return);
+    valid_example!(entry_fall_through);
+    valid_example!(unary_minus);
+    valid_example!(grouping);
+    valid_example!(if_expression);
+    valid_example!(block_value);
 
     invalid_example!(variable_mutability_invalid, beryllium::CompileError::ChangedImmutableVariable(_));
+    invalid_example!(array_as_value_invalid, beryllium::CompileError::ArrayAsValue(_));
+    invalid_example!(assign_immutable_invalid, beryllium::CompileError::ChangedImmutableVariable(_));
+    invalid_example!(if_expression_invalid, beryllium::CompileError::MissingValue(_));
+    invalid_example!(block_value_invalid, beryllium::CompileError::MissingValue(_));
+    invalid_example!(iteration_label_invalid, beryllium::CompileError::UndeclaredLabel { .. });
+    invalid_example!(function_return_mixed_invalid, beryllium::CompileError::MixedReturns(_));
+    invalid_example!(function_return_type_invalid, beryllium::CompileError::NoReturnValue(_));
+    invalid_example!(let_typed_invalid, beryllium::CompileError::LiteralOutOfType { .. });
 }
 
+
+mod harness {
+    use std::{
+        fs::{create_dir, read_to_string},
+        path::PathBuf,
+        process::Command,
+    };
+
+    use super::{interpret, Header};
+
+    test_harness_example!(test_harness, 1);
+    test_harness_example!(test_harness_results, 1);
+}
+
+
+
+#[test]
+fn headers_are_read() {
+    assert_eq!(Header::read("//! exit: 20\n//! stdout: hello\n//! stdout:\nfn _start() exit(20);\n//! exit: 1\n"), Header {
+        exit: Some(20),
+        stdout: Some(String::from("hello\n\n")),
+    });
+    assert_eq!(Header::read("fn _start() exit(0);"), Header::default());
+}
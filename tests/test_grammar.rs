@@ -0,0 +1,111 @@
+use std::path::Path;
+
+use beryllium::{
+    CompileError,
+    FallibleIterator,
+    parser::Parser,
+    tokenize::Tokenize,
+};
+
+
+// each snippet in `tests/grammar` starts with comments saying what it should give:
+//
+//   // tokens: fn f ( ) return 1 ;     the spelling of every token, `tokens:` lines are joined
+//   // ast: fn f() return 1;           one line for each item, as the tree prints it
+//   // error: BE0005                   the code of the error tokenizing or parsing fails with
+//
+// the annotations that are left out aren't checked
+#[derive(Default)]
+struct Expectations {
+    tokens: Option<String>,
+    items: Option<Vec<String>>,
+    error: Option<String>,
+}
+
+impl Expectations {
+    fn read(source: &str) -> Self {
+        let mut expectations = Self::default();
+        for line in source.lines().map_while(|line| line.trim().strip_prefix("//")) {
+            let Some((key, value)) = line.split_once(':') else { continue };
+            let value = value.trim().to_string();
+            match key.trim() {
+                "tokens" => match &mut expectations.tokens {
+                    Some(tokens) => { tokens.push(' '); tokens.push_str(&value) },
+                    None => expectations.tokens = Some(value),
+                },
+                "ast" => expectations.items.get_or_insert_with(Vec::new).push(value),
+                "error" => expectations.error = Some(value),
+                _ => (),
+            }
+        }
+        expectations
+    }
+}
+
+// the ways the snippet differs from its annotations
+fn check(source: &str) -> Vec<String> {
+    let expected = Expectations::read(source);
+    let mut failures = Vec::new();
+    if expected.tokens.is_none() && expected.items.is_none() && expected.error.is_none() {
+        failures.push(String::from("has no annotations"));
+    }
+
+    let tokens = source.tokenize().collect::<Vec<_>>();
+    if let (Some(expected), Ok(tokens)) = (&expected.tokens, &tokens) {
+        let spelled = tokens.iter().map(|token| token.data.text()).collect::<Vec<_>>().join(" ");
+        if *expected != spelled {
+            failures.push(format!("tokens are `{spelled}`, expected `{expected}`"));
+        }
+    }
+
+    let error = match tokens {
+        Ok(_) => match Parser::new(source.tokenize()).parse() {
+            Ok(program) => {
+                let items: Vec<String> = program.0.iter().map(ToString::to_string).collect();
+                if expected.items.as_ref().is_some_and(|expected| *expected != items) {
+                    failures.push(format!("items are {items:?}, expected {:?}", expected.items.unwrap_or_default()));
+                }
+                None
+            },
+            Err(err) => Some(CompileError::from(err)),
+        },
+        Err(err) => Some(CompileError::from(err)),
+    };
+    match (&expected.error, error) {
+        (None, None) => (),
+        (Some(expected), Some(error)) if *expected == error.code() => (),
+        (Some(expected), Some(error)) => failures.push(format!("fails with {} ({error}), expected {expected}", error.code())),
+        (Some(expected), None) => failures.push(format!("parses, expected {expected}")),
+        (None, Some(error)) => failures.push(format!("fails with {} ({error})", error.code())),
+    }
+    failures
+}
+
+
+#[test]
+fn grammar_snippets_conform() {
+    let mut snippets: Vec<_> = std::fs::read_dir(Path::new("tests/grammar"))
+        .expect("the grammar snippets are readable")
+        .map(|entry| entry.expect("a directory entry").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "be"))
+        .collect();
+    snippets.sort();
+    assert!(!snippets.is_empty());
+
+    let failures: Vec<String> = snippets
+        .iter()
+        .flat_map(|path| {
+            let source = std::fs::read_to_string(path).expect("the snippet is readable");
+            check(&source).into_iter().map(move |failure| format!("{}: {failure}", path.display()))
+        })
+        .collect();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn annotations_are_checked() {
+    assert!(check("// ast: fn f() return 1;\nfn f() return 1;").is_empty());
+    assert_eq!(check("fn f() return 1;"), vec![String::from("has no annotations")]);
+    assert_eq!(check("// tokens: fn f\nfn g() {}"), vec![String::from("tokens are `fn g ( ) { }`, expected `fn f`")]);
+    assert_eq!(check("// error: BE0005\nfn f() {}"), vec![String::from("parses, expected BE0005")]);
+}